@@ -23,7 +23,9 @@ use std::vec::Vec;
 use std::path::PathBuf;
 use std::sync::{Arc,RwLock};
 use crate::util::io::directory_as_string;
-use crate::util::yaml::blend_variables;
+use crate::util::yaml::{blend_variables,ListMerge};
+use crate::util::terminal::ColorMode;
+use crate::tasks::files::FileAttributesInput;
 use crate::inventory::loading::convert_json_vars;
 use crate::util::io::jet_file_open;
 use crate::util::yaml::show_yaml_error_in_context;
@@ -50,16 +52,38 @@ pub struct CliParser {
     pub show_hosts: Vec<String>,
     pub show_groups: Vec<String>,
     pub batch_size: Option<usize>,
+    pub unreachable_after: usize,
+    pub remote_tmp: Option<String>,
+    pub trace_log: Option<String>,
     pub default_user: String,
     pub sudo: Option<String>,
     pub default_port: i64,
     pub threads: usize,
+    pub timeout: Option<u64>,
+    pub default_file_mode: Option<String>,
+    pub default_dir_mode: Option<String>,
     pub verbosity: u32,
     pub tags: Option<Vec<String>>,
+    pub skip_tags: Option<Vec<String>>,
     pub allow_localhost_delegation: bool,
+    pub allow_lookup_cmd: Vec<String>,
+    pub list_merge: ListMerge,
+    pub color_mode: ColorMode,
     pub extra_vars: serde_yaml::Value,
+    // each -e in the order given, blended into extra_vars once --list-merge can no longer change
+    extra_vars_sources: Vec<serde_yaml::Mapping>,
     pub forward_agent: bool,
+    pub explain: bool,
+    pub profile: bool,
+    pub debug_params: bool,
+    pub diff: bool,
+    pub flush_cache: bool,
+    pub force_handlers: bool,
+    pub syntax_check: bool,
+    pub list_hosts: bool,
+    pub list_tasks: bool,
     pub login_password: Option<String>,
+    pub sudo_password: Option<String>,
     pub argument_map: HashMap<String, Arguments>,
 }
 
@@ -113,7 +137,9 @@ pub enum Arguments {
     ARGUMENT_USER_SHORT,
     ARGUMENT_SUDO,
     ARGUMENT_TAGS,
+    ARGUMENT_SKIP_TAGS,
     ARGUMENT_ALLOW_LOCALHOST,
+    ARGUMENT_ALLOW_LOOKUP_CMD,
     ARGUMENT_FORWARD_AGENT,
     ARGUMENT_THREADS,
     ARGUMENT_THREADS_SHORT,
@@ -124,8 +150,27 @@ pub enum Arguments {
     ARGUMENT_EXTRA_VARS,
     ARGUMENT_EXTRA_VARS_SHORT,
     ARGUMENT_ASK_LOGIN_PASSWORD,
+    ARGUMENT_ASK_SUDO_PASSWORD,
+    ARGUMENT_EXPLAIN,
     ARGUMENT_MODULES,
-    ARGUMENT_MODULES_SHORT
+    ARGUMENT_MODULES_SHORT,
+    ARGUMENT_PROFILE,
+    ARGUMENT_SYNTAX_CHECK,
+    ARGUMENT_LIST_HOSTS,
+    ARGUMENT_LIST_TASKS,
+    ARGUMENT_LIST_MERGE,
+    ARGUMENT_DEBUG_PARAMS,
+    ARGUMENT_UNREACHABLE_AFTER,
+    ARGUMENT_FLUSH_CACHE,
+    ARGUMENT_REMOTE_TMP,
+    ARGUMENT_DIFF,
+    ARGUMENT_TRACE_LOG,
+    ARGUMENT_TIMEOUT,
+    ARGUMENT_NO_COLOR,
+    ARGUMENT_FORCE_COLOR,
+    ARGUMENT_DEFAULT_FILE_MODE,
+    ARGUMENT_DEFAULT_DIR_MODE,
+    ARGUMENT_FORCE_HANDLERS
 }
 
 impl Arguments {
@@ -150,7 +195,9 @@ impl Arguments {
             Arguments::ARGUMENT_USER_SHORT => "-u",
             Arguments::ARGUMENT_SUDO => "--sudo",
             Arguments::ARGUMENT_TAGS => "--tags",
+            Arguments::ARGUMENT_SKIP_TAGS => "--skip-tags",
             Arguments::ARGUMENT_ALLOW_LOCALHOST => "--allow-localhost-delegation",
+            Arguments::ARGUMENT_ALLOW_LOOKUP_CMD => "--allow-lookup-cmd",
             Arguments::ARGUMENT_FORWARD_AGENT => "--forward-agent",
             Arguments::ARGUMENT_THREADS => "--threads",
             Arguments::ARGUMENT_THREADS_SHORT => "-t",
@@ -161,6 +208,25 @@ impl Arguments {
             Arguments::ARGUMENT_EXTRA_VARS => "--extra-vars",
             Arguments::ARGUMENT_EXTRA_VARS_SHORT => "-e",
             Arguments::ARGUMENT_ASK_LOGIN_PASSWORD => "--ask-login-password",
+            Arguments::ARGUMENT_ASK_SUDO_PASSWORD => "--ask-sudo-password",
+            Arguments::ARGUMENT_EXPLAIN => "--explain",
+            Arguments::ARGUMENT_PROFILE => "--profile",
+            Arguments::ARGUMENT_SYNTAX_CHECK => "--syntax-check",
+            Arguments::ARGUMENT_LIST_HOSTS => "--list-hosts",
+            Arguments::ARGUMENT_LIST_TASKS => "--list-tasks",
+            Arguments::ARGUMENT_LIST_MERGE => "--list-merge",
+            Arguments::ARGUMENT_DEBUG_PARAMS => "--debug-params",
+            Arguments::ARGUMENT_UNREACHABLE_AFTER => "--unreachable-after",
+            Arguments::ARGUMENT_FLUSH_CACHE => "--flush-cache",
+            Arguments::ARGUMENT_REMOTE_TMP => "--remote-tmp",
+            Arguments::ARGUMENT_DIFF => "--diff",
+            Arguments::ARGUMENT_TRACE_LOG => "--trace-log",
+            Arguments::ARGUMENT_TIMEOUT => "--timeout",
+            Arguments::ARGUMENT_NO_COLOR => "--no-color",
+            Arguments::ARGUMENT_FORCE_COLOR => "--force-color",
+            Arguments::ARGUMENT_DEFAULT_FILE_MODE => "--default-file-mode",
+            Arguments::ARGUMENT_DEFAULT_DIR_MODE => "--default-dir-mode",
+            Arguments::ARGUMENT_FORCE_HANDLERS => "--force-handlers",
         }
     }
 }
@@ -187,7 +253,9 @@ fn build_argument_map() -> HashMap<String, Arguments> {
         (Arguments::ARGUMENT_USER_SHORT, "-u"),
         (Arguments::ARGUMENT_SUDO, "--sudo"),
         (Arguments::ARGUMENT_TAGS, "--tags"),
+        (Arguments::ARGUMENT_SKIP_TAGS, "--skip-tags"),
         (Arguments::ARGUMENT_ALLOW_LOCALHOST, "--allow-localhost-delegation"),
+        (Arguments::ARGUMENT_ALLOW_LOOKUP_CMD, "--allow-lookup-cmd"),
         (Arguments::ARGUMENT_FORWARD_AGENT, "--forward-agent"),
         (Arguments::ARGUMENT_THREADS, "--threads"),
         (Arguments::ARGUMENT_THREADS_SHORT, "-t"),
@@ -198,6 +266,25 @@ fn build_argument_map() -> HashMap<String, Arguments> {
         (Arguments::ARGUMENT_EXTRA_VARS, "--extra-vars"),
         (Arguments::ARGUMENT_EXTRA_VARS_SHORT, "-e"),
         (Arguments::ARGUMENT_ASK_LOGIN_PASSWORD, "--ask-login-password"),
+        (Arguments::ARGUMENT_ASK_SUDO_PASSWORD, "--ask-sudo-password"),
+        (Arguments::ARGUMENT_EXPLAIN, "--explain"),
+        (Arguments::ARGUMENT_PROFILE, "--profile"),
+        (Arguments::ARGUMENT_SYNTAX_CHECK, "--syntax-check"),
+        (Arguments::ARGUMENT_LIST_HOSTS, "--list-hosts"),
+        (Arguments::ARGUMENT_LIST_TASKS, "--list-tasks"),
+        (Arguments::ARGUMENT_LIST_MERGE, "--list-merge"),
+        (Arguments::ARGUMENT_DEBUG_PARAMS, "--debug-params"),
+        (Arguments::ARGUMENT_UNREACHABLE_AFTER, "--unreachable-after"),
+        (Arguments::ARGUMENT_FLUSH_CACHE, "--flush-cache"),
+        (Arguments::ARGUMENT_REMOTE_TMP, "--remote-tmp"),
+        (Arguments::ARGUMENT_DIFF, "--diff"),
+        (Arguments::ARGUMENT_TRACE_LOG, "--trace-log"),
+        (Arguments::ARGUMENT_TIMEOUT, "--timeout"),
+        (Arguments::ARGUMENT_NO_COLOR, "--no-color"),
+        (Arguments::ARGUMENT_FORCE_COLOR, "--force-color"),
+        (Arguments::ARGUMENT_DEFAULT_FILE_MODE, "--default-file-mode"),
+        (Arguments::ARGUMENT_DEFAULT_DIR_MODE, "--default-dir-mode"),
+        (Arguments::ARGUMENT_FORCE_HANDLERS, "--force-handlers"),
     ];
     let mut map : HashMap<String, Arguments> = HashMap::new();
     for (e,i) in inputs.iter() {
@@ -274,19 +361,61 @@ fn show_help() {
                        | |\n\
                        | | --port N | use this default port instead of $JET_SSH_PORT or 22\n\
                        | |\n\
+                       | | --remote-tmp path | scratch directory on managed hosts, default ~/.jet/tmp. Also $JET_REMOTE_TMP or jet_remote_tmp\n\
+                       | |\n\
                        | | -t, --threads N| how many parallel threads to use. Alternatively set $JET_THREADS\n\
                        | |\n\
+                       | | --unreachable-after N | give up on a host after this many connection failures in a row, default 1\n\
+                       | |\n\
                        | | -u, --user username | use this default username instead of $JET_SSH_USER or $USER\n\
                        | |\n\
                        | --- | ---\n\
                        | Misc options:\n\
                        | | --allow-localhost-delegation | signs off on variable sourcing risks and enables localhost actions with delegate_to\n\
                        | |\n\
+                       | | --allow-lookup-cmd prog1:prog2 | lets the lookup_cmd template helper run these programs on the control machine, such as a secrets tool. Also $JET_ALLOW_LOOKUP_CMD\n\
+                       | |\n\
+                       | | --ask-sudo-password | prompt for the sudo password, which is sent to sudo on standard input and never put in a command\n\
+                       | |\n\
+                       | | --debug-params | shows the parameters of each task after templating, per host, unless the task sets no_log\n\
+                       | |\n\
+                       | | --default-dir-mode mode | the mode directory gives directories when attributes do not set one, unless the play sets default_dir_mode\n\
+                       | |\n\
+                       | | --default-file-mode mode | the mode copy and template give files when attributes do not set one, unless the play sets default_file_mode\n\
+                       | |\n\
+                       | | --diff | shows the before and after values of what modules changed, where they report them\n\
+                       | |\n\
                        | | -e, --extra-vars @filename | injects extra variables into the playbook runtime context from a YAML file, or quoted JSON\n\
                        | |\n\
+                       | | --explain | shows each command exactly as it will be run, including sudo, unless the task sets no_log\n\
+                       | |\n\
+                       | | --flush-cache | ignores facts cached by facts tasks with cache: true and gathers them again, and checksums kept by quick_check\n\
+                       | |\n\
+                       | | --force-color | colors output even when it is not a terminal, such as in CI logs\n\
+                       | |\n\
+                       | | --force-handlers | runs notified handlers on hosts even after a later task failed there, unless the play sets force_handlers: false\n\
+                       | |\n\
+                       | | --list-hosts | shows the hosts each play would target, after any limits, and exits\n\
+                       | |\n\
+                       | | --list-merge strategy | how lists in variables combine across groups, hosts and vars: replace (default), append, or prepend. Also uses $JET_LIST_MERGE\n\
+                       | |\n\
+                       | | --list-tasks | shows the tasks and handlers of each play in order, with their tags, and exits\n\
+                       | |\n\
+                       | | --no-color | never colors output, which is otherwise colored only on a terminal and when $NO_COLOR is not set\n\
+                       | |\n\
+                       | | --profile | times each task and reports the slowest tasks at the end\n\
+                       | |\n\
+                       | | --skip-tags tag1:tag2 | skip tasks with one of these tags, including tags from their role or include, even if --tags selects them\n\
+                       | |\n\
                        | | --sudo username | sudo to this user by default for all tasks\n\
                        | |\n\
-                       | | --tags tag1:tag2 | only run tasks or roles with one of these tags\n\
+                       | | --syntax-check | loads and validates playbooks, roles, task files and src files without connecting to any hosts\n\
+                       | |\n\
+                       | | --tags tag1:tag2 | only run tasks with one of these tags, tags on a role or include apply to all of its tasks\n\
+                       | |\n\
+                       | | --timeout N | fail any command a task runs that takes longer than N seconds, tasks may set their own with 'with: { timeout: N }'\n\
+                       | |\n\
+                       | | --trace-log path | appends a timestamped, human readable trace of every task, command and result, whatever the verbosity. Moved to path.1 at 10MB. Also $JET_TRACE_LOG\n\
                        | |\n\
                        | | -v -vv -vvv| ever increasing verbosity\n\
                        | |\n\
@@ -317,6 +446,9 @@ impl CliParser  {
             show_hosts: Vec::new(),
             show_groups: Vec::new(),
             batch_size: None,
+            unreachable_after: 1,
+            remote_tmp: env::var("JET_REMOTE_TMP").ok(),
+            trace_log: env::var("JET_TRACE_LOG").ok(),
             default_user: match env::var("JET_SSH_USER") {
                 Ok(x) => {
                     println!("$JET_SSH_USER: {}", x);
@@ -345,16 +477,46 @@ impl CliParser  {
                 },
                 Err(_) => 20
             },
+            timeout: None,
+            default_file_mode: None,
+            default_dir_mode: None,
             inventory_set: false,
             playbook_set: false,
             verbosity: 0,
             limit_groups: Vec::new(),
             limit_hosts: Vec::new(),
             tags: None,
+            skip_tags: None,
             allow_localhost_delegation: false,
+            allow_lookup_cmd: match env::var("JET_ALLOW_LOOKUP_CMD") {
+                Ok(x) => x.split(':').filter(|y| ! y.is_empty()).map(String::from).collect(),
+                Err(_) => Vec::new()
+            },
+            list_merge: match env::var("JET_LIST_MERGE") {
+                Ok(x) => match ListMerge::from_name(&x) {
+                    Ok(strategy) => strategy,
+                    Err(_) => {
+                        println!("environment variable JET_LIST_MERGE has an invalid value, ignoring: {}", x);
+                        ListMerge::Replace
+                    }
+                },
+                Err(_) => ListMerge::Replace
+            },
+            color_mode: ColorMode::Auto,
             extra_vars: serde_yaml::Value::Mapping(serde_yaml::Mapping::new()),
+            extra_vars_sources: Vec::new(),
             forward_agent: false,
+            explain: false,
+            profile: false,
+            force_handlers: false,
+            debug_params: false,
+            diff: false,
+            flush_cache: false,
+            syntax_check: false,
+            list_hosts: false,
+            list_tasks: false,
             login_password: None,
+            sudo_password: None,
             argument_map: build_argument_map(),
         }
     }
@@ -370,6 +532,11 @@ impl CliParser  {
     // actual CLI parsing happens here
 
     pub fn parse(&mut self) -> Result<(), String> {
+        let args: Vec<String> = env::args().collect();
+        self.parse_args(&args)
+    }
+
+    fn parse_args(&mut self, args: &[String]) -> Result<(), String> {
 
         let mut arg_count: usize = 0;
         let mut next_is_value = false;
@@ -377,8 +544,7 @@ impl CliParser  {
         // we go through each CLI arg in a loop, certain arguments take
         // parameters and others do not.
 
-        let args: Vec<String> = env::args().collect();
-        'each_argument: for argument in &args {
+        'each_argument: for argument in args {
 
             let argument_str = argument.as_str();
             arg_count += 1;
@@ -440,6 +606,18 @@ impl CliParser  {
                             Arguments::ARGUMENT_VERBOSER           => self.increase_verbosity(2),
                             Arguments::ARGUMENT_VERBOSEST          => self.increase_verbosity(3),
                             Arguments::ARGUMENT_ASK_LOGIN_PASSWORD => self.store_login_password(),
+                            Arguments::ARGUMENT_ASK_SUDO_PASSWORD  => self.store_sudo_password(),
+                            Arguments::ARGUMENT_EXPLAIN            => self.store_explain(),
+                            Arguments::ARGUMENT_PROFILE            => self.store_profile(),
+                            Arguments::ARGUMENT_DEBUG_PARAMS       => self.store_debug_params(),
+                            Arguments::ARGUMENT_DIFF               => self.store_diff(),
+                            Arguments::ARGUMENT_FLUSH_CACHE        => self.store_flush_cache(),
+                            Arguments::ARGUMENT_FORCE_HANDLERS     => self.store_force_handlers(),
+                            Arguments::ARGUMENT_SYNTAX_CHECK       => self.store_syntax_check(),
+                            Arguments::ARGUMENT_LIST_HOSTS         => self.store_list_hosts(),
+                            Arguments::ARGUMENT_LIST_TASKS         => self.store_list_tasks(),
+                            Arguments::ARGUMENT_NO_COLOR           => self.store_color_mode(ColorMode::Never),
+                            Arguments::ARGUMENT_FORCE_COLOR        => self.store_color_mode(ColorMode::Always),
                             _ => {
                                 { standalone_arg_found = false; next_is_value = true; };
                                 Ok(())
@@ -463,6 +641,8 @@ impl CliParser  {
                                     Arguments::ARGUMENT_INVENTORY_SHORT   => self.append_inventory(&args[arg_count]),
                                     Arguments::ARGUMENT_SUDO              => self.store_sudo(&args[arg_count]),
                                     Arguments::ARGUMENT_TAGS              => self.store_tags(&args[arg_count]),
+                                    Arguments::ARGUMENT_SKIP_TAGS         => self.store_skip_tags(&args[arg_count]),
+                                    Arguments::ARGUMENT_ALLOW_LOOKUP_CMD  => self.store_allow_lookup_cmd(&args[arg_count]),
                                     Arguments::ARGUMENT_USER              => self.store_default_user(&args[arg_count]),
                                     Arguments::ARGUMENT_USER_SHORT        => self.store_default_user(&args[arg_count]),
                                     Arguments::ARGUMENT_SHOW_GROUPS       => self.store_show_groups(&args[arg_count]),
@@ -470,11 +650,18 @@ impl CliParser  {
                                     Arguments::ARGUMENT_LIMIT_GROUPS      => self.store_limit_groups(&args[arg_count]),
                                     Arguments::ARGUMENT_LIMIT_HOSTS       => self.store_limit_hosts(&args[arg_count]),
                                     Arguments::ARGUMENT_BATCH_SIZE        => self.store_batch_size(&args[arg_count]),
+                                    Arguments::ARGUMENT_UNREACHABLE_AFTER => self.store_unreachable_after(&args[arg_count]),
+                                    Arguments::ARGUMENT_REMOTE_TMP        => self.store_remote_tmp(&args[arg_count]),
+                                    Arguments::ARGUMENT_TRACE_LOG         => self.store_trace_log(&args[arg_count]),
+                                    Arguments::ARGUMENT_TIMEOUT           => self.store_timeout(&args[arg_count]),
+                                    Arguments::ARGUMENT_DEFAULT_FILE_MODE => self.store_default_file_mode(&args[arg_count]),
+                                    Arguments::ARGUMENT_DEFAULT_DIR_MODE  => self.store_default_dir_mode(&args[arg_count]),
                                     Arguments::ARGUMENT_THREADS           => self.store_threads(&args[arg_count]),
                                     Arguments::ARGUMENT_THREADS_SHORT     => self.store_threads(&args[arg_count]),
                                     Arguments::ARGUMENT_PORT              => self.store_port(&args[arg_count]),
                                     Arguments::ARGUMENT_EXTRA_VARS        => self.store_extra_vars(&args[arg_count]),
                                     Arguments::ARGUMENT_EXTRA_VARS_SHORT  => self.store_extra_vars(&args[arg_count]),
+                                    Arguments::ARGUMENT_LIST_MERGE        => self.store_list_merge(&args[arg_count]),
                                     _  => Err(format!("invalid flag: {}", argument_str)),
                                 };
                             }
@@ -492,6 +679,10 @@ impl CliParser  {
 
         }
 
+        for source in self.extra_vars_sources.drain(..) {
+            blend_variables(&mut self.extra_vars, serde_yaml::Value::Mapping(source), self.list_merge);
+        }
+
         // make adjustments based on modes
         match self.mode {
             CLI_MODE_LOCAL       => { self.threads = 1 },
@@ -632,6 +823,19 @@ impl CliParser  {
         Ok(())
     }
 
+    fn store_skip_tags(&mut self, value: &str) -> Result<(), String> {
+        match split_string(value) {
+            Ok(values)  =>  { self.skip_tags = Some(values); },
+            Err(err_msg) =>  return Err(format!("{} {}", Arguments::ARGUMENT_SKIP_TAGS.as_str(), err_msg)),
+        }
+        Ok(())
+    }
+
+    fn store_allow_lookup_cmd(&mut self, value: &str) -> Result<(), String> {
+        self.allow_lookup_cmd = value.split(':').filter(|x| ! x.is_empty()).map(String::from).collect();
+        Ok(())
+    }
+
     fn store_sudo(&mut self, value: &str) -> Result<(), String> {
         self.sudo = Some(value.to_owned());
         Ok(())
@@ -652,6 +856,54 @@ impl CliParser  {
         }
     }
 
+    fn store_color_mode(&mut self, mode: ColorMode) -> Result<(), String> {
+        self.color_mode = mode;
+        Ok(())
+    }
+
+    fn store_list_merge(&mut self, value: &str) -> Result<(), String> {
+        self.list_merge = ListMerge::from_name(value)?;
+        Ok(())
+    }
+
+    fn store_unreachable_after(&mut self, value: &str) -> Result<(), String> {
+        match value.parse::<usize>() {
+            Ok(n) if n > 0 => { self.unreachable_after = n; Ok(())},
+            _ => { Err(format!("{}: invalid value", Arguments::ARGUMENT_UNREACHABLE_AFTER.as_str()))}
+        }
+    }
+
+    fn store_timeout(&mut self, value: &str) -> Result<(), String> {
+        match value.parse::<u64>() {
+            Ok(n) if n > 0 => { self.timeout = Some(n); Ok(())},
+            _ => { Err(format!("{}: invalid value", Arguments::ARGUMENT_TIMEOUT.as_str()))}
+        }
+    }
+
+    fn store_default_file_mode(&mut self, value: &str) -> Result<(), String> {
+        match FileAttributesInput::normalize_mode(value) {
+            Some(x) => { self.default_file_mode = Some(x); Ok(())},
+            None => { Err(format!("{}: invalid value, expecting an octal mode such as 0644", Arguments::ARGUMENT_DEFAULT_FILE_MODE.as_str()))}
+        }
+    }
+
+    fn store_default_dir_mode(&mut self, value: &str) -> Result<(), String> {
+        match FileAttributesInput::normalize_mode(value) {
+            Some(x) => { self.default_dir_mode = Some(x); Ok(())},
+            None => { Err(format!("{}: invalid value, expecting an octal mode such as 0755", Arguments::ARGUMENT_DEFAULT_DIR_MODE.as_str()))}
+        }
+    }
+
+    fn store_remote_tmp(&mut self, value: &str) -> Result<(), String> {
+        self.remote_tmp = Some(value.to_owned());
+        Ok(())
+    }
+
+    fn store_trace_log(&mut self, value: &str) -> Result<(), String> {
+        self.trace_log = Some(value.to_owned());
+        Ok(())
+    }
+
     fn store_threads(&mut self, value: &str) -> Result<(), String> {
         match value.parse::<usize>() {
             Ok(n) =>  { self.threads = n; Ok(())}
@@ -671,6 +923,51 @@ impl CliParser  {
         Ok(())
     }
 
+    fn store_explain(&mut self) -> Result<(), String> {
+        self.explain = true;
+        Ok(())
+    }
+
+    fn store_profile(&mut self) -> Result<(), String> {
+        self.profile = true;
+        Ok(())
+    }
+
+    fn store_force_handlers(&mut self) -> Result<(), String> {
+        self.force_handlers = true;
+        Ok(())
+    }
+
+    fn store_debug_params(&mut self) -> Result<(), String> {
+        self.debug_params = true;
+        Ok(())
+    }
+
+    fn store_diff(&mut self) -> Result<(), String> {
+        self.diff = true;
+        Ok(())
+    }
+
+    fn store_flush_cache(&mut self) -> Result<(), String> {
+        self.flush_cache = true;
+        Ok(())
+    }
+
+    fn store_syntax_check(&mut self) -> Result<(), String> {
+        self.syntax_check = true;
+        Ok(())
+    }
+
+    fn store_list_hosts(&mut self) -> Result<(), String> {
+        self.list_hosts = true;
+        Ok(())
+    }
+
+    fn store_list_tasks(&mut self) -> Result<(), String> {
+        self.list_tasks = true;
+        Ok(())
+    }
+
     fn increase_verbosity(&mut self, amount: u32) -> Result<(), String> {
         self.verbosity += amount;
         Ok(())
@@ -765,7 +1062,7 @@ impl CliParser  {
                 show_yaml_error_in_context(&parsed, path);
                 return Err("edit the file and try again?".to_string());
             }   
-            blend_variables(&mut self.extra_vars, serde_yaml::Value::Mapping(parsed.unwrap()));
+            self.extra_vars_sources.push(parsed.unwrap());
 
         } else {
             // input is inline JSON (as YAML wouldn't make sense with the newlines)
@@ -776,7 +1073,7 @@ impl CliParser  {
                 Err(y) => { return Err(format!("inline json is not valid: {}", y)) }
             };   
             let serde_map = convert_json_vars(&actual);
-            blend_variables(&mut self.extra_vars, serde_yaml::Value::Mapping(serde_map));
+            self.extra_vars_sources.push(serde_map);
         
         }
         
@@ -799,6 +1096,13 @@ impl CliParser  {
         Ok(())
      }
 
+     fn store_sudo_password(&mut self) -> Result<(), String>{
+        // kept only in memory, remote.rs hands it to sudo on standard input
+        let value = crate::util::terminal::prompt_line(&String::from("enter sudo password: "), true)?;
+        self.sudo_password = Some(value);
+        Ok(())
+     }
+
 }
 
 impl Default for CliParser {
@@ -826,3 +1130,31 @@ fn parse_paths(from: &String, value: &str) -> Result<Vec<PathBuf>, String> {
     }
     Ok(results)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_local(flags: &[&str]) -> CliParser {
+        let mut args: Vec<String> = vec![String::from("jetp"), String::from("local")];
+        args.extend(flags.iter().map(|x| String::from(*x)));
+        let mut parser = CliParser::new();
+        parser.parse_args(&args).unwrap();
+        parser
+    }
+
+    #[test]
+    fn test_extra_vars_are_blended_with_the_final_list_merge() {
+        let ports = |flags: &[&str]| parse_local(flags).extra_vars.get("ports").cloned().unwrap();
+        let both : serde_yaml::Value = serde_yaml::from_str("[80, 443]").unwrap();
+        assert_eq!(ports(&["-e", "{\"ports\": [80]}", "-e", "{\"ports\": [443]}", "--list-merge", "append"]), both);
+        assert_eq!(ports(&["--list-merge", "append", "-e", "{\"ports\": [80]}", "-e", "{\"ports\": [443]}"]), both);
+    }
+
+    #[test]
+    fn test_color_flags_are_stored_on_the_parser() {
+        assert_eq!(parse_local(&[]).color_mode, ColorMode::Auto);
+        assert_eq!(parse_local(&["--no-color"]).color_mode, ColorMode::Never);
+        assert_eq!(parse_local(&["--force-color"]).color_mode, ColorMode::Always);
+    }
+}
@@ -20,6 +20,8 @@ use crate::connection::ssh::SshFactory;
 use crate::connection::local::LocalFactory;
 use crate::connection::no::NoFactory;
 use crate::playbooks::traversal::{playbook_traversal,RunState};
+use crate::playbooks::syntax::playbook_syntax_check;
+use crate::playbooks::listing::playbook_listing;
 use crate::playbooks::context::PlaybookContext;
 use crate::playbooks::visitor::{PlaybookVisitor,CheckMode};
 use crate::inventory::inventory::Inventory;
@@ -54,6 +56,13 @@ pub fn playbook_simulate(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser)
 }
 
 fn playbook(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser, check_mode: CheckMode, connection_mode: ConnectionMode) -> i32 {
+    let mut visitor = PlaybookVisitor::new(check_mode);
+    if let Some(path) = &parser.trace_log {
+        if let Err(msg) = visitor.set_trace_log(path) {
+            println!("{}", msg);
+            return 1;
+        }
+    }
     let run_state = Arc::new(RunState {
         // every object gets an inventory, though with local modes it's empty.
         inventory: Arc::clone(inventory),
@@ -67,15 +76,28 @@ fn playbook(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser, check_mode:
         // to run-state.  Context should mostly *not* get parameters from the parser unless they
         // are going to appear in variables.
         context: Arc::new(RwLock::new(PlaybookContext::new(parser))),
-        visitor: Arc::new(RwLock::new(PlaybookVisitor::new(check_mode))),
+        visitor: Arc::new(RwLock::new(visitor)),
         connection_factory: match connection_mode {
             ConnectionMode::Ssh => Arc::new(RwLock::new(SshFactory::new(inventory, parser.forward_agent, parser.login_password.clone()))),
             ConnectionMode::Local => Arc::new(RwLock::new(LocalFactory::new(inventory))),
             ConnectionMode::Simulate => Arc::new(RwLock::new(NoFactory::new()))
         },
         tags: parser.tags.clone(),
+        skip_tags: parser.skip_tags.clone(),
         allow_localhost_delegation: parser.allow_localhost_delegation
     });
+    if parser.list_hosts || parser.list_tasks {
+        return match playbook_listing(&run_state, parser.list_hosts, parser.list_tasks) {
+            Ok(_)  => 0,
+            Err(s) => { println!("{}", s); 1 }
+        };
+    }
+    if parser.syntax_check {
+        return match playbook_syntax_check(&run_state) {
+            Ok(_)  => 0,
+            Err(s) => { println!("{}", s); 1 }
+        };
+    }
     match playbook_traversal(&run_state) {
         Ok(_)  => run_state.visitor.read().unwrap().get_exit_status(&run_state.context),
         Err(s) => { println!("{}", s); 1 }
@@ -15,6 +15,7 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::util::terminal::{two_column_table, captioned_display};
+use crate::util::yaml::ListMerge;
 use std::sync::Arc;
 use std::sync::RwLock;
 use crate::inventory::inventory::Inventory;
@@ -38,7 +39,7 @@ fn string_slice(values: &[String]) -> String {
 
 // jetp show --inventory <path> --hosts host1:host2
 
-pub fn show_inventory_host(inventory: &Arc<RwLock<Inventory>>, host_name: &String) -> Result<(),String> {
+pub fn show_inventory_host(inventory: &Arc<RwLock<Inventory>>, host_name: &String, list_merge: ListMerge) -> Result<(),String> {
 
     let inventory = inventory.read().expect("inventory read");
 
@@ -53,7 +54,7 @@ pub fn show_inventory_host(inventory: &Arc<RwLock<Inventory>>, host_name: &Strin
 
     let mut parents               : Vec<String> = host.get_group_names();
     let mut ancestors             : Vec<String> = host.get_ancestor_group_names();
-    let blended_variables     = host.get_blended_variables_yaml()?;
+    let blended_variables     = host.get_blended_variables_yaml(list_merge)?;
     
     parents.sort();
     ancestors.sort();
@@ -79,7 +80,7 @@ pub fn show_inventory_host(inventory: &Arc<RwLock<Inventory>>, host_name: &Strin
 // jetp show --inventory <path> # implicit --group all
 // jetp show --inventory <path> --groups group1:group2
 
-pub fn show_inventory_group(inventory: &Arc<RwLock<Inventory>>, group_name: &String) -> Result<(),String> {
+pub fn show_inventory_group(inventory: &Arc<RwLock<Inventory>>, group_name: &String, list_merge: ListMerge) -> Result<(),String> {
 
     let inventory = inventory.read().expect("inventory read");
 
@@ -106,7 +107,7 @@ pub fn show_inventory_group(inventory: &Arc<RwLock<Inventory>>, group_name: &Str
     descendant_hosts.sort();
     child_hosts.sort();
 
-    let blended_variables      = group.get_blended_variables_yaml()?;
+    let blended_variables      = group.get_blended_variables_yaml(list_merge)?;
     let descendant_hosts_count = format!("{}", descendant_hosts.len());
     let child_hosts_count      = format!("{}", child_hosts.len());
     
@@ -14,7 +14,12 @@
 // You should have received a copy of the GNU General Public License
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::sync::mpsc::{channel,Sender,RecvTimeoutError};
+use std::thread::{self,JoinHandle};
+use std::time::Duration;
+use std::process::Command;
 use crate::tasks::response::TaskResponse;
 
 // details useful for working with commands
@@ -33,8 +38,197 @@ pub enum Forward {
     No
 }
 
+// whether output lines are shown as they arrive (see Connection::run_command_streaming) or only once the command is done
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum Stream {
+    Yes,
+    No
+}
+
+// for streaming output: bytes are fed in as they arrive and each complete line is handed to the callback,
+// a line split across two reads (or a multi-byte character split across two reads) is held until it completes
+
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>
+}
+
+impl LineBuffer {
+
+    pub fn new() -> Self {
+        Self { pending: Vec::new() }
+    }
+
+    pub fn push(&mut self, bytes: &[u8], on_line: &mut dyn FnMut(&str)) {
+        self.pending.extend_from_slice(bytes);
+        while let Some(pos) = self.pending.iter().position(|x| *x == b'\n') {
+            let line : Vec<u8> = self.pending.drain(..=pos).collect();
+            let text = String::from_utf8_lossy(&line[..line.len()-1]);
+            on_line(text.trim_end_matches('\r'));
+        }
+    }
+
+    pub fn finish(&mut self, on_line: &mut dyn FnMut(&str)) {
+        if ! self.pending.is_empty() {
+            let text = String::from_utf8_lossy(&self.pending).to_string();
+            self.pending.clear();
+            on_line(text.trim_end_matches('\r'));
+        }
+    }
+}
+
+// with a max, only the first and last halves of that many bytes of a command's output are kept as it is read, so
+// a command that prints megabytes never has all of it in memory.  what was dropped is replaced with a note, and
+// neither end is cut inside a multi-byte character.  without a max everything is kept.
+
+pub struct CappedOutput {
+    max: Option<usize>,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    dropped: usize
+}
+
+impl CappedOutput {
+
+    pub fn new(max: Option<usize>) -> Self {
+        Self { max, head: Vec::new(), tail: VecDeque::new(), dropped: 0 }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        let max = match self.max {
+            Some(x) => x,
+            None => { self.head.extend_from_slice(bytes); return; }
+        };
+        let room = (max / 2).saturating_sub(self.head.len()).min(bytes.len());
+        self.head.extend_from_slice(&bytes[..room]);
+        self.tail.extend(&bytes[room..]);
+        let excess = self.tail.len().saturating_sub(max - max / 2);
+        self.tail.drain(..excess);
+        self.dropped += excess;
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut head = self.head;
+        let mut tail : Vec<u8> = self.tail.into();
+        if self.dropped == 0 {
+            head.extend_from_slice(&tail);
+            return head;
+        }
+        let mut dropped = self.dropped;
+        if let Err(e) = std::str::from_utf8(&head) {
+            if e.error_len().is_none() {
+                dropped += head.len() - e.valid_up_to();
+                head.truncate(e.valid_up_to());
+            }
+        }
+        let partial = tail.iter().take(3).take_while(|x| (**x & 0xc0) == 0x80).count();
+        tail.drain(..partial);
+        dropped += partial;
+        head.extend_from_slice(format!("\n... {} bytes truncated ...\n", dropped).as_bytes());
+        head.extend_from_slice(&tail);
+        head
+    }
+}
+
+// the return code given to a command that ran past its timeout, the same one coreutils timeout uses
+pub const TIMEOUT_RC : i32 = 124;
+
+pub fn timeout_message(seconds: u64) -> String {
+    format!("command timed out after {} seconds and was killed", seconds)
+}
+
+// kills a locally started process group once it has run for longer than the timeout.  the process must have
+// been spawned with process_group(0), so that the group id is its pid and anything it started goes with it.
+
+pub struct Watchdog {
+    done: Option<Sender<()>>,
+    thread: Option<JoinHandle<bool>>
+}
+
+impl Watchdog {
+
+    pub fn start(pid: u32, timeout: Option<u64>) -> Self {
+        let seconds = match timeout {
+            Some(x) => x,
+            None => { return Self { done: None, thread: None }; }
+        };
+        let (done, wait) = channel::<()>();
+        let thread = thread::spawn(move || {
+            match wait.recv_timeout(Duration::from_secs(seconds)) {
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = Command::new("kill").arg("-s").arg("KILL").arg("--").arg(format!("-{}", pid)).status();
+                    true
+                },
+                _ => false
+            }
+        });
+        Self { done: Some(done), thread: Some(thread) }
+    }
+
+    // call once the process has exited, returns true if it was killed for running too long
+    pub fn finish(mut self) -> bool {
+        if let Some(done) = self.done.take() {
+            let _ = done.send(());
+        }
+        match self.thread.take() {
+            Some(x) => x.join().unwrap_or(false),
+            None => false
+        }
+    }
+}
+
 pub fn cmd_info(info: &Arc<TaskResponse>) -> (i32, String) {
     assert!(info.command_result.is_some(), "called cmd_info on a response that is not a command result");
     let result = info.command_result.as_ref().as_ref().unwrap();
     (result.rc, result.out.clone())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer_joins_split_reads() {
+        let mut lines : Vec<String> = Vec::new();
+        let mut buffer = LineBuffer::new();
+        buffer.push(b"one\ntw", &mut |x| lines.push(x.to_string()));
+        buffer.push(b"o\r\nthr\xc3", &mut |x| lines.push(x.to_string()));
+        buffer.push(b"\xa9e", &mut |x| lines.push(x.to_string()));
+        buffer.finish(&mut |x| lines.push(x.to_string()));
+        assert_eq!(lines, vec![String::from("one"), String::from("two"), String::from("thr\u{e9}e")]);
+    }
+
+    #[test]
+    fn test_capped_output_keeps_both_ends() {
+        let mut output = CappedOutput::new(Some(10));
+        output.push(b"short");
+        assert_eq!(output.finish(), b"short");
+        let mut output = CappedOutput::new(Some(8));
+        for chunk in [&b"aa"[..], b"aa-mid", b"dle-zz", b"zz"] {
+            output.push(chunk);
+        }
+        assert_eq!(String::from_utf8(output.finish()).unwrap(), "aaaa\n... 8 bytes truncated ...\nzzzz");
+        // never splits a multi-byte character
+        let mut output = CappedOutput::new(Some(5));
+        output.push("ééééé".as_bytes());
+        assert_eq!(String::from_utf8(output.finish()).unwrap(), "é\n... 6 bytes truncated ...\né");
+        let mut output = CappedOutput::new(None);
+        output.push(b"everything");
+        assert_eq!(output.finish(), b"everything");
+    }
+
+    #[test]
+    fn test_watchdog_kills_the_process_group() {
+        use std::os::unix::process::CommandExt;
+        let started = std::time::Instant::now();
+        let mut child = Command::new("sh").arg("-c").arg("sleep 30 & sleep 20").process_group(0).spawn().expect("spawn");
+        let watchdog = Watchdog::start(child.id(), Some(1));
+        let _ = child.wait();
+        assert!(watchdog.finish());
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let mut quick = Command::new("true").process_group(0).spawn().expect("spawn");
+        let watchdog = Watchdog::start(quick.id(), Some(5));
+        let _ = quick.wait();
+        assert!(! watchdog.finish());
+    }
+}
@@ -20,6 +20,8 @@ use crate::handle::response::Response;
 use std::sync::Arc;
 use std::marker::{Send,Sync};
 use std::path::Path;
+use std::io::Read;
+use std::fs::File;
 use crate::connection::command::Forward;
 
 // the connection trait that serves as the base for SshConnection, LocalConnection, and NoConnection
@@ -29,13 +31,78 @@ pub trait Connection : Send + Sync {
     fn connect(&mut self) -> Result<(),String>;  
 
     // FIXME: add error return objects
+
+    // writes everything from the reader to the remote path in chunks, so the whole file never has to be in memory.
+    // write_data and copy_file are built on this unless a connection has a better way to do them.
     
-    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &str, remote_path: &str) -> Result<(),Arc<TaskResponse>>;
+    fn write_stream(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, reader: &mut dyn Read, remote_path: &str) -> Result<(),Arc<TaskResponse>>;
+
+    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &str, remote_path: &str) -> Result<(),Arc<TaskResponse>> {
+        self.write_stream(response, request, &mut data.as_bytes(), remote_path)
+    }
+
+    fn copy_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, src: &Path, dest: &str) -> Result<(), Arc<TaskResponse>> {
+        let src_file = match File::open(src) {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("failed to open source file: {y}"))); }
+        };
+        let mut reader = std::io::BufReader::with_capacity(1000000, src_file);
+        self.write_stream(response, request, &mut reader, dest)
+    }
+
+    // sends src over whatever is already at dest using rsync's delta transfer, so only the changed blocks cross
+    // the wire.  returns Ok(false) when the connection or either end cannot do this, and the caller should fall
+    // back to copy_file.
+
+    fn copy_file_delta(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _src: &Path, _dest: &str) -> Result<bool, Arc<TaskResponse>> {
+        Ok(false)
+    }
+
+    // connections that can see the managed filesystem directly answer these without running a command.
+    // Ok(None) means the caller should run the usual command instead, which is also the answer when the
+    // connection could not read the file itself, for instance when only sudo can.
+
+    fn get_sha512(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _path: &str) -> Result<Option<String>, Arc<TaskResponse>> {
+        Ok(None)
+    }
 
-    fn copy_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, src: &Path, dest: &str) -> Result<(), Arc<TaskResponse>>;
+    fn get_mode(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _path: &str) -> Result<Option<Option<String>>, Arc<TaskResponse>> {
+        Ok(None)
+    }
+
+    fn get_is_directory(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _path: &str) -> Result<Option<bool>, Arc<TaskResponse>> {
+        Ok(None)
+    }
 
     fn whoami(&self) -> Result<String,String>;
 
-    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>>;
+    // a command still running after timeout seconds, if given, is stopped and fails with TIMEOUT_RC.  connections
+    // kill it where they can.  with max_output, the output is cut down to that many bytes as it is read, see
+    // CappedOutput.
+
+    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward, timeout: Option<u64>, max_output: Option<usize>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>>;
+
+    // like run_command, but each line of output is passed to on_line as soon as it is read, and the whole output
+    // is still returned in the result.  connections that cannot read incrementally report the lines at the end.
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_command_streaming(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward, timeout: Option<u64>, max_output: Option<usize>, on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let result = self.run_command(response, request, cmd, forward, timeout, max_output);
+        if let Ok(x) = &result {
+            if let Some(command_result) = x.command_result.as_ref() {
+                for line in command_result.out.lines() { on_line(line); }
+            }
+        }
+        result
+    }
+
+    // like run_command_streaming, but writes input to the command's standard input first. this is how a sudo
+    // password gets to sudo without ever being part of the command. connections that cannot do this fail
+    // rather than leave sudo waiting at a prompt.
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_command_with_input(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, _cmd: &str, _forward: Forward, _timeout: Option<u64>, _max_output: Option<usize>, _input: &str, _on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        Err(response.is_failed(request, &String::from("this connection type cannot send a sudo password")))
+    }
 
 }
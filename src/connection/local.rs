@@ -15,7 +15,7 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::connection::connection::Connection;
-use crate::connection::command::CommandResult;
+use crate::connection::command::{CommandResult,CappedOutput,LineBuffer,Watchdog,TIMEOUT_RC,timeout_message};
 use crate::playbooks::context::PlaybookContext;
 use crate::connection::factory::ConnectionFactory;
 use crate::connection::command::Forward;
@@ -27,12 +27,15 @@ use crate::tasks::{TaskRequest,TaskResponse};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
-use std::process::Command;
+use std::process::{Command,Stdio};
 use crate::Inventory;
-use crate::util::io::jet_file_open;
 use std::fs::File;
 use std::path::Path;
-use std::io::Write;
+use std::io::{Read,Write,ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use crate::tasks::checksum::sha512_file;
+use crate::tasks::cmd_library::screen_path;
 use std::env;
 
 // implementation for both the local connection factory and local connections
@@ -87,6 +90,58 @@ impl LocalConnection {
             }
         }
     }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_command_streaming_with_input(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, input: Option<&str>, timeout: Option<u64>, max_output: Option<usize>, on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        // the command's stderr is folded into stdout so the order is kept, only errors from sh itself are left on stderr
+        let cmd2 = format!("LANG=C {} 2>&1", cmd);
+        let stdin = match input { Some(_) => Stdio::piped(), None => Stdio::inherit() };
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd2).stdin(stdin).stdout(Stdio::piped()).stderr(Stdio::piped());
+        if timeout.is_some() {
+            // in a group of its own, whatever the command starts is killed along with it
+            command.process_group(0);
+        }
+        let mut child = match command.spawn() {
+            Ok(x) => x,
+            Err(_x) => { return Err(response.command_failed(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: String::from(""), rc: 404 })))); }
+        };
+        let watchdog = Watchdog::start(child.id(), timeout);
+        if let (Some(data), Some(mut child_stdin)) = (input, child.stdin.take()) {
+            // a command that exits without reading is not an error here, its rc says what happened
+            let _ = child_stdin.write_all(data.as_bytes());
+        }
+        let mut stdout = child.stdout.take().expect("piped stdout");
+        let mut lines = LineBuffer::new();
+        let mut all = CappedOutput::new(max_output);
+        let mut chunk = [0u8; 8192];
+        loop {
+            match stdout.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    all.push(&chunk[..n]);
+                    lines.push(&chunk[..n], on_line);
+                },
+                Err(_) => break
+            }
+        }
+        lines.finish(on_line);
+        let mut shell_errors : Vec<u8> = Vec::new();
+        if let Some(mut stderr) = child.stderr.take() {
+            let _ = stderr.read_to_end(&mut shell_errors);
+        }
+        let rc = match child.wait() {
+            Ok(status) => status.code().unwrap_or(418),
+            Err(_) => 418
+        };
+        if watchdog.finish() {
+            return Err(response.command_failed(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: timeout_message(timeout.unwrap_or(0)), rc: TIMEOUT_RC }))));
+        }
+        let mut out = convert_out(&all.finish(), &shell_errors);
+        self.trim_newlines(&mut out);
+        Ok(response.command_ok(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out, rc }))))
+    }
+
 }
 
 impl Connection for LocalConnection {
@@ -112,7 +167,10 @@ impl Connection for LocalConnection {
         }
     }
 
-    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, _forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, _forward: Forward, timeout: Option<u64>, max_output: Option<usize>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        if timeout.is_some() || max_output.is_some() {
+            return self.run_command_streaming_with_input(response, request, cmd, None, timeout, max_output, &mut |_line| {});
+        }
         let mut base = Command::new("sh");
         let cmd2 = format!("LANG=C {}", cmd);
         let command = base.arg("-c").arg(cmd2).arg("2>&1");
@@ -135,45 +193,93 @@ impl Connection for LocalConnection {
         }
     }
 
+    fn run_command_streaming(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, _forward: Forward, timeout: Option<u64>, max_output: Option<usize>, on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        self.run_command_streaming_with_input(response, request, cmd, None, timeout, max_output, on_line)
+    }
+
+    fn run_command_with_input(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, _forward: Forward, timeout: Option<u64>, max_output: Option<usize>, input: &str, on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        self.run_command_streaming_with_input(response, request, cmd, Some(input), timeout, max_output, on_line)
+    }
+
+    fn get_sha512(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, path: &str) -> Result<Option<String>, Arc<TaskResponse>> {
+        // the managed host is this one, so there is no reason to run sha512sum
+        let path = screen_local_path(response, request, path)?;
+        match sha512_file(Path::new(&path)) {
+            Ok(x) => Ok(Some(x)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Some(String::new())),
+            Err(_) => Ok(None)
+        }
+    }
+
+    fn get_mode(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, path: &str) -> Result<Option<Option<String>>, Arc<TaskResponse>> {
+        // same format as stat's %a, which does not follow symlinks either
+        let path = screen_local_path(response, request, path)?;
+        match std::fs::symlink_metadata(path) {
+            Ok(x) => Ok(Some(Some(format!("{:o}", x.permissions().mode() & 0o7777)))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Some(None)),
+            Err(_) => Ok(None)
+        }
+    }
+
+    fn get_is_directory(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, path: &str) -> Result<Option<bool>, Arc<TaskResponse>> {
+        // a missing path is left to the command so the error reads the same everywhere
+        let path = screen_local_path(response, request, path)?;
+        match std::fs::symlink_metadata(path) {
+            Ok(x) => Ok(Some(x.is_dir())),
+            Err(_) => Ok(None)
+        }
+    }
+
+    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &str, remote_path: &str) -> Result<(),Arc<TaskResponse>> {
+        let remote_path2 = screen_local_path(response, request, remote_path)?;
+        match std::fs::write(&remote_path2, data) {
+            Ok(_) => Ok(()),
+            Err(y) => Err(response.is_failed(request, &format!("failed to write: {}: {:?}", remote_path2, y)))
+        }
+    }
+
     fn copy_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, src: &Path, remote_path: &str) -> Result<(), Arc<TaskResponse>> {
-        // FIXME: this (temporary) implementation currently loads the file contents into memory which we do not want
-        // copy the files with system calls instead.
-        let remote_path2 = Path::new(remote_path);
-        let result = std::fs::copy(src, remote_path2);
+        // the OS can do this one without going through a reader at all
+        let remote_path2 = screen_local_path(response, request, remote_path)?;
+        let result = std::fs::copy(src, &remote_path2);
         match result {
             Ok(_x) => Ok(()),
             Err(e) => { Err(response.is_failed(request, &format!("copy failed: {:?}", e))) }
         }
     }
 
-    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &str, remote_path: &str) -> Result<(),Arc<TaskResponse>> {
+    fn write_stream(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, reader: &mut dyn Read, remote_path: &str) -> Result<(),Arc<TaskResponse>> {
+        // create truncates any existing file, so this works the same whether or not the path is already there
+        let remote_path = screen_local_path(response, request, remote_path)?;
         let path = Path::new(&remote_path);
-        if path.exists() {
-            let mut file = match jet_file_open(path) {
-                Ok(x) => x,
-                Err(y) => return Err(response.is_failed(request, &format!("failed to open: {}: {:?}", remote_path, y)))
-            };
-            let write_result = write!(file, "{}", data);
-            match write_result {
-                Ok(_) => {},
-                Err(y) => return Err(response.is_failed(request, &format!("failed to write: {}: {:?}", remote_path, y)))
-            };
-        } else {
-            let mut file = match File::create(path) {
-                Ok(x) => x,
-                Err(y) => return Err(response.is_failed(request, &format!("failed to create: {}: {:?}", remote_path, y)))
-            };
-            let write_result = write!(file, "{}", data);
-            match write_result {
-                Ok(_) => {},
-                Err(y) => return Err(response.is_failed(request, &format!("failed to write: {}: {:?}", remote_path, y)))
-            };
-        }
+        let file = match File::create(path) {
+            Ok(x) => x,
+            Err(y) => return Err(response.is_failed(request, &format!("failed to create: {}: {:?}", remote_path, y)))
+        };
+        let mut writer = std::io::BufWriter::new(file);
+        match std::io::copy(reader, &mut writer) {
+            Ok(_) => {},
+            Err(y) => return Err(response.is_failed(request, &format!("failed to write: {}: {:?}", remote_path, y)))
+        };
+        match writer.flush() {
+            Ok(_) => {},
+            Err(y) => return Err(response.is_failed(request, &format!("failed to write: {}: {:?}", remote_path, y)))
+        };
         Ok(())
     }
 
 }
 
+// the paths above never reach a shell, but they get the same screening the command library gives them on
+// other connections so that a playbook does not behave differently depending on where it runs
+
+fn screen_local_path(response: &Arc<Response>, request: &Arc<TaskRequest>, path: &str) -> Result<String,Arc<TaskResponse>> {
+    match screen_path(path) {
+        Ok(x) => Ok(x),
+        Err(y) => Err(response.is_failed(request, &y))
+    }
+}
+
 pub fn convert_out(output: &[u8], err: &[u8]) -> String {
     // output from the Rust command class can contain junk bytes, here we mostly don't try to solve this yet
     // and will basically fail if output contains junk. This may be dealt with later.
@@ -202,7 +308,7 @@ fn detect_os(host: &Arc<RwLock<Host>>) -> Result<(),(i32, String)> {
                 {
                     match host.write().unwrap().set_os_info(&out) {
                         Ok(_) => { },
-                        Err(_) => { return Err((500, String::from("failed to set OS info"))); }
+                        Err(y) => { return Err((500, format!("failed to set OS info: {}", y))); }
                     }
                 }
                 Ok(())
@@ -213,3 +319,35 @@ fn detect_os(host: &Arc<RwLock<Host>>) -> Result<(),(i32, String)> {
         Err(_x) => Err((418, String::from("uname -a failed without status code")))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle::handle::TaskHandle;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_fast_paths_screen_paths_and_write_through_std_fs() {
+        let host = Arc::new(RwLock::new(Host::new(&String::from("localhost"))));
+        let connection = Arc::new(Mutex::new(LocalConnection::new(&host)));
+        let dyn_connection : Arc<Mutex<dyn Connection>> = connection.clone();
+        let handle = TaskHandle::simulated(dyn_connection);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let dir = env::temp_dir().join(format!("jet_local_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a").display().to_string();
+
+        let local = connection.lock().unwrap();
+        local.write_data(&handle.response, &request, "hello", &path).expect("write");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+        assert_eq!(local.get_sha512(&handle.response, &request, &path).unwrap(), Some(sha512_file(Path::new(&path)).unwrap()));
+        assert_eq!(local.get_is_directory(&handle.response, &request, &dir.display().to_string()).unwrap(), Some(true));
+
+        let bad = format!("{};touch {}/b", path, dir.display());
+        assert!(local.get_sha512(&handle.response, &request, &bad).is_err());
+        assert!(local.get_mode(&handle.response, &request, &bad).is_err());
+        assert!(local.write_data(&handle.response, &request, "x", &bad).is_err());
+        assert!(!Path::new(&bad).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
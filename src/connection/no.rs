@@ -21,7 +21,7 @@ use crate::inventory::hosts::{Host,HostOSType};
 use crate::tasks::request::TaskRequest;
 use crate::tasks::response::TaskResponse;
 use crate::handle::response::Response;
-use crate::connection::command::CommandResult;
+use crate::connection::command::{CommandResult,CappedOutput};
 use crate::connection::command::Forward;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -30,6 +30,10 @@ use std::path::Path;
 
 // the noconnection and nofactory are not really used in normal execution of jet, but are around in the "__simulate" hidden
 // suboption, as this is occasionally useful for testing certain jet internals.  This is not meant for serious work.
+//
+// NoConnection also records every command it is asked to run, and can be given a script of results to return
+// for matching commands. Together with TaskHandle::simulated this lets module tests call dispatch() and
+// assert on the exact commands a module generates, see the tests in modules/files/git.rs for an example.
 
 pub struct NoFactory {}
 
@@ -53,13 +57,49 @@ impl ConnectionFactory for NoFactory {
 }
 
 pub struct NoConnection {
+    commands: Mutex<Vec<String>>,
+    inputs: Mutex<Vec<String>>,
+    script: Vec<(String, i32, String)>
 }
 
 impl NoConnection {
     pub fn new() -> Self {
         Self { 
+            commands: Mutex::new(Vec::new()),
+            inputs: Mutex::new(Vec::new()),
+            script: Vec::new()
+        }
+    }
+
+    // each entry is (pattern, rc, output). A command gets the result of the first entry whose pattern it contains,
+    // commands matching nothing succeed with junk output like they do when simulating.
+
+    #[cfg(test)]
+    pub fn with_script(script: Vec<(&str, i32, &str)>) -> Self {
+        Self {
+            commands: Mutex::new(Vec::new()),
+            inputs: Mutex::new(Vec::new()),
+            script: script.iter().map(|(p, rc, out)| (p.to_string(), *rc, out.to_string())).collect()
         }
     }
+
+    // everything run so far, in order. file transfers are recorded as "write_data: path" and "copy_file: src -> dest"
+
+    #[cfg(test)]
+    pub fn get_commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    // whatever was sent to the standard input of commands, kept apart from the commands themselves
+
+    #[cfg(test)]
+    pub fn get_inputs(&self) -> Vec<String> {
+        self.inputs.lock().unwrap().clone()
+    }
+
+    fn record(&self, entry: String) {
+        self.commands.lock().unwrap().push(entry);
+    }
 }
 
 impl Connection for NoConnection {
@@ -74,18 +114,40 @@ impl Connection for NoConnection {
        Ok(())
    }
 
-   fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, _forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-       // all commands return junk output pretending they were successful
-       Ok(response.command_ok(request,&Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: String::from("__simulated__"), rc: 0 }))))
+   fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, _forward: Forward, _timeout: Option<u64>, max_output: Option<usize>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+       // unless scripted, all commands return junk output pretending they were successful
+       self.record(cmd.to_owned());
+       let (rc, out) = match self.script.iter().find(|(pattern, _, _)| cmd.contains(pattern.as_str())) {
+           Some((_, rc, out)) => (*rc, out.clone()),
+           None => (0, String::from("__simulated__"))
+       };
+       let mut capped = CappedOutput::new(max_output);
+       capped.push(out.as_bytes());
+       let out = String::from_utf8_lossy(&capped.finish()).to_string();
+       // like the real connections, a non-zero rc is still Ok here and is checked by the caller
+       Ok(response.command_ok(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out, rc }))))
+   }
+
+   fn run_command_with_input(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward, timeout: Option<u64>, max_output: Option<usize>, input: &str, _on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+       self.inputs.lock().unwrap().push(input.to_owned());
+       self.run_command(response, request, cmd, forward, timeout, max_output)
+   }
+
+   fn write_stream(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _reader: &mut dyn std::io::Read, remote_path: &str) -> Result<(),Arc<TaskResponse>>{
+       // the reader is not even consumed
+       self.record(format!("write_stream: {}", remote_path));
+       Ok(())
    }
 
-   fn write_data(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _data: &str, _remote_path: &str) -> Result<(),Arc<TaskResponse>>{
+   fn write_data(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _data: &str, remote_path: &str) -> Result<(),Arc<TaskResponse>>{
        // no data is transferred, we just pretend things were successful
+       self.record(format!("write_data: {}", remote_path));
        Ok(())
    }
 
-   fn copy_file(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _src: &Path, _dest: &str) -> Result<(), Arc<TaskResponse>> {
+   fn copy_file(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, src: &Path, dest: &str) -> Result<(), Arc<TaskResponse>> {
        // no data is transferred, as per above
+       self.record(format!("copy_file: {} -> {}", src.display(), dest));
        Ok(())
    }
 
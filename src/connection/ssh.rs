@@ -15,7 +15,7 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::connection::connection::Connection;
-use crate::connection::command::CommandResult;
+use crate::connection::command::{CommandResult,CappedOutput,LineBuffer,Watchdog,TIMEOUT_RC,timeout_message};
 use crate::connection::factory::ConnectionFactory;
 use crate::playbooks::context::PlaybookContext;
 use crate::connection::local::LocalFactory;
@@ -25,15 +25,15 @@ use crate::Inventory;
 use crate::handle::response::Response;
 use crate::connection::command::Forward;
 use crate::connection::local::convert_out;
-use std::process::Command;
+use std::process::{Command,Stdio};
 use std::sync::{Arc,Mutex,RwLock};
-use ssh2::Session;
+use ssh2::{Session,MethodType};
 use std::io::{Read,Write};
 use std::net::TcpStream;
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration,Instant};
 use std::net::ToSocketAddrs;
-use std::fs::File;
+use std::os::unix::process::CommandExt;
 //use std::io;
 use std::io;
 
@@ -94,8 +94,11 @@ impl ConnectionFactory for SshFactory {
             return Ok(conn); 
         }
 
+        let options = ctx.get_ssh_options(host)?;
+
         // actually connect here
-        let mut conn = SshConnection::new(Arc::clone(host), &user, port, hostname2, self.forward_agent, self.login_password.clone(), key, passphrase, key_comment);
+        let details = SshDetails { hostname: hostname2, username: user, port, key, passphrase, key_comment };
+        let mut conn = SshConnection::new(Arc::clone(host), details, self.forward_agent, self.login_password.clone(), options);
         match conn.connect() {
             Ok(_)  => { 
                 let conn2 : Arc<Mutex<dyn Connection>> = Arc::new(Mutex::new(conn));
@@ -119,14 +122,129 @@ pub struct SshConnection {
     pub key: Option<String>,
     pub passphrase: Option<String>,
     pub key_comment: Option<String>,
+    pub options: SshOptions,
+}
+
+// where and as whom to connect, as worked out by get_ssh_connection_details in context.rs
+
+pub struct SshDetails {
+    pub hostname: String,
+    pub username: String,
+    pub port: i64,
+    pub key: Option<String>,
+    pub passphrase: Option<String>,
+    pub key_comment: Option<String>
 }
 
 impl SshConnection {
-    pub fn new(host: Arc<RwLock<Host>>, username: &str, port: i64, hostname: String, forward_agent: bool, login_password: Option<String>, key: Option<String>, passphrase: Option<String>, key_comment: Option<String>) -> Self {
-        Self { host: Arc::clone(&host), username: username.to_owned(), port, hostname, session: None, forward_agent, login_password, key, passphrase, key_comment }
+    pub fn new(host: Arc<RwLock<Host>>, details: SshDetails, forward_agent: bool, login_password: Option<String>, options: SshOptions) -> Self {
+        let SshDetails { hostname, username, port, key, passphrase, key_comment } = details;
+        Self { host: Arc::clone(&host), username, port, hostname, session: None, forward_agent, login_password, key, passphrase, key_comment, options }
     }
 }
 
+// crypto and compression settings for the session, named after the matching OpenSSH options.  they come from the
+// ssh_options of the play and the jet_ssh_options variable of the host, see get_ssh_options in context.rs
+
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct SshOptions {
+    pub ciphers: Option<String>,
+    pub kex_algorithms: Option<String>,
+    pub macs: Option<String>,
+    pub host_key_algorithms: Option<String>,
+    pub compression: bool
+}
+
+impl SshOptions {
+
+    pub fn from_mapping(mapping: &serde_yaml::Mapping) -> Result<Self, String> {
+        let mut options = SshOptions::default();
+        for (k,v) in mapping.iter() {
+            let key = match k.as_str() {
+                Some(x) => x,
+                None => { return Err(format!("SSH option names must be strings, got {:?}", k)); }
+            };
+            let value = match v {
+                serde_yaml::Value::String(x) => x.clone(),
+                serde_yaml::Value::Bool(x) => x.to_string(),
+                _ => { return Err(format!("SSH option {} must be a string", key)); }
+            };
+            match key {
+                "Ciphers"           => { options.ciphers = Some(check_algorithms(key, &value)?); },
+                "KexAlgorithms"     => { options.kex_algorithms = Some(check_algorithms(key, &value)?); },
+                "MACs"              => { options.macs = Some(check_algorithms(key, &value)?); },
+                "HostKeyAlgorithms" => { options.host_key_algorithms = Some(check_algorithms(key, &value)?); },
+                "Compression"       => {
+                    options.compression = match value.as_str() {
+                        "yes" | "true" => true,
+                        "no" | "false" => false,
+                        _ => { return Err(format!("SSH option Compression must be yes or no, got {}", value)); }
+                    };
+                },
+                _ => { return Err(format!("unknown SSH option {}, expecting one of: Ciphers, Compression, HostKeyAlgorithms, KexAlgorithms, MACs", key)); }
+            }
+        }
+        Ok(options)
+    }
+
+    // the same settings as -o arguments, for the places an ssh command is run instead of going through libssh2
+
+    pub fn get_ssh_args(&self) -> Vec<String> {
+        let mut args : Vec<String> = Vec::new();
+        let prefs = [
+            ("Ciphers", &self.ciphers),
+            ("KexAlgorithms", &self.kex_algorithms),
+            ("MACs", &self.macs),
+            ("HostKeyAlgorithms", &self.host_key_algorithms),
+        ];
+        for (key, value) in prefs.into_iter() {
+            if let Some(value) = value {
+                args.push(String::from("-o"));
+                args.push(format!("{}={}", key, value));
+            }
+        }
+        if self.compression {
+            args.push(String::from("-o"));
+            args.push(String::from("Compression=yes"));
+        }
+        args
+    }
+
+    // must be called before the handshake, which is when the methods are negotiated
+
+    fn apply(&self, session: &Session) -> Result<(), String> {
+        let prefs = [
+            (MethodType::CryptCs, "Ciphers", &self.ciphers),
+            (MethodType::CryptSc, "Ciphers", &self.ciphers),
+            (MethodType::Kex, "KexAlgorithms", &self.kex_algorithms),
+            (MethodType::MacCs, "MACs", &self.macs),
+            (MethodType::MacSc, "MACs", &self.macs),
+            (MethodType::HostKey, "HostKeyAlgorithms", &self.host_key_algorithms),
+        ];
+        for (method_type, key, value) in prefs.into_iter() {
+            if let Some(value) = value {
+                if let Err(x) = session.method_pref(method_type, value) {
+                    return Err(format!("SSH option {} was not accepted ({}): {}", key, value, x));
+                }
+            }
+        }
+        session.set_compress(self.compression);
+        Ok(())
+    }
+}
+
+// values are comma separated algorithm names, handed to libssh2 as is.  the +, - and ^ prefixes OpenSSH uses to
+// edit its default list have no equivalent there, so the full list has to be given
+
+fn check_algorithms(key: &str, value: &str) -> Result<String, String> {
+    for name in value.split(',') {
+        if name.is_empty() || name.starts_with(['+','-','^']) || ! name.chars().all(|c| c.is_ascii_alphanumeric() || "@._+-".contains(c)) {
+            return Err(format!("invalid value for SSH option {}: {}", key, value));
+        }
+    }
+    Ok(value.to_owned())
+}
+
 impl Connection for SshConnection {
 
     fn whoami(&self) -> Result<String,String> {
@@ -184,6 +302,7 @@ impl Connection for SshConnection {
         // new session & handshake
         let mut sess = match Session::new() { Ok(x) => x, _ => { return Err(String::from("SSH session failed")); } };
         sess.set_tcp_stream(tcp);
+        self.options.apply(&sess)?;
         match sess.handshake() { Ok(_) => {}, _ => { return Err(String::from("SSH handshake failed")); } } ;
         
         if self.login_password.is_some() {
@@ -271,7 +390,7 @@ impl Connection for SshConnection {
                 {
                     match self.host.write().unwrap().set_os_info(&out.clone()) {
                         Ok(_x) => {},
-                        Err(_y) => return Err("failed to set OS info".to_string())
+                        Err(y) => return Err(format!("failed to set OS info: {}", y))
                     }
                 }
                 //match result2 { Ok(_) => {}, Err(s) => { return Err(s.to_string()) } }
@@ -283,13 +402,13 @@ impl Connection for SshConnection {
         Ok(())
     }
 
-    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward, timeout: Option<u64>, max_output: Option<usize>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
         let result = match forward {   
             Forward::Yes => match self.forward_agent {
-                false => self.run_command_low_level(cmd),
-                true  => self.run_command_with_ssh_a(cmd)
+                false => self.run_command_low_level_streaming(cmd, None, timeout, max_output, &mut |_line| {}),
+                true  => self.run_command_with_ssh_a(cmd, None, timeout, max_output)
             },
-            Forward::No => self.run_command_low_level(cmd)
+            Forward::No => self.run_command_low_level_streaming(cmd, None, timeout, max_output, &mut |_line| {})
         };
 
         match result {
@@ -303,43 +422,43 @@ impl Connection for SshConnection {
         }
     }
 
-    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &str, remote_path: &str) -> Result<(),Arc<TaskResponse>> {
-
-        // SFTP writing does not allow root to overwrite files root does not own, and does not support sudo. 
-        // as such this is a pretty low level write (as is copy_file) and logic around tempfiles and permissions is handled in remote.rs
-
-        // write_data writes a string and is really meant for small files like the template module. Large files should use copy_file instead.
+    fn run_command_streaming(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward, timeout: Option<u64>, max_output: Option<usize>, on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        // the ssh -A path shells out and is left buffered, the lines are reported once it finishes
+        if forward == Forward::Yes && self.forward_agent {
+            let result = self.run_command(response, request, cmd, forward, timeout, max_output);
+            if let Ok(x) = &result {
+                if let Some(command_result) = x.command_result.as_ref() {
+                    for line in command_result.out.lines() { on_line(line); }
+                }
+            }
+            return result;
+        }
+        match self.run_command_low_level_streaming(cmd, None, timeout, max_output, on_line) {
+            Ok((rc,s)) => Ok(response.command_ok(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: s.clone(), rc })))),
+            Err((rc,s)) => Err(response.command_failed(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: s.clone(), rc }))))
+        }
+    }
 
-        let session = self.session.as_ref().expect("session not established");
-        let sftp_result = session.sftp();
-        let sftp = match sftp_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp connection failed: {y}"))); }
+    fn run_command_with_input(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &str, forward: Forward, timeout: Option<u64>, max_output: Option<usize>, input: &str, on_line: &mut dyn FnMut(&str)) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let result = match forward == Forward::Yes && self.forward_agent {
+            true => self.run_command_with_ssh_a(cmd, Some(input), timeout, max_output).inspect(|(_rc, s)| {
+                for line in s.lines() { on_line(line); }
+            }),
+            false => self.run_command_low_level_streaming(cmd, Some(input), timeout, max_output, on_line)
         };
-        let sftp_path = Path::new(&remote_path);
-        let fh_result = sftp.create(sftp_path);
-        let mut fh = match fh_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp open failed: {y}"))) }
-        };
-        let bytes = data.as_bytes();
-        match fh.write_all(bytes) {
-            Ok(_x) => {},
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp write failed: {y}"))); }
+        match result {
+            Ok((rc,s)) => Ok(response.command_ok(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: s.clone(), rc })))),
+            Err((rc,s)) => Err(response.command_failed(request, &Arc::new(Some(CommandResult { cmd: cmd.to_owned(), out: s.clone(), rc }))))
         }
-
-        Ok(())
     }
 
-    fn copy_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, src: &Path, remote_path: &str) -> Result<(), Arc<TaskResponse>> {
+    fn write_stream(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, reader: &mut dyn Read, remote_path: &str) -> Result<(),Arc<TaskResponse>> {
 
-        // this is a streaming copy that should be fine with large files.
+        // SFTP writing does not allow root to overwrite files root does not own, and does not support sudo. 
+        // as such this is a pretty low level write (used by both write_data and copy_file) and logic around tempfiles 
+        // and permissions is handled in remote.rs
 
-        let src_open_result = File::open(src);
-        let src = match src_open_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("failed to open source file: {y}"))); }
-        };
+        // this is a streaming copy that should be fine with large files.
 
         let session = self.session.as_ref().expect("session not established");
         let sftp_result = session.sftp();
@@ -354,16 +473,51 @@ impl Connection for SshConnection {
             Err(y) => { return Err(response.is_failed(request, &format!("sftp write failed (1): {y}"))) }
         };
 
-        let mut src2 = std::io::BufReader::with_capacity(1000000, src);
         let mut fh2 = std::io::BufWriter::with_capacity(1000000, fh);
 
-        match io::copy(&mut src2, &mut fh2) {
+        match io::copy(reader, &mut fh2) {
             Ok(_) => {},
             Err(y) => { return Err(response.is_failed(request, &format!("sftp copy failed (1): {y}"))) }
         };
+        match fh2.flush() {
+            Ok(_) => {},
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp copy failed (2): {y}"))) }
+        };
 
         Ok(())
     }
+
+    fn copy_file_delta(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, src: &Path, dest: &str) -> Result<bool, Arc<TaskResponse>> {
+
+        // rsync runs its own ssh, which cannot use a login password or key passphrase we were given, so in those
+        // cases don't try.  BatchMode makes it fail instead of prompting, and any failure means a normal copy.
+
+        if self.login_password.is_some() || self.passphrase.is_some() {
+            return Ok(false);
+        }
+        match Command::new("rsync").arg("--version").output() {
+            Ok(x) if x.status.success() => {},
+            _ => { return Ok(false); }
+        }
+        match self.run_command_low_level("command -v rsync") {
+            Ok((0,_)) => {},
+            _ => { return Ok(false); }
+        }
+        let remote_shell = match self.get_rsync_remote_shell() {
+            Some(x) => x,
+            None => { return Ok(false); }
+        };
+        let target = format!("{}:{}", self.hostname, dest);
+        let result = Command::new("rsync")
+            .arg("--ignore-times").arg("--compress").arg("--protect-args")
+            .arg("-e").arg(remote_shell)
+            .arg(src).arg(target)
+            .output();
+        match result {
+            Ok(x) => Ok(x.status.success()),
+            Err(_) => Ok(false)
+        }
+    }
 }
 
 impl SshConnection {
@@ -377,7 +531,34 @@ impl SshConnection {
         }
     }
 
+    // rsync splits the -e string itself, honoring quotes but not backslashes, so a value holding a quote can't be
+    // passed safely and makes us use a normal copy instead.  the hostname is a separate argument to ssh, but must not
+    // look like an option or confuse rsync's host:path parsing.
+
+    fn get_rsync_remote_shell(&self) -> Option<String> {
+        if self.username.contains('\'') || self.key.as_ref().is_some_and(|k| k.contains('\'')) {
+            return None;
+        }
+        if self.hostname.is_empty() || self.hostname.starts_with('-') || self.hostname.contains([':', '\'', '"', ' ', '\t', '\n']) {
+            return None;
+        }
+        let mut remote_shell = format!("ssh -p {} -l '{}' -o BatchMode=yes", self.port, self.username);
+        if let Some(key) = self.key.as_ref() {
+            remote_shell.push_str(&format!(" -i '{}'", key));
+        }
+        // check_algorithms only lets through characters that need no quoting here
+        for arg in self.options.get_ssh_args() {
+            remote_shell.push(' ');
+            remote_shell.push_str(&arg);
+        }
+        Some(remote_shell)
+    }
+
     fn run_command_low_level(&self, cmd: &str) -> Result<(i32,String),(i32,String)> {
+        self.run_command_low_level_streaming(cmd, None, None, None, &mut |_line| {})
+    }
+
+    fn run_command_low_level_streaming(&self, cmd: &str, input: Option<&str>, timeout: Option<u64>, max_output: Option<usize>, on_line: &mut dyn FnMut(&str)) -> Result<(i32,String),(i32,String)> {
         // FIXME: catch the rare possibility this unwrap fails and return a nice error?
         let session = self.session.as_ref().unwrap();
         let mut channel = match session.channel_session() {
@@ -386,16 +567,52 @@ impl SshConnection {
         };
         let actual_cmd = format!("LANG=C {} 2>&1", cmd);
         match channel.exec(&actual_cmd) { Ok(_x) => {}, Err(y) => { return Err((500,y.to_string())) } };
-        let mut s = String::new();
-        match channel.read_to_string(&mut s) { Ok(_x) => {}, Err(y) => { return Err((500,y.to_string())) } };
-        // BOOKMARK: add sudo password prompt (configurable) support here (and below)
+        if let Some(data) = input {
+            // sudo -S reads the password from here, so it never has to be part of the command
+            if let Err(y) = channel.write_all(data.as_bytes()) { return Err((500,y.to_string())) };
+            let _ = channel.send_eof();
+        }
+        let mut all = CappedOutput::new(max_output);
+        let mut lines = LineBuffer::new();
+        let mut chunk = [0u8; 8192];
+        // libssh2 can only time out single calls, so each read gets whatever is left until the deadline
+        let deadline = timeout.map(|x| Instant::now() + Duration::from_secs(x));
+        let mut timed_out = false;
+        loop {
+            if let Some(limit) = deadline {
+                let left = limit.saturating_duration_since(Instant::now()).as_millis();
+                if left == 0 { timed_out = true; break; }
+                session.set_timeout(left.min(u32::MAX as u128) as u32);
+            }
+            match channel.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    all.push(&chunk[..n]);
+                    lines.push(&chunk[..n], on_line);
+                },
+                Err(_) if deadline.is_some_and(|x| Instant::now() >= x) => { timed_out = true; break; },
+                Err(y) => { session.set_timeout(0); return Err((500,y.to_string())) }
+            }
+        }
+        session.set_timeout(0);
+        if timed_out {
+            // there is no way to signal the remote process over the channel, closing it leaves the command
+            // without anywhere to write, which stops most of them
+            let _ = channel.close();
+            return Err((TIMEOUT_RC, timeout_message(timeout.unwrap_or(0))));
+        }
+        lines.finish(on_line);
+        let mut s = match String::from_utf8(all.finish()) {
+            Ok(x) => x,
+            Err(y) => { return Err((500,y.to_string())) }
+        };
         let _w = channel.wait_close();
         let exit_status = match channel.exit_status() { Ok(x) => x, Err(y) => { return Err((500,y.to_string())) } };
         self.trim_newlines(&mut s);
         Ok((exit_status, s.clone()))
     }
 
-    fn run_command_with_ssh_a(&self, cmd: &str) -> Result<(i32,String),(i32,String)> {
+    fn run_command_with_ssh_a(&self, cmd: &str, input: Option<&str>, timeout: Option<u64>, max_output: Option<usize>) -> Result<(i32,String),(i32,String)> {
         // this is annoying but libssh2 agent support is not really working, so if we need to SSH -A we need to invoke
         // SSHd directly, which we need to for example with git clones. we will likely use this again
         // for fanout support.
@@ -404,8 +621,34 @@ impl SshConnection {
         let hostname = &self.host.read().unwrap().name;
         let port = format!("{}", self.port);
         let cmd2 = format!("LANG=C {} 2>&1", cmd);
-        let command = base.arg(hostname).arg("-p").arg(port).arg("-l").arg(self.username.clone()).arg("-A").arg(cmd2);
-        match command.output() {
+        let command = base.args(self.options.get_ssh_args()).arg(hostname).arg("-p").arg(port).arg("-l").arg(self.username.clone()).arg("-A").arg(cmd2);
+        if timeout.is_some() {
+            command.process_group(0);
+        }
+        let mut timed_out = false;
+        let output = match (input, timeout, max_output) {
+            (None, None, None) => command.output(),
+            _ => command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().and_then(|mut child| {
+                let watchdog = Watchdog::start(child.id(), timeout);
+                if let (Some(data), Some(mut child_stdin)) = (input, child.stdin.take()) {
+                    let _ = child_stdin.write_all(data.as_bytes());
+                }
+                let mut stdout = child.stdout.take().expect("piped stdout");
+                let mut all = CappedOutput::new(max_output);
+                let mut chunk = [0u8; 8192];
+                while let Ok(n) = stdout.read(&mut chunk) {
+                    if n == 0 { break; }
+                    all.push(&chunk[..n]);
+                }
+                let output = child.wait_with_output().map(|mut x| { x.stdout = all.finish(); x });
+                timed_out = watchdog.finish();
+                output
+            })
+        };
+        if timed_out {
+            return Err((TIMEOUT_RC, timeout_message(timeout.unwrap_or(0))));
+        }
+        match output {
             Ok(x) => {
                 match x.status.code() {
                     Some(rc) => {
@@ -425,3 +668,46 @@ impl SshConnection {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_options_are_validated() {
+        let options : serde_yaml::Mapping = serde_yaml::from_str("Ciphers: aes128-ctr,aes256-gcm@openssh.com\nCompression: yes").unwrap();
+        assert_eq!(SshOptions::from_mapping(&options), Ok(SshOptions {
+            ciphers: Some(String::from("aes128-ctr,aes256-gcm@openssh.com")), compression: true, ..Default::default()
+        }));
+        for bad in ["Ciphers: \"aes128-ctr;rm -rf /\"", "Ciphers: +aes128-cbc", "ProxyCommand: nc %h %p", "Compression: maybe", "MACs: ''"] {
+            let options : serde_yaml::Mapping = serde_yaml::from_str(bad).unwrap();
+            assert!(SshOptions::from_mapping(&options).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_ssh_options_become_ssh_arguments() {
+        assert!(SshOptions::default().get_ssh_args().is_empty());
+        let options : serde_yaml::Mapping = serde_yaml::from_str("Ciphers: aes128-ctr,aes256-gcm@openssh.com\nMACs: hmac-sha2-256\nCompression: yes").unwrap();
+        assert_eq!(SshOptions::from_mapping(&options).unwrap().get_ssh_args(), vec![
+            String::from("-o"), String::from("Ciphers=aes128-ctr,aes256-gcm@openssh.com"),
+            String::from("-o"), String::from("MACs=hmac-sha2-256"),
+            String::from("-o"), String::from("Compression=yes")
+        ]);
+    }
+
+    #[test]
+    fn test_rsync_remote_shell_quotes_or_refuses_values() {
+        let connect = |hostname: &str, username: &str, key: Option<&str>| SshConnection::new(
+            Arc::new(RwLock::new(Host::new(&String::from("web1")))),
+            SshDetails { hostname: String::from(hostname), username: String::from(username), port: 22,
+                key: key.map(String::from), passphrase: None, key_comment: None },
+            false, None, SshOptions::default()
+        );
+        assert_eq!(connect("web1", "deploy", Some("/keys/my key")).get_rsync_remote_shell(),
+            Some(String::from("ssh -p 22 -l 'deploy' -o BatchMode=yes -i '/keys/my key'")));
+        assert_eq!(connect("web1", "deploy", Some("/keys/it's")).get_rsync_remote_shell(), None);
+        assert_eq!(connect("web1", "o'neil", None).get_rsync_remote_shell(), None);
+        assert_eq!(connect("-oProxyCommand=x", "deploy", None).get_rsync_remote_shell(), None);
+    }
+}
@@ -17,7 +17,7 @@
 use std::sync::{Arc,Mutex,RwLock};
 use crate::connection::connection::Connection;
 use crate::tasks::request::TaskRequest;
-use crate::inventory::hosts::Host;
+use crate::inventory::hosts::{Host,HostOSType};
 use crate::playbooks::traversal::RunState;
 
 use crate::handle::local::Local;
@@ -50,6 +50,14 @@ pub struct TaskHandle {
 impl TaskHandle {
 
     pub fn new(run_state_handle: Arc<RunState>, connection_handle: Arc<Mutex<dyn Connection>>, host_handle: Arc<RwLock<Host>>) -> Self {
+        Self::new_delegated(run_state_handle, connection_handle, host_handle, None)
+    }
+
+    // with delegate_to or local_action the connection goes to another machine but variables still come from
+    // host_handle, so commands must be built for the OS of the machine actually being talked to
+
+    pub fn new_delegated(run_state_handle: Arc<RunState>, connection_handle: Arc<Mutex<dyn Connection>>, host_handle: Arc<RwLock<Host>>,
+        delegate_os_type: Option<HostOSType>) -> Self {
 
         // since we can't really have back-references (thanks Rust?) we pass to each namespace what we need of the others
         // thankfully, no circular references seem to be required :)
@@ -75,6 +83,7 @@ impl TaskHandle {
             Arc::clone(&run_state_handle), 
             Arc::clone(&connection_handle), 
             Arc::clone(&host_handle),
+            delegate_os_type,
             Arc::clone(&template),
             Arc::clone(&response)
         ));
@@ -107,4 +116,45 @@ impl TaskHandle {
         self.run_state.visitor.read().unwrap().debug_host(&self.host, message);
     }
 
+    pub fn warning(&self, _request: &Arc<TaskRequest>, message: &String) {
+        self.run_state.visitor.read().unwrap().warning_host(&self.host, message);
+    }
+
+    // for module tests: a handle for a single Linux host with an empty inventory and default CLI settings,
+    // where every command goes to the given connection (normally a scripted NoConnection)
+
+    #[cfg(test)]
+    pub fn simulated(connection_handle: Arc<Mutex<dyn Connection>>) -> Arc<Self> {
+        let parser = crate::cli::parser::CliParser::new();
+        let inventory = Arc::new(RwLock::new(crate::inventory::inventory::Inventory::new()));
+        let run_state = Arc::new(RunState {
+            inventory: Arc::clone(&inventory),
+            playbook_paths: Arc::clone(&parser.playbook_paths),
+            role_paths: Arc::clone(&parser.role_paths),
+            module_paths: Arc::clone(&parser.module_paths),
+            limit_hosts: Vec::new(),
+            limit_groups: Vec::new(),
+            batch_size: None,
+            context: Arc::new(RwLock::new(crate::playbooks::context::PlaybookContext::new(&parser))),
+            visitor: Arc::new(RwLock::new(crate::playbooks::visitor::PlaybookVisitor::new(crate::playbooks::visitor::CheckMode::No))),
+            connection_factory: Arc::new(RwLock::new(crate::connection::no::NoFactory::new())),
+            tags: None,
+            skip_tags: None,
+            allow_localhost_delegation: false
+        });
+        let host = Arc::new(RwLock::new(Host::new(&String::from("simulated"))));
+        host.write().unwrap().os_type = Some(crate::inventory::hosts::HostOSType::Linux);
+        Arc::new(Self::new(run_state, connection_handle, host))
+    }
+
+    // the same, with every command answered from the script (see NoConnection::with_script), keeping hold
+    // of the connection so tests can look at what was run
+
+    #[cfg(test)]
+    pub fn scripted(script: Vec<(&str, i32, &str)>) -> (Arc<Self>, Arc<Mutex<crate::connection::no::NoConnection>>) {
+        let connection = Arc::new(Mutex::new(crate::connection::no::NoConnection::with_script(script)));
+        let handle = Self::simulated(connection.clone());
+        (handle, connection)
+    }
+
 }
@@ -24,6 +24,7 @@ use crate::tasks::cmd_library::screen_general_input_loose;
 use crate::handle::handle::CheckRc;
 use crate::handle::response::Response;
 use crate::connection::command::Forward;
+use crate::tasks::checksum::ChecksumAlgo;
 
 // local contains code that always executes on the control machine, whether in SSH mode or 'local' execution
 // mode. The code that refers to the machine being configured is always in 'remote.rs', whether in SSH
@@ -74,7 +75,8 @@ impl Local {
             Ok(x) => x,
             Err(y) => { return Err(self.response.is_failed(request, &y.clone())) }
         };
-        let result = local_conn.lock().unwrap().run_command(&self.response, request, cmd, Forward::No);
+        let timeout = ctx.read().unwrap().timeout;
+        let result = local_conn.lock().unwrap().run_command(&self.response, request, cmd, Forward::No, timeout, None);
 
         if check_rc == CheckRc::Checked && result.is_ok() {
             let ok_result = result.as_ref().unwrap();
@@ -93,10 +95,10 @@ impl Local {
         }
     }
 
-    fn internal_sha512(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
+    fn internal_checksum(&self, request: &Arc<TaskRequest>, path: &String, algo: ChecksumAlgo) -> Result<String,Arc<TaskResponse>> {
         let localhost = self.get_localhost();
         let os_type = localhost.read().unwrap().os_type.expect("unable to detect host OS type");
-        let get_cmd_result = crate::tasks::cmd_library::get_sha512_command(os_type, path);
+        let get_cmd_result = crate::tasks::cmd_library::get_checksum_command(os_type, algo, path);
         let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
         let result = self.run(request, &cmd, CheckRc::Unchecked)?;
         let (rc, out) = cmd_info(&result);
@@ -119,24 +121,36 @@ impl Local {
     pub fn get_sha512(&self, request: &Arc<TaskRequest>, path: &Path, use_cache: bool) -> Result<String,Arc<TaskResponse>> {
         let path2 = format!("{}", path.display());
         let localhost = self.get_localhost();
-        if use_cache {
-            let ctx = self.run_state.context.read().unwrap();
-            let task_id = ctx.get_task_count();
-            let mut localhost2 = localhost.write().unwrap();
-            let cached = localhost2.get_checksum_cache(task_id, &path2);
-            if let Some(cached) = cached {
+        // taken before hashing, so a file that changes while being hashed is hashed again next time
+        let metadata = match use_cache {
+            true => get_size_mtime(path),
+            false => None
+        };
+        if let Some((size, mtime)) = &metadata {
+            if let Some(cached) = localhost.read().unwrap().get_checksum_cache(&path2, *size, mtime) {
                 return Ok(cached);
             }
         }
 
         // this is a little weird.
-        let value = self.internal_sha512(request, &path2)?;
-        if use_cache {
-            let mut localhost2 = localhost.write().unwrap();
-            localhost2.set_checksum_cache(&path2, &value);
+        let value = self.internal_checksum(request, &path2, ChecksumAlgo::Sha512)?;
+        if let Some((size, mtime)) = &metadata {
+            localhost.write().unwrap().set_checksum_cache(&path2, *size, mtime, &value);
         }
         Ok(value)
     }
 
 
 }
+
+// formatted the same way as remote.get_mtime, so the two can be compared
+
+pub fn get_mtime(path: &Path) -> Option<String> {
+    get_size_mtime(path).map(|(_size, mtime)| mtime)
+}
+
+fn get_size_mtime(path: &Path) -> Option<(u64, String)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some((metadata.len(), format!("{}.{:09}", mtime.as_secs(), mtime.subsec_nanos())))
+}
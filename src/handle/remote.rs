@@ -17,21 +17,26 @@
 use std::sync::{Arc,Mutex,RwLock};
 use std::path::Path;
 use crate::connection::connection::Connection;
-use crate::connection::command::cmd_info;
+use crate::connection::command::{cmd_info,CommandResult};
 use crate::tasks::request::{TaskRequest, TaskRequestType};
 use crate::tasks::response::TaskResponse;
 use crate::inventory::hosts::{Host,HostOSType};
 use crate::playbooks::traversal::RunState;
 use crate::tasks::fields::Field;
 use crate::tasks::FileAttributesEvaluated;
-use crate::connection::command::Forward;
-use crate::tasks::cmd_library::screen_general_input_loose;
+use crate::connection::command::{Forward,Stream};
+use crate::tasks::cmd_library::{screen_general_input_loose,screen_path,is_numeric_id,get_environment_wrapped_command};
 use crate::handle::handle::CheckRc;
 use crate::handle::template::Safety;
 use crate::handle::response::Response;
 use crate::handle::template::Template;
+use crate::playbooks::templar::TemplateMode;
 use crate::tasks::files::Recurse;
+use crate::tasks::checksum::ChecksumAlgo;
 use std::path::PathBuf;
+use crate::util::io::{path_as_string,path_basename_as_string};
+use std::collections::HashMap;
+use expanduser::expanduser;
 
 // contains all code that eventually reaches out and touches systems to be configured.
 // this includes the local system (somewhat confusingly) in 'local' mode, and of course
@@ -42,8 +47,22 @@ pub struct Remote {
     run_state: Arc<RunState>, 
     connection: Arc<Mutex<dyn Connection>>,
     host: Arc<RwLock<Host>>, 
+    delegate_os_type: Option<HostOSType>,
     template: Arc<Template>,
-    response: Arc<Response>
+    response: Arc<Response>,
+    rollback: RwLock<Option<Vec<RollbackEntry>>>,
+    timeout: RwLock<Option<u64>>,
+    max_output: RwLock<Option<usize>>
+}
+
+// a file replaced by a task with 'rollback' set, and where its previous contents were copied to.
+// a backup of None means the file did not exist before. the request that replaced it is kept
+// for running the restore commands.
+
+pub struct RollbackEntry {
+    pub path: String,
+    pub backup: Option<String>,
+    pub request: Arc<TaskRequest>
 }
 
 #[derive(Debug,Copy,Clone,PartialEq)]
@@ -58,6 +77,7 @@ impl Remote {
         run_state: Arc<RunState>, 
         connection: Arc<Mutex<dyn Connection>>, 
         host: Arc<RwLock<Host>>, 
+        delegate_os_type: Option<HostOSType>,
         template: Arc<Template>,
         response: Arc<Response>) -> Self {
         
@@ -65,8 +85,12 @@ impl Remote {
             run_state,
             connection,
             host,
+            delegate_os_type,
             template,
             response,
+            rollback: RwLock::new(None),
+            timeout: RwLock::new(None),
+            max_output: RwLock::new(None),
         }
     }
 
@@ -89,45 +113,88 @@ impl Remote {
 
     pub fn make_temp_path(&self, who: &String, request: &Arc<TaskRequest>) -> Result<(PathBuf, PathBuf), Arc<TaskResponse>> {
         let mut pb = PathBuf::new();
-        let tmpdir = match who.eq("root") {
-            false => match self.host.read().unwrap().os_type {
-                Some(HostOSType::MacOS) => format!("/Users/{}/.jet/tmp", who),
-                _ => format!("/home/{}/.jet/tmp", who),
+        let home = match who.eq("root") {
+            false => match self.get_os_type() {
+                HostOSType::MacOS => format!("/Users/{}", who),
+                _ => format!("/home/{}", who),
             }
-            true => String::from("/root/.jet/tmp")
+            true => String::from("/root")
+        };
+        // every temp file goes through here, so modules never pick their own scratch space
+        let configured = self.run_state.context.read().unwrap().get_remote_tmp(&self.host);
+        let tmpdir = match configured {
+            Some(x) if x.eq("~") => home.clone(),
+            Some(x) if x.starts_with("~/") => format!("{}/{}", home, &x[2..]),
+            Some(x) => x,
+            None => format!("{}/.jet/tmp", home)
         };
+        if let Err(e) = screen_path(&tmpdir) {
+            return Err(self.response.is_failed(request, &format!("remote temp directory: {}", e)));
+        }
         pb.push(tmpdir);
         let mut pb2 = pb.clone();
         let guid = self.run_state.context.read().unwrap().get_guid();
         pb2.push(guid.as_str());
-        let create_tmp_dir = format!("mkdir -p '{}'", pb.display());
+        // other users on the host have no business reading files on their way to being installed
+        let create_tmp_dir = format!("mkdir -p -m 700 '{}'", pb.display());
         self.run_no_sudo(request, &create_tmp_dir, CheckRc::Checked)?;
         Ok((pb.clone(), pb2.clone()))
     }
 
+    // a task's own timeout (from 'with') replaces the --timeout default for every command the task runs
+
+    pub fn set_timeout(&self, timeout: Option<u64>) {
+        *self.timeout.write().unwrap() = timeout;
+    }
+
+    pub fn get_timeout(&self) -> Option<u64> {
+        match *self.timeout.read().unwrap() {
+            Some(x) => Some(x),
+            None => self.response.get_context().read().unwrap().timeout
+        }
+    }
+
+    // set by shell's max_output, commands run after it keep only that many bytes of output.  cleared for each task
+    // like the timeout
+
+    pub fn set_max_output(&self, max_output: Option<usize>) {
+        *self.max_output.write().unwrap() = max_output;
+    }
+
     // wrappers around running CLI commands
 
     pub fn run(&self, request: &Arc<TaskRequest>, cmd: &str, check_rc: CheckRc) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::Yes, Forward::No)
+        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::Yes, Forward::No, Stream::No)
     }
 
     pub fn run_forwardable(&self, request: &Arc<TaskRequest>, cmd: &str, check_rc: CheckRc) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::Yes, Forward::Yes)
+        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::Yes, Forward::Yes, Stream::No)
     }
 
     pub fn run_no_sudo(&self, request: &Arc<TaskRequest>, cmd: &str, check_rc: CheckRc) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::No, Forward::No)
+        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::No, Forward::No, Stream::No)
     }
 
     // the unsafe version of this doesn't check the shell string for possible shell variable injections, the most obvious and basic being ";"
     // usage of unsafe requires a special keyword in the 'shell' module for instance, or that no variables are present in the cmd parameter.
 
     pub fn run_unsafe(&self, request: &Arc<TaskRequest>, cmd: &str, check_rc: CheckRc) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        self.internal_run(request, cmd, Safety::Unsafe, check_rc, UseSudo::Yes, Forward::No)
+        self.internal_run(request, cmd, Safety::Unsafe, check_rc, UseSudo::Yes, Forward::No, Stream::No)
+    }
+
+    // for long running commands, output is shown line by line as it comes in, and the result is the same as run/run_unsafe
+
+    pub fn run_streaming(&self, request: &Arc<TaskRequest>, cmd: &str, check_rc: CheckRc) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        self.internal_run(request, cmd, Safety::Safe, check_rc, UseSudo::Yes, Forward::No, Stream::Yes)
+    }
+
+    pub fn run_unsafe_streaming(&self, request: &Arc<TaskRequest>, cmd: &str, check_rc: CheckRc) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        self.internal_run(request, cmd, Safety::Unsafe, check_rc, UseSudo::Yes, Forward::No, Stream::Yes)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn internal_run(&self, request: &Arc<TaskRequest>, cmd: &str, 
-        safe: Safety, check_rc: CheckRc, use_sudo: UseSudo, forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        safe: Safety, check_rc: CheckRc, use_sudo: UseSudo, forward: Forward, stream: Stream) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
         
         assert!(request.request_type != TaskRequestType::Validate, "commands cannot be run in validate stage");
 
@@ -142,6 +209,15 @@ impl Remote {
             }
         }
 
+        // the play's environment block (if any) applies to every command the tasks run, but not to
+        // OS detection and other internal commands that do not run under sudo
+
+        let cmd = match use_sudo {
+            UseSudo::Yes => self.add_environment(request, cmd)?,
+            UseSudo::No => cmd.to_owned()
+        };
+        let cmd = cmd.as_str();
+
         // use the sudo template to choose a new command to execute if specified.
         // this doesn't need to be sudo specifically, it's really a generic concept that can wrap a command with another tool
 
@@ -153,9 +229,37 @@ impl Remote {
             UseSudo::No => cmd.to_owned() 
         };
 
-        self.response.get_visitor().read().expect("read visitor").on_command_run(&self.response.get_context(), &Arc::clone(&self.host), cmd);
+        self.response.get_visitor().read().expect("read visitor").on_command_run(&self.response.get_context(), &Arc::clone(&self.host), request, cmd, &cmd_out);
+
+        // a sudo password goes to the command's standard input, and is scrubbed from anything the command prints
 
-        let result = self.connection.lock().unwrap().run_command(&self.response, request, &cmd_out, forward);
+        let sudo_input = match use_sudo {
+            UseSudo::Yes => self.get_sudo_input(request),
+            UseSudo::No => None
+        };
+
+        let timeout = self.get_timeout();
+        let max_output = *self.max_output.read().unwrap();
+        let result = match (stream, &sudo_input) {
+            (Stream::No, None) => self.connection.lock().unwrap().run_command(&self.response, request, &cmd_out, forward, timeout, max_output),
+            (Stream::Yes, None) => {
+                let visitor = self.response.get_visitor();
+                let context = self.response.get_context();
+                self.connection.lock().unwrap().run_command_streaming(&self.response, request, &cmd_out, forward, timeout, max_output, &mut |line| {
+                    visitor.read().expect("read visitor").on_command_output_line(&context, &self.host, request, line);
+                })
+            },
+            (_, Some(input)) => {
+                let visitor = self.response.get_visitor();
+                let context = self.response.get_context();
+                let result = self.connection.lock().unwrap().run_command_with_input(&self.response, request, &cmd_out, forward, timeout, max_output, input, &mut |line| {
+                    if stream == Stream::Yes {
+                        visitor.read().expect("read visitor").on_command_output_line(&context, &self.host, request, &self.redact(line));
+                    }
+                });
+                self.redact_result(request, result)
+            }
+        };
 
         // if requested, turn non-zero return codes into errors
 
@@ -170,9 +274,60 @@ impl Remote {
         result
     }
 
+    // what to send to sudo on standard input, if this request sudos and a password was given with --ask-sudo-password
+
+    pub fn get_sudo_input(&self, request: &Arc<TaskRequest>) -> Option<String> {
+        if ! request.is_sudoing() {
+            return None;
+        }
+        self.response.get_context().read().unwrap().sudo_password.as_ref().map(|x| format!("{}\n", x))
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        match &self.response.get_context().read().unwrap().sudo_password {
+            Some(x) if ! x.is_empty() && text.contains(x.as_str()) => text.replace(x.as_str(), "********"),
+            _ => text.to_owned()
+        }
+    }
+
+    pub fn redact_result(&self, request: &Arc<TaskRequest>, result: Result<Arc<TaskResponse>,Arc<TaskResponse>>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let (is_ok, response) = match &result { Ok(x) => (true, x), Err(x) => (false, x) };
+        let redacted = match response.command_result.as_ref() {
+            Some(x) => {
+                let out = self.redact(&x.out);
+                if out == x.out { return result; }
+                Arc::new(Some(CommandResult { cmd: x.cmd.clone(), out, rc: x.rc }))
+            },
+            None => { return result; }
+        };
+        match is_ok {
+            true => Ok(self.response.command_ok(request, &redacted)),
+            false => Err(self.response.command_failed(request, &redacted))
+        }
+    }
+
+    fn add_environment(&self, request: &Arc<TaskRequest>, cmd: &str) -> Result<String,Arc<TaskResponse>> {
+        let environment = self.response.get_context().read().unwrap().environment_storage.read().unwrap().clone();
+        if environment.is_empty() {
+            return Ok(cmd.to_owned());
+        }
+        let mut rendered : Vec<(String,String)> = Vec::new();
+        for (k, v) in environment.iter() {
+            let value = self.template.string_unsafe_for_shell(request, TemplateMode::Strict, &format!("environment.{}", k), v)?;
+            rendered.push((k.clone(), value));
+        }
+        match get_environment_wrapped_command(self.get_os_type(), &rendered, cmd) {
+            Ok(x) => Ok(x),
+            Err(y) => Err(self.response.is_failed(request, &y))
+        }
+    }
+
     // the OS type of a host is set on connection by automatically running a discovery command
 
     pub fn get_os_type(&self) -> HostOSType {
+        if let Some(os_type) = self.delegate_os_type {
+            return os_type;
+        }
         let os_type = self.host.read().unwrap().os_type;
         if os_type.is_none() {
             panic!("failed to detect OS type for {}, bailing out", self.host.read().unwrap().name);
@@ -204,50 +359,263 @@ impl Remote {
         result
     }
 
-    // more supporting code for file transfer using temp files
+    // more supporting code for file transfer using temp files.  the temp file usually lives on another filesystem
+    // (under ~/.jet/tmp) so it is first moved next to the destination under a hidden name, and only then renamed
+    // over the destination.  the final rename stays within one directory, so readers of the destination will only
+    // ever see the old or the new contents, never a partially written file.
 
     fn conditionally_move_back(&self, request: &Arc<TaskRequest>, temp_dir: Option<PathBuf>, temp_path: Option<PathBuf>, desired_path: &String) -> Result<(), Arc<TaskResponse>> {
         if temp_dir.is_some() {
-            let move_to_correct_location = format!("mv '{}' '{}'", temp_path.as_ref().unwrap().display(), desired_path);
-            let delete_tmp_location = format!("rm '{}'", temp_path.as_ref().unwrap().display());
-            let result = self.run(request, &move_to_correct_location, CheckRc::Checked);
+            let temp_path = temp_path.as_ref().unwrap();
+            let staged_path = self.get_staging_path(temp_path, desired_path);
+            let move_next_to_destination = format!("mv '{}' '{}'", temp_path.display(), staged_path);
+            let rename_into_place = format!("mv -f '{}' '{}'", staged_path, desired_path);
+            let result = self.run(request, &move_next_to_destination, CheckRc::Checked);
             if let Err(e) = result {
-                let _ = self.run(request, &delete_tmp_location, CheckRc::Unchecked);
+                let _ = self.run(request, &format!("rm -f '{}'", temp_path.display()), CheckRc::Unchecked);
                 return Err(e);
             }
+            // mode, owner and group were set on the temp file and stay with it through the rename, the label and ACL
+            // are taken from the file being replaced, see get_keep_security_attributes_command
+            let existed = self.get_mode(request, desired_path)?.is_some();
+            if existed {
+                let keep = crate::tasks::cmd_library::get_keep_security_attributes_command(self.get_os_type(), desired_path, &staged_path);
+                let result = match keep {
+                    Ok(Some(cmd)) => self.run_unsafe(request, &cmd, CheckRc::Checked).map(|_| ()),
+                    Ok(None) => Ok(()),
+                    Err(y) => Err(self.response.is_failed(request, &y))
+                };
+                if let Err(e) = result {
+                    let _ = self.run(request, &format!("rm -f '{}'", staged_path), CheckRc::Unchecked);
+                    return Err(e);
+                }
+            }
+            let result = self.run(request, &rename_into_place, CheckRc::Checked);
+            if let Err(e) = result {
+                let _ = self.run(request, &format!("rm -f '{}'", staged_path), CheckRc::Unchecked);
+                return Err(e);
+            }
+            if ! existed {
+                match crate::tasks::cmd_library::get_restore_security_context_command(self.get_os_type(), desired_path) {
+                    Ok(Some(cmd)) => { self.run_unsafe(request, &cmd, CheckRc::Checked)?; },
+                    Ok(None) => {},
+                    Err(y) => { return Err(self.response.is_failed(request, &y)); }
+                }
+            }
         }
         Ok(())
     }
 
+    // the hidden name a temp file takes in the destination directory just before it is renamed over the destination
+
+    fn get_staging_path(&self, temp_path: &Path, desired_path: &str) -> String {
+        let temp_name = match temp_path.file_name() {
+            Some(x) => x.to_string_lossy().to_string(),
+            None => String::from("tmp")
+        };
+        self.get_hidden_sibling_path(desired_path, &temp_name, "jet-tmp")
+    }
+
+    fn forget_rollback(&self, request: &Arc<TaskRequest>, path: &str) {
+        let entry = match self.rollback.write().unwrap().as_mut() {
+            Some(entries) => match entries.iter().position(|x| x.path == path) {
+                Some(index) => entries.remove(index),
+                None => { return; }
+            },
+            None => { return; }
+        };
+        if let Some(backup) = entry.backup {
+            let _ = self.run(request, &format!("rm -f '{}'", backup), CheckRc::Unchecked);
+        }
+    }
+
+    fn get_hidden_sibling_path(&self, desired_path: &str, unique: &str, suffix: &str) -> String {
+        let desired = Path::new(desired_path);
+        let file_name = match desired.file_name() {
+            Some(x) => x.to_string_lossy().to_string(),
+            None => String::from("file")
+        };
+        let hidden_name = format!(".{}.{}.{}", file_name, unique, suffix);
+        match desired.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.join(hidden_name).display().to_string(),
+            _ => hidden_name
+        }
+    }
+
     // writes a string (for example, from a template) to a remote file location
 
-    pub fn write_data<G>(&self, request: &Arc<TaskRequest>, data: &str, path: &String, mut before_complete: G) -> Result<(), Arc<TaskResponse>> 
+    pub fn write_data<G>(&self, request: &Arc<TaskRequest>, data: &str, path: &String, before_complete: G) -> Result<(), Arc<TaskResponse>> 
         where G: FnMut(&String) -> Result<(), Arc<TaskResponse>> {   
-        let (temp_dir, temp_path) = self.get_transfer_location(request)?;
-        let real_path = self.get_effective_filename(temp_dir.clone(), temp_path.clone(), path); /* will be either temp_path or path */
-        self.response.get_visitor().read().expect("read visitor").on_before_transfer(&self.response.get_context(), &Arc::clone(&self.host), &real_path);
-        self.connection.lock().unwrap().write_data(&self.response, request, data, &real_path)?;
-        before_complete(&real_path.clone())?;
-        self.conditionally_move_back(request, temp_dir.clone(), temp_path.clone(), path)?;
-        Ok(())
+        self.write_data_atomic(request, data, path, None, before_complete)
     }
 
     // copies a file to a remote location
 
-    pub fn copy_file<G>(&self, request: &Arc<TaskRequest>, src: &Path, dest: &String, mut before_complete: G) -> Result<(), Arc<TaskResponse>> 
+    pub fn copy_file<G>(&self, request: &Arc<TaskRequest>, src: &Path, dest: &String, before_complete: G) -> Result<(), Arc<TaskResponse>> 
     where G: FnMut(&String) -> Result<(), Arc<TaskResponse>> {   
+        self.copy_file_atomic(request, src, dest, None, before_complete)
+    }
+
+    // the safe-write versions of the above, for file modules that manage real configuration.  the data lands in a
+    // temp file, the optional validate command is run against it with %s replaced by the temp path, the attribute
+    // callback is applied, and only then is the file renamed over the destination.  if validation fails the temp
+    // file is removed and the destination is left untouched.
+
+    pub fn write_data_atomic<G>(&self, request: &Arc<TaskRequest>, data: &str, path: &String, validate: Option<&String>, before_complete: G) -> Result<(), Arc<TaskResponse>> 
+        where G: FnMut(&String) -> Result<(), Arc<TaskResponse>> {   
+        self.transfer(request, path, validate, |real_path| {
+            self.connection.lock().unwrap().write_data(&self.response, request, data, real_path)
+        }, before_complete)
+    }
+
+    pub fn copy_file_atomic<G>(&self, request: &Arc<TaskRequest>, src: &Path, dest: &String, validate: Option<&String>, before_complete: G) -> Result<(), Arc<TaskResponse>> 
+        where G: FnMut(&String) -> Result<(), Arc<TaskResponse>> {   
+        self.transfer(request, dest, validate, |real_path| {
+            self.connection.lock().unwrap().copy_file(&self.response, request, src, real_path)
+        }, before_complete)
+    }
+
+    // like copy_file_atomic, but the connection may send only the blocks that changed.  the temp file is seeded
+    // with the current destination so rsync has a basis to diff against, and what arrives is checked against the
+    // local sha512 before anything else happens.  when the delta copy can't be done or doesn't match, the whole
+    // file is sent as usual.
+
+    pub fn copy_file_delta<G>(&self, request: &Arc<TaskRequest>, src: &Path, dest: &String, local_sha512: &String, before_complete: G) -> Result<(), Arc<TaskResponse>> 
+        where G: FnMut(&String) -> Result<(), Arc<TaskResponse>> {   
+        self.transfer(request, dest, None, |real_path| {
+            if self.seed_delta_basis(request, dest, real_path)? {
+                let sent = self.connection.lock().unwrap().copy_file_delta(&self.response, request, src, real_path)?;
+                if sent && self.get_sha512(request, real_path)?.eq(local_sha512) {
+                    return Ok(());
+                }
+            }
+            self.connection.lock().unwrap().copy_file(&self.response, request, src, real_path)
+        }, before_complete)
+    }
+
+    // copies the existing destination to the temp path, owned by the login user so rsync can update it.
+    // returns false if there is no usable destination to start from.
+
+    fn seed_delta_basis(&self, request: &Arc<TaskRequest>, dest: &String, real_path: &String) -> Result<bool, Arc<TaskResponse>> {
+        if real_path.eq(dest) || self.get_mode(request, dest)?.is_none() || self.get_is_directory(request, dest)? {
+            return Ok(false);
+        }
+        let whoami = match self.get_whoami() {
+            Ok(x) => x,
+            Err(y) => { return Err(self.response.is_failed(request, &format!("cannot determine current user: {}", y))) }
+        };
+        let copy_cmd = self.unwrap_string_result(request, &crate::tasks::cmd_library::get_copy_file_command(self.get_os_type(), dest, real_path))?;
+        let owner_cmd = self.unwrap_string_result(request, &crate::tasks::cmd_library::set_owner_command(self.get_os_type(), real_path, &whoami, Recurse::No))?;
+        for cmd in [copy_cmd, owner_cmd] {
+            let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+            let (rc, _out) = cmd_info(&result);
+            if rc != 0 {
+                let _ = self.run(request, &format!("rm -f '{}'", real_path), CheckRc::Unchecked);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn transfer<W,G>(&self, request: &Arc<TaskRequest>, path: &String, validate: Option<&String>, write: W, mut before_complete: G) -> Result<(), Arc<TaskResponse>> 
+        where W: FnOnce(&String) -> Result<(), Arc<TaskResponse>>, G: FnMut(&String) -> Result<(), Arc<TaskResponse>> {
         let (temp_dir, temp_path) = self.get_transfer_location(request)?;
-        let real_path = self.get_effective_filename(temp_dir.clone(), temp_path.clone(), dest); /* will be either temp_path or path */
+        let real_path = self.get_effective_filename(temp_dir.clone(), temp_path.clone(), path); /* will be either temp_path or path */
         self.response.get_visitor().read().expect("read visitor").on_before_transfer(&self.response.get_context(), &Arc::clone(&self.host), &real_path);
-        self.connection.lock().unwrap().copy_file(&self.response, request, src, &real_path)?;        
+        write(&real_path)?;
+        if let Some(validate) = validate {
+            // %s is the only substitution made here, the command was already templated by the module
+            let cmd = validate.replace("%s", &format!("'{}'", real_path));
+            if let Err(e) = self.run(request, &cmd, CheckRc::Checked) {
+                if temp_dir.is_some() {
+                    let _ = self.run(request, &format!("rm -f '{}'", real_path), CheckRc::Unchecked);
+                }
+                return Err(e);
+            }
+        }
         before_complete(&real_path.clone())?;
-        self.conditionally_move_back(request, temp_dir.clone(), temp_path.clone(), dest)?;
+        if let Err(e) = self.record_rollback(request, path) {
+            if temp_dir.is_some() {
+                let _ = self.run(request, &format!("rm -f '{}'", real_path), CheckRc::Unchecked);
+            }
+            return Err(e);
+        }
+        if let Err(e) = self.conditionally_move_back(request, temp_dir.clone(), temp_path.clone(), path) {
+            // the file was never replaced, so there is nothing to put back
+            self.forget_rollback(request, path);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // rollback covers a single task on a single host, including all of its items and retries. the first time
+    // the task is about to replace a file, the file is copied to a hidden name next to it. if the task then
+    // fails, finish_rollback puts every file back (or removes it, if the task created it). otherwise the
+    // copies are deleted. earlier tasks are not undone when a later one fails.
+
+    pub fn enable_rollback(&self) {
+        let mut rollback = self.rollback.write().unwrap();
+        if rollback.is_none() {
+            *rollback = Some(Vec::new());
+        }
+    }
+
+    fn record_rollback(&self, request: &Arc<TaskRequest>, path: &str) -> Result<(), Arc<TaskResponse>> {
+        match self.rollback.read().unwrap().as_ref() {
+            None => { return Ok(()); },
+            Some(entries) => if entries.iter().any(|x| x.path == path) { return Ok(()); }
+        }
+        let backup = match self.get_mode(request, path)? {
+            None => None,
+            Some(_) => {
+                let guid = self.run_state.context.read().unwrap().get_guid();
+                let backup = self.get_hidden_sibling_path(path, &guid, "jet-rollback");
+                let cmd = self.unwrap_string_result(request, &crate::tasks::cmd_library::get_backup_file_command(self.get_os_type(), path, &backup))?;
+                self.run(request, &cmd, CheckRc::Checked)?;
+                Some(backup)
+            }
+        };
+        if let Some(entries) = self.rollback.write().unwrap().as_mut() {
+            entries.push(RollbackEntry { path: path.to_owned(), backup, request: Arc::clone(request) });
+        }
         Ok(())
     }
 
+    // returns the paths that were restored, newest change first
+
+    pub fn finish_rollback(&self, failed: bool) -> Result<Vec<String>, Arc<TaskResponse>> {
+        let entries = match self.rollback.write().unwrap().take() {
+            Some(x) => x,
+            None => { return Ok(Vec::new()); }
+        };
+        let mut restored : Vec<String> = Vec::new();
+        for entry in entries.iter().rev() {
+            let cmd = match (failed, &entry.backup) {
+                (true, Some(backup)) => format!("mv -f '{}' '{}'", backup, entry.path),
+                (true, None) => format!("rm -f '{}'", entry.path),
+                (false, Some(backup)) => format!("rm -f '{}'", backup),
+                (false, None) => { continue; }
+            };
+            if let Err(e) = self.run(&entry.request, &cmd, CheckRc::Checked) {
+                return match failed {
+                    true => Err(self.response.is_failed(&entry.request, &format!("the task failed, and then restoring {} also failed: {}", entry.path,
+                        e.msg.clone().unwrap_or_default()))),
+                    false => Err(e)
+                };
+            }
+            if failed {
+                restored.push(entry.path.clone());
+            }
+        }
+        Ok(restored)
+    }
+
     // gets the octal string mode of a remote file
 
     pub fn get_mode(&self, request: &Arc<TaskRequest>, path: &str) -> Result<Option<String>,Arc<TaskResponse>> {
+        if let Some(mode) = self.connection.lock().unwrap().get_mode(&self.response, request, path)? {
+            return Ok(mode);
+        }
         let get_cmd_result = crate::tasks::cmd_library::get_mode_command(self.get_os_type(), path);
         let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
         
@@ -260,6 +628,16 @@ impl Remote {
         }
     }
 
+    // reads back a small remote file, such as a config file a module edits in place. the final newline is not preserved.
+
+    pub fn read_file(&self, request: &Arc<TaskRequest>, path: &str) -> Result<String,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_read_file_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Checked)?;
+        let (_rc, out) = cmd_info(&result);
+        Ok(out)
+    }
+
     // is a remote path a file?
 
     pub fn get_is_file(&self, request: &Arc<TaskRequest>, path: &str) -> Result<bool,Arc<TaskResponse>> {
@@ -273,19 +651,61 @@ impl Remote {
     // is a remote path a directory?
 
     pub fn get_is_directory(&self, request: &Arc<TaskRequest>, path: &str) -> Result<bool,Arc<TaskResponse>> {
+        if let Some(is_directory) = self.connection.lock().unwrap().get_is_directory(&self.response, request, path)? {
+            return Ok(is_directory);
+        }
         let get_cmd_result = crate::tasks::cmd_library::get_is_directory_command(self.get_os_type(), path);
         let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
         
         let result = self.run(request, &cmd, CheckRc::Checked)?;
         let (_rc, out) = cmd_info(&result);
-        // so far this assumes reliable ls -ld output across all supported operating systems, this may change
-        // in wich case we may need to consider os_type here
-        if out.starts_with("d") {
+        // the command library prints just the file type, see get_is_directory_command
+        if out.trim().eq_ignore_ascii_case("directory") {
             return Ok(true);
         }
         Ok(false)
     }
 
+    // like cp, a destination that ends in a slash or is an existing directory gets the file placed inside it under
+    // the source's name.  used by copy and template in every leg so that query and create agree on the real path.
+
+    pub fn get_destination_path(&self, request: &Arc<TaskRequest>, dest: &String, src: &Path) -> Result<String,Arc<TaskResponse>> {
+        let is_directory = dest.ends_with('/') || (self.get_mode(request, dest)?.is_some() && self.get_is_directory(request, dest)?);
+        if ! is_directory {
+            return Ok(dest.clone());
+        }
+        if src.file_name().is_none() {
+            return Err(self.response.is_failed(request, &format!("dest is a directory ({}) but the source has no file name to place inside it", dest)));
+        }
+        let joined = Path::new(dest).join(path_basename_as_string(src));
+        Ok(path_as_string(&joined))
+    }
+
+    pub fn get_link_target(&self, request: &Arc<TaskRequest>, path: &str) -> Result<Option<String>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_read_link_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let (rc, _out) = cmd_info(&self.run(request, &cmd, CheckRc::Unchecked)?);
+        if rc != 0 {
+            return Ok(None);
+        }
+        let get_cmd_result = crate::tasks::cmd_library::get_resolve_link_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let (_rc, out) = cmd_info(&self.run(request, &cmd, CheckRc::Checked)?);
+        Ok(Some(out.trim_end().to_string()))
+    }
+
+    // for modules writing a file to dest: when dest is a symlink, follow writes to the file it points at and
+    // keeps the link, otherwise the link itself is replaced with a regular file.  returns the path to work on
+    // and whether that path is a link that has to be replaced
+
+    pub fn follow_destination(&self, request: &Arc<TaskRequest>, dest: &str, follow: bool) -> Result<(String, bool),Arc<TaskResponse>> {
+        match self.get_link_target(request, dest)? {
+            Some(target) if follow => Ok((target, false)),
+            Some(_) => Ok((dest.to_owned(), true)),
+            None => Ok((dest.to_owned(), false))
+        }
+    }
+
     pub fn touch_file(&self, request: &Arc<TaskRequest>, path: &str) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
         let get_cmd_result = crate::tasks::cmd_library::get_touch_command(self.get_os_type(), path);
         let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
@@ -328,15 +748,15 @@ impl Remote {
             _ => { return Ok(None); },
         }
 
+        // output is "owner group", see get_ownership_command
         let mut split = out.split_whitespace();
-        let owner = match split.nth(2) {
+        let owner = match split.next() {
             Some(x) => x,
             None => { 
                 return Err(self.response.is_failed(request, &format!("unexpected output format from {}: {}", cmd, out)));
             }
         };
-        // this is a progressive iterator, hence 0 and not 3 for nth() below!
-        let group = match split.nth(0) {
+        let group = match split.next() {
             Some(x) => x,
             None => { 
                 return Err(self.response.is_failed(request, &format!("unexpected output format from {}: {}", cmd, out))); 
@@ -345,6 +765,62 @@ impl Remote {
         Ok(Some((owner.to_string(),group.to_string())))
     }
 
+    // like get_ownership, but the uid and gid, for comparing against owners and groups given as numbers
+
+    pub fn get_numeric_ownership(&self, request: &Arc<TaskRequest>, path: &str) -> Result<Option<(String,String)>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_numeric_ownership_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        if rc != 0 {
+            return Ok(None);
+        }
+        let mut split = out.split_whitespace();
+        match (split.next(), split.next()) {
+            (Some(uid), Some(gid)) => Ok(Some((uid.to_string(), gid.to_string()))),
+            _ => Err(self.response.is_failed(request, &format!("unexpected output format from {}: {}", cmd, out)))
+        }
+    }
+
+    pub fn user_exists(&self, request: &Arc<TaskRequest>, user: &str) -> Result<bool,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_user_exists_command(self.get_os_type(), user);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        Ok(rc == 0 && ! out.trim().is_empty())
+    }
+
+    pub fn group_exists(&self, request: &Arc<TaskRequest>, group: &str) -> Result<bool,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_group_exists_command(self.get_os_type(), group);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        Ok(rc == 0 && ! out.trim().is_empty())
+    }
+
+    // checked before asking for an owner or group change, so a typo fails with a clear message rather than
+    // whatever chown or chgrp print.  numeric ids may be let through with allow_unknown_ids.
+
+    fn verify_owner_and_group(&self, request: &Arc<TaskRequest>, attributes: &FileAttributesEvaluated, owner: bool, group: bool) -> Result<(),Arc<TaskResponse>> {
+        if owner {
+            if let Some(x) = attributes.owner.as_ref() {
+                let unchecked = attributes.allow_unknown_ids && is_numeric_id(x);
+                if ! unchecked && ! self.user_exists(request, x)? {
+                    return Err(self.response.is_failed(request, &format!("owner '{}' does not exist", x)));
+                }
+            }
+        }
+        if group {
+            if let Some(x) = attributes.group.as_ref() {
+                let unchecked = attributes.allow_unknown_ids && is_numeric_id(x);
+                if ! unchecked && ! self.group_exists(request, x)? {
+                    return Err(self.response.is_failed(request, &format!("group '{}' does not exist", x)));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn set_owner(&self, request: &Arc<TaskRequest>, remote_path: &str, owner: &str, recurse: Recurse) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
         let get_cmd_result = crate::tasks::cmd_library::set_owner_command(self.get_os_type(), remote_path, owner, recurse);
         let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
@@ -364,15 +840,109 @@ impl Remote {
     }
 
     pub fn get_sha512(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
-        self.internal_sha512(request, path)
+        self.get_checksum(request, path, ChecksumAlgo::Sha512)
     }
 
-    // right now we assume there's a good way to run SHA-512 preinstalled on all platforms.
+    // with quick_check, a file whose size and modification time are the same as when it was last hashed is not
+    // hashed again. cheaper for big files, but a rewrite that keeps both is missed.  what was hashed is kept on the
+    // control machine in ~/.jet/checksums/<host>.yml so later runs skip it too, --flush-cache starts over.
+
+    pub fn get_sha512_quick(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
+        self.load_checksum_cache();
+        let metadata = self.get_size_mtime(request, path)?;
+        if let Some((size, mtime)) = &metadata {
+            if let Some(cached) = self.host.read().unwrap().get_checksum_cache(path, *size, mtime) {
+                return Ok(cached);
+            }
+        }
+        let value = self.get_sha512(request, path)?;
+        if let Some((size, mtime)) = &metadata {
+            self.host.write().unwrap().set_checksum_cache(path, *size, mtime, &value);
+        }
+        Ok(value)
+    }
 
-    fn internal_sha512(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
+    fn load_checksum_cache(&self) {
+        if self.host.read().unwrap().is_checksum_cache_loaded() {
+            return;
+        }
+        let flush = self.response.get_context().read().unwrap().flush_cache;
+        let entries = match (flush, get_checksum_cache_path(&self.host.read().unwrap().name)) {
+            (false, Some(path)) => std::fs::read_to_string(path).ok().and_then(|x| serde_yaml::from_str(&x).ok()).unwrap_or_default(),
+            _ => HashMap::new()
+        };
+        self.host.write().unwrap().load_checksum_cache(entries);
+    }
+
+    // called once a task is done, only writes anything if the task hashed or wrote a file with quick_check
+
+    pub fn save_checksum_cache(&self, request: &Arc<TaskRequest>) -> Result<(),Arc<TaskResponse>> {
+        let entries = match self.host.write().unwrap().take_checksum_cache_changes() {
+            Some(x) => x,
+            None => { return Ok(()); }
+        };
+        let path = match get_checksum_cache_path(&self.host.read().unwrap().name) {
+            Some(x) => x,
+            None => { return Err(self.response.is_failed(request, &String::from("unable to locate the home directory for the checksum cache"))); }
+        };
+        let data = match serde_yaml::to_string(&entries) {
+            Ok(x) => x,
+            Err(e) => { return Err(self.response.is_failed(request, &format!("unable to serialize the checksum cache: {}", e))); }
+        };
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                return Err(self.response.is_failed(request, &format!("unable to create {}: {}", parent.display(), e)));
+            }
+        }
+        match std::fs::write(&path, data) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(self.response.is_failed(request, &format!("unable to write {}: {}", path.display(), e)))
+        }
+    }
+
+    // after a file is written its checksum is already known, so the next quick check can skip hashing it
+
+    pub fn remember_sha512(&self, request: &Arc<TaskRequest>, path: &str, checksum: &str) -> Result<(),Arc<TaskResponse>> {
+        if let Some((size, mtime)) = self.get_size_mtime(request, path)? {
+            self.host.write().unwrap().set_checksum_cache(path, size, &mtime, checksum);
+        }
+        Ok(())
+    }
+
+    // the modification time of a remote file as seconds since the epoch, with a fraction where the filesystem keeps one
+
+    pub fn get_mtime(&self, request: &Arc<TaskRequest>, path: &str) -> Result<Option<String>,Arc<TaskResponse>> {
+        Ok(self.get_size_mtime(request, path)?.map(|(_size, mtime)| mtime))
+    }
+
+    fn get_size_mtime(&self, request: &Arc<TaskRequest>, path: &str) -> Result<Option<(u64,String)>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_size_mtime_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        if rc != 0 {
+            return Ok(None);
+        }
+        let mut split = out.split_whitespace();
+        match (split.next().and_then(|x| x.parse::<u64>().ok()), split.next()) {
+            (Some(size), Some(mtime)) => Ok(Some((size, mtime.to_owned()))),
+            _ => Ok(None)
+        }
+    }
+
+    // right now we assume there's a good way to run each checksum preinstalled on all platforms.
+    // an empty string means the file does not exist
+
+    pub fn get_checksum(&self, request: &Arc<TaskRequest>, path: &String, algo: ChecksumAlgo) -> Result<String,Arc<TaskResponse>> {
+
+        if algo == ChecksumAlgo::Sha512 {
+            if let Some(checksum) = self.connection.lock().unwrap().get_sha512(&self.response, request, path)? {
+                return Ok(checksum);
+            }
+        }
         
         let os_type = self.get_os_type();
-        let get_cmd_result = crate::tasks::cmd_library::get_sha512_command(os_type, path);
+        let get_cmd_result = crate::tasks::cmd_library::get_checksum_command(os_type, algo, path);
         let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
 
         let result = self.run(request, &cmd, CheckRc::Unchecked)?;
@@ -394,38 +964,96 @@ impl Remote {
         }
     }
 
+    // checksums a batch of files in a single command, which saves a round trip per file on slow links.
+    // every requested path is in the result, files that do not exist map to an empty string like get_checksum.
+    // used by copy for the files of a directory or wildcard src
+
+    pub fn get_sha512_many(&self, request: &Arc<TaskRequest>, paths: &[String]) -> Result<HashMap<String,String>,Arc<TaskResponse>> {
+
+        let mut results : HashMap<String,String> = HashMap::new();
+        let mut remaining : Vec<String> = Vec::new();
+        for path in paths.iter() {
+            match self.connection.lock().unwrap().get_sha512(&self.response, request, path)? {
+                Some(checksum) => { results.insert(path.clone(), checksum); },
+                None => { results.insert(path.clone(), String::new()); remaining.push(path.clone()); }
+            }
+        }
+        // kept well under the limit on the length of a command line
+        for chunk in remaining.chunks(100) {
+            self.get_sha512_chunk(request, chunk, &mut results)?;
+        }
+        Ok(results)
+    }
+
+    fn get_sha512_chunk(&self, request: &Arc<TaskRequest>, paths: &[String], results: &mut HashMap<String,String>) -> Result<(),Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_checksum_many_command(self.get_os_type(), ChecksumAlgo::Sha512, paths);
+        let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+        let (_rc, out) = cmd_info(&result);
+
+        for line in out.lines() {
+            // "checksum  path" on Linux, "checksum *path" from shasum -b, anything else is an error message
+            let (checksum, rest) = match line.split_once(char::is_whitespace) {
+                Some(x) => x,
+                None => { continue; }
+            };
+            if checksum.len() != 128 || ! checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            let path = rest.trim_start();
+            let path = path.strip_prefix('*').unwrap_or(path);
+            if let Some(entry) = results.get_mut(path) {
+                *entry = checksum.to_string();
+            }
+        }
+        Ok(())
+    }
+
     // supporting code for any tasks that has an 'attributes' member, see 'template' for one example of usage
     // TODO: add SELinux
 
     pub fn query_common_file_attributes(&self, request: &Arc<TaskRequest>, remote_path: &str, 
         attributes_in: &Option<FileAttributesEvaluated>, changes: &mut Vec<Field>, recurse: Recurse) -> Result<Option<String>,Arc<TaskResponse>> {
 
+        if recurse == Recurse::Yes {
+            return self.query_common_file_attributes_excluding(request, remote_path, attributes_in, changes, &[]);
+        }
+
         let remote_mode = self.get_mode(request, remote_path)?;
         
         if remote_mode.is_none() {
+            if let Some(attributes) = attributes_in.as_ref() {
+                self.verify_owner_and_group(request, attributes, true, true)?;
+            }
             changes.push(Field::Content);
             return Ok(None);
         }
 
-        if attributes_in.is_some() && recurse == Recurse::Yes {
-            changes.push(Field::Owner);
-            changes.push(Field::Group);
-            changes.push(Field::Mode);
-            return Ok(remote_mode);
-        }
-
         if attributes_in.is_some() {
             let attributes = attributes_in.as_ref().unwrap();
             let owner_result = self.get_ownership(request, remote_path)?;
             if owner_result.is_none() {
                 return Err(self.response.is_failed(request, &String::from("file was deleted unexpectedly mid-operation")));
             }
-            let (remote_owner, remote_group) = owner_result.unwrap();
+            let (mut remote_owner, mut remote_group) = owner_result.unwrap();
+
+            // an owner or group given as a number is compared with the uid or gid
+            let numeric_owner = attributes.owner.as_ref().is_some_and(|x| is_numeric_id(x));
+            let numeric_group = attributes.group.as_ref().is_some_and(|x| is_numeric_id(x));
+            if numeric_owner || numeric_group {
+                if let Some((uid, gid)) = self.get_numeric_ownership(request, remote_path)? {
+                    if numeric_owner { remote_owner = uid; }
+                    if numeric_group { remote_group = gid; }
+                }
+            }
 
-            if attributes.owner.is_some() && ! remote_owner.eq(attributes.owner.as_ref().unwrap()) { 
+            let owner_differs = attributes.owner.is_some() && ! remote_owner.eq(attributes.owner.as_ref().unwrap());
+            let group_differs = attributes.group.is_some() && ! remote_group.eq(attributes.group.as_ref().unwrap());
+            self.verify_owner_and_group(request, attributes, owner_differs, group_differs)?;
+            if owner_differs { 
                 changes.push(Field::Owner); 
             }
-            if attributes.group.is_some() && ! remote_group.eq(attributes.group.as_ref().unwrap())  { 
+            if group_differs { 
                 changes.push(Field::Group); 
             }
             if attributes.mode.is_some() && ! remote_mode.as_ref().unwrap().eq(attributes.mode.as_ref().unwrap()) { 
@@ -435,6 +1063,45 @@ impl Remote {
         Ok(remote_mode)
     }
 
+    // the recursive form of query_common_file_attributes, a field is reported as soon as anything under remote_path,
+    // other than the excluded paths, disagrees with it
+
+    pub fn query_common_file_attributes_excluding(&self, request: &Arc<TaskRequest>, remote_path: &str, 
+        attributes_in: &Option<FileAttributesEvaluated>, changes: &mut Vec<Field>, exclude: &[String]) -> Result<Option<String>,Arc<TaskResponse>> {
+
+        let remote_mode = self.get_mode(request, remote_path)?;
+        let attributes = match attributes_in.as_ref() {
+            Some(x) => x,
+            None => {
+                if remote_mode.is_none() { changes.push(Field::Content); }
+                return Ok(remote_mode);
+            }
+        };
+        // find treats an unknown user or group as an error, which would otherwise look like nothing to change
+        self.verify_owner_and_group(request, attributes, true, true)?;
+        if remote_mode.is_none() {
+            changes.push(Field::Content);
+            return Ok(None);
+        }
+
+        let os_type = self.get_os_type();
+        for field in Field::all_file_attributes() {
+            let get_cmd_result = match (field, &attributes.owner, &attributes.group, &attributes.mode) {
+                (Field::Owner, Some(owner), _, _) => crate::tasks::cmd_library::get_owner_drift_command(os_type, remote_path, owner, exclude),
+                (Field::Group, _, Some(group), _) => crate::tasks::cmd_library::get_group_drift_command(os_type, remote_path, group, exclude),
+                (Field::Mode,  _, _, Some(mode))  => crate::tasks::cmd_library::get_mode_drift_command(os_type, remote_path, mode, exclude),
+                _ => { continue; }
+            };
+            let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+            let result = self.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+            let (_rc, out) = cmd_info(&result);
+            if ! out.trim().is_empty() {
+                changes.push(field);
+            }
+        }
+        Ok(remote_mode)
+    }
+
     // supporting code for workign with files that have configurable attributes. See above + also
     // modules like template.
     // TODO: add SELinux
@@ -478,6 +1145,37 @@ impl Remote {
 
     // see above comments about file attributes features.  
 
+    // the recursive form of process_common_file_attributes for when some paths under remote_path are to be left
+    // alone, see is_path_excluded for what the patterns mean
+
+    pub fn process_common_file_attributes_excluding(&self, 
+        request: &Arc<TaskRequest>, 
+        remote_path: &str, 
+        attributes_in: &Option<FileAttributesEvaluated>, 
+        changes: &[Field],
+        exclude: &[String])
+
+            -> Result<(),Arc<TaskResponse>> {
+
+        let attributes = match attributes_in.as_ref() {
+            Some(x) => x,
+            None => { return Ok(()); }
+        };
+        let os_type = self.get_os_type();
+        for change in changes.iter() {
+            let get_cmd_result = match (change, &attributes.owner, &attributes.group, &attributes.mode) {
+                (Field::Owner, Some(owner), _, _) => crate::tasks::cmd_library::set_owner_excluding_command(os_type, remote_path, owner, exclude),
+                (Field::Group, _, Some(group), _) => crate::tasks::cmd_library::set_group_excluding_command(os_type, remote_path, group, exclude),
+                (Field::Mode,  _, _, Some(mode))  => crate::tasks::cmd_library::set_mode_excluding_command(os_type, remote_path, mode, exclude),
+                _ => { continue; }
+            };
+            let cmd = self.unwrap_string_result(request, &get_cmd_result)?;
+            // find needs its wildcards and {}, everything in the command was screened by cmd_library
+            self.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        }
+        Ok(())
+    }
+
     pub fn process_all_common_file_attributes(&self, 
         request: &Arc<TaskRequest>, 
         remote_path: &str, 
@@ -491,3 +1189,194 @@ impl Remote {
 
 
 }
+
+pub fn get_checksum_cache_path(host_name: &str) -> Option<PathBuf> {
+    match expanduser("~/.jet/checksums") {
+        Ok(mut pb) => { pb.push(format!("{}.yml", host_name)); Some(pb) },
+        Err(_) => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle::handle::TaskHandle;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_get_sha512_many_with_missing_file() {
+        let checksum = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let out = format!("{}  /tmp/a\nsha512sum: '/tmp/b': No such file or directory\n{}  /tmp/c d", checksum, checksum);
+        let (handle, _) = TaskHandle::scripted(vec![("sha512sum", 1, &out)]);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let paths = vec![String::from("/tmp/a"), String::from("/tmp/b"), String::from("/tmp/c d")];
+        let results = handle.remote.get_sha512_many(&request, &paths).expect("checksums");
+        assert_eq!(results.get("/tmp/a").unwrap(), checksum);
+        assert_eq!(results.get("/tmp/b").unwrap(), "");
+        assert_eq!(results.get("/tmp/c d").unwrap(), checksum);
+    }
+
+    #[test]
+    fn test_quick_checksum_comes_from_the_cache_while_size_and_mtime_match() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%s %.9Y' '/tmp/a'", 0, "12 1700000000.000000000"),
+            ("stat --format '%s %.9Y' '/tmp/b'", 0, "13 1700000000.000000000"),
+            ("sha512sum", 0, "bbbb  /tmp/b"),
+        ]);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        {
+            // marking the cache loaded keeps the test away from the checksum file in the home directory
+            let mut host = handle.host.write().unwrap();
+            host.load_checksum_cache(HashMap::new());
+            host.set_checksum_cache("/tmp/a", 12, "1700000000.000000000", "aaaa");
+            host.set_checksum_cache("/tmp/b", 12, "1700000000.000000000", "aaaa");
+            host.take_checksum_cache_changes();
+        }
+
+        assert_eq!(handle.remote.get_sha512_quick(&request, &String::from("/tmp/a")).unwrap(), "aaaa");
+        assert!(!connection.lock().unwrap().get_commands().iter().any(|x| x.contains("sha512sum")));
+        assert!(handle.host.write().unwrap().take_checksum_cache_changes().is_none());
+
+        assert_eq!(handle.remote.get_sha512_quick(&request, &String::from("/tmp/b")).unwrap(), "bbbb");
+        assert!(connection.lock().unwrap().get_commands().iter().any(|x| x.contains("sha512sum '/tmp/b'")));
+        let changes = handle.host.write().unwrap().take_checksum_cache_changes().expect("changes");
+        assert_eq!(changes.get("/tmp/b").unwrap().checksum, "bbbb");
+    }
+
+    #[test]
+    fn test_owner_is_verified_and_numeric_ids_compare_with_uid() {
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "644"),
+            ("stat --format '%U %G'", 0, "root root"),
+            ("stat --format '%u %g'", 0, "1000 0"),
+            ("id -u 'deploi'", 1, "id: 'deploi': no such user"),
+            ("id -u '2000'", 1, "id: '2000': no such user"),
+        ]);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let attributes = |owner: &str, allow_unknown_ids: bool| Some(FileAttributesEvaluated {
+            owner: Some(owner.to_owned()), group: None, mode: None, preserve_mode: false, allow_unknown_ids
+        });
+
+        let mut changes : Vec<Field> = Vec::new();
+        let err = handle.remote.query_common_file_attributes(&request, "/tmp/a", &attributes("deploi", false), &mut changes, Recurse::No).unwrap_err();
+        assert_eq!(err.msg.as_deref(), Some("owner 'deploi' does not exist"));
+
+        let mut changes : Vec<Field> = Vec::new();
+        handle.remote.query_common_file_attributes(&request, "/tmp/a", &attributes("1000", false), &mut changes, Recurse::No).expect("query");
+        assert!(changes.is_empty());
+
+        assert!(handle.remote.query_common_file_attributes(&request, "/tmp/a", &attributes("2000", false), &mut Vec::new(), Recurse::No).is_err());
+        let mut changes : Vec<Field> = Vec::new();
+        handle.remote.query_common_file_attributes(&request, "/tmp/a", &attributes("2000", true), &mut changes, Recurse::No).expect("query");
+        assert_eq!(changes, vec![Field::Owner]);
+    }
+
+    #[test]
+    fn test_recursive_query_reports_only_nested_drift() {
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("id -u 'root'", 0, "0"),
+            ("! -user 'root'", 0, ""),
+            ("! -perm '755'", 0, "/srv/app/.git/config"),
+        ]);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let attributes = Some(FileAttributesEvaluated {
+            owner: Some(String::from("root")), group: None, mode: Some(String::from("755")), preserve_mode: false, allow_unknown_ids: false
+        });
+
+        let mut changes : Vec<Field> = Vec::new();
+        handle.remote.query_common_file_attributes(&request, "/srv/app", &attributes, &mut changes, Recurse::Yes).expect("query");
+        assert_eq!(changes, vec![Field::Mode]);
+    }
+
+    #[test]
+    fn test_replaced_file_keeps_security_attributes_and_new_file_gets_default_label() {
+        let dest = String::from("/etc/app.conf");
+        let run = |mode_rc: i32| {
+            let (handle, connection) = TaskHandle::scripted(vec![("stat --format '%a'", mode_rc, "644")]);
+            let request = TaskRequest::create(&SudoDetails::none(), false);
+            handle.remote.write_data(&request, "listen 80", &dest, |_| Ok(())).expect("write");
+            let commands = connection.lock().unwrap().get_commands();
+            commands
+        };
+
+        let commands = run(0);
+        let keep = commands.iter().position(|x| x.contains("chcon --reference")).expect("label is copied");
+        let rename = commands.iter().position(|x| x.contains("mv -f") && x.ends_with("'/etc/app.conf'")).expect("rename");
+        assert!(keep < rename);
+        assert!(commands[keep].contains("setfacl -M -"));
+        assert!(! commands.iter().any(|x| x.contains("restorecon")));
+
+        let commands = run(1);
+        let rename = commands.iter().position(|x| x.contains("mv -f") && x.ends_with("'/etc/app.conf'")).expect("rename");
+        let restore = commands.iter().position(|x| x.contains("restorecon")).expect("label is restored");
+        assert!(rename < restore);
+        assert!(! commands.iter().any(|x| x.contains("chcon")));
+    }
+
+    #[test]
+    fn test_rollback_puts_files_back_only_when_the_task_failed() {
+        let run = |enabled: bool, failed: bool| {
+            let (handle, connection) = TaskHandle::scripted(vec![
+                ("stat --format '%a' '/etc/new.conf'", 1, ""),
+                ("stat --format '%a'", 0, "644"),
+            ]);
+            let request = TaskRequest::create(&SudoDetails::none(), false);
+            if enabled {
+                handle.remote.enable_rollback();
+            }
+            handle.remote.write_data(&request, "listen 80", &String::from("/etc/app.conf"), |_| Ok(())).expect("write");
+            handle.remote.write_data(&request, "listen 81", &String::from("/etc/new.conf"), |_| Ok(())).expect("write");
+            let written = connection.lock().unwrap().get_commands().len();
+            let restored = handle.remote.finish_rollback(failed).expect("rollback");
+            let commands = connection.lock().unwrap().get_commands();
+            (restored, commands[..written].to_vec(), commands[written..].to_vec())
+        };
+
+        // the existing file is copied aside before it is replaced, the new one has nothing to copy
+        let (restored, during, after) = run(true, true);
+        let backups : Vec<&String> = during.iter().filter(|x| x.contains("jet-rollback")).collect();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].contains("'/etc/app.conf'"));
+        assert_eq!(restored, vec![String::from("/etc/new.conf"), String::from("/etc/app.conf")]);
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[0], "rm -f '/etc/new.conf'");
+        assert!(after[1].starts_with("mv -f '/etc/.app.conf.") && after[1].ends_with(".jet-rollback' '/etc/app.conf'"));
+
+        // when the task did not fail the copy is only cleaned up
+        let (restored, _during, after) = run(true, false);
+        assert!(restored.is_empty());
+        assert_eq!(after.len(), 1);
+        assert!(after[0].starts_with("rm -f '/etc/.app.conf.") && after[0].ends_with(".jet-rollback'"));
+
+        // and without rollback nothing is copied aside in the first place
+        let (restored, during, after) = run(false, true);
+        assert!(restored.is_empty());
+        assert!(! during.iter().any(|x| x.contains("jet-rollback")));
+        assert!(after.is_empty());
+    }
+
+    #[test]
+    fn test_sudo_password_is_sent_on_stdin_and_never_in_the_command() {
+        let (handle, connection) = TaskHandle::scripted(vec![("cat", 1, "sudo: s3cret is not a valid password")]);
+        handle.response.get_context().write().unwrap().sudo_password = Some(String::from("s3cret"));
+        let sudo_details = SudoDetails { user: Some(String::from("root")), template: String::from("/usr/bin/sudo -k -S -p '' -u '{{jet_sudo_user}}' {{jet_command}}") };
+        let request = TaskRequest::query(&sudo_details, false);
+
+        let result = handle.remote.run(&request, "cat /etc/shadow", CheckRc::Unchecked).expect("run");
+        let command_result = result.command_result.as_ref().as_ref().unwrap();
+        assert_eq!(command_result.cmd, "/usr/bin/sudo -k -S -p '' -u 'root' cat /etc/shadow");
+        assert_eq!(command_result.out, "sudo: ******** is not a valid password");
+
+        let err = handle.remote.run(&request, "cat /etc/shadow", CheckRc::Checked).unwrap_err();
+        assert!(! err.command_result.as_ref().as_ref().unwrap().out.contains("s3cret"));
+
+        // commands that do not sudo get nothing on stdin
+        handle.remote.run(&TaskRequest::query(&SudoDetails::none(), false), "true", CheckRc::Unchecked).expect("run");
+
+        let commands = connection.lock().unwrap().get_commands();
+        assert_eq!(commands.len(), 3);
+        assert!(! commands.iter().any(|x| x.contains("s3cret")));
+        assert_eq!(connection.lock().unwrap().get_inputs(), vec![String::from("s3cret\n"), String::from("s3cret\n")]);
+    }
+}
@@ -16,7 +16,7 @@
 
 use std::sync::Arc;
 use crate::tasks::request::{TaskRequest, TaskRequestType};
-use crate::tasks::response::{TaskStatus, TaskResponse};
+use crate::tasks::response::{TaskStatus, TaskResponse, ValueDiff};
 use crate::inventory::hosts::Host;
 use crate::playbooks::traversal::RunState;
 use crate::tasks::fields::Field;
@@ -52,14 +52,16 @@ impl Response {
         Arc::clone(&self.run_state.visitor)
     }
 
-    pub fn is_failed(&self, _request: &Arc<TaskRequest>,  msg: &str) -> Arc<TaskResponse> {
-        Arc::new(TaskResponse { 
+    pub fn is_failed(&self, request: &Arc<TaskRequest>,  msg: &str) -> Arc<TaskResponse> {
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::Failed, 
             changes: Vec::new(), 
             msg: Some(msg.to_owned()), 
             command_result: Arc::new(None), 
             with: Arc::new(None), 
-            and: Arc::new(None)
+            and: Arc::new(None),
+            diff: Vec::new()
         })
     }
 
@@ -69,34 +71,48 @@ impl Response {
         self.is_failed(request, &String::from("not supported"))
     }
 
-    pub fn command_failed(&self, _request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>) -> Arc<TaskResponse> {
+    pub fn command_failed(&self, request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>) -> Arc<TaskResponse> {
         // used internally by run functions in remote.rs when commands fail, suitable for use as a final module response
-        self.get_visitor().read().expect("read visitor").on_command_failed(&self.get_context(), &Arc::clone(&self.host), &Arc::clone(result));
+        self.get_visitor().read().expect("read visitor").on_command_failed(&self.get_context(), &Arc::clone(&self.host), request, &Arc::clone(result));
         Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::Failed,
             changes: Vec::new(), 
             msg: Some(String::from("command failed")), 
             command_result: Arc::clone(result), 
             with: Arc::new(None), 
-            and: Arc::new(None)
+            and: Arc::new(None),
+            diff: Vec::new()
         })
     }
 
-    pub fn command_ok(&self, _request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>) -> Arc<TaskResponse> {
+    pub fn command_ok(&self, request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>) -> Arc<TaskResponse> {
         // used internally by run functions in remote.rs when commands succeed, suitable for use as a final module response
-        self.get_visitor().read().expect("read visitor").on_command_ok(&self.get_context(), &Arc::clone(&self.host), &Arc::clone(result));
+        self.get_visitor().read().expect("read visitor").on_command_ok(&self.get_context(), &Arc::clone(&self.host), request, &Arc::clone(result));
         Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsExecuted,
-            changes: Vec::new(), msg: None, command_result: Arc::clone(result), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::clone(result), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
 
-    pub fn is_skipped(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
-        // returned by playbook traversal code when skipping over a task due to a condition not being met or other factors
+    pub fn command_ok_trimmed(&self, request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>) -> Arc<TaskResponse> {
+        // like command_ok for a result the module has cut down after the fact, the full result was already reported
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
+            status: TaskStatus::IsExecuted,
+            changes: Vec::new(), msg: None, command_result: Arc::clone(result), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
+        })
+    }
+
+    pub fn is_skipped(&self, request: &Arc<TaskRequest>, reason: &str) -> Arc<TaskResponse> {
+        // returned by playbook traversal code when skipping over a task due to a condition not being met or other factors,
+        // the reason is shown next to the skipped status
         assert!(request.request_type == TaskRequestType::Validate, "is_skipped response can only be returned for a validation request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsSkipped, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: Some(reason.to_owned()), command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
 
@@ -105,18 +121,20 @@ impl Response {
         // run to configure the remote
         assert!(request.request_type == TaskRequestType::Query || request.request_type == TaskRequestType::Validate,  
             "is_matched response can only be returned for a query request, was {:?}", request.request_type);
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsMatched, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
 
     pub fn is_created(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // the only successful result to return from a Create leg.
         assert!(request.request_type == TaskRequestType::Create, "is_executed response can only be returned for a creation request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsCreated, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
     
@@ -124,47 +142,68 @@ impl Response {
     pub fn is_executed(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // a valid response from an execute leg that is not command based or runs multiple commands
         assert!(request.request_type == TaskRequestType::Execute, "is_executed response can only be returned for a creation request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsExecuted, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
     
     pub fn is_removed(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // the only appropriate response from a removal request
         assert!(request.request_type == TaskRequestType::Remove, "is_removed response can only be returned for a remove request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsRemoved, 
             changes: Vec::new(), 
-            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
 
     pub fn is_passive(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // the only appropriate response from a passive module, for example, echo
         assert!(request.request_type == TaskRequestType::Passive || request.request_type == TaskRequestType::Execute, "is_passive response can only be returned for a passive or execute request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsPassive, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
     
     pub fn is_modified(&self, request: &Arc<TaskRequest>, changes: Vec<Field>) -> Arc<TaskResponse> {
         // the only appropriate response from a modification leg, note that changes must be passed in and should come from fields.rs
         assert!(request.request_type == TaskRequestType::Modify, "is_modified response can only be returned for a modification request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::IsModified, 
             changes, 
-            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
+        })
+    }
+
+    pub fn is_modified_with_diff(&self, request: &Arc<TaskRequest>, changes: Vec<Field>, diff: Vec<ValueDiff>) -> Arc<TaskResponse> {
+        // the same as is_modified, but also says what the changed values were before and after, for --diff.
+        // a no_log task still shows what changed, but not the values, which could be passwords or keys
+        assert!(request.request_type == TaskRequestType::Modify, "is_modified response can only be returned for a modification request");
+        let diff = match request.no_log {
+            true => diff.into_iter().map(|x| ValueDiff { name: x.name, before: String::from("(no_log)"), after: String::from("(no_log)") }).collect(),
+            false => diff
+        };
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
+            status: TaskStatus::IsModified, 
+            changes, 
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff
         })
     }
 
     pub fn needs_creation(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // a response from a query function that requests invocation of the create leg.
         assert!(request.request_type == TaskRequestType::Query, "needs_creation response can only be returned for a query request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::NeedsCreation, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), 
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
     
@@ -172,19 +211,21 @@ impl Response {
         // a response from a query function that requests invocation of the modify leg.
         assert!(request.request_type == TaskRequestType::Query, "needs_modification response can only be returned for a query request");
         assert!(!changes.is_empty(), "changes must not be empty");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::NeedsModification, 
             changes: changes.to_owned(), 
-            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None) 
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
     
     pub fn needs_removal(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // a response from a query function that requests invocation of the removal leg.
         assert!(request.request_type == TaskRequestType::Query, "needs_removal response can only be returned for a query request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::NeedsRemoval, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
 
@@ -192,18 +233,20 @@ impl Response {
         // a response from a query function that requests invocation of the execute leg.
         // modules that use 'execute' should generally not have legs for creation, removal, or modification
         assert!(request.request_type == TaskRequestType::Query, "needs_execution response can only be returned for a query request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::NeedsExecution, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None),and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None),and: Arc::new(None), diff: Vec::new()
         })
     }
     
     pub fn needs_passive(&self, request: &Arc<TaskRequest>) -> Arc<TaskResponse> {
         // this is the response that passive modules use to exit the query leg
         assert!(request.request_type == TaskRequestType::Query, "needs_passive response can only be returned for a query request");
-        Arc::new(TaskResponse { 
+        Arc::new(TaskResponse {
+            no_log: request.no_log,
             status: TaskStatus::NeedsPassive, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), diff: Vec::new()
         })
     }
 
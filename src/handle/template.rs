@@ -18,12 +18,15 @@ use std::sync::{Arc,RwLock};
 use std::path::PathBuf;
 use crate::tasks::request::TaskRequest;
 use crate::tasks::response::TaskResponse;
+use crate::tasks::files::SrcInput;
 use crate::inventory::hosts::Host;
 use crate::playbooks::traversal::RunState;
 use crate::playbooks::context::PlaybookContext;
-use crate::tasks::cmd_library::{screen_path,screen_general_input_strict};
+use crate::tasks::cmd_library::{screen_path,screen_general_input_strict,screen_trusted_input};
 use crate::handle::response::Response;
 use crate::playbooks::templar::{Templar,TemplateMode};
+use crate::util::io::{has_wildcards,wildcard_match};
+use std::collections::BTreeSet;
 
 // template contains support code for all variable evaluation in the playbook language, as well as
 // support for the template module, and ALSO the code to validate and process module arguments to make
@@ -60,7 +63,8 @@ pub struct Template {
     run_state: Arc<RunState>, 
     host: Arc<RwLock<Host>>, 
     response: Arc<Response>,
-    detached_templar: Templar
+    detached_templar: Templar,
+    trusted_fields: RwLock<Vec<String>>
 }
 
 impl Template {
@@ -74,7 +78,23 @@ impl Template {
             run_state,
             host,
             response,
-            detached_templar: Templar::new()
+            detached_templar: Templar::new(),
+            trusted_fields: RwLock::new(Vec::new())
+        }
+    }
+
+    // fields listed under 'trusted' in the task's 'with' block skip the strict character blocklist
+    // in favor of screen_trusted_input. This is set before the task is evaluated.
+
+    pub fn set_trusted_fields(&self, fields: &Option<Vec<String>>) {
+        let mut trusted = self.trusted_fields.write().unwrap();
+        *trusted = fields.clone().unwrap_or_default();
+    }
+
+    fn screen_field(&self, field: &String, value: &str) -> Result<String,String> {
+        match self.trusted_fields.read().unwrap().contains(field) {
+            true => screen_trusted_input(value),
+            false => screen_general_input_strict(value)
         }
     }
 
@@ -109,6 +129,12 @@ impl Template {
         self.template_unsafe_internal(request, tm, field, template, BlendTarget::TemplateModule)
     }
 
+    pub fn string_for_template_module_lenient(&self, request: &Arc<TaskRequest>, template: &str) -> Result<String,Arc<TaskResponse>> {
+        // as above, but undefined variables are left empty instead of being an error
+        let result = self.run_state.context.read().unwrap().render_template_lenient(template, &self.host, BlendTarget::TemplateModule);
+        self.unwrap_string_result(request, &result)
+    }
+
     pub fn string_unsafe_for_shell(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &str, template: &str) -> Result<String,Arc<TaskResponse>> {
         // indicates templating a string that will not without further processing, be passed to a shell command
         self.template_unsafe_internal(request, tm, field, template, BlendTarget::NotTemplateModule)
@@ -139,7 +165,7 @@ impl Template {
         // templates a required string parameter - the simplest of argument processing, this requires no casting to other types
         let result = self.string_unsafe_for_shell(request, tm, field, template);
         match result {
-            Ok(x) => match screen_general_input_strict(&x) {
+            Ok(x) => match self.screen_field(field, &x) {
                 Ok(y) => Ok(y),
                 Err(z) => { Err(self.response.is_failed(request, &format!("field {}, {}", field, z))) }
             },
@@ -212,7 +238,7 @@ impl Template {
         let result = self.string_option_unsafe(request, tm, field, template);
         match result {
             Ok(x1) => match x1 {
-                Some(x) => match screen_general_input_strict(&x) {
+                Some(x) => match self.screen_field(field, &x) {
                     Ok(y) => Ok(Some(y)),
                     Err(z) => { Err(self.response.is_failed(request, &format!("field {}, {}", field, z))) }
                 },
@@ -351,12 +377,6 @@ impl Template {
         }
     }
 
-    pub fn find_template_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
-        // templates a string and then looks for the resulting file in the logical templates/ locations (if not an absolute path)
-        // raises errors if the source files are not found
-        self.find_sub_path(&String::from("templates"), request, tm, field, str_path)
-    }
-
     pub fn find_module_path(&self, request: &Arc<TaskRequest>, _tm: TemplateMode, _field: &str, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
 
         // when we need to find a module we look for it in the configured module paths
@@ -374,38 +394,111 @@ impl Template {
        
     }
 
-    pub fn find_file_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
+    pub fn find_template_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, input: &SrcInput) -> Result<PathBuf, Arc<TaskResponse>> {
+        // templates a string, or each string in a list, and then looks for the first resulting file that exists in the logical
+        // templates/ locations (if not an absolute path). raises errors if none of the source files are found
+        let candidates = self.template_src_candidates(request, tm, field, input)?;
+        self.find_sub_path("templates", request, tm, field, &candidates, false)
+    }
+
+    pub fn find_file_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, input: &SrcInput) -> Result<PathBuf, Arc<TaskResponse>> {
         // simialr to find_template_path, this one assumes a 'files/' directory for relative paths.
-        self.find_sub_path(&String::from("files"), request, tm, field, str_path)
+        // a directory is also found, copy copies those recursively
+        let candidates = self.template_src_candidates(request, tm, field, input)?;
+        self.find_sub_path("files", request, tm, field, &candidates, true)
     }
 
-    fn find_sub_path(&self, prefix: &String, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
-        // supporting code for find_template_path and find_file_path
+    pub fn find_file_paths_matching(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, pattern: &str) -> Result<Vec<PathBuf>, Arc<TaskResponse>> {
+        // expands wildcards in the file name part of a src like files/*.conf against every files/ search path. each
+        // name found is then resolved the same way find_file_path would, so earlier search paths still win.
         if tm == TemplateMode::Off {
-            return Ok(PathBuf::new());
+            return Ok(Vec::new());
+        }
+        // the wildcards themselves would not pass screening, everything else in the pattern has to
+        let templated = self.string_unsafe_for_shell(request, tm, field, pattern)?;
+        if let Err(y) = screen_path(&templated.replace(['*', '?'], "")) {
+            return Err(self.response.is_failed(request, &format!("{}, for field: {}", y, field)));
         }
-        let prelim = match screen_path(str_path) {
-            Ok(x) => x, 
-            Err(y) => { return Err(self.response.is_failed(request, &format!("{}, for field: {}", y, field))) }
+        let pattern_path = PathBuf::from(&templated);
+        let name_pattern = match pattern_path.file_name() {
+            Some(x) => x.to_string_lossy().to_string(),
+            None => { return Err(self.response.is_failed(request, &format!("field ({}): no file name in pattern: {}", field, templated))); }
         };
-        let mut path = PathBuf::new();
-        path.push(prelim);
-        if path.is_absolute() {
-            if path.is_file() {
-                Ok(path)
-            } else {
-                Err(self.response.is_failed(request, &format!("field ({}): no such file: {}", field, str_path)))
+        let parent = pattern_path.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+        if has_wildcards(&parent.display().to_string()) {
+            return Err(self.response.is_failed(request, &format!("field ({}): wildcards are only allowed in the file name: {}", field, templated)));
+        }
+        let directories : Vec<PathBuf> = match pattern_path.is_absolute() {
+            true => vec![parent.clone()],
+            false => self.run_state.context.read().unwrap().get_search_paths("files").iter().map(|x| x.join(&parent)).collect()
+        };
+        let mut names : BTreeSet<String> = BTreeSet::new();
+        for directory in directories.iter() {
+            let entries = match std::fs::read_dir(directory) {
+                Ok(x) => x,
+                Err(_) => { continue; }
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_file() && wildcard_match(&name_pattern, &name) {
+                    names.insert(name);
+                }
+            }
+        }
+        let mut results : Vec<PathBuf> = Vec::new();
+        for name in names.iter() {
+            let candidate = parent.join(name).display().to_string();
+            results.push(self.find_sub_path("files", request, tm, field, &[candidate], false)?);
+        }
+        Ok(results)
+    }
+
+    fn template_src_candidates(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, input: &SrcInput) -> Result<Vec<String>, Arc<TaskResponse>> {
+        match input {
+            SrcInput::SrcString(x) => Ok(vec![self.string(request, tm, field, x)?]),
+            SrcInput::SrcList(x) => {
+                let mut results : Vec<String> = Vec::new();
+                for candidate in x.iter() {
+                    results.push(self.string(request, tm, field, candidate)?);
+                }
+                Ok(results)
             }
-        } else {
-            let mut path2 = PathBuf::new();
-            path2.push(prefix);
-            path2.push(str_path);
-            if path2.is_file() {
-                Ok(path2)
+        }
+    }
+
+    fn find_sub_path(&self, prefix: &str, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, candidates: &[String], directories: bool) -> Result<PathBuf, Arc<TaskResponse>> {
+        // supporting code for find_template_path and find_file_path
+        // absolute paths are used as is, relative ones are tried against each search path in order.
+        // when nothing is found, all the paths we tried are reported.
+        if tm == TemplateMode::Off {
+            return Ok(PathBuf::new());
+        }
+        let search_paths = self.run_state.context.read().unwrap().get_search_paths(prefix);
+        let mut searched : Vec<String> = Vec::new();
+        for str_path in candidates.iter() {
+            let prelim = match screen_path(str_path) {
+                Ok(x) => x, 
+                Err(y) => { return Err(self.response.is_failed(request, &format!("{}, for field: {}", y, field))) }
+            };
+            let mut path = PathBuf::new();
+            path.push(prelim);
+            let found = |x: &PathBuf| x.is_file() || (directories && x.is_dir());
+            if path.is_absolute() {
+                if found(&path) {
+                    return Ok(path);
+                }
+                searched.push(path.display().to_string());
             } else {
-                Err(self.response.is_failed(request, &format!("field ({}): no such file: {}", field, str_path)))
+                for search_path in search_paths.iter() {
+                    let path2 = search_path.join(&path);
+                    if found(&path2) {
+                        return Ok(path2);
+                    }
+                    searched.push(path2.display().to_string());
+                }
             }
         }
+        Err(self.response.is_failed(request, &format!("field ({}): no such file: {}, searched: {}", field, candidates.join(" or "), searched.join(", "))))
     }
 
     fn has_spaces(&self, input: &str) -> bool {
@@ -15,7 +15,7 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
-use crate::util::yaml::blend_variables;
+use crate::util::yaml::{blend_variables,ListMerge};
 use std::sync::Arc;
 use crate::inventory::hosts::Host;
 use std::sync::RwLock;
@@ -81,6 +81,10 @@ impl Group {
         self.get_ancestor_groups(10usize).keys().cloned().collect()
     }
 
+    pub fn get_ancestor_groups_by_depth(&self, depth_limit: usize) -> Vec<Arc<RwLock<Group>>> {
+        order_groups_by_depth(self.get_parent_groups(), depth_limit)
+    }
+
     pub fn get_descendant_groups(&self, depth_limit: usize) -> HashMap<String, Arc<RwLock<Group>>> {
 
         let mut results : HashMap<String, Arc<RwLock<Group>>> = HashMap::new();
@@ -183,15 +187,15 @@ impl Group {
         }
     }
 
-    pub fn get_blended_variables(&self) -> serde_yaml::Mapping {
+    pub fn get_blended_variables(&self, list_merge: ListMerge) -> serde_yaml::Mapping {
         let mut blended : serde_yaml::Value = serde_yaml::Value::from(serde_yaml::Mapping::new());
-        let ancestors = self.get_ancestor_groups(20);
-        for (_k,v) in ancestors.iter() {
+        let ancestors = self.get_ancestor_groups_by_depth(20);
+        for v in ancestors.iter() {
             let theirs : serde_yaml::Value = serde_yaml::Value::from(v.read().expect("group read").get_variables());
-            blend_variables(&mut blended, theirs);
+            blend_variables(&mut blended, theirs, list_merge);
         }
         let mine = serde_yaml::Value::from(self.get_variables());
-        blend_variables(&mut blended, mine);
+        blend_variables(&mut blended, mine, list_merge);
         match blended {
             serde_yaml::Value::Mapping(x) => x,
             _ => panic!("get_blended_variables produced a non-mapping (1)")
@@ -206,8 +210,8 @@ impl Group {
         }
     }
 
-    pub fn get_blended_variables_yaml(&self) -> Result<String,String> {
-        let result = serde_yaml::to_string(&self.get_blended_variables());
+    pub fn get_blended_variables_yaml(&self, list_merge: ListMerge) -> Result<String,String> {
+        let result = serde_yaml::to_string(&self.get_blended_variables(list_merge));
         match result {
             Ok(x) => Ok(x),
             Err(_y) => Err(String::from("error loading blended variables"))
@@ -217,10 +221,32 @@ impl Group {
 
 }
 
+// walks upward from a starting set of groups (depth 1) and returns every ancestor ordered from the most
+// distant to the nearest, so that blending in order lets a group override anything it is nested inside.
+// a group reachable by several paths counts at its longest distance, which keeps a parent ahead of all of
+// its children (and 'all' first), and ties are ordered by name so results are stable between runs.
 
+pub fn order_groups_by_depth(start: HashMap<String, Arc<RwLock<Group>>>, depth_limit: usize) -> Vec<Arc<RwLock<Group>>> {
+    let mut depths : HashMap<String, (usize, Arc<RwLock<Group>>)> = HashMap::new();
+    for (name, group) in start.into_iter() {
+        record_group_depth(&mut depths, name, group, 1, depth_limit);
+    }
+    let mut ordered : Vec<_> = depths.into_iter().collect();
+    ordered.sort_by(|a, b| b.1.0.cmp(&a.1.0).then_with(|| a.0.cmp(&b.0)));
+    ordered.into_iter().map(|(_name, (_depth, group))| group).collect()
+}
 
-
-
-
-
-
+fn record_group_depth(depths: &mut HashMap<String, (usize, Arc<RwLock<Group>>)>, name: String, group: Arc<RwLock<Group>>, depth: usize, depth_limit: usize) {
+    if let Some((existing, _)) = depths.get(&name) {
+        if *existing >= depth {
+            return;
+        }
+    }
+    let parents = group.read().expect("group read").get_parent_groups();
+    depths.insert(name, (depth, group));
+    if depth <= depth_limit {
+        for (parent_name, parent) in parents.into_iter() {
+            record_group_depth(depths, parent_name, parent, depth + 1, depth_limit);
+        }
+    }
+}
@@ -15,12 +15,13 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
-use crate::util::yaml::blend_variables;
+use crate::util::yaml::{blend_variables,ListMerge};
 use std::sync::Arc;
-use crate::inventory::groups::Group;
+use crate::inventory::groups::{Group,order_groups_by_depth};
 use std::sync::RwLock;
 use std::collections::HashSet;
 use serde_yaml;
+use serde::{Deserialize,Serialize};
 
 #[derive(Clone,Copy,Debug,PartialEq)]
 pub enum HostOSType {
@@ -36,16 +37,37 @@ pub enum PackagePreference {
     Yum,
 }
 
+impl PackagePreference {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "dnf" => Ok(PackagePreference::Dnf),
+            "yum" => Ok(PackagePreference::Yum),
+            _ => Err(format!("jet_package_manager: unknown package manager '{}', expected dnf or yum", name))
+        }
+    }
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize)]
+pub struct ChecksumCacheEntry {
+    pub size: u64,
+    pub mtime: String,
+    pub checksum: String
+}
+
 pub struct Host {
     pub name               : String,
     pub groups             : HashMap<String, Arc<RwLock<Group>>>,
     pub variables          : serde_yaml::Mapping,
     pub os_type            : Option<HostOSType>,
-    checksum_cache         : HashMap<String,String>,
-    checksum_cache_task_id : usize,
+    checksum_cache         : HashMap<String,ChecksumCacheEntry>,
+    checksum_cache_loaded  : bool,
+    checksum_cache_dirty   : bool,
     facts                  : serde_yaml::Value,
+    fact_overrides         : serde_yaml::Value,
     pub package_preference : Option<PackagePreference>,
-    notified_handlers      : HashMap<usize, HashSet<String>>
+    pub os_signature       : Option<String>,
+    notified_handlers      : HashMap<usize, HashSet<String>>,
+    saved_results          : HashMap<String, bool>
 }
 
 impl Host {
@@ -57,10 +79,14 @@ impl Host {
             groups: HashMap::new(),
             os_type: None,
             checksum_cache: HashMap::new(),
-            checksum_cache_task_id: 0,
+            checksum_cache_loaded: false,
+            checksum_cache_dirty: false,
             facts: serde_yaml::Value::from(serde_yaml::Mapping::new()),
+            fact_overrides: serde_yaml::Value::from(serde_yaml::Mapping::new()),
             notified_handlers: HashMap::new(),
-            package_preference: None
+            saved_results: HashMap::new(),
+            package_preference: None,
+            os_signature: None
         }
     }
 
@@ -70,6 +96,10 @@ impl Host {
         entry.insert(signal.to_owned());
     }
 
+    pub fn has_notifications(&self, play_number: usize) -> bool {
+        self.notified_handlers.get(&play_number).is_some_and(|x| ! x.is_empty())
+    }
+
     pub fn is_notified(&self, play_number: usize, signal: &str) -> bool {
         let entry = self.notified_handlers.get(&play_number);
         if let Some(e) = entry  {
@@ -79,21 +109,40 @@ impl Host {
         }
     }
 
-    pub fn set_checksum_cache(&mut self, path: &str, checksum: &str) {
-        self.checksum_cache.insert(path.to_owned(), checksum.to_owned());
+    // checksums are remembered along with the size and modification time the file had when it was hashed,
+    // and only handed back while both still match.  the entries are kept between runs, see get_sha512_quick
+
+    pub fn set_checksum_cache(&mut self, path: &str, size: u64, mtime: &str, checksum: &str) {
+        self.checksum_cache.insert(path.to_owned(), ChecksumCacheEntry { size, mtime: mtime.to_owned(), checksum: checksum.to_owned() });
+        self.checksum_cache_dirty = true;
+    }
+
+    pub fn is_checksum_cache_loaded(&self) -> bool {
+        self.checksum_cache_loaded
     }
 
-    pub fn get_checksum_cache(&mut self, task_id: usize, path: &String) -> Option<String> {
-        if task_id > self.checksum_cache_task_id {
-            self.checksum_cache_task_id = task_id;
-            self.checksum_cache.clear();
+    // entries from an earlier run, anything already hashed in this one is newer and kept
+
+    pub fn load_checksum_cache(&mut self, entries: HashMap<String,ChecksumCacheEntry>) {
+        for (path, entry) in entries.into_iter() {
+            self.checksum_cache.entry(path).or_insert(entry);
         }
-        if self.checksum_cache.contains_key(path) {
-            let result = self.checksum_cache.get(path).unwrap();
-            Some(result.clone())
+        self.checksum_cache_loaded = true;
+    }
+
+    // everything to save, if anything changed since the last time this was called
+
+    pub fn take_checksum_cache_changes(&mut self) -> Option<HashMap<String,ChecksumCacheEntry>> {
+        match std::mem::take(&mut self.checksum_cache_dirty) {
+            true => Some(self.checksum_cache.clone()),
+            false => None
         }
-        else {
-            None
+    }
+
+    pub fn get_checksum_cache(&self, path: &str, size: u64, mtime: &str) -> Option<String> {
+        match self.checksum_cache.get(path) {
+            Some(entry) if entry.size == size && entry.mtime == mtime => Some(entry.checksum.clone()),
+            _ => None
         }
     }
 
@@ -104,7 +153,45 @@ impl Host {
         else {
             return Err(format!("OS Type could not be detected from uname -a: {}", uname_output));
         }
-        Ok(())
+        self.os_signature = Some(uname_output.trim().to_string());
+        self.set_os_facts(uname_output);
+        self.set_package_preference_from_variables()
+    }
+
+    // jet_os_type and jet_arch are available to templates and conditions from the first connection,
+    // without running the facts module. the machine name is the last field of uname -a on macOS,
+    // and the last before the "GNU/Linux" operating system field on most Linux systems.
+    fn set_os_facts(&mut self, uname_output: &str) {
+        let mut mapping = serde_yaml::Mapping::new();
+        let os_name = match self.os_type {
+            Some(HostOSType::MacOS) => "MacOS",
+            _ => "Linux"
+        };
+        mapping.insert(serde_yaml::Value::from("jet_os_type"), serde_yaml::Value::from(os_name));
+        let tokens: Vec<&str> = uname_output.split_whitespace().collect();
+        let arch = match tokens.last() {
+            Some(x) if x.ends_with("Linux") && tokens.len() > 1 => tokens.get(tokens.len() - 2),
+            x => x
+        };
+        if let Some(arch) = arch {
+            mapping.insert(serde_yaml::Value::from("jet_arch"), serde_yaml::Value::from(*arch));
+        }
+        self.update_facts2(mapping);
+    }
+
+    // the yum/dnf modules guess which tool to use by looking for dnf first, the jet_package_manager
+    // variable (usually from inventory) skips the guess for hosts where that would be wrong
+    fn set_package_preference_from_variables(&mut self) -> Result<(),String> {
+        let key = serde_yaml::Value::from("jet_package_manager");
+        // only a string is read here, so the list merge strategy makes no difference
+        match self.get_blended_variables(ListMerge::Replace).get(&key) {
+            None => Ok(()),
+            Some(serde_yaml::Value::String(x)) => {
+                self.package_preference = Some(PackagePreference::from_name(x)?);
+                Ok(())
+            },
+            Some(x) => Err(format!("jet_package_manager: expected a string, got: {:?}", x))
+        }
     }
 
     // ==============================================================================================================
@@ -182,16 +269,31 @@ impl Host {
         }
     }
 
-    pub fn get_blended_variables(&self) -> serde_yaml::Mapping {
+    // what a task saves lands with the other host variables, the name is also remembered, along with whether the
+    // task set no_log, so debug can show everything saved so far
+
+    pub fn save_result(&mut self, key: &str, value: serde_yaml::Value, no_log: bool) {
+        self.variables.insert(serde_yaml::Value::String(key.to_owned()), value);
+        self.saved_results.insert(key.to_owned(), no_log);
+    }
+
+    pub fn get_saved_results(&self) -> Vec<(String, bool)> {
+        let mut results : Vec<(String, bool)> = self.saved_results.iter().map(|(k,v)| (k.clone(), *v)).collect();
+        results.sort();
+        results
+    }
+
+    pub fn get_blended_variables(&self, list_merge: ListMerge) -> serde_yaml::Mapping {
         let mut blended : serde_yaml::Value = serde_yaml::Value::from(serde_yaml::Mapping::new());
-        let ancestors = self.get_ancestor_groups(20);
-        for (_k,v) in ancestors.iter() {
+        let ancestors = order_groups_by_depth(self.get_groups(), 20);
+        for v in ancestors.iter() {
             let theirs : serde_yaml::Value = serde_yaml::Value::from(v.read().unwrap().get_variables());
-            blend_variables(&mut blended, theirs);
+            blend_variables(&mut blended, theirs, list_merge);
         }
         let mine = serde_yaml::Value::from(self.get_variables());
-        blend_variables(&mut blended, mine);
-        blend_variables(&mut blended, self.facts.clone());
+        blend_variables(&mut blended, mine, list_merge);
+        blend_variables(&mut blended, self.facts.clone(), list_merge);
+        blend_variables(&mut blended, self.fact_overrides.clone(), list_merge);
         match blended {
             serde_yaml::Value::Mapping(x) => x,
             _ => panic!("get_blended_variables produced a non-mapping (1)")
@@ -200,11 +302,26 @@ impl Host {
 
     pub fn update_facts(&mut self, mapping: &Arc<RwLock<serde_yaml::Mapping>>) {
         let map = mapping.read().unwrap().clone();
-        blend_variables(&mut self.facts, serde_yaml::Value::Mapping(map));
+        blend_variables(&mut self.facts, serde_yaml::Value::Mapping(map), ListMerge::Replace);
     }
 
     pub fn update_facts2(&mut self, mapping: serde_yaml::Mapping) {
-        blend_variables(&mut self.facts, serde_yaml::Value::Mapping(mapping));
+        blend_variables(&mut self.facts, serde_yaml::Value::Mapping(mapping), ListMerge::Replace);
+    }
+
+    // facts set by a task rather than gathered, these win over gathered facts even when the facts module runs again
+
+    pub fn override_facts(&mut self, mapping: serde_yaml::Mapping) {
+        blend_variables(&mut self.fact_overrides, serde_yaml::Value::Mapping(mapping), ListMerge::Replace);
+    }
+
+    // forgets gathered and overridden facts alike, only the ones known from connecting are put back
+    pub fn clear_facts(&mut self) {
+        self.facts = serde_yaml::Value::from(serde_yaml::Mapping::new());
+        self.fact_overrides = serde_yaml::Value::from(serde_yaml::Mapping::new());
+        if let Some(signature) = self.os_signature.clone() {
+            self.set_os_facts(&signature);
+        }
     }
 
     pub fn get_variables_yaml(&self) -> Result<String, String> {
@@ -215,8 +332,8 @@ impl Host {
         }
     }
 
-    pub fn get_blended_variables_yaml(&self) -> Result<String,String> {
-        let result = serde_yaml::to_string(&self.get_blended_variables());
+    pub fn get_blended_variables_yaml(&self, list_merge: ListMerge) -> Result<String,String> {
+        let result = serde_yaml::to_string(&self.get_blended_variables(list_merge));
         match result {
             Ok(x) => Ok(x),
             Err(_y) => Err(String::from("error loading blended variables"))
@@ -224,3 +341,109 @@ impl Host {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_manager_variable() {
+        let mut host = Host::new("h1");
+        host.variables.insert(serde_yaml::Value::from("jet_package_manager"), serde_yaml::Value::from("yum"));
+        assert!(host.set_os_info(&String::from("Linux h1 6.1.0 x86_64")).is_ok());
+        assert!(matches!(host.package_preference, Some(PackagePreference::Yum)));
+
+        host.variables.insert(serde_yaml::Value::from("jet_package_manager"), serde_yaml::Value::from("apt"));
+        assert!(host.set_os_info(&String::from("Linux h1 6.1.0 x86_64")).is_err());
+    }
+
+    #[test]
+    fn test_os_facts_from_uname() {
+        let mut host = Host::new("h1");
+        host.set_os_info(&String::from("Linux h1 6.1.0 #1 SMP PREEMPT_DYNAMIC Debian 6.1.55-1 aarch64 GNU/Linux")).unwrap();
+        let vars = host.get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("jet_os_type"), Some(&serde_yaml::Value::from("Linux")));
+        assert_eq!(vars.get("jet_arch"), Some(&serde_yaml::Value::from("aarch64")));
+
+        let mut host = Host::new("h2");
+        host.set_os_info(&String::from("Darwin h2 23.0.0 Darwin Kernel Version 23.0.0: Fri Sep 15 14:41:43 PDT 2023; root:xnu-10002.1.13~1/RELEASE_ARM64_T6000 arm64")).unwrap();
+        let vars = host.get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("jet_os_type"), Some(&serde_yaml::Value::from("MacOS")));
+        assert_eq!(vars.get("jet_arch"), Some(&serde_yaml::Value::from("arm64")));
+    }
+
+    #[test]
+    fn test_fact_overrides_until_cleared() {
+        let fact = |k: &str, v: &str| {
+            let mut m = serde_yaml::Mapping::new();
+            m.insert(serde_yaml::Value::from(k), serde_yaml::Value::from(v));
+            m
+        };
+        let mut host = Host::new("h1");
+        host.set_os_info(&String::from("Linux h1 6.1.0 x86_64")).unwrap();
+        host.update_facts2(fact("jet_hostname", "old"));
+        host.override_facts(fact("jet_hostname", "new"));
+        host.update_facts2(fact("jet_hostname", "gathered"));
+        assert_eq!(host.get_blended_variables(ListMerge::Replace).get("jet_hostname"), Some(&serde_yaml::Value::from("new")));
+
+        host.clear_facts();
+        let vars = host.get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("jet_hostname"), None);
+        assert_eq!(vars.get("jet_arch"), Some(&serde_yaml::Value::from("x86_64")));
+    }
+
+    #[test]
+    fn test_saved_results_remember_no_log() {
+        let mut host = Host::new("h1");
+        host.variables.insert(serde_yaml::Value::from("port"), serde_yaml::Value::from(80));
+        host.save_result("token", serde_yaml::Value::from("s3cret"), true);
+        host.save_result("out", serde_yaml::Value::from("first"), true);
+        host.save_result("out", serde_yaml::Value::from("second"), false);
+        assert_eq!(host.get_saved_results(), vec![(String::from("out"), false), (String::from("token"), true)]);
+        assert_eq!(host.get_variables().get("out"), Some(&serde_yaml::Value::from("second")));
+    }
+
+    #[test]
+    fn test_list_merge_strategy_is_passed_in() {
+        use crate::inventory::inventory::Inventory;
+        let mut inventory = Inventory::new();
+        inventory.store_host(&String::from("webservers"), &String::from("web1"));
+        inventory.store_group_variables(&String::from("webservers"), serde_yaml::from_str("packages: [ a, b ]").unwrap());
+        let host = inventory.get_host(&String::from("web1"));
+        host.write().unwrap().variables.insert(serde_yaml::Value::from("packages"), serde_yaml::from_str("[ c ]").unwrap());
+
+        let vars = host.read().unwrap().get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("packages"), Some(&serde_yaml::from_str("[ c ]").unwrap()));
+        let vars = host.read().unwrap().get_blended_variables(ListMerge::Append);
+        assert_eq!(vars.get("packages"), Some(&serde_yaml::from_str("[ a, b, c ]").unwrap()));
+    }
+
+    #[test]
+    fn test_child_group_variables_override_parents() {
+        use crate::inventory::inventory::Inventory;
+        let var = |v: &str| {
+            let mut m = serde_yaml::Mapping::new();
+            m.insert(serde_yaml::Value::from("tier"), serde_yaml::Value::from(v));
+            m
+        };
+        let mut inventory = Inventory::new();
+        inventory.store_subgroup(&String::from("production"), &String::from("webservers"));
+        inventory.store_host(&String::from("webservers"), &String::from("web1"));
+        inventory.store_group_variables(&String::from("all"), var("all"));
+        inventory.store_group_variables(&String::from("production"), var("production"));
+        inventory.store_group_variables(&String::from("webservers"), var("webservers"));
+
+        let host = inventory.get_host(&String::from("web1"));
+        assert!(! host.read().unwrap().get_group_names().contains(&String::from("production")));
+        assert!(host.read().unwrap().has_ancestor_group(&String::from("production")));
+        let vars = host.read().unwrap().get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("tier"), Some(&serde_yaml::Value::from("webservers")));
+
+        let group = inventory.get_group(&String::from("webservers"));
+        let vars = group.read().unwrap().get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("tier"), Some(&serde_yaml::Value::from("webservers")));
+        inventory.store_group_variables(&String::from("webservers"), serde_yaml::Mapping::new());
+        let vars = host.read().unwrap().get_blended_variables(ListMerge::Replace);
+        assert_eq!(vars.get("tier"), Some(&serde_yaml::Value::from("production")));
+    }
+}
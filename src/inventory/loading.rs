@@ -18,7 +18,7 @@ use std::path::{Path,PathBuf};
 use Vec;
 use serde::Deserialize;
 use crate::util::io::{path_walk,jet_file_open,path_basename_as_string,is_executable};
-use crate::util::yaml::show_yaml_error_in_context;
+use crate::util::yaml::{show_yaml_error_in_context,ListMerge};
 use crate::inventory::inventory::Inventory;
 use std::sync::Arc;
 use std::sync::RwLock;
@@ -27,6 +27,7 @@ use std::collections::HashMap;
 use std::process::Command;
 use crate::connection::local::convert_out;
 use crate::util::io::directory_as_string;
+use crate::inventory::schema::{VariableSchema,SCHEMA_FILE,load_schema_file,validate_inventory};
 
 // ==============================================================================================================
 // YAML SPEC
@@ -38,6 +39,7 @@ use crate::util::io::directory_as_string;
 #[serde(deny_unknown_fields)]
 pub struct YamlGroup {
     hosts     : Option<Vec<String>>,
+    #[serde(alias = "children")]
     subgroups : Option<Vec<String>>,
 }
 
@@ -62,15 +64,25 @@ pub struct DynamicInventoryJsonEntry {
 // PUBLIC API
 // ==============================================================================================================
 
-pub fn load_inventory(inventory: &Arc<RwLock<Inventory>>, inventory_paths: Arc<RwLock<Vec<PathBuf>>>) -> Result<(), String> {
+pub fn load_inventory(inventory: &Arc<RwLock<Inventory>>, inventory_paths: Arc<RwLock<Vec<PathBuf>>>, list_merge: ListMerge) -> Result<(), String> {
 
     {
         let mut inv_obj = inventory.write().unwrap();
         inv_obj.store_group(&String::from("all"));
     }
 
+    let mut schema = VariableSchema::new();
+
     for inventory_path_buf in inventory_paths.read().unwrap().iter() {
         let inventory_path = inventory_path_buf.as_path();
+        let schema_dir = match inventory_path.is_dir() {
+            true => inventory_path.to_path_buf(),
+            false => PathBuf::from(directory_as_string(inventory_path))
+        };
+        let schema_path = schema_dir.join(SCHEMA_FILE);
+        if schema_path.is_file() {
+            schema.extend(load_schema_file(&schema_path)?);
+        }
         if inventory_path.is_dir() {
             let groups_pathbuf = inventory_path_buf.join("groups");
             let groups_path = groups_pathbuf.as_path();
@@ -89,6 +101,9 @@ pub fn load_inventory(inventory: &Arc<RwLock<Inventory>>, inventory_paths: Arc<R
             return Err(format!("non-directory path to --inventory ({}) is not executable", inventory_path.display()))
         }
     }
+    if ! schema.is_empty() {
+        validate_inventory(inventory, &schema, list_merge)?;
+    }
     Ok(())
 }
 
@@ -17,5 +17,6 @@
 pub mod groups;
 pub mod hosts;
 pub mod loading;
+pub mod schema;
 #[allow(clippy::module_inception)] // FIXME
 pub mod inventory;
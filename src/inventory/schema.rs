@@ -0,0 +1,168 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::{Arc,RwLock};
+use crate::inventory::inventory::Inventory;
+use crate::util::io::jet_file_open;
+use crate::util::yaml::{show_yaml_error_in_context,ListMerge};
+
+// an inventory directory may have a vars_schema.yml beside groups/ describing the variables hosts
+// are expected to have, for example:
+//
+//   port: { type: integer, min: 1, max: 65535, required: true }
+//   env:  { type: string, choices: [ staging, prod ] }
+//
+// every host's blended inventory variables (group_vars and host_vars) are checked once the inventory is
+// loaded, so a bad value stops the run before anything is connected to.  min and max bound integers and
+// floats, and the length of strings and lists.  variables not in the schema are not checked.
+
+pub const SCHEMA_FILE: &str = "vars_schema.yml";
+
+#[derive(Deserialize,Debug,Copy,Clone,PartialEq)]
+#[serde(rename_all="lowercase")]
+pub enum VariableType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    List,
+    Mapping
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VariableSpec {
+    #[serde(rename = "type")]
+    pub var_type: Option<VariableType>,
+    pub required: Option<bool>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub choices: Option<Vec<serde_yaml::Value>>,
+}
+
+pub type VariableSchema = indexmap::IndexMap<String, VariableSpec>;
+
+pub fn load_schema_file(path: &Path) -> Result<VariableSchema, String> {
+    let file = jet_file_open(path)?;
+    let parsed: Result<VariableSchema, serde_yaml::Error> = serde_yaml::from_reader(file);
+    match parsed {
+        Ok(x) => Ok(x),
+        Err(e) => {
+            show_yaml_error_in_context(&e, path);
+            Err("edit the file and try again?".to_string())
+        }
+    }
+}
+
+pub fn validate_inventory(inventory: &Arc<RwLock<Inventory>>, schema: &VariableSchema, list_merge: ListMerge) -> Result<(), String> {
+    let inv = inventory.read().unwrap();
+    let mut names : Vec<&String> = inv.hosts.keys().collect();
+    names.sort();
+    let mut errors : Vec<String> = Vec::new();
+    for name in names.into_iter() {
+        let vars = inv.hosts.get(name).unwrap().read().unwrap().get_blended_variables(list_merge);
+        for (var, spec) in schema.iter() {
+            if let Err(msg) = check_variable(spec, vars.get(var.as_str())) {
+                errors.push(format!("host {}: {}: {}", name, var, msg));
+            }
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(format!("inventory variables do not match {}:\n{}", SCHEMA_FILE, errors.join("\n")))
+    }
+}
+
+pub fn check_variable(spec: &VariableSpec, value: Option<&serde_yaml::Value>) -> Result<(), String> {
+    let value = match value {
+        Some(x) => x,
+        None => return match spec.required.unwrap_or(false) {
+            true => Err(String::from("is required but not set")),
+            false => Ok(())
+        }
+    };
+    if let Some(var_type) = spec.var_type {
+        let matches = match var_type {
+            VariableType::String  => value.is_string(),
+            VariableType::Integer => value.is_i64() || value.is_u64(),
+            VariableType::Float   => value.is_number(),
+            VariableType::Boolean => value.is_bool(),
+            VariableType::List    => value.is_sequence(),
+            VariableType::Mapping => value.is_mapping(),
+        };
+        if ! matches {
+            return Err(format!("expected {}, got {}", format!("{:?}", var_type).to_lowercase(), describe(value)));
+        }
+    }
+    if let Some(choices) = &spec.choices {
+        if ! choices.contains(value) {
+            let allowed : Vec<String> = choices.iter().map(describe).collect();
+            return Err(format!("{} is not one of: {}", describe(value), allowed.join(", ")));
+        }
+    }
+    let measure = match value {
+        serde_yaml::Value::Number(x) => x.as_f64(),
+        serde_yaml::Value::String(x) => Some(x.chars().count() as f64),
+        serde_yaml::Value::Sequence(x) => Some(x.len() as f64),
+        _ => None
+    };
+    if let Some(measure) = measure {
+        let what = match value.is_number() {
+            true => "value",
+            false => "length"
+        };
+        if let Some(min) = spec.min {
+            if measure < min { return Err(format!("{} {} is below the minimum of {}", what, measure, min)); }
+        }
+        if let Some(max) = spec.max {
+            if measure > max { return Err(format!("{} {} is above the maximum of {}", what, measure, max)); }
+        }
+    }
+    Ok(())
+}
+
+fn describe(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::Null        => String::from("null"),
+        serde_yaml::Value::Sequence(_) => String::from("a list"),
+        serde_yaml::Value::Mapping(_)  => String::from("a mapping"),
+        serde_yaml::Value::Tagged(_)   => String::from("a tagged value"),
+        _ => serde_yaml::to_string(value).map(|x| x.trim_end().to_string()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_variable() {
+        let schema : VariableSchema = serde_yaml::from_str("
+port: { type: integer, min: 1, max: 65535, required: true }
+env:  { type: string, choices: [ staging, prod ] }
+").unwrap();
+        let port = schema.get("port").unwrap();
+        let env = schema.get("env").unwrap();
+        assert!(check_variable(port, Some(&serde_yaml::Value::from(8080))).is_ok());
+        assert_eq!(check_variable(port, Some(&serde_yaml::Value::from(70000))), Err(String::from("value 70000 is above the maximum of 65535")));
+        assert_eq!(check_variable(port, Some(&serde_yaml::Value::from("80"))), Err(String::from("expected integer, got '80'")));
+        assert_eq!(check_variable(port, None), Err(String::from("is required but not set")));
+        assert!(check_variable(env, None).is_ok());
+        assert_eq!(check_variable(env, Some(&serde_yaml::Value::from("dev"))), Err(String::from("dev is not one of: staging, prod")));
+    }
+}
@@ -29,6 +29,7 @@ use crate::inventory::inventory::Inventory;
 use crate::inventory::loading::load_inventory;
 use crate::cli::show::{show_inventory_group,show_inventory_host};
 use crate::cli::parser::CliParser;
+use crate::util::terminal::init_colors;
 use crate::cli::playbooks::{playbook_ssh,playbook_local,playbook_check_ssh,playbook_check_local,playbook_simulate}; // FIXME: check modes coming
 use std::sync::{Arc,RwLock};
 use std::process;
@@ -41,6 +42,7 @@ fn liftoff() -> Result<(),String> {
 
     let mut cli_parser = CliParser::new();
     cli_parser.parse()?;
+    init_colors(cli_parser.color_mode);
 
     // jetp --help was given, or no arguments
     if cli_parser.needs_help {
@@ -56,7 +58,7 @@ fn liftoff() -> Result<(),String> {
 
     match cli_parser.mode {
         cli::parser::CLI_MODE_SSH | cli::parser::CLI_MODE_CHECK_SSH | cli::parser::CLI_MODE_SHOW | cli::parser::CLI_MODE_SIMULATE => {
-            load_inventory(&inventory, Arc::clone(&cli_parser.inventory_paths))?;
+            load_inventory(&inventory, Arc::clone(&cli_parser.inventory_paths), cli_parser.list_merge)?;
             if ! cli_parser.inventory_set {
                 return Err(String::from("--inventory is required"));
             }
@@ -109,13 +111,13 @@ pub fn handle_show(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser) -> Re
     // jetp show -i inventory --groups g1:g2
     // jetp show -i inventory --hosts h1:h2
     if parser.show_groups.is_empty() && parser.show_hosts.is_empty() {
-        show_inventory_group(inventory, &String::from("all"))?;
+        show_inventory_group(inventory, &String::from("all"), parser.list_merge)?;
     }
     for group_name in parser.show_groups.iter() {
-        show_inventory_group(inventory, &group_name.clone())?;
+        show_inventory_group(inventory, &group_name.clone(), parser.list_merge)?;
     }
     for host_name in parser.show_hosts.iter() {
-        show_inventory_host(inventory, &host_name.clone())?;
+        show_inventory_host(inventory, &host_name.clone(), parser.list_merge)?;
     }
     Ok(())
 }
@@ -39,6 +39,7 @@ pub struct GroupTask {
     pub and:            Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct GroupAction {
     pub group:          String,
     pub gid:            Option<u64>,
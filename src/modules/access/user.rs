@@ -45,6 +45,7 @@ pub struct UserTask {
     pub and:               Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct UserAction {
     pub user:              String,
     pub uid:               Option<u64>,
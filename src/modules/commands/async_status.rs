@@ -0,0 +1,206 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::connection::command::{cmd_info,CommandResult};
+use crate::tasks::cmd_library::{get_async_status_command,get_async_output_command,get_async_cleanup_command};
+use crate::inventory::hosts::Host;
+use serde::Deserialize;
+use std::sync::{Arc,RwLock};
+
+const MODULE: &str = "async_status";
+
+// checks on a job started by !shell with async: true.  a job that is still running fails the task,
+// so waiting for it is done with retry and delay in the and section:
+//
+//   - !async_status
+//     job: "{{ migration.job }}"
+//     save: result
+//     and: { retry: 120, delay: 60 }
+//
+// once the job is done, save gets rc and out like shell would, and a non-zero rc fails the task.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AsyncStatusTask {
+    pub name: Option<String>,
+    pub job: String,
+    pub save: Option<String>,
+    pub cleanup: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+struct AsyncStatusAction {
+    pub job: String,
+    pub save: Option<String>,
+    pub cleanup: bool,
+}
+
+impl IsTask for AsyncStatusTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(AsyncStatusAction {
+                    job:     handle.template.string_no_spaces(request, tm, &String::from("job"), &self.job)?,
+                    save:    handle.template.string_option_no_spaces(request, tm, &String::from("save"), &self.save)?,
+                    cleanup: handle.template.boolean_option_default_true(request, tm, &String::from("cleanup"), &self.cleanup)?,
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+
+}
+
+impl IsAction for AsyncStatusAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            // in check mode the job was never started, so this is skipped along with the shell task
+            TaskRequestType::Query => {
+                Ok(handle.response.needs_execution(request))
+            },
+
+            TaskRequestType::Execute => {
+                let os_type = handle.remote.get_os_type();
+                let cmd = self.unwrap(handle, request, get_async_status_command(os_type, &self.job))?;
+                let (status, job_rc) = cmd_info(&handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?);
+                match status {
+                    0 => {},
+                    2 => { return Err(handle.response.is_failed(request, &format!("no async job {} was started on this host", self.job))); },
+                    3 => { return Err(handle.response.is_failed(request, &format!("async job {} is still running", self.job))); },
+                    4 => { return Err(handle.response.is_failed(request, &format!("async job {} died without finishing, the process is gone and left no rc", self.job))); },
+                    x => { return Err(handle.response.is_failed(request, &format!("unable to check on async job {}, rc: {}", self.job, x))); }
+                }
+                let rc = match job_rc.trim().parse::<i32>() {
+                    Ok(x) => x,
+                    Err(_) => { return Err(handle.response.is_failed(request, &format!("async job {} left an unreadable rc: {}", self.job, job_rc))); }
+                };
+                let cmd = self.unwrap(handle, request, get_async_output_command(os_type, &self.job))?;
+                let (_rc, out) = cmd_info(&handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?);
+                // a failed job keeps its files, so a retry sees the same result rather than a missing job
+                if self.cleanup && rc == 0 {
+                    let cmd = self.unwrap(handle, request, get_async_cleanup_command(os_type, &self.job))?;
+                    handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+                }
+
+                if let Some(save) = self.save.as_ref() {
+                    save_results(&handle.host, request, save, &self.job, rc, &out);
+                }
+                let result = Arc::new(Some(CommandResult { cmd: format!("async job {}", self.job), out, rc }));
+                match rc {
+                    0 => Ok(handle.response.command_ok_trimmed(request, &result)),
+                    _ => Err(handle.response.command_failed(request, &result))
+                }
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+impl AsyncStatusAction {
+
+    fn unwrap(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, cmd: Result<String,String>) -> Result<String, Arc<TaskResponse>> {
+        match cmd {
+            Ok(x) => Ok(x),
+            Err(y) => Err(handle.response.is_failed(request, &y))
+        }
+    }
+
+}
+
+fn save_results(host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, key: &str, job: &str, rc: i32, out: &str) {
+    let mut map_data = serde_yaml::Mapping::new();
+    map_data.insert(serde_yaml::Value::String(String::from("job")), serde_yaml::Value::String(job.to_owned()));
+    map_data.insert(serde_yaml::Value::String(String::from("rc")), serde_yaml::Value::from(rc));
+    map_data.insert(serde_yaml::Value::String(String::from("out")), serde_yaml::Value::String(out.to_owned()));
+    host.write().unwrap().save_result(key, serde_yaml::Value::Mapping(map_data), request.no_log);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+    use crate::tasks::response::TaskStatus;
+    use crate::inventory::hosts::HostOSType;
+    use crate::tasks::cmd_library::get_async_start_command;
+
+    const JOB: &str = "0A1B-2C3D";
+
+    fn run(script: Vec<(&str, i32, &str)>) -> (Result<Arc<TaskResponse>, Arc<TaskResponse>>, Vec<String>) {
+        let sudo_details = SudoDetails::none();
+        let (handle, connection) = TaskHandle::scripted(script);
+        let action = AsyncStatusAction { job: String::from(JOB), save: None, cleanup: true };
+        let result = action.dispatch(&handle, &TaskRequest::execute(&sudo_details, false));
+        let commands = connection.lock().unwrap().get_commands();
+        (result, commands)
+    }
+
+    fn failure(result: Result<Arc<TaskResponse>, Arc<TaskResponse>>) -> String {
+        result.expect_err("should fail").msg.clone().unwrap()
+    }
+
+    #[test]
+    fn test_start_command_runs_whole_under_one_shell() {
+        let cmd = get_async_start_command(HostOSType::Linux, JOB, "echo 'hi'").unwrap();
+        // one sh -c, so a sudo prefix covers the mkdir, the nohup and the pid file alike
+        assert!(cmd.starts_with("sh -c 'mkdir -p ~/.jet/async && chmod 0700 ~/.jet/async && { nohup sh -c "));
+        assert!(cmd.ends_with("echo $! > ~/.jet/async/0A1B-2C3D.pid; }'"));
+        assert!(get_async_start_command(HostOSType::Linux, "x; reboot", "true").is_err());
+        assert!(get_async_status_command(HostOSType::Linux, "../x").is_err());
+    }
+
+    #[test]
+    fn test_finished_job_collects_output_and_cleans_up() {
+        let (result, commands) = run(vec![(".rc", 0, "0\n"), (".out", 0, "migrated")]);
+        let response = result.unwrap();
+        assert_eq!(response.status, TaskStatus::IsExecuted);
+        assert_eq!(response.command_result.as_ref().as_ref().unwrap().out, "migrated");
+        assert_eq!(commands.len(), 3);
+        assert!(commands[0].contains("kill -0"));
+        assert_eq!(commands[1], get_async_output_command(HostOSType::Linux, JOB).unwrap());
+        assert_eq!(commands[2], get_async_cleanup_command(HostOSType::Linux, JOB).unwrap());
+
+        // a failed job keeps its files for the next retry
+        let (result, commands) = run(vec![(".rc", 0, "3\n"), (".out", 0, "boom")]);
+        assert_eq!(result.expect_err("rc 3").command_result.as_ref().as_ref().unwrap().rc, 3);
+        assert_eq!(commands.len(), 2);
+    }
+
+    #[test]
+    fn test_running_missing_and_dead_jobs_are_told_apart() {
+        assert!(failure(run(vec![("kill -0", 2, "")]).0).contains("no async job 0A1B-2C3D was started"));
+        assert!(failure(run(vec![("kill -0", 3, "")]).0).contains("is still running"));
+        let (result, commands) = run(vec![("kill -0", 4, "")]);
+        assert!(failure(result).contains("died without finishing"));
+        // nothing is cleaned up or read for a job that never finished
+        assert_eq!(commands.len(), 1);
+    }
+}
@@ -37,6 +37,7 @@ pub struct ExternalTask {
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>,
 }
+#[derive(Debug)]
 struct ExternalAction {
     pub use_module: PathBuf,
     pub params: String,
@@ -142,8 +143,8 @@ impl IsAction for ExternalAction {
                     }
                 };
 
-                if self.save.is_some() {
-                    save_results(&handle.host, self.save.as_ref().unwrap(), map_data);
+                if let Some(save) = &self.save {
+                    save_results(&handle.host, request, save, map_data);
                 }
 
                 match should_fail {
@@ -183,9 +184,7 @@ fn build_results_map(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, rc: i
     Ok(result)
 }
 
-fn save_results(host: &Arc<RwLock<Host>>, key: &str, map_data: serde_yaml::Mapping) {
-    let mut result = serde_yaml::Mapping::new();
-    result.insert(serde_yaml::Value::String(key.to_owned()), serde_yaml::Value::Mapping(map_data.clone()));
-    host.write().unwrap().update_variables(result);
+fn save_results(host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, key: &str, map_data: serde_yaml::Mapping) {
+    host.write().unwrap().save_result(key, serde_yaml::Value::Mapping(map_data), request.no_log);
 }
 
@@ -17,5 +17,7 @@
 #[allow(clippy::empty_line_after_doc_comments)]
 /** ADD MODULES HERE, KEEP ALPHABETIZED **/
 
+pub mod async_status;
 pub mod external;
+pub mod reboot;
 pub mod shell;
@@ -0,0 +1,169 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::connection::command::{cmd_info,Forward};
+use crate::tasks::cmd_library::{get_boot_id_command,get_reboot_command};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration,Instant};
+
+const MODULE: &str = "reboot";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RebootTask {
+    pub name: Option<String>,
+    pub timeout: Option<String>,
+    pub delay: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+struct RebootAction {
+    pub timeout: u64,
+    pub delay: u64,
+}
+
+impl IsTask for RebootTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(RebootAction {
+                    timeout: handle.template.integer_option_to_integer(request, tm, &String::from("timeout"), &self.timeout, 600)?,
+                    delay:   handle.template.integer_option_to_integer(request, tm, &String::from("delay"), &self.delay, 5)?,
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+}
+
+impl IsAction for RebootAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                Ok(handle.response.needs_execution(request))
+            },
+
+            TaskRequestType::Execute => {
+                if handle.host.read().unwrap().name.eq("localhost") {
+                    return Err(handle.response.is_failed(request, "refusing to reboot the control machine"));
+                }
+                let os_type = handle.remote.get_os_type();
+                let boot_id_cmd = self.unwrap(handle, request, get_boot_id_command(os_type))?;
+                let before = cmd_info(&handle.remote.run(request, &boot_id_cmd, CheckRc::Checked)?).1;
+
+                let reboot_cmd = self.unwrap(handle, request, get_reboot_command(os_type))?;
+                handle.remote.run_unsafe(request, &reboot_cmd, CheckRc::Checked)?;
+
+                // the old connection is dead once the host goes down, forget it so the next connect is a fresh one
+                let context = handle.run_state.context.clone();
+                context.read().unwrap().connection_cache.write().unwrap().remove_connection(&handle.host);
+
+                // the last wait is cut short so that there is always one more try right at the deadline
+                let deadline = Instant::now() + Duration::from_secs(self.timeout);
+                loop {
+                    if self.has_rebooted(handle, request, &boot_id_cmd, &before)? {
+                        return Ok(handle.response.is_executed(request));
+                    }
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(handle.response.is_failed(request, &format!("host did not come back within {} seconds of the reboot", self.timeout)));
+                    }
+                    std::thread::sleep(Duration::from_secs(self.delay).min(deadline - now));
+                }
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+
+    }
+
+}
+
+impl RebootAction {
+
+    // true once a fresh connection reports a different boot id.  connecting before the host actually went
+    // down, or to a host that is still coming up, is not an error, it just means trying again later
+
+    fn has_rebooted(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, boot_id_cmd: &String, before: &String) -> Result<bool, Arc<TaskResponse>> {
+        let context = handle.run_state.context.clone();
+        let connection = match handle.run_state.connection_factory.read().unwrap().get_connection(&context, &handle.host) {
+            Ok(x) => x,
+            Err(_) => { return Ok(false); }
+        };
+        let cmd = self.unwrap(handle, request, handle.template.add_sudo_details(request, boot_id_cmd))?;
+        let timeout = handle.remote.get_timeout();
+        let run_result = match handle.remote.get_sudo_input(request) {
+            Some(input) => connection.lock().unwrap().run_command_with_input(&handle.response, request, &cmd, Forward::No, timeout, None, &input, &mut |_line| {}),
+            None => connection.lock().unwrap().run_command(&handle.response, request, &cmd, Forward::No, timeout, None)
+        };
+        let after = match run_result {
+            Ok(x) => cmd_info(&x),
+            Err(_) => (1, String::new())
+        };
+        if after.0 == 0 && ! after.1.eq(before) {
+            return Ok(true);
+        }
+        context.read().unwrap().connection_cache.write().unwrap().remove_connection(&handle.host);
+        Ok(false)
+    }
+
+    fn unwrap(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, cmd: Result<String,String>) -> Result<String, Arc<TaskResponse>> {
+        match cmd {
+            Ok(x) => Ok(x),
+            Err(y) => Err(handle.response.is_failed(request, &y))
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_wait_tries_at_once_and_stops_at_the_deadline() {
+        let action = RebootAction { timeout: 1, delay: 30 };
+        let execute = TaskRequest::execute(&SudoDetails::none(), false);
+
+        // the simulated connections all report the same boot id, so the host never seems to come back
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let started = Instant::now();
+        assert!(action.dispatch(&handle, &execute).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // a new boot id on the first try is taken without waiting out the delay
+        let (handle, _) = TaskHandle::scripted(vec![("boot_id", 0, "before")]);
+        let started = Instant::now();
+        assert!(action.dispatch(&handle, &execute).is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -16,34 +16,111 @@
 
 use crate::tasks::*;
 use crate::handle::handle::TaskHandle;
-use crate::connection::command::cmd_info;
+use crate::connection::command::{cmd_info,CommandResult,TIMEOUT_RC,timeout_message};
+use crate::tasks::cmd_library::{get_async_start_command,get_chdir_wrapped_command,get_environment_wrapped_command,get_output_file_wrapped_command,get_timeout_wrapped_command,is_environment_name,screen_general_input_loose};
 use serde::Deserialize;
 use std::sync::{Arc,RwLock};
+use std::collections::HashMap;
 use crate::inventory::hosts::Host;
 
 const MODULE: &str = "Shell";
 
+// commands that are better written with a module, which can tell when nothing needs to change.
+// only the first word of the command is looked at, see builtin_suggestion
+
+const SUGGESTIONS: &[(&str, &str)] = &[
+    ("apt",        "!apt"),
+    ("apt-get",    "!apt"),
+    ("cd",         "chdir or a full path, the working directory does not carry over to other tasks"),
+    ("chgrp",      "attributes on !file or !directory"),
+    ("chmod",      "attributes on !file or !directory"),
+    ("chown",      "attributes on !file or !directory"),
+    ("cp",         "!copy"),
+    ("dnf",        "!dnf"),
+    ("git",        "!git"),
+    ("groupadd",   "!group"),
+    ("mkdir",      "!directory"),
+    ("pacman",     "!pacman"),
+    ("rm",         "!file or !directory with remove: true"),
+    ("launchctl",  "!service"),
+    ("service",    "!service"),
+    ("systemctl",  "!service"),
+    ("touch",      "!file"),
+    ("useradd",    "!user"),
+    ("usermod",    "!user"),
+    ("yum",        "!yum"),
+    ("zypper",     "!zypper"),
+];
+
 #[derive(Deserialize,Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ShellTask {
     pub name: Option<String>,
     pub cmd: String,
+    pub chdir: Option<String>,
+    pub environment: Option<HashMap<String,String>>,
+    pub timeout: Option<String>,
     pub save: Option<String>, 
-    pub failed_when: Option<String>, 
+    pub failed_when: Option<FailedWhenInput>, 
     pub changed_when: Option<String>, 
+    pub creates: Option<String>,
+    pub removes: Option<String>,
     #[serde(rename = "unsafe")]
     pub unsafe_: Option<String>, /* FIXME: can use r#unsafe instead */
+    pub warn: Option<String>,
+    pub stream: Option<String>,
+    pub max_output: Option<String>,
+    pub output_file: Option<String>,
+    #[serde(rename = "async")]
+    pub async_: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>,
 }
+#[derive(Debug)]
 struct ShellAction {
     pub cmd: String,
+    pub chdir: Option<String>,
+    // on top of the play's environment, which wraps every command from the outside
+    pub environment: Vec<(String,String)>,
+    // enforced on the host, unlike the timeout in 'with' which only gives up on the connection
+    pub timeout: Option<u64>,
     pub save: Option<String>, 
-    pub failed_when: Option<String>,
+    pub failed_when: Option<FailedWhen>,
     pub changed_when: Option<String>,
+    pub creates: Option<String>,
+    pub removes: Option<String>,
     pub unsafe_: bool,
+    pub stream: bool,
+    // cut down by the connection as the output is read, so all of it is never in memory here
+    pub max_output: Option<usize>,
+    // on the managed host, so hosts running at once each write their own
+    pub output_file: Option<String>,
+    pub async_: bool,
+}
+
+
+// failed_when is either a condition, or for the common case of looking for a string in the output,
+// failed_when: { contains: "ERROR", stream: stderr }
+
+#[derive(Deserialize,Debug)]
+#[serde(untagged)]
+pub enum FailedWhenInput {
+    Condition(String),
+    Match(OutputMatchInput),
+}
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OutputMatchInput {
+    pub contains: String,
+    pub stream: Option<String>,
 }
 
+#[derive(Debug,PartialEq)]
+pub enum FailedWhen {
+    Condition(String),
+    Contains { text: String, stream: String },
+}
 
 impl IsTask for ShellTask {
 
@@ -52,6 +129,12 @@ impl IsTask for ShellTask {
     fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
 
     fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        // booleans always read false with templating off, so this only happens on the real evaluation
+        if tm == TemplateMode::Strict && handle.template.boolean_option_default_true(request, tm, &String::from("warn"), &self.warn)? {
+            if let Some(suggestion) = builtin_suggestion(&self.cmd) {
+                handle.warning(request, &format!("consider using {} instead of running this with {}, set warn: false to silence this", suggestion, MODULE.to_lowercase()));
+            }
+        }
         Ok(
             EvaluatedTask {
                 action: Arc::new(ShellAction {
@@ -65,9 +148,37 @@ impl IsTask for ShellTask {
                         }
                     },
                     cmd:  handle.template.string_unsafe_for_shell(request, tm, &String::from("cmd"), &self.cmd)?,
+                    chdir: match &self.chdir {
+                        Some(x) => Some(handle.template.path(request, tm, &String::from("chdir"), x)?),
+                        None => None
+                    },
+                    environment: template_environment(handle, request, tm, &self.environment)?,
+                    timeout: match handle.template.integer_option(request, tm, &String::from("timeout"), &self.timeout, None)? {
+                        Some(0) => { return Err(handle.response.is_failed(request, "field timeout: must be at least one second")); },
+                        x => x
+                    },
                     save: handle.template.string_option_no_spaces(request, tm, &String::from("save"), &self.save)?,
-                    failed_when: handle.template.string_option_unsafe_for_shell(request, tm, &String::from("failed_when"), &self.failed_when)?,
+                    failed_when: template_failed_when(handle, request, tm, &self.failed_when)?,
                     changed_when: handle.template.string_option_unsafe_for_shell(request, tm, &String::from("changed_when"), &self.changed_when)?,
+                    creates: match &self.creates {
+                        Some(x) => Some(handle.template.path(request, tm, &String::from("creates"), x)?),
+                        None => None
+                    },
+                    removes: match &self.removes {
+                        Some(x) => Some(handle.template.path(request, tm, &String::from("removes"), x)?),
+                        None => None
+                    },
+                    // off by default, lines from several hosts running at once would be interleaved
+                    stream: handle.template.boolean_option_default_false(request, tm, &String::from("stream"), &self.stream)?,
+                    max_output: handle.template.integer_option(request, tm, &String::from("max_output"), &self.max_output, None)?.map(|x| x as usize),
+                    output_file: handle.template.string_option_no_spaces(request, tm, &String::from("output_file"), &self.output_file)?,
+                    async_: {
+                        let async_ = handle.template.boolean_option_default_false(request, tm, &String::from("async"), &self.async_)?;
+                        if async_ && (self.failed_when.is_some() || self.changed_when.is_some() || self.stream.is_some() || self.max_output.is_some() || self.output_file.is_some()) {
+                            return Err(handle.response.is_failed(request, "with async, the result is only known to async_status: failed_when, changed_when, stream, max_output and output_file cannot be used"));
+                        }
+                        async_
+                    },
 
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
@@ -85,24 +196,69 @@ impl IsAction for ShellAction {
         match request.request_type {
 
             TaskRequestType::Query => {
+                // creates and removes are checked before anything runs, so a task they stop is matched and does not
+                // save results. otherwise changed_when and failed_when only reinterpret the result after it runs
+                if let Some(path) = &self.creates {
+                    if handle.remote.get_mode(request, path)?.is_some() {
+                        return Ok(handle.response.is_matched(request));
+                    }
+                }
+                if let Some(path) = &self.removes {
+                    if handle.remote.get_mode(request, path)?.is_none() {
+                        return Ok(handle.response.is_matched(request));
+                    }
+                }
+                if let Some(dir) = &self.chdir {
+                    if handle.remote.get_mode(request, dir)?.is_none() || ! handle.remote.get_is_directory(request, dir)? {
+                        return Err(handle.response.is_failed(request, &format!("chdir: {} is not a directory", dir)));
+                    }
+                }
                 Ok(handle.response.needs_execution(request))
             },
 
+            TaskRequestType::Execute if self.async_ => {
+                self.start_async(handle, request)
+            },
+
             TaskRequestType::Execute => {
-                let task_result: Arc<TaskResponse> = if self.unsafe_ {
-                    handle.remote.run_unsafe(request, &self.cmd.clone(), CheckRc::Unchecked)?
-                } else {
-                    handle.remote.run(request, &self.cmd.clone(), CheckRc::Unchecked)?
+                let cmd = self.get_command(handle, request)?;
+                if let Some(seconds) = self.timeout {
+                    // a backstop in case the command ignores the signals, it fires after timeout's own KILL
+                    if handle.remote.get_timeout().is_none_or(|x| x > seconds + 10) {
+                        handle.remote.set_timeout(Some(seconds + 10));
+                    }
+                }
+                handle.remote.set_max_output(self.max_output);
+                // the wrappers need shell characters, the command inside them was screened by get_command
+                let task_result: Arc<TaskResponse> = match (self.unsafe_ || self.is_wrapped(), self.stream) {
+                    (true, false)  => handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?,
+                    (false, false) => handle.remote.run(request, &cmd, CheckRc::Unchecked)?,
+                    (true, true)   => handle.remote.run_unsafe_streaming(request, &cmd, CheckRc::Unchecked)?,
+                    (false, true)  => handle.remote.run_streaming(request, &cmd, CheckRc::Unchecked)?
                 };
                 let (rc, out) = cmd_info(&task_result);
-                let map_data = build_results_map(rc, &out);
+                if let Some(seconds) = self.timeout.filter(|_| rc == TIMEOUT_RC) {
+                    let cmd = task_result.command_result.as_ref().as_ref().unwrap().cmd.clone();
+                    return Err(handle.response.command_failed(request, &Arc::new(Some(CommandResult { cmd, out: timeout_message(seconds), rc }))));
+                }
+                // output that was cut always comes back longer than max_output, because of the note in the middle.
+                // everything after this point, including what is saved and shown on failure, sees the shortened output
+                let truncated = self.max_output.is_some_and(|max| out.len() > max);
+                let task_result = match truncated {
+                    true => {
+                        let cmd = task_result.command_result.as_ref().as_ref().unwrap().cmd.clone();
+                        handle.response.command_ok_trimmed(request, &Arc::new(Some(CommandResult { cmd, out: out.clone(), rc })))
+                    },
+                    false => task_result
+                };
+                let map_data = build_results_map(rc, &out, truncated);
 
-                let should_fail = match self.failed_when.is_none() {
-                    true => !matches!(rc, 0),
-                    false => {
-                        let condition = self.failed_when.as_ref().unwrap();
+                let should_fail = match self.failed_when.as_ref() {
+                    None => !matches!(rc, 0),
+                    Some(FailedWhen::Condition(condition)) => {
                         handle.template.test_condition_with_extra_data(request, TemplateMode::Strict, condition, &handle.host, map_data.clone())?
-                    }
+                    },
+                    Some(FailedWhen::Contains { text, stream }) => output_contains(&map_data, stream, text)
                 };
 
                 let should_mark_changed = match self.changed_when.is_none() {
@@ -113,8 +269,8 @@ impl IsAction for ShellAction {
                     }
                 };
 
-                if self.save.is_some() {
-                    save_results(&handle.host, self.save.as_ref().unwrap(), map_data);
+                if let Some(save) = &self.save {
+                    save_results(&handle.host, request, save, map_data);
                 }
 
                 match should_fail {
@@ -134,18 +290,270 @@ impl IsAction for ShellAction {
 
 }
 
-fn build_results_map(rc: i32, out: &str) -> serde_yaml::Mapping {
+impl ShellAction {
+
+    fn is_wrapped(&self) -> bool {
+        self.chdir.is_some() || ! self.environment.is_empty() || self.timeout.is_some() || self.output_file.is_some()
+    }
+
+    fn get_command(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<String, Arc<TaskResponse>> {
+        if ! self.is_wrapped() {
+            return Ok(self.cmd.clone());
+        }
+        if ! self.unsafe_ {
+            if let Err(msg) = screen_general_input_loose(&self.cmd) {
+                return Err(handle.response.is_failed(request, &msg));
+            }
+        }
+        let os_type = handle.remote.get_os_type();
+        let mut cmd = self.cmd.clone();
+        if let Some(dir) = &self.chdir {
+            cmd = handle.remote.unwrap_string_result(request, &get_chdir_wrapped_command(os_type, dir, &cmd))?;
+        }
+        if let Some(seconds) = self.timeout {
+            cmd = handle.remote.unwrap_string_result(request, &get_timeout_wrapped_command(os_type, seconds, &cmd))?;
+        }
+        if let Some(path) = &self.output_file {
+            cmd = handle.remote.unwrap_string_result(request, &get_output_file_wrapped_command(os_type, path, &cmd))?;
+        }
+        if ! self.environment.is_empty() {
+            cmd = handle.remote.unwrap_string_result(request, &get_environment_wrapped_command(os_type, &self.environment, &cmd))?;
+        }
+        Ok(cmd)
+    }
+
+    // with async the command is left running on the host, detached from the connection, and the task
+    // only saves the job id.  a later !async_status with that job collects rc and out once it is done
+
+    fn start_async(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        if ! self.unsafe_ {
+            // the wrapper around the command needs the shell characters that run() would refuse
+            if let Err(msg) = screen_general_input_loose(&self.cmd) {
+                return Err(handle.response.is_failed(request, &msg));
+            }
+        }
+        let job = handle.run_state.context.read().unwrap().get_guid();
+        let cmd = match get_async_start_command(handle.remote.get_os_type(), &job, &self.get_command(handle, request)?) {
+            Ok(x) => x,
+            Err(msg) => { return Err(handle.response.is_failed(request, &msg)); }
+        };
+        let task_result = handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        if let Some(save) = self.save.as_ref() {
+            let mut map_data = serde_yaml::Mapping::new();
+            map_data.insert(serde_yaml::Value::String(String::from("job")), serde_yaml::Value::String(job));
+            save_results(&handle.host, request, save, map_data);
+        }
+        Ok(task_result)
+    }
+
+}
+
+// sorted so the command is the same on every run
+
+fn template_environment(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<HashMap<String,String>>) -> Result<Vec<(String,String)>, Arc<TaskResponse>> {
+    let mut environment : Vec<(String,String)> = Vec::new();
+    if let Some(input) = input {
+        for (k, v) in input.iter() {
+            if ! is_environment_name(k) {
+                return Err(handle.response.is_failed(request, &format!("field environment: invalid environment variable name: {}", k)));
+            }
+            environment.push((k.clone(), handle.template.string_unsafe_for_shell(request, tm, &format!("environment.{}", k), v)?));
+        }
+    }
+    environment.sort();
+    Ok(environment)
+}
+
+fn template_failed_when(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<FailedWhenInput>) -> Result<Option<FailedWhen>, Arc<TaskResponse>> {
+    let field = String::from("failed_when");
+    match input {
+        None => Ok(None),
+        Some(FailedWhenInput::Condition(condition)) => {
+            Ok(Some(FailedWhen::Condition(handle.template.string_unsafe_for_shell(request, tm, &field, condition)?)))
+        },
+        Some(FailedWhenInput::Match(m)) => {
+            let stream = handle.template.string_option_default(request, tm, &field, &m.stream, "out")?;
+            match stream.as_str() {
+                "out" | "stdout" | "stderr" => {},
+                _ if tm == TemplateMode::Off => {},
+                _ => { return Err(handle.response.is_failed(request, &format!("field {}: stream must be one of out, stdout, or stderr, got: {}", field, stream))); }
+            }
+            Ok(Some(FailedWhen::Contains {
+                text: handle.template.string_unsafe_for_shell(request, tm, &field, &m.contains)?,
+                stream
+            }))
+        }
+    }
+}
+
+// commands are run with stderr sent to stdout, so unless the results carry a separate entry for the stream
+// asked for, the combined output is searched
+
+fn output_contains(map_data: &serde_yaml::Mapping, stream: &str, text: &str) -> bool {
+    let value = map_data.get(serde_yaml::Value::String(stream.to_owned()))
+        .or_else(|| map_data.get(serde_yaml::Value::String(String::from("out"))));
+    match value {
+        Some(serde_yaml::Value::String(x)) => x.contains(text),
+        _ => false
+    }
+}
+
+fn builtin_suggestion(cmd: &str) -> Option<&'static str> {
+    // skip over a leading sudo or env assignments so they don't hide the real command
+    let first = cmd.split_whitespace().find(|x| *x != "sudo" && ! x.contains('='))?;
+    let program = first.rsplit('/').next().unwrap_or(first);
+    SUGGESTIONS.iter().find(|(name, _)| *name == program).map(|(_, suggestion)| *suggestion)
+}
+
+fn build_results_map(rc: i32, out: &str, truncated: bool) -> serde_yaml::Mapping {
     let mut result = serde_yaml::Mapping::new();
     let num : serde_yaml::Value = serde_yaml::from_str(&format!("{}", rc)).unwrap();
     result.insert(serde_yaml::Value::String(String::from("rc")), num);
     //result.insert(serde_yaml::Value::String(String::from("rc")),  serde_yaml::Value::String(format!("{}", rc)));
 
     result.insert(serde_yaml::Value::String(String::from("out")), serde_yaml::Value::String(out.to_owned()));
+    result.insert(serde_yaml::Value::String(String::from("truncated")), serde_yaml::Value::Bool(truncated));
     result
 }
 
-fn save_results(host: &Arc<RwLock<Host>>, key: &str, map_data: serde_yaml::Mapping) {
-    let mut result = serde_yaml::Mapping::new();
-    result.insert(serde_yaml::Value::String(key.to_owned()), serde_yaml::Value::Mapping(map_data.clone()));
-    host.write().unwrap().update_variables(result);
+fn save_results(host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, key: &str, map_data: serde_yaml::Mapping) {
+    host.write().unwrap().save_result(key, serde_yaml::Value::Mapping(map_data), request.no_log);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+    use crate::tasks::response::TaskStatus;
+
+    #[test]
+    fn test_creates_with_changed_when_and_failed_when() {
+        let sudo_details = SudoDetails::none();
+        let action = |cmd: &str| ShellAction {
+            cmd: cmd.to_owned(), chdir: None, environment: Vec::new(), timeout: None, save: Some(String::from("migration")),
+            failed_when: Some(FailedWhen::Contains { text: String::from("ERROR"), stream: String::from("out") }),
+            changed_when: Some(String::from("(contains out \"applied\")")),
+            creates: Some(String::from("/srv/app/.migrated")), removes: None,
+            unsafe_: true, stream: false, max_output: None, output_file: None, async_: false
+        };
+
+        // the marker is missing, so the command runs and only output mentioning "applied" counts as a change
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a' '/srv/app/.migrated'", 1, "stat: cannot statx '/srv/app/.migrated': No such file or directory"),
+            ("migrate && touch", 0, "3 migrations applied"),
+            ("migrate --noop", 0, "nothing to do"),
+            ("migrate --broken", 0, "ERROR: lock held"),
+        ]);
+        let query = TaskRequest::query(&sudo_details, false);
+        let execute = TaskRequest::execute(&sudo_details, false);
+        let ran = action("migrate && touch /srv/app/.migrated");
+        assert_eq!(ran.dispatch(&handle, &query).unwrap().status, TaskStatus::NeedsExecution);
+        assert_eq!(ran.dispatch(&handle, &execute).unwrap().status, TaskStatus::IsExecuted);
+        assert_eq!(action("migrate --noop").dispatch(&handle, &execute).unwrap().status, TaskStatus::IsPassive);
+        assert!(action("migrate --broken").dispatch(&handle, &execute).is_err());
+        assert!(connection.lock().unwrap().get_commands().iter().any(|x| x.starts_with("migrate && touch")));
+
+        // once the marker exists the command does not run at all, whatever its output would have been
+        let (handle, connection) = TaskHandle::scripted(vec![("stat --format '%a' '/srv/app/.migrated'", 0, "644")]);
+        assert_eq!(ran.dispatch(&handle, &query).unwrap().status, TaskStatus::IsMatched);
+        assert!(! connection.lock().unwrap().get_commands().iter().any(|x| x.starts_with("migrate")));
+    }
+
+    #[test]
+    fn test_max_output_is_cut_by_the_connection_and_output_file_is_kept_on_the_host() {
+        let sudo_details = SudoDetails::none();
+        let directory = std::env::temp_dir().join(format!("jetp-output-file-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("build.log").to_string_lossy().to_string();
+        let action = ShellAction {
+            cmd: String::from("make"), chdir: None, environment: Vec::new(), timeout: None, save: None, failed_when: None, changed_when: None,
+            creates: None, removes: None, unsafe_: false, stream: false, max_output: Some(8), output_file: Some(path.clone()), async_: false
+        };
+
+        let (handle, connection) = TaskHandle::scripted(vec![("make", 0, "aaaa-middle-zzzz")]);
+        let result = action.dispatch(&handle, &TaskRequest::execute(&sudo_details, false)).unwrap();
+        assert_eq!(cmd_info(&result), (0, String::from("aaaa\n... 8 bytes truncated ...\nzzzz")));
+        let commands = connection.lock().unwrap().get_commands();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].contains(&format!("tee '\\''{}'\\''", path)));
+
+        // the wrapper keeps the command's rc and leaves everything it printed in the file
+        let cmd = get_output_file_wrapped_command(crate::inventory::hosts::HostOSType::Linux, &path, "echo one; echo two >&2; exit 3").unwrap();
+        let output = std::process::Command::new("sh").arg("-c").arg(&cmd).output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_chdir_is_checked_and_wraps_the_screened_command() {
+        let sudo_details = SudoDetails::none();
+        let action = |cmd: &str, unsafe_: bool| ShellAction {
+            cmd: cmd.to_owned(), chdir: Some(String::from("/srv/app")), environment: Vec::new(), timeout: None, save: None, failed_when: None, changed_when: None,
+            creates: None, removes: None, unsafe_, stream: false, max_output: None, output_file: None, async_: false
+        };
+
+        let (handle, _) = TaskHandle::scripted(vec![("stat --format '%a' '/srv/app'", 1, "")]);
+        assert!(action("make", true).dispatch(&handle, &TaskRequest::query(&sudo_details, false)).is_err());
+
+        let (handle, connection) = TaskHandle::scripted(vec![]);
+        let execute = TaskRequest::execute(&sudo_details, false);
+        assert!(action("make install", false).dispatch(&handle, &execute).is_ok());
+        // the wrapper would otherwise let shell characters through that run() refuses
+        assert!(action("make; reboot", false).dispatch(&handle, &execute).is_err());
+        let commands = connection.lock().unwrap().get_commands();
+        assert_eq!(commands, vec![String::from("sh -c 'cd '\\''/srv/app'\\'' && ( make install\n)'")]);
+    }
+
+    #[test]
+    fn test_environment_wraps_the_command() {
+        let sudo_details = SudoDetails::none();
+        let action = ShellAction {
+            cmd: String::from("rake db:migrate"), chdir: None, save: None, failed_when: None, changed_when: None,
+            environment: vec![(String::from("RAILS_ENV"), String::from("production")), (String::from("SECRET"), String::from("a$b"))], timeout: None,
+            creates: None, removes: None, unsafe_: false, stream: false, max_output: None, output_file: None, async_: false
+        };
+        let (handle, connection) = TaskHandle::scripted(vec![]);
+        assert!(action.dispatch(&handle, &TaskRequest::execute(&sudo_details, false)).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert_eq!(commands, vec![String::from("env RAILS_ENV='production' SECRET='a$b' sh -c 'rake db:migrate'")]);
+    }
+
+    #[test]
+    fn test_timeout_is_enforced_on_the_host() {
+        let sudo_details = SudoDetails::none();
+        let action = ShellAction {
+            cmd: String::from("sleep 60"), chdir: None, environment: Vec::new(), timeout: Some(30), save: None, failed_when: None,
+            changed_when: None, creates: None, removes: None, unsafe_: false, stream: false, max_output: None, output_file: None, async_: false
+        };
+        let (handle, connection) = TaskHandle::scripted(vec![("timeout -k 5 30", TIMEOUT_RC, "")]);
+        let response = action.dispatch(&handle, &TaskRequest::execute(&sudo_details, false)).expect_err("timed out");
+        assert_eq!(response.command_result.as_ref().as_ref().unwrap().out, timeout_message(30));
+        assert_eq!(handle.remote.get_timeout(), Some(40));
+        let commands = connection.lock().unwrap().get_commands();
+        assert_eq!(commands, vec![String::from("timeout -k 5 30 sh -c 'sleep 60'")]);
+    }
+
+    #[test]
+    fn test_failed_when_contains_looks_at_the_output_instead_of_rc() {
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("deploy --quiet", 0, "ERROR: disk full"),
+            ("deploy --retry", 1, "retrying later"),
+        ]);
+        let evaluate = |yaml: &str| {
+            let task : ShellTask = serde_yaml::from_str(yaml).unwrap();
+            task.evaluate(&handle, &TaskRequest::validate(), TemplateMode::Strict)
+        };
+
+        // stream defaults to the combined output, which is also searched for stderr since it is merged in
+        let quiet = evaluate("cmd: deploy --quiet\nfailed_when: { contains: ERROR }").expect("evaluate");
+        let retry = evaluate("cmd: deploy --retry\nfailed_when: { contains: ERROR, stream: stderr }").expect("evaluate");
+        let execute = TaskRequest::execute(&SudoDetails::none(), false);
+        assert!(quiet.action.dispatch(&handle, &execute).is_err());
+        assert_eq!(retry.action.dispatch(&handle, &execute).unwrap().status, TaskStatus::IsExecuted);
+
+        let bad = evaluate("cmd: deploy\nfailed_when: { contains: ERROR, stream: stdin }").err().expect("bad stream");
+        assert_eq!(bad.msg.as_deref(), Some("field failed_when: stream must be one of out, stdout, or stderr, got: stdin"));
+    }
 }
@@ -0,0 +1,152 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const MODULE: &str = "add_host";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AddHostTask {
+    pub name: Option<String>,
+    pub host: String,
+    pub groups: Option<Vec<String>>,
+    pub vars: Option<serde_yaml::Mapping>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+struct AddHostAction {
+    pub host: String,
+    pub groups: Vec<String>,
+    pub vars: Option<serde_yaml::Mapping>,
+}
+
+impl IsTask for AddHostTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let mut groups : Vec<String> = Vec::new();
+        for group in self.groups.clone().unwrap_or_default().iter() {
+            groups.push(handle.template.string_no_spaces(request, tm, &String::from("groups"), group)?);
+        }
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(AddHostAction {
+                    host:   handle.template.string_no_spaces(request, tm, &String::from("host"), &self.host)?,
+                    groups,
+                    vars:   self.vars.clone() /* templated when the host is added, as in set */
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+
+}
+
+impl IsAction for AddHostAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                Ok(handle.response.needs_passive(request))
+            },
+
+            // the host joins the live inventory, so plays after this one can select it through its groups.
+            // the play that adds it has already picked its hosts and does not. connection settings such as
+            // jet_ssh_hostname and jet_ssh_port go in vars and are read when the host is first connected to.
+            // adding a host that already exists adds it to any new groups and updates its variables.
+
+            TaskRequestType::Passive => {
+                let mut mapping = serde_yaml::Mapping::new();
+                for (k,v) in self.vars.clone().unwrap_or_default().iter() {
+                    match v.as_str() {
+                        Some(vs) => {
+                            let ks = k.as_str().unwrap_or("vars").to_string();
+                            let templated = handle.template.string_unsafe_for_shell(request, TemplateMode::Strict, &ks, vs)?;
+                            mapping.insert(k.clone(), serde_yaml::Value::String(templated));
+                        },
+                        None => { mapping.insert(k.clone(), v.clone()); }
+                    }
+                }
+
+                let mut inventory = handle.run_state.inventory.write().unwrap();
+                if ! inventory.has_host(&self.host) {
+                    inventory.create_host(&self.host);
+                }
+                let groups = match self.groups.is_empty() {
+                    true => vec![String::from("all")],
+                    false => self.groups.clone()
+                };
+                for group in groups.iter() {
+                    inventory.store_host(group, &self.host);
+                }
+                inventory.get_host(&self.host).write().unwrap().update_variables(mapping);
+                Ok(handle.response.is_passive(request))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+    use crate::tasks::response::TaskStatus;
+
+    #[test]
+    fn test_adds_the_host_to_the_inventory_and_updates_it_when_added_again() {
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let mut vars = serde_yaml::Mapping::new();
+        vars.insert(serde_yaml::Value::from("port"), serde_yaml::Value::from("2222"));
+        handle.host.write().unwrap().update_variables(vars);
+        let passive = TaskRequest::passive(&SudoDetails::none(), false);
+        let add = |yaml: &str| {
+            let task : AddHostTask = serde_yaml::from_str(yaml).unwrap();
+            let evaluated = task.evaluate(&handle, &TaskRequest::validate(), TemplateMode::Strict).unwrap_or_else(|e| panic!("evaluate: {:?}", e.msg));
+            evaluated.action.dispatch(&handle, &passive).expect("dispatch")
+        };
+
+        // vars are templated against the host running the task
+        assert_eq!(add("host: web9\ngroups: [ web ]\nvars: { jet_ssh_port: '{{ port }}', tier: front }").status, TaskStatus::IsPassive);
+        assert_eq!(add("host: web9\ngroups: [ canary ]\nvars: { tier: back }").status, TaskStatus::IsPassive);
+        assert_eq!(add("host: db1").status, TaskStatus::IsPassive);
+
+        let inventory = handle.run_state.inventory.read().unwrap();
+        let web9 = inventory.get_host(&String::from("web9"));
+        let mut groups = web9.read().unwrap().get_group_names();
+        groups.sort();
+        assert_eq!(groups, vec![String::from("canary"), String::from("web")]);
+        let variables = web9.read().unwrap().get_variables();
+        assert_eq!(variables.get("jet_ssh_port"), Some(&serde_yaml::Value::from("2222")));
+        assert_eq!(variables.get("tier"), Some(&serde_yaml::Value::from("back")));
+        assert_eq!(inventory.get_group(&String::from("all")).read().unwrap().get_direct_host_names(), vec![String::from("db1")]);
+    }
+}
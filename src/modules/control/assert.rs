@@ -36,6 +36,7 @@ pub struct AssertTask {
 }
 
 #[allow(dead_code)]
+#[derive(Debug)]
 struct AssertAction {
     pub name: String,
     pub msg: Option<String>,
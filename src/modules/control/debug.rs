@@ -27,14 +27,17 @@ const MODULE: &str = "debug";
 pub struct DebugTask {
     pub name: Option<String>,
     pub vars: Option<Vec<String>>,
+    pub registered: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
 
 #[allow(dead_code)]
+#[derive(Debug)]
 struct DebugAction {
     pub name: String,
     pub vars: Option<Vec<String>>,
+    pub registered: bool,
 }
 
 impl IsTask for DebugTask {
@@ -48,7 +51,8 @@ impl IsTask for DebugTask {
             EvaluatedTask {
                 action: Arc::new(DebugAction {
                     name: self.name.clone().unwrap_or(String::from(MODULE)),
-                    vars: self.vars.clone()
+                    vars: self.vars.clone(),
+                    registered: handle.template.boolean_option_default_false(request, tm, &String::from("registered"), &self.registered)?
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
@@ -71,13 +75,21 @@ impl IsAction for DebugAction {
                 let mut map : serde_yaml::Mapping = serde_yaml::Mapping::new();
                 let no_vars = self.vars.is_none();
                 let blended = handle.run_state.context.read().unwrap().get_complete_blended_variables(&handle.host, BlendTarget::NotTemplateModule);
+                // results saved by no_log tasks are shown by name only
+                let saved = handle.host.read().unwrap().get_saved_results();
                 for (k,v) in blended.iter() {
                     let k2 : String = match k {
                         serde_yaml::Value::String(s) => s.clone(),
                         _ => { panic!("invalid key in mapping"); }
                     };
                     if (no_vars || self.vars.as_ref().unwrap().contains(&k2)) && ! k2.eq(&String::from("item")) {
-                        map.insert(k.clone(), v.clone());
+                        let value = match saved.iter().find(|(name, _)| name.eq(&k2)) {
+                            Some((_, true)) => serde_yaml::Value::String(String::from("********")),
+                            Some((_, false)) => v.clone(),
+                            None if self.registered => { continue; },
+                            None => v.clone()
+                        };
+                        map.insert(k.clone(), value);
                     }
                 }
                 let msg = serde_yaml::to_string(&map).unwrap();
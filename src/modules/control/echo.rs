@@ -31,6 +31,7 @@ pub struct EchoTask {
 }
 
 #[allow(dead_code)]
+#[derive(Debug)]
 struct EchoAction {
     pub name: String,
     pub msg: String,
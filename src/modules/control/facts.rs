@@ -17,8 +17,10 @@
 use crate::tasks::*;
 use crate::handle::handle::TaskHandle;
 use crate::inventory::hosts::HostOSType;
-use serde::Deserialize;
+use serde::{Deserialize,Serialize};
 use std::sync::{Arc,RwLock};
+use std::path::PathBuf;
+use expanduser::expanduser;
 
 const MODULE: &str = "facts";
 
@@ -28,12 +30,27 @@ pub struct FactsTask {
     pub name: Option<String>,
     pub facter: Option<String>,
     pub ohai: Option<String>,
+    pub cache: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
+#[derive(Debug)]
 struct FactsAction {
     facter: bool,
     ohai: bool,
+    cache: bool,
+}
+
+// with cache: true, gathered facts are kept on the control machine in ~/.jet/facts/<host>.yml so slow
+// sources like facter and ohai don't run every time.  an entry is only used while the uname -a output
+// seen on connect is unchanged, so kernel or distro upgrades gather again.  --flush-cache ignores entries.
+
+#[derive(Deserialize,Serialize,Debug)]
+struct CachedFacts {
+    signature: String,
+    facter: bool,
+    ohai: bool,
+    facts: serde_yaml::Mapping
 }
 
 impl IsTask for FactsTask {
@@ -48,6 +65,7 @@ impl IsTask for FactsTask {
                 action: Arc::new(FactsAction {
                     facter:  handle.template.boolean_option_default_false(request, tm, &String::from("facter"), &self.facter)?,
                     ohai:    handle.template.boolean_option_default_false(request, tm, &String::from("ohai"), &self.ohai)?,
+                    cache:   handle.template.boolean_option_default_false(request, tm, &String::from("cache"), &self.cache)?,
 
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
@@ -82,6 +100,12 @@ impl IsAction for FactsAction {
 impl FactsAction {
     
     fn do_facts(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        if self.cache && ! handle.run_state.context.read().unwrap().flush_cache {
+            if let Some(cached) = self.load_cached_facts(handle) {
+                handle.host.write().unwrap().update_facts2(cached);
+                return Ok(());
+            }
+        }
         let os_type = handle.host.read().unwrap().os_type;
         let facts = Arc::new(RwLock::new(serde_yaml::Mapping::new()));
         match os_type {
@@ -90,6 +114,7 @@ impl FactsAction {
             None => { return Err(handle.response.is_failed(request, &String::from("facts not implemented for OS Type"))) }
         };
         self.do_arch(handle, request, &facts)?;
+        self.do_network(handle, request, &facts, os_type.expect("os type"))?;
         if self.facter {
             self.do_facter(handle, request, &facts)?;
         }
@@ -97,10 +122,53 @@ impl FactsAction {
             self.do_ohai(handle, request, &facts)?;
 
         }
+        if self.cache {
+            self.save_cached_facts(handle, request, &facts)?;
+        }
         handle.host.write().unwrap().update_facts(&facts);
         Ok(())
     }
 
+    fn get_cache_path(&self, handle: &Arc<TaskHandle>) -> Option<PathBuf> {
+        get_facts_cache_path(&handle.host.read().unwrap().name)
+    }
+
+    fn load_cached_facts(&self, handle: &Arc<TaskHandle>) -> Option<serde_yaml::Mapping> {
+        let signature = handle.host.read().unwrap().os_signature.clone()?;
+        let path = self.get_cache_path(handle)?;
+        let data = std::fs::read_to_string(path).ok()?;
+        let cached : CachedFacts = serde_yaml::from_str(&data).ok()?;
+        match cached.signature == signature && cached.facter == self.facter && cached.ohai == self.ohai {
+            true => Some(cached.facts),
+            false => None
+        }
+    }
+
+    fn save_cached_facts(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, facts: &Arc<RwLock<serde_yaml::Mapping>>) -> Result<(), Arc<TaskResponse>> {
+        let signature = match handle.host.read().unwrap().os_signature.clone() {
+            Some(x) => x,
+            None => { return Ok(()); }
+        };
+        let path = match self.get_cache_path(handle) {
+            Some(x) => x,
+            None => { return Err(handle.response.is_failed(request, &String::from("unable to locate the home directory for the facts cache"))); }
+        };
+        let cached = CachedFacts { signature, facter: self.facter, ohai: self.ohai, facts: facts.read().unwrap().clone() };
+        let data = match serde_yaml::to_string(&cached) {
+            Ok(x) => x,
+            Err(e) => { return Err(handle.response.is_failed(request, &format!("unable to serialize facts for the cache: {}", e))); }
+        };
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                return Err(handle.response.is_failed(request, &format!("unable to create {}: {}", parent.display(), e)));
+            }
+        }
+        match std::fs::write(&path, data) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(handle.response.is_failed(request, &format!("unable to write {}: {}", path.display(), e)))
+        }
+    }
+
     fn insert_string(&self, mapping: &Arc<RwLock<serde_yaml::Mapping>>, key: &str, value: &str) {
         mapping.write().unwrap().insert(serde_yaml::Value::String(key.to_owned()), serde_yaml::Value::String(value.to_owned())); 
     }
@@ -124,6 +192,40 @@ impl FactsAction {
         Ok(())
     }
 
+    fn do_network(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, mapping: &Arc<RwLock<serde_yaml::Mapping>>, os_type: HostOSType) -> Result<(), Arc<TaskResponse>> {
+        // makes jet_network, which always has this shape so templates and the fact_ip/default_ipv4 helpers
+        // can rely on it.  the default_* keys are left out when there is no default route:
+        //   jet_network:
+        //     interfaces:
+        //       eth0: { ipv4: [ 192.0.2.2 ], ipv6: [ "fe80::1" ] }
+        //     default_interface: eth0
+        //     default_gateway: 192.0.2.1
+        //     default_ipv4: 192.0.2.2
+        // a host without the ip (Linux) or ifconfig (MacOS) tools just gets an empty interfaces mapping.
+        let (addr_cmd, route_cmd) = match os_type {
+            HostOSType::Linux => ("ip -o addr show", "ip route show default"),
+            HostOSType::MacOS => ("ifconfig", "route -n get default"),
+        };
+        let result = handle.remote.run(request, &String::from(addr_cmd), CheckRc::Unchecked)?;
+        let addresses = match cmd_info(&result) {
+            (0, out) => match os_type {
+                HostOSType::Linux => parse_ip_addr(&out),
+                HostOSType::MacOS => parse_ifconfig(&out),
+            },
+            _ => Vec::new()
+        };
+        let result = handle.remote.run(request, &String::from(route_cmd), CheckRc::Unchecked)?;
+        let route = match cmd_info(&result) {
+            (0, out) => match os_type {
+                HostOSType::Linux => parse_ip_route(&out),
+                HostOSType::MacOS => parse_route_get(&out),
+            },
+            _ => DefaultRoute::default()
+        };
+        mapping.write().unwrap().insert(serde_yaml::Value::String(String::from("jet_network")), serde_yaml::Value::Mapping(get_network_mapping(&addresses, &route)));
+        Ok(())
+    }
+
     fn do_linux_os_release(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, mapping: &Arc<RwLock<serde_yaml::Mapping>>) -> Result<(), Arc<TaskResponse>> {
         // makes a lot of variables from everything in /etc/os-release with a jet_os_release prefix such as:
         // jet_os_release_id="rocky" 
@@ -198,3 +300,164 @@ impl FactsAction {
 
 }
 
+#[derive(Debug,PartialEq)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6
+}
+
+#[derive(Debug,PartialEq)]
+pub struct NetworkAddress {
+    pub interface: String,
+    pub family: AddressFamily,
+    pub address: String
+}
+
+#[derive(Debug,Default,PartialEq)]
+pub struct DefaultRoute {
+    pub interface: Option<String>,
+    pub gateway: Option<String>,
+    pub source: Option<String>
+}
+
+pub fn parse_ip_addr(out: &str) -> Vec<NetworkAddress> {
+    // lines look like "4: eth0    inet 192.0.2.2/24 brd 192.0.2.255 scope global eth0\ ..."
+    let mut results = Vec::new();
+    for line in out.lines() {
+        let tokens : Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 4 {
+            continue;
+        }
+        let family = match tokens[2] {
+            "inet"  => AddressFamily::Ipv4,
+            "inet6" => AddressFamily::Ipv6,
+            _ => { continue; }
+        };
+        let interface = tokens[1].trim_end_matches(':').split('@').next().unwrap_or_default().to_string();
+        let address = tokens[3].split('/').next().unwrap_or_default().to_string();
+        results.push(NetworkAddress { interface, family, address });
+    }
+    results
+}
+
+pub fn parse_ifconfig(out: &str) -> Vec<NetworkAddress> {
+    // interface lines start in the first column ("en0: flags=8863<UP,...> mtu 1500"), addresses are indented
+    let mut results = Vec::new();
+    let mut interface : Option<String> = None;
+    for line in out.lines() {
+        if ! line.starts_with(char::is_whitespace) {
+            interface = line.split(':').next().map(|x| x.to_string());
+            continue;
+        }
+        let tokens : Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 2 || interface.is_none() {
+            continue;
+        }
+        let family = match tokens[0] {
+            "inet"  => AddressFamily::Ipv4,
+            "inet6" => AddressFamily::Ipv6,
+            _ => { continue; }
+        };
+        // link local ipv6 addresses carry a zone suffix like fe80::1%lo0
+        let address = tokens[1].split('%').next().unwrap_or_default().to_string();
+        results.push(NetworkAddress { interface: interface.clone().unwrap(), family, address });
+    }
+    results
+}
+
+pub fn parse_ip_route(out: &str) -> DefaultRoute {
+    // "default via 192.0.2.1 dev eth0 proto dhcp src 192.0.2.2 metric 100", only the first route is used
+    let mut route = DefaultRoute::default();
+    if let Some(line) = out.lines().find(|x| x.starts_with("default")) {
+        let tokens : Vec<&str> = line.split_whitespace().collect();
+        for pair in tokens.windows(2) {
+            match pair[0] {
+                "via" => { route.gateway   = Some(pair[1].to_string()); },
+                "dev" => { route.interface = Some(pair[1].to_string()); },
+                "src" => { route.source    = Some(pair[1].to_string()); },
+                _ => {}
+            }
+        }
+    }
+    route
+}
+
+pub fn parse_route_get(out: &str) -> DefaultRoute {
+    let mut route = DefaultRoute::default();
+    for line in out.lines() {
+        match line.trim().split_once(':') {
+            Some(("gateway", value))   => { route.gateway   = Some(value.trim().to_string()); },
+            Some(("interface", value)) => { route.interface = Some(value.trim().to_string()); },
+            _ => {}
+        }
+    }
+    route
+}
+
+pub fn get_network_mapping(addresses: &[NetworkAddress], route: &DefaultRoute) -> serde_yaml::Mapping {
+    let mut interfaces = serde_yaml::Mapping::new();
+    for address in addresses.iter() {
+        let key = serde_yaml::Value::String(address.interface.clone());
+        if ! interfaces.contains_key(&key) {
+            let mut entry = serde_yaml::Mapping::new();
+            entry.insert(serde_yaml::Value::String(String::from("ipv4")), serde_yaml::Value::Sequence(Vec::new()));
+            entry.insert(serde_yaml::Value::String(String::from("ipv6")), serde_yaml::Value::Sequence(Vec::new()));
+            interfaces.insert(key.clone(), serde_yaml::Value::Mapping(entry));
+        }
+        let family = match address.family {
+            AddressFamily::Ipv4 => "ipv4",
+            AddressFamily::Ipv6 => "ipv6"
+        };
+        if let Some(serde_yaml::Value::Sequence(list)) = interfaces.get_mut(&key).and_then(|x| x.get_mut(family)) {
+            list.push(serde_yaml::Value::String(address.address.clone()));
+        }
+    }
+
+    // without a src hint on the route, the default address is the first ipv4 address of the route's interface
+    let default_ipv4 = match &route.source {
+        Some(x) => Some(x.clone()),
+        None => route.interface.as_ref().and_then(|interface| {
+            addresses.iter().find(|x| x.interface.eq(interface) && x.family == AddressFamily::Ipv4).map(|x| x.address.clone())
+        })
+    };
+
+    let mut network = serde_yaml::Mapping::new();
+    network.insert(serde_yaml::Value::String(String::from("interfaces")), serde_yaml::Value::Mapping(interfaces));
+    for (key, value) in [("default_interface", &route.interface), ("default_gateway", &route.gateway), ("default_ipv4", &default_ipv4)] {
+        if let Some(v) = value {
+            network.insert(serde_yaml::Value::String(String::from(key)), serde_yaml::Value::String(v.clone()));
+        }
+    }
+    network
+}
+
+pub fn get_facts_cache_path(host_name: &str) -> Option<PathBuf> {
+    match expanduser("~/.jet/facts") {
+        Ok(mut pb) => { pb.push(format!("{}.yml", host_name)); Some(pb) },
+        Err(_) => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_mapping() {
+        let addresses = parse_ip_addr("1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever\n\
+            4: eth0    inet 192.0.2.2/24 brd 192.0.2.255 scope global eth0\\       valid_lft forever\n\
+            4: eth0    inet6 fe80::fc:ff:fe00:1/64 scope link \\       valid_lft forever\n");
+        assert_eq!(addresses.len(), 3);
+        let route = parse_ip_route("default via 192.0.2.1 dev eth0 proto dhcp metric 100\n");
+        assert_eq!(route.gateway, Some(String::from("192.0.2.1")));
+        let network = get_network_mapping(&addresses, &route);
+        assert_eq!(network.get("default_ipv4").and_then(|x| x.as_str()), Some("192.0.2.2"));
+        assert_eq!(network["interfaces"]["eth0"]["ipv6"][0].as_str(), Some("fe80::fc:ff:fe00:1"));
+
+        let addresses = parse_ifconfig("en0: flags=8863<UP,BROADCAST> mtu 1500\n\tinet6 fe80::1%en0 prefixlen 64\n\tinet 10.0.0.5 netmask 0xffffff00\n");
+        let route = parse_route_get("   route to: default\n    gateway: 10.0.0.1\n  interface: en0\n");
+        let network = get_network_mapping(&addresses, &route);
+        assert_eq!(network["interfaces"]["en0"]["ipv6"][0].as_str(), Some("fe80::1"));
+        assert_eq!(network.get("default_ipv4").and_then(|x| x.as_str()), Some("10.0.0.5"));
+    }
+}
@@ -31,6 +31,7 @@ pub struct FailTask {
 }
 
 #[allow(dead_code)]
+#[derive(Debug)]
 struct FailAction {
     pub name: String,
     pub msg: Option<String>,
@@ -0,0 +1,46 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use serde::Deserialize;
+use std::sync::Arc;
+
+// !import splices a list of tasks from another file into the current task list when
+// the playbook or role task file is parsed. Because this happens before any hosts
+// are contacted, imports cannot be conditional, use !include for that.
+
+const MODULE: &str = "import";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ImportTask {
+    pub name: Option<String>,
+    pub file: String,
+}
+
+impl IsTask for ImportTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { None }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, _tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        // imports are replaced by their tasks at parse time so this should not happen
+        Err(handle.response.is_failed(request, &format!("import of {} should have been expanded at parse time", self.file)))
+    }
+
+}
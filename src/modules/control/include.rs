@@ -0,0 +1,78 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use serde::Deserialize;
+use std::sync::Arc;
+
+// !include pulls in a list of tasks from another file at runtime, when the include is reached
+// in playbook traversal. Unlike other modules it does not execute on the remote hosts, 
+// see playbooks/traversal.rs for where the file is loaded and the tasks are run.
+
+const MODULE: &str = "include";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeTask {
+    pub name: Option<String>,
+    pub file: String,
+    pub vars: Option<serde_yaml::Mapping>,
+    pub with: Option<PreLogicInput>
+}
+
+impl IsTask for IncludeTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, _tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        // traversal intercepts includes before they reach the task FSM so this should not happen
+        Err(handle.response.is_failed(request, &format!("include of {} should have been handled by playbook traversal", self.file)))
+    }
+
+}
+
+impl IncludeTask {
+
+    // traversal only knows what to do with the condition, tags, and vars of a 'with' block on an include,
+    // anything else is refused rather than quietly dropped
+
+    pub fn check_with(&self) -> Result<(), String> {
+        let with = match &self.with {
+            Some(x) => x,
+            None => { return Ok(()); }
+        };
+        let unsupported = [
+            ("subscribe", with.subscribe.is_some()),
+            ("listen", with.listen.is_some()),
+            ("sudo", with.sudo.is_some()),
+            ("items", with.items.is_some()),
+            ("delegate_to", with.delegate_to.is_some()),
+            ("local_action", with.local_action.is_some()),
+            ("no_log", with.no_log.is_some()),
+            ("timeout", with.timeout.is_some()),
+            ("trusted", with.trusted.is_some()),
+        ];
+        let found : Vec<&str> = unsupported.iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+        match found.is_empty() {
+            true => Ok(()),
+            false => Err(format!("{} not supported in 'with' on !include, only condition, tags, and vars are: {}", found.join(", "), self.file))
+        }
+    }
+
+}
@@ -0,0 +1,105 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::modules::control::facts::get_facts_cache_path;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const MODULE: &str = "meta";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MetaTask {
+    pub name: Option<String>,
+    pub action: String,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+enum MetaOperation {
+    ClearFacts,
+    ResetConnection,
+}
+
+#[derive(Debug)]
+struct MetaAction {
+    pub operation: MetaOperation,
+}
+
+impl IsTask for MetaTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let operation = match self.action.as_str() {
+            "clear_facts" => MetaOperation::ClearFacts,
+            "reset_connection" => MetaOperation::ResetConnection,
+            _ => { return Err(handle.response.is_failed(request, &format!("field (action): unknown meta action: {}, expected clear_facts or reset_connection", self.action))); }
+        };
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(MetaAction {
+                    operation,
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+}
+
+impl IsAction for MetaAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                Ok(handle.response.needs_passive(request))
+            },
+
+            TaskRequestType::Passive => {
+                match self.operation {
+                    MetaOperation::ClearFacts => {
+                        // the facts cache on the control machine would otherwise hand the old facts back to the next facts task
+                        let name = handle.host.read().unwrap().name.clone();
+                        if let Some(path) = get_facts_cache_path(&name) {
+                            if path.exists() && std::fs::remove_file(&path).is_err() {
+                                return Err(handle.response.is_failed(request, &format!("unable to remove the cached facts in {}", path.display())));
+                            }
+                        }
+                        handle.host.write().unwrap().clear_facts();
+                    },
+                    MetaOperation::ResetConnection => {
+                        // the connection in use stays open until this task is done with it, the next task connects again
+                        handle.run_state.context.read().unwrap().connection_cache.write().unwrap().remove_connection(&handle.host);
+                    }
+                }
+                Ok(handle.response.is_passive(request))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+
+    }
+
+}
@@ -17,9 +17,14 @@
 #[allow(clippy::empty_line_after_doc_comments)]
 /** ADD MODULES HERE, KEEP ALPHABETIZED **/
 
+pub mod add_host;
 pub mod assert;
 pub mod debug;
 pub mod echo;
 pub mod fail;
 pub mod facts;
+pub mod import;
+pub mod include;
+pub mod meta;
 pub mod set;
+pub mod wait_for_connection;
@@ -27,12 +27,15 @@ const MODULE: &str = "Set";
 pub struct SetTask {
     pub name: Option<String>,
     pub vars: Option<serde_yaml::Mapping>, 
+    pub facts: Option<serde_yaml::Mapping>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>,
 
 }
+#[derive(Debug)]
 struct SetAction {
     pub vars: Option<serde_yaml::Mapping>, 
+    pub facts: Option<serde_yaml::Mapping>,
 }
 
 
@@ -46,7 +49,8 @@ impl IsTask for SetTask {
         Ok(
             EvaluatedTask {
                 action: Arc::new(SetAction {
-                    vars: self.vars.clone() /* templating will happen below */
+                    vars: self.vars.clone(), /* templating will happen below */
+                    facts: self.facts.clone()
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
@@ -70,21 +74,12 @@ impl IsAction for SetAction {
                 
                 /* so far this only templates top level strings, which is probably sufficient, rather than strings found in deeper levels */
 
-                let mut mapping = serde_yaml::Mapping::new();
-                if self.vars.as_ref().is_some() {
-                    for (k,v) in self.vars.as_ref().unwrap().iter() {
-                        if v.is_string() {
-                            let ks = v.as_str().unwrap().to_string();
-                            let vs = v.as_str().unwrap().to_string();
-                            let templated = handle.template.string_unsafe_for_shell(request, TemplateMode::Strict, &ks.clone(), &vs)?;
-                            mapping.insert(k.clone(), serde_yaml::Value::String(templated));
-                        } else {
-                            mapping.insert(k.clone(), v.clone());
-                        }   
-                    }
-                }
-
+                let mapping = self.template_mapping(handle, request, &self.vars)?;
                 handle.host.write().unwrap().update_variables(mapping);
+
+                // facts set here outrank gathered ones until a meta task clears them
+                let facts = self.template_mapping(handle, request, &self.facts)?;
+                handle.host.write().unwrap().override_facts(facts);
                 Ok(handle.response.is_passive(request))
             
             }
@@ -96,3 +91,23 @@ impl IsAction for SetAction {
 
 }
 
+impl SetAction {
+
+    fn template_mapping(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, input: &Option<serde_yaml::Mapping>) -> Result<serde_yaml::Mapping, Arc<TaskResponse>> {
+        let mut mapping = serde_yaml::Mapping::new();
+        if input.as_ref().is_some() {
+            for (k,v) in input.as_ref().unwrap().iter() {
+                if v.is_string() {
+                    let ks = v.as_str().unwrap().to_string();
+                    let vs = v.as_str().unwrap().to_string();
+                    let templated = handle.template.string_unsafe_for_shell(request, TemplateMode::Strict, &ks.clone(), &vs)?;
+                    mapping.insert(k.clone(), serde_yaml::Value::String(templated));
+                } else {
+                    mapping.insert(k.clone(), v.clone());
+                }   
+            }
+        }
+        Ok(mapping)
+    }
+
+}
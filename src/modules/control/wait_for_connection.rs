@@ -0,0 +1,107 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::handle::template::BlendTarget;
+use crate::inventory::hosts::Host;
+use crate::playbooks::traversal::RunState;
+use serde::Deserialize;
+use std::sync::{Arc,RwLock};
+
+const MODULE: &str = "wait_for_connection";
+
+// the waiting happens in the task executor (see task_fsm.rs), which keeps trying to connect to the host
+// for up to timeout seconds, delay seconds apart, before the task itself runs.  by the time dispatch is
+// reached the host is connected, so there is nothing left to do here.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WaitForConnectionTask {
+    pub name: Option<String>,
+    pub timeout: Option<String>,
+    pub delay: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+struct WaitForConnectionAction {
+}
+
+impl IsTask for WaitForConnectionTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        // checked here too so that --syntax-check and the error messages match other modules
+        handle.template.integer_option_to_integer(request, tm, &String::from("timeout"), &self.timeout, 600)?;
+        handle.template.integer_option_to_integer(request, tm, &String::from("delay"), &self.delay, 5)?;
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(WaitForConnectionAction {}),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+
+}
+
+impl WaitForConnectionTask {
+
+    // the executor needs these before there is a connection, and so before there is a task handle
+    pub fn get_timing(&self, run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>) -> Result<(u64, u64), String> {
+        let timeout = self.get_seconds(run_state, host, "timeout", &self.timeout, 600)?;
+        let delay = self.get_seconds(run_state, host, "delay", &self.delay, 5)?;
+        Ok((timeout, delay))
+    }
+
+    fn get_seconds(&self, run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, field: &str, input: &Option<String>, default: u64) -> Result<u64, String> {
+        let value = match input {
+            Some(x) => run_state.context.read().unwrap().render_template(x, host, BlendTarget::NotTemplateModule, TemplateMode::Strict)?,
+            None => { return Ok(default); }
+        };
+        match value.trim().parse::<u64>() {
+            Ok(x) => Ok(x),
+            Err(_) => Err(format!("field ({}) value is not an integer: {}", field, value))
+        }
+    }
+
+}
+
+impl IsAction for WaitForConnectionAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                Ok(handle.response.needs_passive(request))
+            },
+
+            TaskRequestType::Passive => {
+                Ok(handle.response.is_passive(request))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
@@ -18,10 +18,14 @@ use crate::tasks::*;
 use crate::handle::handle::TaskHandle;
 use crate::tasks::fields::Field;
 use std::path::PathBuf;
+use std::collections::HashMap;
 use serde::Deserialize;
 use std::sync::Arc;
 use std::vec::Vec;
-use crate::tasks::files::Recurse;
+use crate::tasks::files::{Recurse,TextNormalization,preview_file};
+use crate::tasks::checksum::sha512;
+use crate::util::io::{has_wildcards,path_basename_as_string,path_walk_recursive};
+use crate::handle::local::get_mtime;
 
 const MODULE: &str = "copy";
 
@@ -29,16 +33,51 @@ const MODULE: &str = "copy";
 #[serde(deny_unknown_fields)]
 pub struct CopyTask {
     pub name: Option<String>,
-    pub src: String,
+    pub src: SrcInput,
     pub dest: String,
     pub attributes: Option<FileAttributesInput>,
+    pub force: Option<String>,
+    pub preserve: Option<String>,
+    pub rsync: Option<String>,
+    pub quick_check: Option<String>,
+    pub follow: Option<String>,
+    pub update: Option<String>,
+    pub preview: Option<String>,
+    pub line_endings: Option<String>,
+    pub trailing_newline: Option<String>,
+    pub exclude: Option<Vec<String>>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
+#[derive(Debug)]
 struct CopyAction {
     pub src: PathBuf,
     pub dest: String,
     pub attributes: Option<FileAttributesEvaluated>,
+    pub force: bool,
+    pub rsync: bool,
+    pub quick_check: bool,
+    pub follow: bool,
+    pub update: bool,
+    pub preview: bool,
+    pub normalize: TextNormalization,
+}
+
+// a src with wildcards becomes one copy per matching file, each landing in dest under its own name
+#[derive(Debug)]
+struct CopyGlobAction {
+    pub dest: String,
+    pub copies: Vec<CopyAction>,
+}
+
+// a src that is a directory is copied along with everything under it, each file landing at the same path
+// relative to dest.  the files are copied as in CopyGlobAction, directories only get the owner and group.
+#[derive(Debug)]
+struct CopyTreeAction {
+    pub dest: String,
+    pub directories: Vec<String>,
+    pub copies: Vec<CopyAction>,
+    pub attributes: Option<FileAttributesEvaluated>,
 }
 
 impl IsTask for CopyTask {
@@ -48,14 +87,37 @@ impl IsTask for CopyTask {
     fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
 
     fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
-        let src = handle.template.string(request, tm, &String::from("src"), &self.src)?;
+        let action : Arc<dyn IsAction> = match &self.src {
+            // a wildcard src copies every match into dest, which has to be a directory
+            SrcInput::SrcString(pattern) if has_wildcards(pattern) => {
+                let field = String::from("src");
+                let dest = handle.template.path(request, tm, &String::from("dest"), &self.dest)?;
+                let matches = handle.template.find_file_paths_matching(request, tm, &field, pattern)?;
+                if tm != TemplateMode::Off && matches.is_empty() {
+                    return Err(handle.response.is_failed(request, &format!("field ({}): no files match {}", field, pattern)));
+                }
+                let mut copies : Vec<CopyAction> = Vec::new();
+                for src in matches.into_iter() {
+                    let file_dest = format!("{}/{}", dest.trim_end_matches('/'), path_basename_as_string(&src));
+                    copies.push(self.evaluate_action(handle, request, tm, src, file_dest)?);
+                }
+                Arc::new(CopyGlobAction { dest, copies })
+            },
+            _ => {
+                let src = handle.template.find_file_path(request, tm, &String::from("src"), &self.src)?;
+                let dest = handle.template.path(request, tm, &String::from("dest"), &self.dest)?;
+                if tm != TemplateMode::Off && self.exclude.is_some() && ! src.is_dir() {
+                    return Err(handle.response.is_failed(request, &String::from("exclude can only be used when src is a directory")));
+                }
+                match src.is_dir() {
+                    true => Arc::new(self.evaluate_tree(handle, request, tm, src, dest)?),
+                    false => Arc::new(self.evaluate_action(handle, request, tm, src, dest)?)
+                }
+            }
+        };
         Ok(
             EvaluatedTask {
-                action: Arc::new(CopyAction {
-                    src:        handle.template.find_file_path(request, tm, &String::from("src"), &src)?,
-                    dest:       handle.template.path(request, tm, &String::from("dest"), &self.dest)?,
-                    attributes: FileAttributesInput::template(handle, request, tm, &self.attributes)?
-                }),
+                action,
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
             }
@@ -64,44 +126,117 @@ impl IsTask for CopyTask {
 
 }
 
+impl CopyTask {
+
+    fn evaluate_action(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, src: PathBuf, dest: String) -> Result<CopyAction, Arc<TaskResponse>> {
+        let mut attributes = FileAttributesInput::template_preserving(handle, request, tm, &self.attributes)?;
+        // preserve takes the mode from the source file, the same as attributes/mode: preserve
+        let preserve = handle.template.boolean_option_default_false(request, tm, &String::from("preserve"), &self.preserve)?;
+        if tm != TemplateMode::Off && (preserve || FileAttributesEvaluated::wants_source_mode(&attributes)) {
+            attributes = FileAttributesEvaluated::with_source_mode(handle, request, &src, attributes)?;
+        }
+        if tm != TemplateMode::Off {
+            let default_mode = handle.run_state.context.read().unwrap().default_file_mode.clone();
+            attributes = FileAttributesEvaluated::with_default_mode(handle, request, &default_mode, attributes)?;
+        }
+        let rsync = handle.template.boolean_option_default_false(request, tm, &String::from("rsync"), &self.rsync)?;
+        let normalize = TextNormalization::template(handle, request, tm, &self.line_endings, &self.trailing_newline)?;
+        if rsync && normalize.is_active() {
+            return Err(handle.response.is_failed(request, &String::from("rsync cannot be combined with line_endings or trailing_newline")));
+        }
+        Ok(CopyAction {
+            src,
+            dest,
+            attributes,
+            force:      handle.template.boolean_option_default_true(request, tm, &String::from("force"), &self.force)?,
+            rsync,
+            quick_check: handle.template.boolean_option_default_false(request, tm, &String::from("quick_check"), &self.quick_check)?,
+            update:     handle.template.boolean_option_default_false(request, tm, &String::from("update"), &self.update)?,
+            follow:     handle.template.boolean_option_default_false(request, tm, &String::from("follow"), &self.follow)?,
+            preview:    handle.template.boolean_option_default_false(request, tm, &String::from("preview"), &self.preview)?,
+            normalize
+        })
+    }
+
+    fn evaluate_tree(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, src: PathBuf, dest: String) -> Result<CopyTreeAction, Arc<TaskResponse>> {
+        let prefix = dest.trim_end_matches('/').to_string();
+        let mut directories : Vec<String> = Vec::new();
+        let mut files : Vec<(PathBuf, String)> = Vec::new();
+        // matched against paths relative to src, such as .git or node_modules
+        let mut exclude : Vec<String> = Vec::new();
+        for pattern in self.exclude.iter().flatten() {
+            exclude.push(handle.template.string_unsafe_for_shell(request, tm, &String::from("exclude"), pattern)?);
+        }
+        let walked = path_walk_recursive(&src, &exclude, |path, relative| {
+            let remote_path = format!("{}/{}", prefix, relative.display());
+            match (path.is_dir(), path.is_symlink()) {
+                (true, false) => { directories.push(remote_path); },
+                // links to directories are not followed, so there is nothing under them to copy
+                (true, true) => {},
+                (false, _) => { files.push((path.to_path_buf(), remote_path)); }
+            }
+            Ok(())
+        });
+        if let Err(msg) = walked {
+            return Err(handle.response.is_failed(request, &msg));
+        }
+        let mut copies : Vec<CopyAction> = Vec::new();
+        for (path, remote_path) in files.into_iter() {
+            copies.push(self.evaluate_action(handle, request, tm, path, remote_path)?);
+        }
+        // a file mode would leave the directories unusable
+        let attributes = FileAttributesInput::template_preserving(handle, request, tm, &self.attributes)?
+            .filter(|x| x.owner.is_some() || x.group.is_some())
+            .map(|x| FileAttributesEvaluated { owner: x.owner, group: x.group, mode: None, preserve_mode: false, allow_unknown_ids: x.allow_unknown_ids });
+        Ok(CopyTreeAction { dest, directories, copies, attributes })
+    }
+
+}
+
 impl IsAction for CopyAction {
 
     fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        self.dispatch_with_checksums(handle, request, &HashMap::new())
+    }
+
+}
+
+impl CopyAction {
+
+    // checksums are of remote files, fetched up front for many copies at once, see query_copies
+
+    fn dispatch_with_checksums(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, checksums: &HashMap<String,String>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
     
+        let dest = handle.remote.get_destination_path(request, &self.dest, &self.src)?;
+        let (dest, replaces_link) = handle.remote.follow_destination(request, &dest, self.follow)?;
+
         match request.request_type {
 
             TaskRequestType::Query => {
-
-                let mut changes : Vec<Field> = Vec::new();
-                let remote_mode = handle.remote.query_common_file_attributes(request, &self.dest, &self.attributes, &mut changes, Recurse::No)?;                   
-                if remote_mode.is_none() {
-                    return Ok(handle.response.needs_creation(request));
-                }
-                // this query leg is (at least originally) the same as the template module query except these two lines
-                // to calculate the checksum differently
-                let src_path = self.src.as_path();
-                let local_512 = handle.local.get_sha512(request, src_path, true)?;
-                let remote_512 = handle.remote.get_sha512(request, &self.dest)?;
-                if ! remote_512.eq(&local_512) { 
-                    changes.push(Field::Content); 
-                }
-                if ! changes.is_empty() {
-                    return Ok(handle.response.needs_modification(request, &changes));
+                let result = self.query(handle, request, &dest, replaces_link, checksums)?;
+                if ! self.preview {
+                    return Ok(result);
                 }
+                // src is shown against what is there now when both are text, and nothing is copied
+                let data = match self.normalize.is_active() {
+                    true => self.read_normalized(handle, request)?,
+                    false => std::fs::read(&self.src).ok().and_then(|x| String::from_utf8(x).ok())
+                };
+                preview_file(handle, request, &dest, &result, data)?;
                 Ok(handle.response.is_matched(request))
             },
 
             TaskRequestType::Create => {
-                self.do_copy(handle, request, None)?;               
+                self.do_copy(handle, request, &dest, None)?;               
                 Ok(handle.response.is_created(request))
             },
 
             TaskRequestType::Modify => {
                 if request.changes.contains(&Field::Content) {
-                    self.do_copy(handle, request, Some(request.changes.clone()))?;
+                    self.do_copy(handle, request, &dest, Some(request.changes.clone()))?;
                 }
                 else {
-                    handle.remote.process_common_file_attributes(request, &self.dest, &self.attributes, &request.changes, Recurse::No)?;
+                    handle.remote.process_common_file_attributes(request, &dest, &self.attributes, &request.changes, Recurse::No)?;
                 }
                 Ok(handle.response.is_modified(request, request.changes.clone()))
             },
@@ -113,15 +248,323 @@ impl IsAction for CopyAction {
 
 }
 
+impl IsAction for CopyGlobAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                if handle.remote.get_mode(request, &self.dest)?.is_none() || ! handle.remote.get_is_directory(request, &self.dest)? {
+                    return Err(handle.response.is_failed(request, &format!("dest must be an existing directory when src has wildcards: {}", self.dest)));
+                }
+                let mut changes : Vec<Field> = Vec::new();
+                query_copies(handle, request, &self.copies, &mut changes)?;
+                if ! changes.is_empty() {
+                    return Ok(handle.response.needs_modification(request, &changes));
+                }
+                Ok(handle.response.is_matched(request))
+            },
+
+            TaskRequestType::Modify => {
+                apply_copies(handle, request, &self.copies)?;
+                Ok(handle.response.is_modified(request, request.changes.clone()))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+impl IsAction for CopyTreeAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                match handle.remote.get_mode(request, &self.dest)? {
+                    None => { return Ok(handle.response.needs_creation(request)); },
+                    Some(_) if ! handle.remote.get_is_directory(request, &self.dest)? => {
+                        return Err(handle.response.is_failed(request, &format!("dest must be a directory when src is a directory: {}", self.dest)));
+                    },
+                    _ => {}
+                }
+                let mut changes : Vec<Field> = Vec::new();
+                for directory in std::iter::once(&self.dest).chain(self.directories.iter()) {
+                    let mut directory_changes : Vec<Field> = Vec::new();
+                    handle.remote.query_common_file_attributes(request, directory, &self.attributes, &mut directory_changes, Recurse::No)?;
+                    add_changes(&mut changes, directory_changes);
+                }
+                query_copies(handle, request, &self.copies, &mut changes)?;
+                if ! changes.is_empty() {
+                    return Ok(handle.response.needs_modification(request, &changes));
+                }
+                Ok(handle.response.is_matched(request))
+            },
+
+            TaskRequestType::Create | TaskRequestType::Modify => {
+                // parents come before their children, so each mkdir only makes one level
+                for directory in std::iter::once(&self.dest).chain(self.directories.iter()) {
+                    if handle.remote.get_mode(request, directory)?.is_none() {
+                        handle.remote.create_directory(request, directory)?;
+                    }
+                    handle.remote.process_all_common_file_attributes(request, directory, &self.attributes, Recurse::No)?;
+                }
+                apply_copies(handle, request, &self.copies)?;
+                match request.request_type {
+                    TaskRequestType::Create => Ok(handle.response.is_created(request)),
+                    _ => Ok(handle.response.is_modified(request, request.changes.clone()))
+                }
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+fn add_changes(changes: &mut Vec<Field>, more: Vec<Field>) {
+    for change in more.into_iter() {
+        if ! changes.contains(&change) { changes.push(change); }
+    }
+}
+
+// a file that is missing counts as a content change of the whole set
+
+fn query_copies(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, copies: &[CopyAction], changes: &mut Vec<Field>) -> Result<(), Arc<TaskResponse>> {
+    let checksums = get_checksums(handle, request, copies)?;
+    for copy in copies.iter() {
+        let result = copy.dispatch_with_checksums(handle, request, &checksums)?;
+        match result.status {
+            TaskStatus::NeedsCreation => add_changes(changes, vec![Field::Content]),
+            TaskStatus::NeedsModification => add_changes(changes, result.changes.clone()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// each file is asked again what it needs, and only the ones that differ are copied or fixed up
+
+fn apply_copies(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, copies: &[CopyAction]) -> Result<(), Arc<TaskResponse>> {
+    let sudo_details = request.sudo_details.clone().expect("sudo details");
+    let checksums = get_checksums(handle, request, copies)?;
+    for copy in copies.iter() {
+        let query = TaskRequest::query(&sudo_details, request.no_log);
+        let result = copy.dispatch_with_checksums(handle, &query, &checksums)?;
+        match result.status {
+            TaskStatus::NeedsCreation => { copy.dispatch(handle, &TaskRequest::create(&sudo_details, request.no_log))?; },
+            TaskStatus::NeedsModification => { copy.dispatch(handle, &TaskRequest::modify(&sudo_details, request.no_log, result.changes.clone()))?; },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// the files that are compared by checksum are hashed in one command instead of one each.  a dest that more than one
+// copy writes to is left out, its checksum would be out of date by the time the second copy looks
+
+fn get_checksums(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, copies: &[CopyAction]) -> Result<HashMap<String,String>, Arc<TaskResponse>> {
+    let mut paths : Vec<String> = Vec::new();
+    let mut repeated : Vec<String> = Vec::new();
+    for copy in copies.iter().filter(|x| x.force && ! x.quick_check) {
+        match paths.contains(&copy.dest) {
+            true => repeated.push(copy.dest.clone()),
+            false => paths.push(copy.dest.clone())
+        }
+    }
+    paths.retain(|x| ! repeated.contains(x));
+    // one file gains nothing over the usual command
+    if paths.len() < 2 {
+        return Ok(HashMap::new());
+    }
+    handle.remote.get_sha512_many(request, &paths)
+}
+
 impl CopyAction {
 
-    pub fn do_copy(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, _changes: Option<Vec<Field>>) -> Result<(), Arc<TaskResponse>> {
-        handle.remote.copy_file(request, &self.src, &self.dest, |f| { /* after save */
+    fn query(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, dest: &String, replaces_link: bool, checksums: &HashMap<String,String>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        let mut changes : Vec<Field> = Vec::new();
+        if self.rsync {
+            // local commands only run while querying, so the checksum the delta copy verifies against
+            // is cached now
+            handle.local.get_sha512(request, self.src.as_path(), true)?;
+        }
+        // without follow a symlink at dest is always replaced, whatever it points at
+        if replaces_link && self.force {
+            return Ok(handle.response.needs_modification(request, &[Field::Content]));
+        }
+        let remote_mode = handle.remote.query_common_file_attributes(request, dest, &self.attributes, &mut changes, Recurse::No)?;                   
+        if remote_mode.is_none() {
+            return Ok(handle.response.needs_creation(request));
+        }
+        // this query leg is (at least originally) the same as the template module query except these two lines
+        // to calculate the checksum differently
+        // with force off, an existing file is never replaced, only its attributes are managed
+        // with update, like cp -u, a file on the host at least as new as src keeps its content even if it differs
+        let keep_content = ! self.force || (self.update && self.is_remote_newer(handle, request, dest)?);
+        if ! keep_content {
+            let local_512 = match self.read_normalized(handle, request)? {
+                Some(data) => sha512(&data),
+                None => handle.local.get_sha512(request, self.src.as_path(), true)?
+            };
+            let remote_512 = match (self.quick_check, checksums.get(dest)) {
+                (true, _) => handle.remote.get_sha512_quick(request, dest)?,
+                (false, Some(checksum)) => checksum.clone(),
+                (false, None) => handle.remote.get_sha512(request, dest)?
+            };
+            if ! remote_512.eq(&local_512) { 
+                changes.push(Field::Content); 
+            }
+        }
+        if ! changes.is_empty() {
+            return Ok(handle.response.needs_modification(request, &changes));
+        }
+        Ok(handle.response.is_matched(request))
+    }
+
+    fn is_remote_newer(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, dest: &str) -> Result<bool, Arc<TaskResponse>> {
+        let local_mtime = get_mtime(self.src.as_path()).and_then(|x| parse_mtime(&x));
+        let remote_mtime = handle.remote.get_mtime(request, dest)?.and_then(|x| parse_mtime(&x));
+        match (local_mtime, remote_mtime) {
+            (Some(local), Some(remote)) => Ok(remote >= local),
+            _ => Ok(false)
+        }
+    }
+
+    // with line_endings or trailing_newline, src is read as text and what gets copied is the normalized form
+
+    fn read_normalized(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<String>, Arc<TaskResponse>> {
+        if ! self.normalize.is_active() {
+            return Ok(None);
+        }
+        let data = handle.local.read_file(request, &self.src)?;
+        Ok(Some(self.normalize.apply(&data)))
+    }
+
+    pub fn do_copy(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, dest: &String, _changes: Option<Vec<Field>>) -> Result<(), Arc<TaskResponse>> {
+        let after_save = |f: &String| {
             match handle.remote.process_all_common_file_attributes(request, f, &self.attributes, Recurse::No) {
                 Ok(_x) => Ok(()), Err(y) => Err(y)
             }
-        })?;
+        };
+        if let Some(data) = self.read_normalized(handle, request)? {
+            handle.remote.write_data_atomic(request, &data, dest, None, after_save)?;
+            if self.quick_check {
+                handle.remote.remember_sha512(request, dest, &sha512(&data))?;
+            }
+            return Ok(());
+        }
+        match self.rsync {
+            // only the changed blocks are sent when rsync is usable on both ends, otherwise the whole file
+            true => {
+                let local_512 = handle.local.get_sha512(request, self.src.as_path(), true)?;
+                handle.remote.copy_file_delta(request, &self.src, dest, &local_512, after_save)?;
+            },
+            false => handle.remote.copy_file_atomic(request, &self.src, dest, None, after_save)?
+        }
+        if self.quick_check {
+            let local_512 = handle.local.get_sha512(request, self.src.as_path(), true)?;
+            handle.remote.remember_sha512(request, dest, &local_512)?;
+        }
         Ok(())
     }
 
 }
+
+// stat prints the modification time as seconds with a fraction of varying length (or none), this turns it into
+// seconds and nanoseconds so that times from the local and remote side compare exactly
+
+pub fn parse_mtime(mtime: &str) -> Option<(i64, u32)> {
+    let (seconds, fraction) = mtime.trim().split_once('.').unwrap_or((mtime.trim(), ""));
+    let seconds = seconds.parse::<i64>().ok()?;
+    let digits : String = fraction.chars().chain(std::iter::repeat('0')).take(9).collect();
+    Some((seconds, digits.parse::<u32>().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_parse_mtime_compares_fractions_exactly() {
+        assert_eq!(parse_mtime("1700000000.5"), Some((1700000000, 500000000)));
+        assert_eq!(parse_mtime("1700000000"), Some((1700000000, 0)));
+        assert!(parse_mtime("1700000000.000000002") > parse_mtime("1700000000.000000001"));
+        assert_eq!(parse_mtime("soon"), None);
+    }
+
+    #[test]
+    fn test_tree_creates_missing_directories_parents_first() {
+        let sudo_details = SudoDetails::none();
+        let action = CopyTreeAction {
+            dest: String::from("/srv/site"),
+            directories: vec![String::from("/srv/site/css"), String::from("/srv/site/css/vendor")],
+            copies: Vec::new(),
+            attributes: None
+        };
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a' '/srv/site'", 1, ""),
+            ("stat --format '%a' '/srv/site/css'", 1, ""),
+            ("stat --format '%a' '/srv/site/css/vendor'", 1, ""),
+        ]);
+        let response = action.dispatch(&handle, &TaskRequest::query(&sudo_details, false)).expect("query");
+        assert_eq!(response.status, TaskStatus::NeedsCreation);
+        assert!(action.dispatch(&handle, &TaskRequest::create(&sudo_details, false)).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        let mkdirs : Vec<&String> = commands.iter().filter(|x| x.starts_with("mkdir")).collect();
+        assert_eq!(mkdirs, vec!["mkdir -p '/srv/site'", "mkdir -p '/srv/site/css'", "mkdir -p '/srv/site/css/vendor'"]);
+    }
+
+    #[test]
+    fn test_tree_checksums_every_file_in_one_command() {
+        let src = std::env::temp_dir().join(format!("jetp-copy-checksums-{}", std::process::id()));
+        std::fs::create_dir_all(&src).unwrap();
+        for file in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(src.join(file), "x").unwrap();
+        }
+        let same = sha512(&String::from("x"));
+        let other = sha512(&String::from("y"));
+        let out = format!("{}  /srv/site/a.txt\n{}  /srv/site/b.txt\n{}  /srv/site/c.txt", same, other, same);
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%F' '/srv/site'", 0, "directory"),
+            ("stat --format '%F'", 0, "regular file"),
+            ("stat --format '%a'", 0, "644"),
+            ("sha512sum", 0, &out),
+        ]);
+        let task : CopyTask = serde_yaml::from_str(&format!("src: {}\ndest: /srv/site", src.display())).unwrap();
+        let tree = task.evaluate_tree(&handle, &TaskRequest::validate(), TemplateMode::Strict, src.clone(), String::from("/srv/site"));
+        std::fs::remove_dir_all(&src).unwrap();
+        let response = tree.expect("evaluate").dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.status, TaskStatus::NeedsModification);
+        assert_eq!(response.changes, vec![Field::Content]);
+        let commands = connection.lock().unwrap().get_commands();
+        let checksums : Vec<&String> = commands.iter().filter(|x| x.starts_with("sha512sum")).collect();
+        assert_eq!(checksums, vec!["sha512sum '/srv/site/a.txt' '/srv/site/b.txt' '/srv/site/c.txt'"]);
+    }
+
+    #[test]
+    fn test_tree_leaves_out_excluded_paths() {
+
+        let src = std::env::temp_dir().join(format!("jetp-copy-exclude-{}", std::process::id()));
+        for directory in [".git", "css", "node_modules/left-pad"] {
+            std::fs::create_dir_all(src.join(directory)).unwrap();
+        }
+        for file in [".git/config", "index.html", "css/site.css", "node_modules/left-pad/index.js"] {
+            std::fs::write(src.join(file), "x").unwrap();
+        }
+        let task : CopyTask = serde_yaml::from_str(&format!("src: {}\ndest: /srv/site\nexclude: [.git, node_modules]", src.display())).unwrap();
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let tree = task.evaluate_tree(&handle, &TaskRequest::validate(), TemplateMode::Strict, src.clone(), String::from("/srv/site"));
+        std::fs::remove_dir_all(&src).unwrap();
+        let tree = tree.expect("evaluate");
+        assert_eq!(tree.directories, vec![String::from("/srv/site/css")]);
+        let dests : Vec<&String> = tree.copies.iter().map(|x| &x.dest).collect();
+        assert_eq!(dests, vec!["/srv/site/css/site.css", "/srv/site/index.html"]);
+    }
+}
@@ -31,14 +31,17 @@ pub struct DirectoryTask {
     pub path: String,
     pub remove: Option<String>,
     pub recurse: Option<String>,
+    pub exclude: Option<Vec<String>>,
     pub attributes: Option<FileAttributesInput>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
+#[derive(Debug)]
 struct DirectoryAction {
     pub path: String,
     pub remove: bool,
     pub recurse: Recurse,
+    pub exclude: Vec<String>,
     pub attributes: Option<FileAttributesEvaluated>,
 }
 
@@ -53,13 +56,28 @@ impl IsTask for DirectoryTask {
             true => Recurse::Yes,
             false => Recurse::No
         };
+        // paths under a recursive directory to leave the attributes of alone, such as .git or node_modules
+        let mut exclude : Vec<String> = Vec::new();
+        for pattern in self.exclude.iter().flatten() {
+            exclude.push(handle.template.string_unsafe_for_shell(request, tm, &String::from("exclude"), pattern)?);
+        }
+        if tm != TemplateMode::Off && ! exclude.is_empty() && recurse == Recurse::No {
+            return Err(handle.response.is_failed(request, &String::from("exclude can only be used with recurse: true")));
+        }
+        let mut attributes = FileAttributesInput::template(handle, request, tm, &self.attributes)?;
+        // a recursive mode would also land on the files underneath, so the default only applies to the directory itself
+        if tm != TemplateMode::Off && recurse == Recurse::No {
+            let default_mode = handle.run_state.context.read().unwrap().default_dir_mode.clone();
+            attributes = FileAttributesEvaluated::with_default_mode(handle, request, &default_mode, attributes)?;
+        }
         Ok(
             EvaluatedTask {
                 action: Arc::new(DirectoryAction {
                     remove:     handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?,
                     recurse, 
+                    exclude,
                     path:       handle.template.path(request, tm, &String::from("path"), &self.path)?,
-                    attributes: FileAttributesInput::template(handle, request, tm, &self.attributes)?
+                    attributes
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
@@ -77,7 +95,10 @@ impl IsAction for DirectoryAction {
 
             TaskRequestType::Query => {
                 let mut changes : Vec<Field> = Vec::new();
-                let remote_mode = handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, self.recurse)?;                 
+                let remote_mode = match self.exclude.is_empty() {
+                    true  => handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, self.recurse)?,
+                    false => handle.remote.query_common_file_attributes_excluding(request, &self.path, &self.attributes, &mut changes, &self.exclude)?
+                };
                 if remote_mode.is_none() {
                     if self.remove             { Ok(handle.response.is_matched(request))} 
                     else                       { Ok(handle.response.needs_creation(request))}
@@ -92,12 +113,12 @@ impl IsAction for DirectoryAction {
 
             TaskRequestType::Create => {
                 handle.remote.create_directory(request, &self.path)?;               
-                handle.remote.process_all_common_file_attributes(request, &self.path, &self.attributes, self.recurse)?;
+                self.process_attributes(handle, request, &Field::all_file_attributes())?;
                 Ok(handle.response.is_created(request))
             },
 
             TaskRequestType::Modify => {
-                handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, &request.changes, self.recurse)?;
+                self.process_attributes(handle, request, &request.changes)?;
                 Ok(handle.response.is_modified(request, request.changes.clone()))
             },
 
@@ -113,3 +134,14 @@ impl IsAction for DirectoryAction {
         }
     }
 }
+
+impl DirectoryAction {
+
+    fn process_attributes(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, changes: &[Field]) -> Result<(), Arc<TaskResponse>> {
+        match self.exclude.is_empty() {
+            true  => handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, changes, self.recurse),
+            false => handle.remote.process_common_file_attributes_excluding(request, &self.path, &self.attributes, changes, &self.exclude)
+        }
+    }
+
+}
@@ -34,6 +34,7 @@ pub struct FileTask {
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
+#[derive(Debug)]
 struct FileAction {
     pub path: String,
     pub remove: bool,
@@ -21,6 +21,7 @@ use serde::Deserialize;
 use std::sync::Arc;
 use std::vec::Vec;
 use crate::tasks::files::Recurse;
+use crate::tasks::cmd_library::{screen_trusted_input,screen_general_input_loose,screen_general_input_strict,shell_quote};
 use std::collections::HashMap;
 
 const MODULE: &str = "git";
@@ -32,21 +33,40 @@ pub struct GitTask {
     pub repo: String,
     pub path: String,
     pub branch: Option<String>,
+    pub version: Option<String>,
     pub ssh_options: Option<HashMap<String,String>>,
+    pub key_file: Option<String>,
     pub accept_keys: Option<String>,
     pub update: Option<String>,
+    pub clean: Option<String>,
+    pub force: Option<String>,
+    pub sparse_paths: Option<Vec<String>>,
+    pub config: Option<HashMap<String,String>>,
+    pub verify: Option<String>,
+    pub depth: Option<String>,
+    pub submodules: Option<String>,
     pub attributes: Option<FileAttributesInput>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct GitAction {
     pub repo: String,
     pub path: String,
     pub branch: String,
+    pub version: Option<String>,
     pub ssh_options: Vec<String>,
+    pub key_file: Option<String>,
     pub accept_keys: bool,
     pub update: bool,
+    pub clean: bool,
+    pub force: bool,
+    pub sparse_paths: Option<Vec<String>>,
+    pub config: Vec<(String,String)>,
+    pub verify: bool,
+    pub depth: u64,
+    pub submodules: bool,
     pub attributes: Option<FileAttributesEvaluated>,
 }
 
@@ -57,19 +77,75 @@ impl IsTask for GitTask {
     fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
 
     fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        // a version is checked out with a detached HEAD, there is no branch left to follow
+        if self.branch.is_some() && self.version.is_some() {
+            return Err(handle.response.is_failed(request, &String::from("branch and version cannot both be set")));
+        }
+        let version = match handle.template.string_option_no_spaces(request, tm, &String::from("version"), &self.version)? {
+            Some(x) => Some(screen_git_input(handle, request, "version", x)?),
+            None => None
+        };
+        let depth = handle.template.integer_option_to_integer(request, tm, &String::from("depth"), &self.depth, 0)?;
         Ok(
             EvaluatedTask {
                 action: Arc::new(GitAction {
-                    repo:         handle.template.string(request, tm, &String::from("repo"), &self.repo)?,
+                    repo:         screen_git_input(handle, request, "repo", handle.template.string(request, tm, &String::from("repo"), &self.repo)?)?,
                     path:         handle.template.path(request, tm, &String::from("path"), &self.path)?,
-                    branch:       handle.template.string_option_default(request, tm, &String::from("branch"), &self.branch, &String::from("main"))?,
+                    branch:       screen_git_input(handle, request, "branch", handle.template.string_option_default(request, tm, &String::from("branch"), &self.branch, &String::from("main"))?)?,
+                    version,
+                    key_file:     match &self.key_file {
+                        Some(x) => Some(handle.template.path(request, tm, &String::from("key_file"), x)?),
+                        None => None
+                    },
                     accept_keys:  handle.template.boolean_option_default_true(request, tm, &String::from("accept_keys"), &self.accept_keys)?,
                     update:       handle.template.boolean_option_default_true(request, tm, &String::from("update"), &self.update)?,
+                    clean:        handle.template.boolean_option_default_false(request, tm, &String::from("clean"), &self.clean)?,
+                    force:        handle.template.boolean_option_default_false(request, tm, &String::from("force"), &self.force)?,
+                    sparse_paths: match &self.sparse_paths {
+                        Some(input_paths) => {
+                            let mut paths : Vec<String> = Vec::new();
+                            for input_path in input_paths.iter() {
+                                paths.push(handle.template.string_no_spaces(request, tm, &String::from("sparse_paths"), input_path)?);
+                            }
+                            Some(paths)
+                        },
+                        None => None
+                    },
+                    config:       {
+                        let mut config : Vec<(String,String)> = Vec::new();
+                        if let Some(input_config) = &self.config {
+                            for (k,v) in input_config.iter() {
+                                let key = handle.template.string_no_spaces(request, tm, &String::from("config"), k)?;
+                                if ! key.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_') {
+                                    return Err(handle.response.is_failed(request, &format!("field (config): not a valid git config key: {}", key)));
+                                }
+                                let value = handle.template.string_unsafe_for_shell(request, tm, &String::from("config"), v)?;
+                                let value = match screen_trusted_input(&value) {
+                                    Ok(x) => x,
+                                    Err(y) => { return Err(handle.response.is_failed(request, &format!("field (config), {}: {}", key, y))); }
+                                };
+                                config.push((key, value));
+                            }
+                        }
+                        // the input is a map, keep the commands in a predictable order
+                        config.sort();
+                        config
+                    },
+                    verify:       handle.template.boolean_option_default_false(request, tm, &String::from("verify"), &self.verify)?,
+                    depth,
+                    submodules:   handle.template.boolean_option_default_false(request, tm, &String::from("submodules"), &self.submodules)?,
                     attributes:   FileAttributesInput::template(handle, request, tm, &self.attributes)?,
                     ssh_options:  {
                         let mut options : Vec<String> = Vec::new();
                         if let Some(input_options) = &self.ssh_options {
                             for (k,v) in input_options.iter() {
+                                // these end up inside GIT_SSH_COMMAND, which the shell splits again
+                                if ! k.chars().all(|c| c.is_ascii_alphanumeric()) {
+                                    return Err(handle.response.is_failed(request, &format!("field (ssh_options): not a valid ssh option: {}", k)));
+                                }
+                                if v.contains(' ') || v.contains('\'') || screen_general_input_strict(v).is_err() {
+                                    return Err(handle.response.is_failed(request, &format!("field (ssh_options), {}: illegal characters found", k)));
+                                }
                                 options.push(format!("-o {}={}", k, v))
                             }
                         };
@@ -94,6 +170,14 @@ impl IsAction for GitAction {
             TaskRequestType::Query => {
 
                 let mut changes : Vec<Field> = Vec::new();
+
+                // a deploy key that is not there would only fail later with a much less clear ssh error
+                if let Some(key_file) = &self.key_file {
+                    if handle.remote.get_mode(request, key_file)?.is_none() {
+                        return Err(handle.response.is_failed(request, &format!("key_file not found: {}", key_file)));
+                    }
+                }
+
                 // see if the remote directory exists
                 let remote_mode = handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, Recurse::Yes)?;                 
 
@@ -105,36 +189,79 @@ impl IsAction for GitAction {
                     // so more checking needed...
                     _ => {
                         
-                        let git_path = match self.path.ends_with("/") {
-                            // could have used pathbuf, but ... anyway ...
-                            true => format!("{}{}", self.path, String::from(".git")),
-                            false => format!("{}/{}", self.path, String::from(".git")),
-                        };
-
-                        match handle.remote.get_mode(request, &git_path)? {
+                        match handle.remote.get_mode(request, &self.get_git_path())? {
 
                             // the repo does not exist, so do everything
                             None => Ok(handle.response.needs_creation(request)),
 
                             // the repo does exist, see what needs to change depending on parameters
-                            // minor FIXME: this module does not currently deal with repo URLs changing
-                            // when a git directory has already been checked out at a given location
                             _ => {
-                                let local_version = self.get_local_version(handle, request)?;
-                                if local_version.is_none() {
-                                    changes.push(Field::Version);
+                                // a checkout made from another URL, such as an old mirror, is repointed
+                                if ! self.get_local_remote_url(handle, request)?.is_some_and(|x| x.eq(&self.repo)) {
+                                    changes.push(Field::Url);
                                 }
-                                else {
-                                    let remote_version = self.get_remote_version(handle, request)?;
-                                    let local_branch = self.get_local_branch(handle, request)?;
-                                    if self.update && (! remote_version.eq(&local_version.unwrap())) {
-                                        changes.push(Field::Version);
+
+                                // locally modified tracked files are drift too, and would make a pull fail later
+                                if ! self.get_dirty_files(handle, request)?.is_empty() {
+                                    changes.push(Field::Content);
+                                }
+
+                                if let Some(sparse_paths) = &self.sparse_paths {
+                                    let local_paths = self.get_sparse_paths(handle, request)?;
+                                    if ! Self::same_sparse_paths(&local_paths, sparse_paths) {
+                                        changes.push(Field::SparsePaths);
+                                    }
+                                }
+
+                                for (key, value) in self.config.iter() {
+                                    if ! self.is_config_set(handle, request, key, value)? {
+                                        changes.push(Field::Config);
+                                        break;
                                     }
-                                    if ! local_branch.eq(&self.branch) {
-                                        changes.push(Field::Branch);
+                                }
+
+                                let local_version = self.get_local_version(handle, request)?;
+                                match (&local_version, &self.version) {
+                                    (None, _) => {
+                                        changes.push(Field::Version);
+                                    },
+                                    (Some(local), Some(sha)) if is_commit_sha(sha) => {
+                                        // a commit never moves, so there is nothing to ask the remote
+                                        if ! local.eq(&sha.to_ascii_lowercase()) {
+                                            changes.push(Field::Version);
+                                        }
+                                    },
+                                    (Some(local), Some(tag)) => {
+                                        // pinned to a tag, so HEAD is expected to be detached and only the commit matters
+                                        let tag_version = self.get_remote_tag_version(handle, request, tag)?;
+                                        if ! tag_version.eq(local) {
+                                            changes.push(Field::Version);
+                                        }
+                                    },
+                                    (Some(local), None) => {
+                                        let remote_version = self.get_remote_version(handle, request)?;
+                                        let local_branch = self.get_local_branch(handle, request)?;
+                                        if self.update && (! remote_version.eq(local)) {
+                                            changes.push(Field::Version);
+                                        }
+                                        // a detached HEAD reports its branch as "HEAD", which never equals the branch we want,
+                                        // so checking out the requested branch reconciles it
+                                        if ! local_branch.eq(&self.branch) {
+                                            changes.push(Field::Branch);
+                                        }
                                     }
                                 }
 
+                                if self.submodules && self.has_stale_submodules(handle, request)? {
+                                    changes.push(Field::Submodules);
+                                }
+
+                                // a checkout that is about to move is verified before it moves instead
+                                if self.verify && ! changes.contains(&Field::Version) && ! changes.contains(&Field::Branch) 
+                                    && self.get_verify_error(handle, request, "HEAD")?.is_some() {
+                                    changes.push(Field::Signature);
+                                }
+
                                 if !changes.is_empty() {
                                     Ok(handle.response.needs_modification(request, &changes))
                                 } else {
@@ -151,18 +278,83 @@ impl IsAction for GitAction {
                 handle.remote.create_directory(request, &self.path)?;
                 handle.remote.process_all_common_file_attributes(request, &self.path, &self.attributes, Recurse::Yes)?;
                 self.clone(handle, request)?;
-                self.switch_branch(handle, request)?;                           
+                if self.version.is_some() {
+                    self.fetch(handle, request)?;
+                }
+                // with verify the clone has nothing checked out yet, and one that fails is removed again
+                // so that the next run starts over instead of finding an unverified checkout
+                if let Err(failed) = self.verify_signature(handle, request, &self.get_origin_branch()) {
+                    handle.remote.delete_directory(request, &self.get_git_path(), Recurse::Yes)?;
+                    return Err(failed);
+                }
+                if self.sparse_paths.is_some() {
+                    self.set_sparse_paths(handle, request)?;
+                }
+                match &self.version {
+                    Some(_) => { self.checkout_version(handle, request)?; },
+                    None => { self.switch_branch(handle, request)?; }
+                };
+                self.update_submodules(handle, request)?;
+                self.set_config(handle, request)?;
                 Ok(handle.response.is_created(request))
             },
 
             TaskRequestType::Modify => {
 
                 handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, &request.changes, Recurse::Yes)?;
-                if request.changes.contains(&Field::Branch) || request.changes.contains(&Field::Version) {
-                    self.pull(handle,request)?;
+                if request.changes.contains(&Field::Config) {
+                    self.set_config(handle, request)?;
+                }
+                if request.changes.contains(&Field::Url) {
+                    // before anything is fetched, so the new URL is the one it comes from
+                    self.set_remote_url(handle, request)?;
+                }
+                let moves = request.changes.contains(&Field::Branch) || request.changes.contains(&Field::Version) || request.changes.contains(&Field::Signature);
+                // nothing in the checkout changes until what it is about to become has been fetched and verified
+                let fetched = self.verify && moves;
+                if fetched {
+                    self.fetch(handle, request)?;
+                    self.verify_signature(handle, request, &self.get_origin_branch())?;
+                }
+                if request.changes.contains(&Field::Content) {
+                    match self.force {
+                        true => { self.reset(handle, request)?; },
+                        false => {
+                            let dirty = self.get_dirty_files(handle, request)?;
+                            return Err(handle.response.is_failed(request, &format!("checkout has local modifications, set force to discard them: {}", dirty.join(", "))));
+                        }
+                    }
+                }
+                if request.changes.contains(&Field::SparsePaths) {
+                    self.set_sparse_paths(handle, request)?;
+                }
+                if self.clean && (request.changes.contains(&Field::Branch) || request.changes.contains(&Field::Version)) {
+                    // untracked files could collide with incoming ones
+                    self.clean_untracked(handle, request)?;
+                }
+                if self.version.is_some() {
+                    // tags are checked out directly, there is no branch to pull
+                    if request.changes.contains(&Field::Version) || request.changes.contains(&Field::Signature) {
+                        if ! fetched {
+                            self.fetch(handle, request)?;
+                        }
+                        self.checkout_version(handle, request)?;
+                    }
                 }
-                if request.changes.contains(&Field::Branch) {
-                    self.switch_branch(handle, request)?;
+                else {
+                    if request.changes.contains(&Field::Branch) {
+                        // switch first, a pull cannot work from a detached HEAD or update a branch we are not on
+                        if ! fetched {
+                            self.fetch(handle, request)?;
+                        }
+                        self.switch_branch(handle, request)?;
+                    }
+                    if moves {
+                        self.pull(handle, request, fetched)?;
+                    }
+                }
+                if moves || request.changes.contains(&Field::Submodules) {
+                    self.update_submodules(handle, request)?;
                 }
                 Ok(handle.response.is_modified(request, request.changes.clone()))
             },
@@ -194,12 +386,35 @@ impl GitAction {
                 true  => String::from(" -o StrictHostKeyChecking=accept-new"),
                 false => String::from("")
             };
-            format!("GIT_SSH_COMMAND=\"ssh {}{}\" GIT_TERMINAL_PROMPT=0", options, accept_keys)
+            // only offer the deploy key, not whatever else is in the agent or ~/.ssh
+            let key_file = match &self.key_file {
+                Some(x) => format!(" -i {} -o IdentitiesOnly=yes", shell_quote(x)),
+                None => String::from("")
+            };
+            format!("GIT_SSH_COMMAND={} GIT_TERMINAL_PROMPT=0", shell_quote(&format!("ssh {}{}{}", options, accept_keys, key_file)))
+        }
+    }
+
+    // every command here is run with run_unsafe, which does not screen it, so all values are quoted
+
+    fn git_c(&self) -> String {
+        format!("git -C {}", shell_quote(&self.path))
+    }
+
+    fn get_git_path(&self) -> String {
+        match self.path.ends_with("/") {
+            // could have used pathbuf, but ... anyway ...
+            true => format!("{}{}", self.path, String::from(".git")),
+            false => format!("{}/{}", self.path, String::from(".git")),
         }
     }
 
+    fn get_origin_branch(&self) -> String {
+        format!("origin/{}", self.branch)
+    }
+
     fn get_local_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<String>, Arc<TaskResponse>> {
-        let cmd = format!("git -C {} rev-parse HEAD", self.path);
+        let cmd = format!("{} rev-parse HEAD", self.git_c());
         let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
         let (rc, out) = cmd_info(&result);
         if rc == 0 {
@@ -209,9 +424,29 @@ impl GitAction {
         }
     }
 
+    fn get_local_remote_url(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<String>, Arc<TaskResponse>> {
+        // a checkout without an origin remote fails here
+        let cmd = format!("{} remote get-url origin", self.git_c());
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        match rc {
+            0 => Ok(Some(out.trim().to_string())),
+            _ => Ok(None)
+        }
+    }
+
+    fn set_remote_url(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let cmd = match self.get_local_remote_url(handle, request)? {
+            Some(_) => format!("{} remote set-url origin {}", self.git_c(), shell_quote(&self.repo)),
+            None => format!("{} remote add origin {}", self.git_c(), shell_quote(&self.repo))
+        };
+        handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        Ok(())
+    }
+
     fn get_remote_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<String, Arc<TaskResponse>> {
         let ssh_options = self.get_ssh_options_string();
-        let cmd = format!("{} git ls-remote {} | head -n 1 | cut -f 1", ssh_options, self.repo);
+        let cmd = format!("{} git ls-remote {} {} | head -n 1 | cut -f 1", ssh_options, shell_quote(&self.repo), shell_quote(&format!("refs/heads/{}", self.branch)));
         let result = match self.is_ssh_repo() {
             true  => handle.remote.run_forwardable(request, &cmd, CheckRc::Checked)?,
             false => handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?
@@ -221,9 +456,82 @@ impl GitAction {
     }
     
 
-    fn pull(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+    fn get_remote_tag_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tag: &String) -> Result<String, Arc<TaskResponse>> {
+        // annotated tags have their own object, the ^{} line is the commit the tag points at, 
+        // lightweight tags only have the one line
         let ssh_options = self.get_ssh_options_string();
-        let cmd = format!("{} git -C {} pull", ssh_options, self.path);
+        let cmd = format!("{} git ls-remote {} {} {}", ssh_options, shell_quote(&self.repo), shell_quote(&format!("refs/tags/{}", tag)), shell_quote(&format!("refs/tags/{}^{{}}", tag)));
+        let result = match self.is_ssh_repo() {
+            true  => handle.remote.run_forwardable(request, &cmd, CheckRc::Checked)?,
+            false => handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?
+        };
+        let (_rc, out) = cmd_info(&result);
+        let mut found : Option<String> = None;
+        for line in out.lines() {
+            let mut tokens = line.split_whitespace();
+            let (sha, refname) = match (tokens.next(), tokens.next()) {
+                (Some(a), Some(b)) => (a, b),
+                _ => { continue; }
+            };
+            if refname.ends_with("^{}") {
+                return Ok(sha.to_string());
+            }
+            found = Some(sha.to_string());
+        }
+        match found {
+            Some(x) => Ok(x),
+            None => match tag.chars().all(|c| c.is_ascii_hexdigit()) {
+                true  => Err(handle.response.is_failed(request, &format!("version {} is not a tag in {}, a commit must be given as a full 40 character SHA", tag, self.repo))),
+                false => Err(handle.response.is_failed(request, &format!("version {} is not a tag in {}", tag, self.repo)))
+            }
+        }
+    }
+
+    fn pull(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, fetched: bool) -> Result<(), Arc<TaskResponse>> {
+        if self.depth > 0 {
+            // a merge needs history a shallow clone does not have, so the branch is fetched at the same depth
+            // and the checkout moved to it. local modifications were already refused or reset by now.
+            if ! fetched {
+                self.fetch(handle, request)?;
+            }
+            let cmd = format!("{} reset --hard --quiet {}", self.git_c(), shell_quote(&self.get_origin_branch()));
+            handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        }
+        else if fetched {
+            // a pull would fetch again and could bring in a commit newer than the one that was verified
+            let cmd = format!("{} merge --ff-only --quiet {}", self.git_c(), shell_quote(&self.get_origin_branch()));
+            handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        }
+        else {
+            let ssh_options = self.get_ssh_options_string();
+            let cmd = format!("{} {} pull", ssh_options, self.git_c());
+            match self.is_ssh_repo() {
+                true  => handle.remote.run_forwardable(request, &cmd, CheckRc::Checked)?,
+                false => handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?
+            };
+        }
+        if self.sparse_paths.is_some() {
+            // files that newly match the sparse patterns after a pull are not checked out until the patterns are reapplied
+            let cmd = format!("{} sparse-checkout reapply", self.git_c());
+            handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        }
+        Ok(())
+    }
+
+    fn has_stale_submodules(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<bool, Arc<TaskResponse>> {
+        // - is a submodule that was never initialized, + one checked out at another commit than recorded
+        let cmd = format!("{} submodule status --recursive", self.git_c());
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        let (_rc, out) = cmd_info(&result);
+        Ok(out.lines().any(|x| x.starts_with('+') || x.starts_with('-')))
+    }
+
+    fn update_submodules(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        if ! self.submodules {
+            return Ok(());
+        }
+        let ssh_options = self.get_ssh_options_string();
+        let cmd = format!("{} {} submodule update --init --recursive", ssh_options, self.git_c());
         match self.is_ssh_repo() {
             true  => handle.remote.run_forwardable(request, &cmd, CheckRc::Checked)?,
             false => handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?
@@ -231,8 +539,173 @@ impl GitAction {
         Ok(())
     }
 
+    fn get_sparse_paths(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Vec<String>, Arc<TaskResponse>> {
+        // a checkout that was never made sparse fails here, which is the same as having no paths configured
+        let cmd = format!("{} sparse-checkout list", self.git_c());
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        match rc {
+            0 => Ok(out.lines().map(|x| x.trim().to_string()).filter(|x| ! x.is_empty()).collect()),
+            _ => Ok(Vec::new())
+        }
+    }
+
+    fn same_sparse_paths(local: &[String], wanted: &[String]) -> bool {
+        // cone mode reports directories without surrounding slashes and in its own order
+        let normalize = |paths: &[String]| {
+            let mut out : Vec<String> = paths.iter().map(|x| x.trim_matches('/').to_string()).collect();
+            out.sort();
+            out.dedup();
+            out
+        };
+        normalize(local) == normalize(wanted)
+    }
+
+    // git only honors safe.directory from the global or system config, never from the repo being protected.
+    // there it is a list shared by every checkout of the user, so our entry is added next to the others
+
+    fn is_safe_directory(&self, key: &str) -> bool {
+        key.eq_ignore_ascii_case("safe.directory")
+    }
+
+    fn get_config_scope(&self, key: &str) -> String {
+        match self.is_safe_directory(key) {
+            true  => String::from("--global"),
+            false => String::from("--local")
+        }
+    }
+
+    fn get_config(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, key: &str) -> Result<Vec<String>, Arc<TaskResponse>> {
+        // a key that is not set exits with 1
+        let cmd = format!("{} config {} --get-all {}", self.git_c(), self.get_config_scope(key), key);
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        match rc {
+            0 => Ok(out.lines().map(|x| x.trim().to_string()).collect()),
+            _ => Ok(Vec::new())
+        }
+    }
+
+    fn is_config_set(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, key: &str, value: &String) -> Result<bool, Arc<TaskResponse>> {
+        let values = self.get_config(handle, request, key)?;
+        match self.is_safe_directory(key) {
+            true  => Ok(values.contains(value)),
+            false => Ok(values.len() == 1 && values[0].eq(value))
+        }
+    }
+
+    fn set_config(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        // --replace-all so that a key with several values ends up with exactly the one requested
+        for (key, value) in self.config.iter() {
+            let cmd = match self.is_safe_directory(key) {
+                true => match self.is_config_set(handle, request, key, value)? {
+                    true => { continue; },
+                    false => format!("{} config {} --add {} {}", self.git_c(), self.get_config_scope(key), key, shell_quote(value))
+                },
+                false => format!("{} config {} --replace-all {} {}", self.git_c(), self.get_config_scope(key), key, shell_quote(value))
+            };
+            handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        }
+        Ok(())
+    }
+
+    fn set_sparse_paths(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let paths : Vec<String> = self.sparse_paths.as_ref().unwrap().iter().map(|x| shell_quote(x)).collect();
+        let cmd = format!("{} sparse-checkout set --cone {}", self.git_c(), paths.join(" "));
+        handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        Ok(())
+    }
+
+    fn get_verify_error(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, head: &str) -> Result<Option<String>, Arc<TaskResponse>> {
+        // a pinned tag carries its own signature, a pinned commit or the branch head (HEAD, or the fetched branch
+        // before moving to it) has to be signed itself. either way gpg needs the signer's public key in the keyring
+        // of the user running git
+        let cmd = match &self.version {
+            Some(tag) if ! is_commit_sha(tag) => format!("{} verify-tag {}", self.git_c(), shell_quote(tag)),
+            Some(sha) => format!("{} verify-commit {}", self.git_c(), shell_quote(sha)),
+            None => format!("{} verify-commit {}", self.git_c(), shell_quote(head))
+        };
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        match rc {
+            0 => Ok(None),
+            // an unsigned commit fails without saying anything
+            _ => match out.trim().is_empty() {
+                true  => Ok(Some(String::from("no signature found"))),
+                false => Ok(Some(out))
+            }
+        }
+    }
+
+    fn verify_signature(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, head: &str) -> Result<(), Arc<TaskResponse>> {
+        if ! self.verify {
+            return Ok(());
+        }
+        match self.get_verify_error(handle, request, head)? {
+            None => Ok(()),
+            Some(out) => {
+                let what = match &self.version {
+                    Some(tag) if ! is_commit_sha(tag) => format!("tag {}", tag),
+                    Some(sha) => format!("commit {}", sha),
+                    None => head.to_owned()
+                };
+                Err(handle.response.is_failed(request, &format!("signature of {} in {} could not be verified: {}", what, self.path, out.trim())))
+            }
+        }
+    }
+
+    fn fetch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let ssh_options = self.get_ssh_options_string();
+        let cmd = match self.depth {
+            0 => format!("{} {} fetch --tags", ssh_options, self.git_c()),
+            // a single branch clone only fetches the branch it was made from, so name what is wanted
+            _ => match &self.version {
+                Some(sha) if is_commit_sha(sha) => format!("{} {} fetch --depth {} origin {}", ssh_options, self.git_c(), self.depth, shell_quote(sha)),
+                Some(tag) => format!("{} {} fetch --depth {} origin tag {}", ssh_options, self.git_c(), self.depth, shell_quote(tag)),
+                None => format!("{} {} fetch --depth {} origin {}", ssh_options, self.git_c(), self.depth, shell_quote(&format!("+refs/heads/{}:refs/remotes/origin/{}", self.branch, self.branch)))
+            }
+        };
+        match self.is_ssh_repo() {
+            true  => handle.remote.run_forwardable(request, &cmd, CheckRc::Checked)?,
+            false => handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?
+        };
+        Ok(())
+    }
+
+    fn checkout_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        // git switch only works with branches, so tags and commits use checkout
+        let cmd = format!("{} checkout --quiet {}", self.git_c(), shell_quote(self.version.as_ref().unwrap()));
+        handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        Ok(())
+    }
+
+    fn get_dirty_files(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Vec<String>, Arc<TaskResponse>> {
+        // untracked files are not considered here, see the clean option for those. with submodules, one at another
+        // commit is put back by update_submodules rather than counted as a modification
+        let ignore_submodules = match self.submodules {
+            true  => " --ignore-submodules",
+            false => ""
+        };
+        let cmd = format!("{} status --porcelain --untracked-files=no{}", self.git_c(), ignore_submodules);
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        let (_rc, out) = cmd_info(&result);
+        Ok(out.lines().map(|x| x.trim().to_string()).filter(|x| ! x.is_empty()).collect())
+    }
+
+    fn reset(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let cmd = format!("{} reset --hard --quiet", self.git_c());
+        handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        Ok(())
+    }
+
+    fn clean_untracked(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let cmd = format!("{} clean -fd", self.git_c());
+        handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        Ok(())
+    }
+
     fn get_local_branch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<String, Arc<TaskResponse>> {
-        let cmd = format!("git -C {} rev-parse --abbrev-ref HEAD", self.path);
+        let cmd = format!("{} rev-parse --abbrev-ref HEAD", self.git_c());
         let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
         let (_rc, out) = cmd_info(&result);
         Ok(out)
@@ -241,7 +714,23 @@ impl GitAction {
     fn clone(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(),Arc<TaskResponse>> {
         let ssh_options = self.get_ssh_options_string();
         handle.remote.create_directory(request, &self.path)?;
-        let cmd = format!("{} git clone {} {}", ssh_options, self.repo, self.path);
+        // --sparse leaves out everything below the top level until set_sparse_paths says which directories to add
+        let sparse = match self.sparse_paths.is_some() {
+            true  => " --sparse",
+            false => ""
+        };
+        // with verify nothing is checked out until the signature has been checked
+        let no_checkout = match self.verify {
+            true  => " --no-checkout",
+            false => ""
+        };
+        // clone cannot start from a commit, so with one the default branch is cloned and the commit fetched after
+        let shallow = match (self.depth, &self.version) {
+            (0, _) => String::from(""),
+            (_, Some(sha)) if is_commit_sha(sha) => format!(" --depth {} --single-branch", self.depth),
+            (_, version) => format!(" --depth {} --single-branch --branch {}", self.depth, shell_quote(version.as_ref().unwrap_or(&self.branch)))
+        };
+        let cmd = format!("{} git clone{}{}{} -- {} {}", ssh_options, sparse, no_checkout, shallow, shell_quote(&self.repo), shell_quote(&self.path));
         match self.is_ssh_repo() {
             true =>  handle.remote.run_forwardable(request, &cmd, CheckRc::Checked)?,
             false => handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?
@@ -250,12 +739,341 @@ impl GitAction {
     }
 
     fn switch_branch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
-        let cmd = format!("git -C {} switch {}", self.path, self.branch);
+        // switch only guesses the remote branch from the fetch refspec, which in a single branch clone
+        // does not cover any other branch, so start from what fetch brought in
+        let cmd = match self.depth {
+            0 => format!("{} switch {}", self.git_c(), shell_quote(&self.branch)),
+            _ => format!("{} switch -C {} {}", self.git_c(), shell_quote(&self.branch), shell_quote(&format!("origin/{}", self.branch)))
+        };
         handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
         Ok(())
     }
 
 }
+
+// repo, branch and version are quoted in every command, but even a field marked trusted keeps the loose check
+// and cannot start with a dash, where git would read it as an option
+
+fn screen_git_input(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, field: &str, value: String) -> Result<String, Arc<TaskResponse>> {
+    if value.starts_with('-') {
+        return Err(handle.response.is_failed(request, &format!("field ({}): cannot start with a dash", field)));
+    }
+    match screen_general_input_loose(&value) {
+        Ok(x) => Ok(x),
+        Err(y) => Err(handle.response.is_failed(request, &format!("field ({}): {}", field, y)))
+    }
+}
+
+// a version is taken as a commit only when it is a full SHA-1, anything else is a tag. tags such as 20240101
+// or deadbeef look just like an abbreviated SHA, and a shallow fetch cannot ask for one anyway
+
+fn is_commit_sha(version: &str) -> bool {
+    version.len() == 40 && version.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    fn new_action(version: Option<&str>) -> GitAction {
+        GitAction {
+            repo: String::from("https://example.com/repo.git"),
+            path: String::from("/opt/repo"),
+            branch: String::from("main"),
+            version: version.map(|x| x.to_string()),
+            ssh_options: Vec::new(),
+            key_file: None,
+            accept_keys: false,
+            update: true,
+            clean: false,
+            force: false,
+            sparse_paths: None,
+            config: Vec::new(),
+            verify: false,
+            depth: 0,
+            submodules: false,
+            attributes: None
+        }
+    }
+
+    fn new_task(repo: &str, branch: Option<&str>) -> GitTask {
+        GitTask {
+            name: None, repo: repo.to_owned(), path: String::from("/opt/repo"), branch: branch.map(|x| x.to_owned()),
+            version: None, ssh_options: None, key_file: None, accept_keys: None, update: None, clean: None, force: None,
+            sparse_paths: None, config: None, verify: None, depth: None, submodules: None, attributes: None, with: None, and: None
+        }
+    }
+
+    #[test]
+    fn test_shell_characters_are_refused_even_when_trusted() {
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let request = TaskRequest::validate();
+        handle.template.set_trusted_fields(&Some(vec![String::from("repo"), String::from("branch")]));
+        assert!(new_task("https://example.com/repo.git", None).evaluate(&handle, &request, TemplateMode::Strict).is_ok());
+        for repo in ["x; rm -rf ~", "https://example.com/$(id).git", "https://example.com/`id`.git", "--upload-pack=touch /tmp/x"] {
+            assert!(new_task(repo, None).evaluate(&handle, &request, TemplateMode::Strict).is_err(), "{}", repo);
+        }
+        assert!(new_task("https://example.com/repo.git", Some("main && reboot")).evaluate(&handle, &request, TemplateMode::Strict).is_err());
+        assert!(new_task("https://example.com/repo.git", Some("-f")).evaluate(&handle, &request, TemplateMode::Strict).is_err());
+
+        let mut task = new_task("https://example.com/repo.git", None);
+        task.ssh_options = Some(HashMap::from([(String::from("ProxyCommand"), String::from("$(reboot)"))]));
+        assert!(task.evaluate(&handle, &request, TemplateMode::Strict).is_err());
+    }
+
+    #[test]
+    fn test_values_are_quoted_in_commands() {
+        let (handle, connection) = TaskHandle::scripted(Vec::new());
+        let mut action = new_action(None);
+        action.repo = String::from("https://example.com/it's here.git");
+        action.path = String::from("/opt/my repo");
+        action.branch = String::from("release 1");
+        assert!(action.dispatch(&handle, &TaskRequest::create(&SudoDetails::none(), false)).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.iter().any(|x| x.ends_with("git clone -- 'https://example.com/it'\\''s here.git' '/opt/my repo'")));
+        assert!(commands.contains(&String::from("git -C '/opt/my repo' switch 'release 1'")));
+    }
+
+    #[test]
+    fn test_create_checks_out_version() {
+        let (handle, connection) = TaskHandle::scripted(Vec::new());
+        let request = TaskRequest::create(&SudoDetails::none(), false);
+        assert!(new_action(Some("v1")).dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.iter().any(|x| x.ends_with("git clone -- 'https://example.com/repo.git' '/opt/repo'")));
+        assert!(commands.contains(&String::from("git -C '/opt/repo' checkout --quiet 'v1'")));
+        assert!(! commands.iter().any(|x| x.contains("switch")));
+    }
+
+    #[test]
+    fn test_sparse_paths_on_create_and_query() {
+        let (handle, connection) = TaskHandle::scripted(Vec::new());
+        let mut action = new_action(None);
+        action.sparse_paths = Some(vec![String::from("services/api"), String::from("lib/")]);
+        let request = TaskRequest::create(&SudoDetails::none(), false);
+        assert!(action.dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.iter().any(|x| x.ends_with("git clone --sparse -- 'https://example.com/repo.git' '/opt/repo'")));
+        assert!(commands.contains(&String::from("git -C '/opt/repo' sparse-checkout set --cone 'services/api' 'lib/'")));
+
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("sparse-checkout list", 0, "lib\nservices/web"),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "abc123"),
+        ]);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let response = action.dispatch(&handle, &request).expect("query");
+        assert_eq!(response.changes, vec![Field::SparsePaths]);
+    }
+
+    #[test]
+    fn test_config_mismatch_is_a_change() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("config --local --get-all user.email", 0, "old@example.com"),
+            ("config --global --get-all safe.directory", 0, "/srv/other\n/srv/another"),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "abc123"),
+        ]);
+        let mut action = new_action(None);
+        action.config = vec![
+            (String::from("safe.directory"), String::from("/opt/repo")),
+            (String::from("user.email"), String::from("deploy@example.com"))
+        ];
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Config]);
+
+        let request = TaskRequest::modify(&SudoDetails::none(), false, response.changes.clone());
+        assert!(action.dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        // the other safe.directory entries of the user are left alone
+        assert!(commands.contains(&String::from("git -C '/opt/repo' config --global --add safe.directory '/opt/repo'")));
+        assert!(! commands.iter().any(|x| x.contains("--replace-all safe.directory")));
+        assert!(commands.contains(&String::from("git -C '/opt/repo' config --local --replace-all user.email 'deploy@example.com'")));
+
+        // one entry among several is enough for safe.directory
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("config --local --get-all user.email", 0, "deploy@example.com"),
+            ("config --global --get-all safe.directory", 0, "/srv/other\n/opt/repo"),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "abc123"),
+        ]);
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.status, TaskStatus::IsMatched);
+    }
+
+    #[test]
+    fn test_query_reports_dirty_checkout() {
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, " M README"),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "abc123"),
+        ]);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let response = new_action(None).dispatch(&handle, &request).expect("query");
+        assert_eq!(response.changes, vec![Field::Content]);
+    }
+
+    #[test]
+    fn test_changed_repo_url_is_set_before_pulling() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://old-mirror.example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "def456"),
+        ]);
+        let action = new_action(None);
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Url, Field::Version]);
+
+        let request = TaskRequest::modify(&SudoDetails::none(), false, response.changes.clone());
+        assert!(action.dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        let set_url = commands.iter().position(|x| x.eq("git -C '/opt/repo' remote set-url origin 'https://example.com/repo.git'")).expect("set-url");
+        let pull = commands.iter().position(|x| x.ends_with("git -C '/opt/repo' pull")).expect("pull");
+        assert!(set_url < pull);
+    }
+
+    #[test]
+    fn test_stale_submodules_are_updated() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("submodule status", 0, " 0636ffa vendor/a (heads/main)\n+b024dea vendor/b (heads/main)"),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "abc123"),
+        ]);
+        let mut action = new_action(None);
+        action.submodules = true;
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Submodules]);
+
+        let request = TaskRequest::modify(&SudoDetails::none(), false, response.changes.clone());
+        assert!(action.dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.iter().any(|x| x.ends_with("git -C '/opt/repo' status --porcelain --untracked-files=no --ignore-submodules")));
+        assert!(commands.last().unwrap().ends_with("git -C '/opt/repo' submodule update --init --recursive"));
+        assert!(! commands.iter().any(|x| x.ends_with(" pull")));
+    }
+
+    #[test]
+    fn test_commit_version_is_compared_without_asking_the_remote() {
+        assert!(is_commit_sha("89F073D4B69EAE16573BC6F67D2C1C28D253BC87"));
+        for tag in ["v1.2.0", "cafe", "deadline", "deadbeef", "1234567", "20240101", "89f073d"] {
+            assert!(! is_commit_sha(tag), "{}", tag);
+        }
+
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("rev-parse HEAD", 0, "89f073d4b69eae16573bc6f67d2c1c28d253bc87"),
+        ]);
+        let response = new_action(Some("89F073D4B69EAE16573BC6F67D2C1C28D253BC87")).dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.status, TaskStatus::IsMatched);
+        let response = new_action(Some("bd9dff6ffd2a81f946ebb1b5b8dd2f53cc01a2e6")).dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Version]);
+        assert!(! connection.lock().unwrap().get_commands().iter().any(|x| x.contains("ls-remote")));
+
+        // a tag that happens to be all hex digits is still looked up as a tag, and matches once checked out
+        let (handle, _) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("rev-parse HEAD", 0, "89f073d4b69eae16573bc6f67d2c1c28d253bc87"),
+            ("ls-remote", 0, "89f073d4b69eae16573bc6f67d2c1c28d253bc87\trefs/tags/20240101"),
+        ]);
+        let response = new_action(Some("20240101")).dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.status, TaskStatus::IsMatched);
+    }
+
+    #[test]
+    fn test_shallow_clone_and_update() {
+        let (handle, connection) = TaskHandle::scripted(Vec::new());
+        let mut action = new_action(None);
+        action.depth = 1;
+        assert!(action.dispatch(&handle, &TaskRequest::create(&SudoDetails::none(), false)).is_ok());
+        assert!(action.dispatch(&handle, &TaskRequest::modify(&SudoDetails::none(), false, vec![Field::Version])).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.iter().any(|x| x.ends_with("git clone --depth 1 --single-branch --branch 'main' -- 'https://example.com/repo.git' '/opt/repo'")));
+        assert!(commands.iter().any(|x| x.ends_with("git -C '/opt/repo' fetch --depth 1 origin '+refs/heads/main:refs/remotes/origin/main'")));
+        assert_eq!(commands.last().unwrap(), "git -C '/opt/repo' reset --hard --quiet 'origin/main'");
+        assert!(! commands.iter().any(|x| x.ends_with(" pull")));
+    }
+
+    #[test]
+    fn test_unsigned_head_is_a_change_and_fails_before_moving() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("stat --format '%a'", 0, "755"),
+            ("remote get-url origin", 0, "https://example.com/repo.git"),
+            ("status --porcelain", 0, ""),
+            ("rev-parse HEAD", 0, "abc123"),
+            ("rev-parse --abbrev-ref HEAD", 0, "main"),
+            ("ls-remote", 0, "abc123"),
+            ("verify-commit", 1, "error: no signature found"),
+        ]);
+        let mut action = new_action(None);
+        action.verify = true;
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Signature]);
+
+        let request = TaskRequest::modify(&SudoDetails::none(), false, response.changes.clone());
+        assert!(action.dispatch(&handle, &request).is_err());
+        let commands = connection.lock().unwrap().get_commands();
+        // the fetched branch is checked where it is, HEAD and the working tree never move
+        let fetch = commands.iter().position(|x| x.ends_with("git -C '/opt/repo' fetch --tags")).expect("fetch");
+        let verify = commands.iter().position(|x| x.eq("git -C '/opt/repo' verify-commit 'origin/main'")).expect("verify");
+        assert!(fetch < verify);
+        assert_eq!(verify, commands.len() - 1);
+        assert!(! commands.iter().any(|x| x.contains(" pull") || x.contains(" merge") || x.contains(" reset") || x.contains(" switch")));
+    }
+
+    #[test]
+    fn test_verified_branch_is_merged_without_fetching_again() {
+        let (handle, connection) = TaskHandle::scripted(Vec::new());
+        let mut action = new_action(None);
+        action.verify = true;
+        assert!(action.dispatch(&handle, &TaskRequest::modify(&SudoDetails::none(), false, vec![Field::Version])).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert_eq!(commands.iter().filter(|x| x.contains(" fetch")).count(), 1);
+        let verify = commands.iter().position(|x| x.eq("git -C '/opt/repo' verify-commit 'origin/main'")).expect("verify");
+        let merge = commands.iter().position(|x| x.eq("git -C '/opt/repo' merge --ff-only --quiet 'origin/main'")).expect("merge");
+        assert!(verify < merge);
+        assert!(! commands.iter().any(|x| x.ends_with(" pull")));
+    }
+
+    #[test]
+    fn test_unsigned_tag_leaves_no_checkout_on_create() {
+        let (handle, connection) = TaskHandle::scripted(vec![("verify-tag", 1, "error: no signature found")]);
+        let mut action = new_action(Some("v1"));
+        action.verify = true;
+        assert!(action.dispatch(&handle, &TaskRequest::create(&SudoDetails::none(), false)).is_err());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.iter().any(|x| x.ends_with("git clone --no-checkout -- 'https://example.com/repo.git' '/opt/repo'")));
+        assert!(commands.contains(&String::from("git -C '/opt/repo' verify-tag 'v1'")));
+        assert_eq!(commands.last().unwrap(), "rm -rf '/opt/repo/.git'");
+        assert!(! commands.iter().any(|x| x.contains(" checkout --quiet")));
+    }
+}
 // TODO: agent forwarding flag used by SSH connections
 // + make stuff work
 // + testing ssh and http repos without passwords
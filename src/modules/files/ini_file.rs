@@ -0,0 +1,330 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use crate::tasks::response::ValueDiff;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::vec::Vec;
+use crate::tasks::files::Recurse;
+
+const MODULE: &str = "ini_file";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct IniFileTask {
+    pub name: Option<String>,
+    pub path: String,
+    pub section: Option<String>,
+    pub option: String,
+    pub value: Option<String>,
+    pub remove: Option<String>,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+struct IniFileAction {
+    pub path: String,
+    pub section: Option<String>,
+    pub option: String,
+    pub value: Option<String>,
+    pub remove: bool,
+    pub attributes: Option<FileAttributesEvaluated>,
+}
+
+impl IsTask for IniFileTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let remove = handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?;
+        let section = handle.template.string_option_unsafe_for_shell(request, tm, "section", &self.section)?;
+        let option = handle.template.string_unsafe_for_shell(request, tm, "option", &self.option)?;
+        let value = handle.template.string_option_unsafe_for_shell(request, tm, "value", &self.value)?;
+        if tm != TemplateMode::Off {
+            if ! remove && value.is_none() {
+                return Err(handle.response.is_failed(request, &String::from("value is required unless remove is set")));
+            }
+            if remove && value.is_some() {
+                return Err(handle.response.is_failed(request, &String::from("value and remove are mutually exclusive")));
+            }
+            let option_trimmed = option.trim();
+            if option_trimmed.is_empty() || option_trimmed.contains(['=', '\n']) || option_trimmed.starts_with(['[', '#', ';']) {
+                return Err(handle.response.is_failed(request, &format!("invalid option name: {}", option)));
+            }
+            if let Some(s) = &section {
+                if s.trim().is_empty() || s.contains([']', '\n']) {
+                    return Err(handle.response.is_failed(request, &format!("invalid section name: {}", s)));
+                }
+            }
+            if let Some(v) = &value {
+                if v.contains('\n') {
+                    return Err(handle.response.is_failed(request, &String::from("value may not span multiple lines")));
+                }
+            }
+        }
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(IniFileAction {
+                    path:       handle.template.path(request, tm, &String::from("path"), &self.path)?,
+                    section:    section.map(|x| x.trim().to_string()),
+                    option:     option.trim().to_string(),
+                    value:      value.map(|x| x.trim().to_string()),
+                    remove,
+                    attributes: FileAttributesInput::template(handle, request, tm, &self.attributes)?
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+
+}
+
+impl IsAction for IniFileAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+    
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                let mut changes : Vec<Field> = Vec::new();
+                let remote_mode = handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, Recurse::No)?;
+                if remote_mode.is_none() {
+                    if self.remove { return Ok(handle.response.is_matched(request)); }
+                    return Ok(handle.response.needs_creation(request));
+                }
+                if handle.remote.get_is_directory(request, &self.path)? {
+                    return Err(handle.response.is_failed(request, &format!("{} is a directory", self.path)));
+                }
+                let lines = self.read_lines(handle, request)?;
+                let (edited, _before) = edit_ini_lines(&lines, &self.section, &self.option, &self.value);
+                if edited != lines {
+                    changes.push(Field::Content);
+                }
+                if ! changes.is_empty() {
+                    return Ok(handle.response.needs_modification(request, &changes));
+                }
+                Ok(handle.response.is_matched(request))
+            },
+
+            TaskRequestType::Create => {
+                let (edited, _before) = edit_ini_lines(&Vec::new(), &self.section, &self.option, &self.value);
+                self.write_lines(handle, request, &edited)?;
+                Ok(handle.response.is_created(request))
+            },
+
+            TaskRequestType::Modify => {
+                if request.changes.contains(&Field::Content) {
+                    let lines = self.read_lines(handle, request)?;
+                    let (edited, before) = edit_ini_lines(&lines, &self.section, &self.option, &self.value);
+                    self.write_lines(handle, request, &edited)?;
+                    let diff = vec![ValueDiff { 
+                        name:   self.get_option_label(),
+                        before: before.unwrap_or(String::from("(none)")),
+                        after:  self.value.clone().unwrap_or(String::from("(none)"))
+                    }];
+                    return Ok(handle.response.is_modified_with_diff(request, request.changes.clone(), diff));
+                }
+                handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, &request.changes, Recurse::No)?;
+                Ok(handle.response.is_modified(request, request.changes.clone()))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+impl IniFileAction {
+
+    fn get_option_label(&self) -> String {
+        match &self.section {
+            Some(s) => format!("{}.{}", s, self.option),
+            None => self.option.clone()
+        }
+    }
+
+    fn read_lines(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Vec<String>, Arc<TaskResponse>> {
+        let data = handle.remote.read_file(request, &self.path)?;
+        Ok(data.lines().map(|x| x.to_string()).collect())
+    }
+
+    fn write_lines(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, lines: &[String]) -> Result<(), Arc<TaskResponse>> {
+        let mut data = lines.join("\n");
+        data.push('\n');
+        handle.remote.write_data_atomic(request, &data, &self.path, None, |f| { /* after save */
+            match handle.remote.process_all_common_file_attributes(request, f, &self.attributes, Recurse::No) {
+                Ok(_x) => Ok(()), Err(y) => Err(y)
+            }
+        })
+    }
+
+}
+
+// only as much INI as we need: '[section]' headers, 'key = value' lines, and everything else (comments, blank
+// lines, other keys) passed through untouched. options before the first header belong to no section.
+
+fn parse_section_header(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('[') && trimmed.ends_with(']') {
+        return Some(trimmed[1..trimmed.len()-1].trim().to_string());
+    }
+    None
+}
+
+fn parse_option_key(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with(['#', ';']) {
+        return None;
+    }
+    trimmed.split_once('=').map(|(k, _v)| k.trim().to_string())
+}
+
+fn replace_option_value(line: &str, value: &str) -> String {
+    // keep the original indentation and spacing around '=' so only the value itself changes
+    let (key, rest) = line.split_once('=').unwrap();
+    let spacing = if rest.starts_with([' ', '\t']) || key.ends_with([' ', '\t']) { " " } else { "" };
+    format!("{}={}{}", key, spacing, value)
+}
+
+// returns the edited lines and the value the option had before, if any. setting an option that appears more
+// than once in the section keeps the first occurrence and drops the rest.
+
+pub fn edit_ini_lines(lines: &[String], section: &Option<String>, option: &str, value: &Option<String>) -> (Vec<String>, Option<String>) {
+    let mut result : Vec<String> = Vec::new();
+    let mut current : Option<String> = None;
+    let mut before : Option<String> = None;
+    let mut found = false;
+    let mut section_seen = section.is_none();
+    // where to put the option if the section exists but does not have it yet
+    let mut insert_at : Option<usize> = if section.is_none() { Some(0) } else { None };
+
+    for line in lines.iter() {
+        if let Some(header) = parse_section_header(line) {
+            current = Some(header.clone());
+            if section.as_ref() == Some(&header) {
+                section_seen = true;
+            }
+            result.push(line.clone());
+            if current == *section {
+                insert_at = Some(result.len());
+            }
+            continue;
+        }
+        if current == *section {
+            if parse_option_key(line).as_deref() == Some(option) {
+                if before.is_none() {
+                    before = Some(line.split_once('=').unwrap().1.trim().to_string());
+                }
+                if ! found {
+                    found = true;
+                    if let Some(v) = value {
+                        result.push(replace_option_value(line, v));
+                    }
+                }
+                continue;
+            }
+            result.push(line.clone());
+            if ! line.trim().is_empty() {
+                insert_at = Some(result.len());
+            }
+            continue;
+        }
+        result.push(line.clone());
+    }
+
+    if let (false, Some(v)) = (found, value) {
+        let new_line = format!("{} = {}", option, v);
+        match (section_seen, insert_at) {
+            (true, Some(index)) => { result.insert(index, new_line); },
+            _ => {
+                if result.last().is_some_and(|x| ! x.trim().is_empty()) {
+                    result.push(String::new());
+                }
+                result.push(format!("[{}]", section.as_ref().unwrap()));
+                result.push(new_line);
+            }
+        }
+    }
+    (result, before)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    fn to_lines(s: &str) -> Vec<String> {
+        s.lines().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_edit_ini_lines() {
+        let lines = to_lines("# top\nglobal=1\n\n[main]\n; comment\nport = 80\nhost=a\n\n[other]\nport = 1");
+        let main = Some(String::from("main"));
+
+        // already set, nothing changes
+        let (edited, before) = edit_ini_lines(&lines, &main, "port", &Some(String::from("80")));
+        assert_eq!(edited, lines);
+        assert_eq!(before.as_deref(), Some("80"));
+
+        // a changed value keeps the spacing style of the line and does not touch the other section
+        let (edited, _) = edit_ini_lines(&lines, &main, "host", &Some(String::from("b")));
+        assert_eq!(edited, to_lines("# top\nglobal=1\n\n[main]\n; comment\nport = 80\nhost=b\n\n[other]\nport = 1"));
+
+        // a new option goes after the last option of its section
+        let (edited, before) = edit_ini_lines(&lines, &main, "user", &Some(String::from("x")));
+        assert_eq!(edited, to_lines("# top\nglobal=1\n\n[main]\n; comment\nport = 80\nhost=a\nuser = x\n\n[other]\nport = 1"));
+        assert_eq!(before, None);
+
+        // a new section is appended
+        let (edited, _) = edit_ini_lines(&lines, &Some(String::from("new")), "a", &Some(String::from("b")));
+        assert_eq!(edited, to_lines("# top\nglobal=1\n\n[main]\n; comment\nport = 80\nhost=a\n\n[other]\nport = 1\n\n[new]\na = b"));
+
+        // options outside any section
+        let (edited, _) = edit_ini_lines(&lines, &None, "global", &Some(String::from("2")));
+        assert_eq!(edited[1], "global=2");
+
+        // removal only affects the named section
+        let (edited, before) = edit_ini_lines(&lines, &Some(String::from("other")), "port", &None);
+        assert_eq!(edited, to_lines("# top\nglobal=1\n\n[main]\n; comment\nport = 80\nhost=a\n\n[other]"));
+        assert_eq!(before.as_deref(), Some("1"));
+        let (edited, _) = edit_ini_lines(&lines, &main, "missing", &None);
+        assert_eq!(edited, lines);
+    }
+
+    #[test]
+    fn test_no_log_diff_hides_values() {
+        let sudo_details = SudoDetails::none();
+        let action = IniFileAction {
+            path: String::from("/etc/app.ini"), section: Some(String::from("db")), option: String::from("password"),
+            value: Some(String::from("s3cret")), remove: false, attributes: None
+        };
+        let (handle, _) = TaskHandle::scripted(vec![("cat '/etc/app.ini'", 0, "[db]\npassword = hunter2")]);
+
+        let response = action.dispatch(&handle, &TaskRequest::modify(&sudo_details, false, vec![Field::Content])).unwrap();
+        assert_eq!(response.diff, vec![ValueDiff { name: String::from("db.password"), before: String::from("hunter2"), after: String::from("s3cret") }]);
+        let response = action.dispatch(&handle, &TaskRequest::modify(&sudo_details, true, vec![Field::Content])).unwrap();
+        assert_eq!(response.diff, vec![ValueDiff { name: String::from("db.password"), before: String::from("(no_log)"), after: String::from("(no_log)") }]);
+    }
+}
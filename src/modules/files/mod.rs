@@ -21,5 +21,7 @@ pub mod copy;
 pub mod directory;
 pub mod file;
 pub mod git;
+pub mod ini_file;
 pub mod stat;
 pub mod template;
+pub mod yaml_edit;
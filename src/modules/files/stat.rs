@@ -16,6 +16,7 @@
 
 use crate::tasks::*;
 use crate::handle::handle::TaskHandle;
+use crate::tasks::checksum::ChecksumAlgo;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -27,14 +28,17 @@ pub struct StatTask {
     pub name: Option<String>,
     pub path: String,
     pub save: String,
+    pub checksum: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
 
 #[allow(dead_code)]
+#[derive(Debug)]
 struct StatAction {
     pub path: String,
     pub save: String,
+    pub checksum: Option<ChecksumAlgo>,
 }
 
 impl IsTask for StatTask {
@@ -49,6 +53,7 @@ impl IsTask for StatTask {
                 action: Arc::new(StatAction {
                     path: handle.template.path(request, tm, &String::from("path"), &self.path)?,
                     save: handle.template.string_no_spaces(request, tm, &String::from("save"), &self.save)?,
+                    checksum: self.get_checksum_algo(handle, request, tm)?,
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
@@ -57,6 +62,22 @@ impl IsTask for StatTask {
     }
 }
 
+impl StatTask {
+
+    fn get_checksum_algo(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<Option<ChecksumAlgo>, Arc<TaskResponse>> {
+        if tm == TemplateMode::Off {
+            return Ok(None);
+        }
+        match handle.template.string_option_no_spaces(request, tm, &String::from("checksum"), &self.checksum)? {
+            None => Ok(None),
+            Some(x) => match ChecksumAlgo::from_name(&x) {
+                Ok(algo) => Ok(Some(algo)),
+                Err(e) => Err(handle.response.is_failed(request, &format!("field (checksum): {}", e)))
+            }
+        }
+    }
+}
+
 impl IsAction for StatAction {
 
     fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
@@ -68,7 +89,7 @@ impl IsAction for StatAction {
             },
 
             TaskRequestType::Passive => {
-                let stat = stat_file(handle, request, &self.path)?;
+                let stat = stat_file(handle, request, &self.path, self.checksum)?;
                 save_results(handle, request, &self.save, stat)?;
                 Ok(handle.response.is_passive(request))
             },
@@ -88,6 +109,7 @@ struct StatResult {
     pub mode: Option<String>,
     pub owner: Option<String>,
     pub group: Option<String>,
+    pub checksum: Option<String>,
 }
 
 const DOESNT_EXIST: StatResult = StatResult{
@@ -96,9 +118,10 @@ const DOESNT_EXIST: StatResult = StatResult{
     mode: None,
     owner: None,
     group: None,
+    checksum: None,
 };
 
-fn stat_file(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, path: &str) -> Result<StatResult, Arc<TaskResponse>> {
+fn stat_file(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, path: &str, checksum: Option<ChecksumAlgo>) -> Result<StatResult, Arc<TaskResponse>> {
     let mode_option = handle.remote.get_mode(request, path)?;
     match mode_option {
         Some(mode) => {
@@ -108,12 +131,18 @@ fn stat_file(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, path: &str) -
                 // we can add other properties here, such as file+directory size, including contents, SELinux attributes, etc
                 // return None for the ones that are not supported
                 let (owner, group) = ownership.unwrap();
+                // directories do not have a checksum
+                let checksum = match (is_dir, checksum) {
+                    (false, Some(algo)) => Some(handle.remote.get_checksum(request, &path.to_string(), algo)?),
+                    _ => None
+                };
                 Ok(StatResult{
                     exists: true,
                     is_dir,
                     mode: Some(format!("0o{}", mode)),
                     owner: Some(owner),
                     group: Some(group),
+                    checksum,
                 })
             }
             else {
@@ -126,11 +155,9 @@ fn stat_file(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, path: &str) -
     }
 }
 
-fn save_results(handle: &Arc<TaskHandle>, _request: &Arc<TaskRequest>, key: &str, stat: StatResult) -> Result<(), Arc<TaskResponse>> {
-    let mut result = serde_yaml::Mapping::new();
+fn save_results(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, key: &str, stat: StatResult) -> Result<(), Arc<TaskResponse>> {
     // the following statement really can't fail.
     let value = serde_yaml::to_value(stat).expect("internal error: failed to unwrap stat");
-    result.insert(serde_yaml::Value::String(key.to_owned()), value);
-    handle.host.write().unwrap().update_variables(result);
+    handle.host.write().unwrap().save_result(key, value, request.no_log);
     Ok(())
 }
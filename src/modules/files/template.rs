@@ -22,7 +22,7 @@ use std::path::PathBuf;
 use serde::Deserialize;
 use std::sync::Arc;
 use std::vec::Vec;
-use crate::tasks::files::Recurse;
+use crate::tasks::files::{Recurse,TextNormalization,preview_file};
 
 const MODULE: &str = "template";
 
@@ -30,17 +30,34 @@ const MODULE: &str = "template";
 #[serde(deny_unknown_fields)]
 pub struct TemplateTask {
     pub name: Option<String>,
-    pub src: String,
+    pub src: SrcInput,
     pub dest: String,
     pub attributes: Option<FileAttributesInput>,
+    pub force: Option<String>,
+    pub strict_undefined: Option<String>,
+    pub quick_check: Option<String>,
+    pub follow: Option<String>,
+    pub preview: Option<String>,
+    pub line_endings: Option<String>,
+    pub trailing_newline: Option<String>,
+    pub validate: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct TemplateAction {
     pub src: PathBuf,
     pub dest: String,
     pub attributes: Option<FileAttributesEvaluated>,
+    pub force: bool,
+    pub strict_undefined: bool,
+    pub quick_check: bool,
+    pub follow: bool,
+    pub preview: bool,
+    pub normalize: TextNormalization,
+    // run against the rendered file before it replaces dest, with %s as its path
+    pub validate: Option<String>,
 }
 
 impl IsTask for TemplateTask {
@@ -50,13 +67,32 @@ impl IsTask for TemplateTask {
     fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
 
     fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
-        let src = handle.template.string(request, tm, &String::from("src"), &self.src)?;
+        let src = handle.template.find_template_path(request, tm, &String::from("src"), &self.src)?;
+        let mut attributes = FileAttributesInput::template_preserving(handle, request, tm, &self.attributes)?;
+        if tm != TemplateMode::Off && FileAttributesEvaluated::wants_source_mode(&attributes) {
+            attributes = FileAttributesEvaluated::with_source_mode(handle, request, &src, attributes)?;
+        }
+        if tm != TemplateMode::Off {
+            let default_mode = handle.run_state.context.read().unwrap().default_file_mode.clone();
+            attributes = FileAttributesEvaluated::with_default_mode(handle, request, &default_mode, attributes)?;
+        }
+        // not templated, a variable in a command that checks the file could otherwise change what gets run
+        if self.validate.as_ref().is_some_and(|x| ! x.contains("%s")) {
+            return Err(handle.response.is_failed(request, &String::from("field validate: must contain %s, which is replaced with the path of the rendered file")));
+        }
         Ok(
             EvaluatedTask {
                 action: Arc::new(TemplateAction {
-                    src:        handle.template.find_template_path(request, tm, &String::from("src"), &src)?,
+                    src,
                     dest:       handle.template.path(request, tm, &String::from("dest"), &self.dest)?,
-                    attributes: FileAttributesInput::template(handle, request, tm, &self.attributes)?
+                    attributes,
+                    force:      handle.template.boolean_option_default_true(request, tm, &String::from("force"), &self.force)?,
+                    strict_undefined: handle.template.boolean_option_default_true(request, tm, &String::from("strict_undefined"), &self.strict_undefined)?,
+                    quick_check: handle.template.boolean_option_default_false(request, tm, &String::from("quick_check"), &self.quick_check)?,
+                    follow:     handle.template.boolean_option_default_false(request, tm, &String::from("follow"), &self.follow)?,
+                    preview:    handle.template.boolean_option_default_false(request, tm, &String::from("preview"), &self.preview)?,
+                    normalize:  TextNormalization::template(handle, request, tm, &self.line_endings, &self.trailing_newline)?,
+                    validate:   self.validate.clone()
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
@@ -70,38 +106,36 @@ impl IsAction for TemplateAction {
 
     fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
     
+        let dest = handle.remote.get_destination_path(request, &self.dest, &self.src)?;
+        let (dest, replaces_link) = handle.remote.follow_destination(request, &dest, self.follow)?;
+
         match request.request_type {
 
             TaskRequestType::Query => {
-
-                let mut changes : Vec<Field> = Vec::new();
-                let remote_mode = handle.remote.query_common_file_attributes(request, &self.dest, &self.attributes, &mut changes, Recurse::No)?;                   
-                if remote_mode.is_none() {
-                    return Ok(handle.response.needs_creation(request));
-                }
-                let data = self.do_template(handle, request, false, None)?;
-                let local_512 = sha512(&data);
-                let remote_512 = handle.remote.get_sha512(request, &self.dest)?;
-                if ! remote_512.eq(&local_512) { 
-                    changes.push(Field::Content); 
-                }
-                if ! changes.is_empty() {
-                    return Ok(handle.response.needs_modification(request, &changes));
+                let (result, data) = self.query(handle, request, &dest, replaces_link)?;
+                if ! self.preview {
+                    return Ok(result);
                 }
+                // the rendered file is shown against what is there now, and nothing is written
+                let data = match data {
+                    Some(x) => x,
+                    None => self.do_template(handle, request, &dest, false, None)?
+                };
+                preview_file(handle, request, &dest, &result, Some(data))?;
                 Ok(handle.response.is_matched(request))
             },
 
             TaskRequestType::Create => {
-                self.do_template(handle, request, true, None)?;               
+                self.do_template(handle, request, &dest, true, None)?;               
                 Ok(handle.response.is_created(request))
             }
 
             TaskRequestType::Modify => {
                 if request.changes.contains(&Field::Content) {
-                    self.do_template(handle, request, true, Some(request.changes.clone()))?;
+                    self.do_template(handle, request, &dest, true, Some(request.changes.clone()))?;
                 }
                 else {
-                    handle.remote.process_common_file_attributes(request, &self.dest, &self.attributes, &request.changes, Recurse::No)?;
+                    handle.remote.process_common_file_attributes(request, &dest, &self.attributes, &request.changes, Recurse::No)?;
                 }
                 Ok(handle.response.is_modified(request, request.changes.clone()))
             }
@@ -115,17 +149,91 @@ impl IsAction for TemplateAction {
 
 impl TemplateAction {
 
-    pub fn do_template(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, write: bool, _changes: Option<Vec<Field>>) -> Result<String, Arc<TaskResponse>> {
+    // the query also hands back the rendered file, when it had to be rendered for the comparison
+
+    fn query(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, dest: &String, replaces_link: bool) -> Result<(Arc<TaskResponse>, Option<String>), Arc<TaskResponse>> {
+        // without follow a symlink at dest is always replaced, whatever it points at
+        if replaces_link && self.force {
+            return Ok((handle.response.needs_modification(request, &[Field::Content]), None));
+        }
+        let mut changes : Vec<Field> = Vec::new();
+        let remote_mode = handle.remote.query_common_file_attributes(request, dest, &self.attributes, &mut changes, Recurse::No)?;                   
+        if remote_mode.is_none() {
+            return Ok((handle.response.needs_creation(request), None));
+        }
+        // force: false seeds the file once, after that the content belongs to whoever edits it
+        let mut data : Option<String> = None;
+        if self.force {
+            let rendered = self.do_template(handle, request, dest, false, None)?;
+            let local_512 = sha512(&rendered);
+            data = Some(rendered);
+            let remote_512 = match self.quick_check {
+                true => handle.remote.get_sha512_quick(request, dest)?,
+                false => handle.remote.get_sha512(request, dest)?
+            };
+            if ! remote_512.eq(&local_512) { 
+                changes.push(Field::Content); 
+            }
+        }
+        if ! changes.is_empty() {
+            return Ok((handle.response.needs_modification(request, &changes), data));
+        }
+        Ok((handle.response.is_matched(request), data))
+    }
+
+    pub fn do_template(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, dest: &String, write: bool, _changes: Option<Vec<Field>>) -> Result<String, Arc<TaskResponse>> {
         let template_contents = handle.local.read_file(request, &self.src)?;
-        let data = handle.template.string_for_template_module_use_only(request, TemplateMode::Strict, &String::from("src"), &template_contents)?;
+        let data = match handle.template.string_for_template_module_use_only(request, TemplateMode::Strict, &String::from("src"), &template_contents) {
+            Ok(x) => x,
+            Err(e) if ! self.strict_undefined => {
+                // if only strict mode was in the way, say what was undefined and carry on with it left empty.
+                // modify always follows a query that already warned.
+                let data = handle.template.string_for_template_module_lenient(request, &template_contents)?;
+                if request.request_type != TaskRequestType::Modify {
+                    handle.warning(request, &format!("{}: undefined variables were left empty ({})", self.src.display(), e.msg.as_deref().unwrap_or("")));
+                }
+                data
+            },
+            Err(e) => { return Err(e); }
+        };
+        let data = self.normalize.apply(&data);
         if write {
-            handle.remote.write_data(request, &data, &self.dest, |f| { /* after save */
+            handle.remote.write_data_atomic(request, &data, dest, self.validate.as_ref(), |f| { /* after save */
                 match handle.remote.process_all_common_file_attributes(request, f, &self.attributes, Recurse::No) {
                     Ok(_x) => Ok(()), Err(y) => Err(y)
                 }
             })?;
+            if self.quick_check {
+                handle.remote.remember_sha512(request, dest, &sha512(&data))?;
+            }
         }
         Ok(data)
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_failed_validate_leaves_dest_untouched() {
+        let src = std::env::temp_dir().join(format!("jetp-template-validate-{}.j2", std::process::id()));
+        std::fs::write(&src, "Defaults !lecture\n").unwrap();
+        let task : TemplateTask = serde_yaml::from_str(&format!("src: {}\ndest: /etc/sudoers.d/jet\nvalidate: visudo -cf %s", src.display())).unwrap();
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("visudo -cf", 1, "syntax error"),
+        ]);
+        let evaluated = task.evaluate(&handle, &TaskRequest::validate(), TemplateMode::Strict);
+        let sudo_details = SudoDetails::none();
+        let result = evaluated.expect("evaluate").action.dispatch(&handle, &TaskRequest::create(&sudo_details, false));
+        std::fs::remove_file(&src).unwrap();
+        assert!(result.is_err());
+        let commands = connection.lock().unwrap().get_commands();
+        // the rendered file is checked and thrown away where it was written, it never moves next to dest
+        let validate = commands.iter().position(|x| x.starts_with("visudo -cf ")).expect("validate ran");
+        assert!(commands[validate+1..].iter().all(|x| x.starts_with("rm -f ") && ! x.contains("/etc/sudoers.d/")), "{:?}", commands);
+        assert!(! commands.iter().any(|x| x.starts_with("mv ") || x == "write_data: /etc/sudoers.d/jet"), "{:?}", commands);
+    }
+}
@@ -0,0 +1,293 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use crate::tasks::response::ValueDiff;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::vec::Vec;
+use crate::tasks::files::Recurse;
+
+const MODULE: &str = "yaml_edit";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct YamlEditTask {
+    pub name: Option<String>,
+    pub path: String,
+    pub key: String,
+    pub value: String,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum DocumentFormat {
+    Json,
+    Yaml
+}
+
+#[derive(Debug)]
+struct YamlEditAction {
+    pub path: String,
+    pub format: DocumentFormat,
+    pub key: Vec<String>,
+    pub value: serde_yaml::Value,
+    pub attributes: Option<FileAttributesEvaluated>,
+}
+
+impl IsTask for YamlEditTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let path = handle.template.path(request, tm, &String::from("path"), &self.path)?;
+        let key = handle.template.string_unsafe_for_shell(request, tm, "key", &self.key)?;
+        let value = handle.template.string_unsafe_for_shell(request, tm, "value", &self.value)?;
+        let (format, key, value) = match tm {
+            TemplateMode::Off => (DocumentFormat::Yaml, Vec::new(), serde_yaml::Value::Null),
+            _ => {
+                let format = match get_document_format(&path) {
+                    Ok(x) => x, Err(y) => { return Err(handle.response.is_failed(request, &y)); }
+                };
+                let key = match parse_key_path(&key) {
+                    Ok(x) => x, Err(y) => { return Err(handle.response.is_failed(request, &y)); }
+                };
+                (format, key, parse_value(&value))
+            }
+        };
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(YamlEditAction {
+                    path,
+                    format,
+                    key,
+                    value,
+                    attributes: FileAttributesInput::template(handle, request, tm, &self.attributes)?
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+
+}
+
+impl IsAction for YamlEditAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+    
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                let mut changes : Vec<Field> = Vec::new();
+                let remote_mode = handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, Recurse::No)?;
+                if remote_mode.is_none() {
+                    return Ok(handle.response.needs_creation(request));
+                }
+                if handle.remote.get_is_directory(request, &self.path)? {
+                    return Err(handle.response.is_failed(request, &format!("{} is a directory", self.path)));
+                }
+                let document = self.read_document(handle, request)?;
+                if get_key_value(&document, &self.key) != Some(&self.value) {
+                    changes.push(Field::Content);
+                }
+                if ! changes.is_empty() {
+                    return Ok(handle.response.needs_modification(request, &changes));
+                }
+                Ok(handle.response.is_matched(request))
+            },
+
+            TaskRequestType::Create => {
+                let mut document = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+                self.set_value(handle, request, &mut document)?;
+                self.write_document(handle, request, &document)?;
+                Ok(handle.response.is_created(request))
+            },
+
+            TaskRequestType::Modify => {
+                if request.changes.contains(&Field::Content) {
+                    let mut document = self.read_document(handle, request)?;
+                    let before = get_key_value(&document, &self.key).map(value_to_string).unwrap_or(String::from("(none)"));
+                    self.set_value(handle, request, &mut document)?;
+                    self.write_document(handle, request, &document)?;
+                    let diff = vec![ValueDiff { name: self.key.join("."), before, after: value_to_string(&self.value) }];
+                    return Ok(handle.response.is_modified_with_diff(request, request.changes.clone(), diff));
+                }
+                handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, &request.changes, Recurse::No)?;
+                Ok(handle.response.is_modified(request, request.changes.clone()))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+impl YamlEditAction {
+
+    fn read_document(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<serde_yaml::Value, Arc<TaskResponse>> {
+        let data = handle.remote.read_file(request, &self.path)?;
+        if data.trim().is_empty() {
+            return Ok(serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
+        }
+        let parsed : Result<serde_yaml::Value, String> = match self.format {
+            DocumentFormat::Json => serde_json::from_str(&data).map_err(|e| e.to_string()),
+            DocumentFormat::Yaml => serde_yaml::from_str(&data).map_err(|e| e.to_string())
+        };
+        match parsed {
+            Ok(x) => Ok(x),
+            Err(y) => Err(handle.response.is_failed(request, &format!("failed to parse {}: {}", self.path, y)))
+        }
+    }
+
+    fn set_value(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, document: &mut serde_yaml::Value) -> Result<(), Arc<TaskResponse>> {
+        match set_key_value(document, &self.key, self.value.clone()) {
+            Ok(_) => Ok(()),
+            Err(y) => Err(handle.response.is_failed(request, &format!("{}: {}", self.path, y)))
+        }
+    }
+
+    // the file is only rewritten when the key changes, but when it is, YAML comments and formatting do not survive
+
+    fn write_document(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, document: &serde_yaml::Value) -> Result<(), Arc<TaskResponse>> {
+        let serialized = match self.format {
+            DocumentFormat::Json => serde_json::to_string_pretty(document).map(|x| format!("{}\n", x)).map_err(|e| e.to_string()),
+            DocumentFormat::Yaml => serde_yaml::to_string(document).map_err(|e| e.to_string())
+        };
+        let data = match serialized {
+            Ok(x) => x,
+            Err(y) => { return Err(handle.response.is_failed(request, &format!("failed to serialize {}: {}", self.path, y))); }
+        };
+        handle.remote.write_data_atomic(request, &data, &self.path, None, |f| { /* after save */
+            match handle.remote.process_all_common_file_attributes(request, f, &self.attributes, Recurse::No) {
+                Ok(_x) => Ok(()), Err(y) => Err(y)
+            }
+        })
+    }
+
+}
+
+pub fn get_document_format(path: &str) -> Result<DocumentFormat, String> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".json") {
+        Ok(DocumentFormat::Json)
+    } else if lower.ends_with(".yml") || lower.ends_with(".yaml") {
+        Ok(DocumentFormat::Yaml)
+    } else {
+        Err(format!("cannot tell from the extension whether {} is JSON or YAML", path))
+    }
+}
+
+// keys are either dotted (server.port) or a JSON pointer (/server/port, with ~1 for '/' and ~0 for '~')
+
+pub fn parse_key_path(key: &str) -> Result<Vec<String>, String> {
+    let segments : Vec<String> = match key.strip_prefix('/') {
+        Some(pointer) => pointer.split('/').map(|x| x.replace("~1", "/").replace("~0", "~")).collect(),
+        None => key.split('.').map(|x| x.to_string()).collect()
+    };
+    if key.is_empty() || segments.iter().any(|x| x.is_empty()) {
+        return Err(format!("invalid key: {}", key));
+    }
+    Ok(segments)
+}
+
+// the value is read as YAML, so 8080 becomes a number, true a boolean, and [a, b] a list.
+// quote it ("'8080'") to keep it a string.
+
+pub fn parse_value(value: &str) -> serde_yaml::Value {
+    match serde_yaml::from_str::<serde_yaml::Value>(value) {
+        Ok(x) => x,
+        Err(_) => serde_yaml::Value::String(value.to_string())
+    }
+}
+
+fn value_to_string(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(x) => x.clone(),
+        _ => serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+fn get_key_value<'a>(document: &'a serde_yaml::Value, key: &[String]) -> Option<&'a serde_yaml::Value> {
+    let mut current = document;
+    for segment in key.iter() {
+        current = match current {
+            serde_yaml::Value::Mapping(m) => m.get(segment.as_str())?,
+            serde_yaml::Value::Sequence(s) => s.get(segment.parse::<usize>().ok()?)?,
+            _ => { return None; }
+        };
+    }
+    Some(current)
+}
+
+// missing intermediate keys are created as mappings. list elements can be addressed by index,
+// and the index one past the end appends.
+
+pub fn set_key_value(document: &mut serde_yaml::Value, key: &[String], value: serde_yaml::Value) -> Result<(), String> {
+    let mut current = document;
+    for (i, segment) in key.iter().enumerate() {
+        let last = i == key.len() - 1;
+        current = match current {
+            serde_yaml::Value::Mapping(m) => {
+                let k = serde_yaml::Value::String(segment.clone());
+                if ! m.contains_key(&k) {
+                    m.insert(k.clone(), serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
+                }
+                m.get_mut(&k).unwrap()
+            },
+            serde_yaml::Value::Sequence(s) => {
+                let index = match segment.parse::<usize>() {
+                    Ok(x) if x <= s.len() => x,
+                    _ => { return Err(format!("{} is not a valid index into the list at {}", segment, key[..i].join("."))); }
+                };
+                if index == s.len() {
+                    s.push(serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
+                }
+                &mut s[index]
+            },
+            _ => { return Err(format!("{} is not a mapping or list", key[..i].join("."))); }
+        };
+        if last {
+            *current = value;
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_key_value_creates_intermediate_keys() {
+        let mut document : serde_yaml::Value = serde_yaml::from_str("server:\n  host: a\nlist: [1, 2]\nname: x").unwrap();
+        set_key_value(&mut document, &parse_key_path("server.port").unwrap(), parse_value("8080")).unwrap();
+        set_key_value(&mut document, &parse_key_path("/a~1b/c").unwrap(), parse_value("true")).unwrap();
+        set_key_value(&mut document, &parse_key_path("list.2").unwrap(), parse_value("'3'")).unwrap();
+        assert_eq!(serde_json::to_string(&document).unwrap(), r#"{"server":{"host":"a","port":8080},"list":[1,2,"3"],"name":"x","a/b":{"c":true}}"#);
+        assert_eq!(get_key_value(&document, &parse_key_path("/server/port").unwrap()), Some(&parse_value("8080")));
+        assert!(set_key_value(&mut document, &parse_key_path("name.first").unwrap(), parse_value("y")).is_err());
+        assert!(set_key_value(&mut document, &parse_key_path("list.7").unwrap(), parse_value("y")).is_err());
+        assert!(parse_key_path("server..port").is_err());
+    }
+}
@@ -30,15 +30,20 @@ pub struct AptTask {
     pub version: Option<String>,
     pub update: Option<String>,
     pub remove: Option<String>,
+    pub update_cache: Option<String>,
+    pub cache_valid_time: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct AptAction {
     pub package: String,
     pub version: Option<String>,
     pub update: bool,
     pub remove: bool,
+    pub update_cache: bool,
+    pub cache_valid_time: u64,
 }
 
 impl IsTask for AptTask {
@@ -48,13 +53,27 @@ impl IsTask for AptTask {
     fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
 
     fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let package = handle.template.string_no_spaces(request, tm, &String::from("package"), &self.package)?;
+        let version = handle.template.string_option_no_spaces(request, tm, &String::from("version"), &self.version)?;
+        if tm != TemplateMode::Off {
+            if let Err(msg) = screen_package_name(&package) {
+                return Err(handle.response.is_failed(request, &msg));
+            }
+            if let Some(v) = &version {
+                if let Err(msg) = screen_package_version(v) {
+                    return Err(handle.response.is_failed(request, &msg));
+                }
+            }
+        }
         Ok(
             EvaluatedTask {
                 action: Arc::new(AptAction {
-                    package:    handle.template.string_no_spaces(request, tm, &String::from("package"), &self.package)?,
-                    version:    handle.template.string_option_no_spaces(request, tm, &String::from("version"), &self.version)?,
-                    update:     handle.template.boolean_option_default_false(request, tm, &String::from("update"), &self.update)?,
-                    remove:     handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?
+                    package,
+                    version,
+                    update:           handle.template.boolean_option_default_false(request, tm, &String::from("update"), &self.update)?,
+                    remove:           handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?,
+                    update_cache:     handle.template.boolean_option_default_false(request, tm, &String::from("update_cache"), &self.update_cache)?,
+                    cache_valid_time: handle.template.integer_option_to_integer(request, tm, &String::from("cache_valid_time"), &self.cache_valid_time, 0)?
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?)
@@ -72,8 +91,10 @@ impl IsAction for AptAction {
 
 impl PackageManagementModule for AptAction {
 
-    fn initial_setup(&self, _handle: &Arc<TaskHandle>, _request: &Arc<TaskRequest>) -> Result<(),Arc<TaskResponse>> {
-        // nothing to do here, see how this was used in yum_dnf.rs
+    fn initial_setup(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(),Arc<TaskResponse>> {
+        if self.update_cache {
+            update_cache(handle, request, self.cache_valid_time)?;
+        }
         Ok(())
     }
 
@@ -90,56 +111,16 @@ impl PackageManagementModule for AptAction {
     }
 
     fn get_remote_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
-        /* need to implement so update returns the correct modification status */
-        let cmd = format!("apt-cache show {} | grep Version | cut -f2 --delimiter=':'", self.package.clone());
-        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked);
-        match result {
-            Ok(r) => {
-                let (rc,out) = cmd_info(&r);
-                // (differentiated return code is unavailable for this module only, don't repeat this pattern)
-                if out.contains("No packages found") {
-                    return Ok(None);
-                }
-                if rc == 0 {
-                    self.parse_remote_package_details(handle, &out.clone())
-                } else {
-                    Ok(None)
-                }
-            },
-            Err(e) => {
-                Err(e)
-            }
-        }
+        let (_installed, candidate) = self.get_policy(handle, request)?;
+        Ok(candidate.map(|version| PackageDetails { name: self.package.clone(), version }))
     }
 
-    fn get_local_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
+    // a package that was removed but still has its configuration files around shows up in dpkg -s,
+    // but apt-cache policy correctly reports it as not installed
 
-        let cmd = format!("dpkg -s '{}' >/dev/null", self.package);
-        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked);
-        match result {
-            Ok(r) => {
-                let (rc,_out) = cmd_info(&r);
-                if rc != 0 {
-                    return Ok(None);
-                }
-            },
-            Err(e) => return Err(e)
-        }
-
-        let cmd = format!("dpkg-query -W '{}'", self.package);
-        let result = handle.remote.run(request, &cmd, CheckRc::Unchecked);
-        match result {
-            Ok(r) => {
-                let (rc,out) = cmd_info(&r);
-                if rc == 0 {
-                    let details = self.parse_local_package_details(handle, &out.clone())?;
-                    Ok(details)
-                } else {
-                    Ok(None)
-                }
-            },
-            Err(e) => Err(e)
-        }
+    fn get_local_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
+        let (installed, _candidate) = self.get_policy(handle, request)?;
+        Ok(installed.map(|version| PackageDetails { name: self.package.clone(), version }))
     }
 
     fn install_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
@@ -167,21 +148,98 @@ impl PackageManagementModule for AptAction {
 
 impl AptAction {
 
-    pub fn parse_local_package_details(&self, _handle: &Arc<TaskHandle>, out: &str) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
-        let mut tokens = out.split("\t");
-        let version = tokens.nth(1);
-        match version {
-            Some(v) => {
-                Ok(Some(PackageDetails { name: self.package.clone(), version: v.trim().to_string() }))
-            },
-            None => {
-                Ok(None)
+    fn get_policy(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(Option<String>,Option<String>),Arc<TaskResponse>> {
+        let cmd = format!("LANG=C apt-cache policy '{}'", self.package);
+        let result = handle.remote.run(request, &cmd, CheckRc::Checked)?;
+        let (_rc,out) = cmd_info(&result);
+        Ok(parse_policy(&out))
+    }
+
+}
+
+// returns the installed and candidate versions from apt-cache policy, either of which may be (none).
+// an unknown package prints nothing at all.
+
+pub fn parse_policy(out: &str) -> (Option<String>,Option<String>) {
+    let mut installed = None;
+    let mut candidate = None;
+    for line in out.lines() {
+        let line = line.trim();
+        let version = |prefix: &str| {
+            line.strip_prefix(prefix).map(|x| x.trim().to_string()).filter(|x| ! x.is_empty() && x != "(none)")
+        };
+        if line.starts_with("Installed:") { installed = version("Installed:"); }
+        if line.starts_with("Candidate:") { candidate = version("Candidate:"); }
+    }
+    (installed, candidate)
+}
+
+// package names and versions go into apt-get commands, so they are held to what Debian policy allows
+// rather than just being free of shell characters. name:arch is accepted for multiarch.
+
+pub fn screen_package_name(package: &str) -> Result<(), String> {
+    let (name, arch) = match package.split_once(':') {
+        Some((n, a)) => (n, Some(a)),
+        None => (package, None)
+    };
+    let name_ok = name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+    let arch_ok = arch.is_none_or(|a| ! a.is_empty() && a.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'));
+    match name_ok && arch_ok {
+        true => Ok(()),
+        false => Err(format!("invalid package name: {}", package))
+    }
+}
+
+pub fn screen_package_version(version: &str) -> Result<(), String> {
+    let ok = version.starts_with(|c: char| c.is_ascii_digit())
+        && version.chars().all(|c| c.is_ascii_alphanumeric() || ".+~:-".contains(c));
+    match ok {
+        true => Ok(()),
+        false => Err(format!("invalid package version: {}", version))
+    }
+}
+
+// apt-get update is skipped if the last successful one was less than cache_valid_time seconds ago
+// (0 means always), and in check mode, where nothing on the host should change.
+// used by both the apt and apt_repository modules.
+
+pub fn update_cache(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, cache_valid_time: u64) -> Result<(),Arc<TaskResponse>> {
+    if handle.run_state.visitor.read().unwrap().is_check_mode() {
+        return Ok(());
+    }
+    if cache_valid_time > 0 {
+        let cmd = "date +%s; stat --format '%Y' /var/lib/apt/periodic/update-success-stamp /var/lib/apt/lists";
+        let result = handle.remote.run_unsafe(request, cmd, CheckRc::Unchecked)?;
+        let (_rc,out) = cmd_info(&result);
+        let mut times = out.lines().filter_map(|x| x.trim().parse::<u64>().ok());
+        if let Some(now) = times.next() {
+            if let Some(last_update) = times.max() {
+                if now.saturating_sub(last_update) < cache_valid_time {
+                    return Ok(());
+                }
             }
         }
     }
+    handle.remote.run(request, "DEBIAN_FRONTEND=noninteractive apt-get update -qq", CheckRc::Checked)?;
+    Ok(())
+}
 
-    pub fn parse_remote_package_details(&self, _handle: &Arc<TaskHandle>, out: &str) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
-        Ok(Some(PackageDetails { name: self.package.clone(), version: out.trim().to_string() }))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy_and_screening() {
+        let out = "nginx:\n  Installed: (none)\n  Candidate: 1.18.0-6ubuntu14.4\n  Version table:\n     1.18.0-6ubuntu14.4 500\n";
+        assert_eq!(parse_policy(out), (None, Some(String::from("1.18.0-6ubuntu14.4"))));
+        assert_eq!(parse_policy(""), (None, None));
+        assert!(screen_package_name("libc6:amd64").is_ok());
+        assert!(screen_package_name("g++").is_ok());
+        assert!(screen_package_name("Nginx").is_err());
+        assert!(screen_package_name("nginx'").is_err());
+        assert!(screen_package_version("1:2.3~rc1-0ubuntu1").is_ok());
+        assert!(screen_package_version("1.0'").is_err());
     }
-
 }
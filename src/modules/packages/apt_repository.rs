@@ -0,0 +1,285 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::{TaskHandle,CheckRc};
+use crate::modules::packages::apt::update_cache;
+use crate::tasks::fields::Field;
+use crate::tasks::files::Recurse;
+use crate::tasks::response::ValueDiff;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::vec::Vec;
+
+const MODULE: &str = "apt_repository";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AptRepositoryTask {
+    pub name: Option<String>,
+    pub repo: String,
+    pub filename: String,
+    pub key_url: Option<String>,
+    pub update_cache: Option<String>,
+    pub remove: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug)]
+struct AptRepositoryAction {
+    pub repo: String,
+    pub list_path: String,
+    pub key_url: Option<String>,
+    pub key_path: Option<String>,
+    pub update_cache: bool,
+    pub remove: bool,
+}
+
+impl IsTask for AptRepositoryTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let filename = handle.template.string_no_spaces(request, tm, &String::from("filename"), &self.filename)?;
+        // the source line is only ever written to a file and the key url is checked below before it reaches a command
+        let repo = handle.template.string_unsafe_for_shell(request, tm, "repo", &self.repo)?;
+        let key_url = handle.template.string_option_unsafe_for_shell(request, tm, "key_url", &self.key_url)?;
+        let mut key_path = None;
+        let mut repo_line = repo.clone();
+        if tm != TemplateMode::Off {
+            let screened = screen_filename(&filename)
+                .and_then(|_| screen_repo_line(&repo))
+                .and_then(|_| key_url.as_ref().map_or(Ok(()), |x| screen_key_url(x)));
+            if let Err(msg) = screened {
+                return Err(handle.response.is_failed(request, &msg));
+            }
+            if let Some(url) = &key_url {
+                let path = get_key_path(&filename, url);
+                repo_line = add_signed_by(&repo, &path);
+                key_path = Some(path);
+            }
+        }
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(AptRepositoryAction {
+                    repo: repo_line,
+                    list_path: format!("/etc/apt/sources.list.d/{}.list", filename),
+                    key_url,
+                    key_path,
+                    update_cache: handle.template.boolean_option_default_true(request, tm, &String::from("update_cache"), &self.update_cache)?,
+                    remove:       handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+            }
+        )
+    }
+
+}
+
+impl IsAction for AptRepositoryAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                let list_exists = handle.remote.get_mode(request, &self.list_path)?.is_some();
+                let key_exists = match &self.key_path {
+                    Some(path) => handle.remote.get_mode(request, path)?.is_some(),
+                    None => false
+                };
+                if self.remove {
+                    return match list_exists || key_exists {
+                        true => Ok(handle.response.needs_removal(request)),
+                        false => Ok(handle.response.is_matched(request))
+                    };
+                }
+                if ! list_exists {
+                    return Ok(handle.response.needs_creation(request));
+                }
+                let mut changes : Vec<Field> = Vec::new();
+                if handle.remote.read_file(request, &self.list_path)?.trim() != self.repo {
+                    changes.push(Field::Content);
+                }
+                if self.key_path.is_some() && ! key_exists {
+                    changes.push(Field::Key);
+                }
+                match changes.is_empty() {
+                    true => Ok(handle.response.is_matched(request)),
+                    false => Ok(handle.response.needs_modification(request, &changes))
+                }
+            },
+
+            TaskRequestType::Create => {
+                self.download_key(handle, request)?;
+                self.write_list(handle, request)?;
+                self.refresh(handle, request)?;
+                Ok(handle.response.is_created(request))
+            },
+
+            TaskRequestType::Modify => {
+                if request.changes.contains(&Field::Key) {
+                    self.download_key(handle, request)?;
+                }
+                let mut diff = Vec::new();
+                if request.changes.contains(&Field::Content) {
+                    let before = handle.remote.read_file(request, &self.list_path)?;
+                    self.write_list(handle, request)?;
+                    diff.push(ValueDiff { name: self.list_path.clone(), before: before.trim().to_string(), after: self.repo.clone() });
+                }
+                self.refresh(handle, request)?;
+                Ok(handle.response.is_modified_with_diff(request, request.changes.clone(), diff))
+            },
+
+            TaskRequestType::Remove => {
+                if handle.remote.get_mode(request, &self.list_path)?.is_some() {
+                    handle.remote.delete_file(request, &self.list_path)?;
+                }
+                if let Some(path) = &self.key_path {
+                    if handle.remote.get_mode(request, path)?.is_some() {
+                        handle.remote.delete_file(request, path)?;
+                    }
+                }
+                self.refresh(handle, request)?;
+                Ok(handle.response.is_removed(request))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+impl AptRepositoryAction {
+
+    fn download_key(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        if let (Some(url), Some(path)) = (&self.key_url, &self.key_path) {
+            // key urls may contain ? and & so this can't go through the usual screening, see screen_key_url
+            let cmd = format!("mkdir -p -m 0755 /etc/apt/keyrings && curl -fsSL '{}' -o '{}' && chmod 0644 '{}'", url, path, path);
+            handle.remote.run_unsafe(request, &cmd, CheckRc::Checked)?;
+        }
+        Ok(())
+    }
+
+    fn write_list(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let data = format!("{}\n", self.repo);
+        handle.remote.write_data_atomic(request, &data, &self.list_path, None, |f| { /* after save */
+            match handle.remote.set_mode(request, f, "0644", Recurse::No) {
+                Ok(_x) => Ok(()), Err(y) => Err(y)
+            }
+        })
+    }
+
+    fn refresh(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        match self.update_cache {
+            true => update_cache(handle, request, 0),
+            false => Ok(())
+        }
+    }
+
+}
+
+pub fn screen_filename(filename: &str) -> Result<(), String> {
+    match ! filename.is_empty() && filename.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c)) {
+        true => Ok(()),
+        false => Err(format!("invalid filename: {}", filename))
+    }
+}
+
+// a one line source entry: deb [options] uri suite [components], with an http(s), ftp or file uri
+
+pub fn screen_repo_line(repo: &str) -> Result<(), String> {
+    let invalid = || Err(format!("invalid apt source line: {}", repo));
+    if repo.contains(['\n', '\'', '"', '`', '$', '\\']) {
+        return invalid();
+    }
+    let rest = match repo.strip_prefix("deb-src ").or_else(|| repo.strip_prefix("deb ")) {
+        Some(x) => x.trim_start(),
+        None => { return invalid(); }
+    };
+    let rest = match rest.strip_prefix('[') {
+        Some(x) => match x.split_once(']') { Some((_options, after)) => after.trim_start(), None => { return invalid(); } },
+        None => rest
+    };
+    let mut tokens = rest.split_whitespace();
+    let uri = tokens.next().unwrap_or("");
+    let schemes = ["http://", "https://", "ftp://", "file:/"];
+    if ! schemes.iter().any(|x| uri.starts_with(x)) || tokens.next().is_none() {
+        return invalid();
+    }
+    Ok(())
+}
+
+// the key url ends up single quoted in a curl command, so beyond the scheme anything that could close
+// the quotes or start a new command is rejected
+
+pub fn screen_key_url(url: &str) -> Result<(), String> {
+    let ok = (url.starts_with("https://") || url.starts_with("http://"))
+        && url.chars().all(|c| c.is_ascii_alphanumeric() || "-._~:/?#@!&+,=%".contains(c));
+    match ok {
+        true => Ok(()),
+        false => Err(format!("invalid key_url: {}", url))
+    }
+}
+
+// apt reads ASCII armored keys from .asc files and binary keys from .gpg files, so keep whichever the url has
+
+pub fn get_key_path(filename: &str, url: &str) -> String {
+    let extension = match url.ends_with(".gpg") {
+        true => "gpg",
+        false => "asc"
+    };
+    format!("/etc/apt/keyrings/{}.{}", filename, extension)
+}
+
+pub fn add_signed_by(repo: &str, key_path: &str) -> String {
+    if repo.contains("signed-by=") {
+        return repo.to_string();
+    }
+    let (kind, rest) = repo.split_once(' ').unwrap_or((repo, ""));
+    let rest = rest.trim_start();
+    match rest.strip_prefix('[') {
+        Some(options) => format!("{} [signed-by={} {}", kind, key_path, options.trim_start()),
+        None => format!("{} [signed-by={}] {}", kind, key_path, rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_lines_are_screened_and_signed() {
+        assert!(screen_repo_line("deb [arch=amd64] https://download.docker.com/linux/ubuntu jammy stable").is_ok());
+        assert!(screen_repo_line("deb https://example.com/repo").is_err());
+        assert!(screen_repo_line("rpm https://example.com/repo jammy main").is_err());
+        assert!(screen_repo_line("deb https://example.com/$x jammy main").is_err());
+        assert!(screen_key_url("https://example.com/key.gpg?x=1&y=2").is_ok());
+        assert!(screen_key_url("https://example.com/key' ; rm -rf /").is_err());
+        assert!(screen_filename("../docker").is_err());
+        let path = get_key_path("docker", "https://download.docker.com/linux/ubuntu/gpg");
+        assert_eq!(path, "/etc/apt/keyrings/docker.asc");
+        assert_eq!(add_signed_by("deb [arch=amd64] https://x.com/ubuntu jammy stable", &path),
+            "deb [signed-by=/etc/apt/keyrings/docker.asc arch=amd64] https://x.com/ubuntu jammy stable");
+        assert_eq!(add_signed_by("deb https://x.com/ubuntu jammy stable", &path),
+            "deb [signed-by=/etc/apt/keyrings/docker.asc] https://x.com/ubuntu jammy stable");
+    }
+}
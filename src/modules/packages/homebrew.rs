@@ -34,6 +34,7 @@ pub struct HomebrewTask {
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct HomebrewAction {
     pub package: String,
     pub version: Option<String>,
@@ -0,0 +1,282 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::modules::packages::common::{PackageManagementModule,PackageDetails};
+use crate::modules::packages::apt::parse_policy;
+use crate::modules::packages::yum_dnf::parse_rpm_query;
+use crate::handle::handle::{TaskHandle,CheckRc};
+use crate::inventory::hosts::{HostOSType,PackagePreference};
+use crate::tasks::cmd_library::*;
+use serde::Deserialize;
+use std::sync::{Arc,Mutex};
+
+const MODULE: &str = "package";
+
+// installs packages with whatever the host uses, for tasks that don't need the options of the specific
+// package modules.  EL hosts pick dnf or yum the way yum_dnf does, so jet_package_manager applies here too.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PackageTask {
+    pub name: Option<String>,
+    pub package: String,
+    pub state: Option<String>,
+    pub version: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+enum PackageState {
+    Present,
+    Absent,
+    Latest
+}
+
+#[derive(Debug)]
+struct PackageAction {
+    pub package: String,
+    pub state: PackageState,
+    pub version: Option<String>,
+    // chosen by the query, which always runs before the other legs
+    pub manager: Mutex<Option<PackageManager>>,
+}
+
+impl IsTask for PackageTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let state = match handle.template.string_option_no_spaces(request, tm, &String::from("state"), &self.state)?.as_deref() {
+            None | Some("present") => PackageState::Present,
+            Some("absent") => PackageState::Absent,
+            Some("latest") => PackageState::Latest,
+            Some(_) if tm == TemplateMode::Off => PackageState::Present,
+            Some(x) => { return Err(handle.response.is_failed(request, &format!("field state: must be one of present, absent, or latest, got: {}", x))); }
+        };
+        let version = handle.template.string_option_no_spaces(request, tm, &String::from("version"), &self.version)?;
+        if version.is_some() && state != PackageState::Present {
+            return Err(handle.response.is_failed(request, &String::from("field version: can only be used with state: present")));
+        }
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(PackageAction {
+                    package:  handle.template.string_no_spaces(request, tm, &String::from("package"), &self.package)?,
+                    state,
+                    version,
+                    manager:  Mutex::new(None),
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?)
+            }
+        )
+    }
+
+}
+
+impl IsAction for PackageAction {
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        self.common_dispatch(handle,request)
+    }
+}
+
+impl PackageManagementModule for PackageAction {
+
+    fn initial_setup(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(),Arc<TaskResponse>> {
+        let manager = self.choose_package_manager(handle, request)?;
+        *self.manager.lock().unwrap() = Some(manager);
+        Ok(())
+    }
+
+    fn is_update(&self) -> bool {
+        self.state == PackageState::Latest
+    }
+
+    fn is_remove(&self) -> bool {
+        self.state == PackageState::Absent
+    }
+
+    fn get_version(&self) -> Option<String> {
+        self.version.clone()
+    }
+
+    fn get_local_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
+        let manager = self.get_package_manager();
+        let cmd = handle.remote.unwrap_string_result(request, &get_package_query_command(manager, &self.package, self.version.as_deref()))?;
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (rc,out) = cmd_info(&result);
+        let version = match manager {
+            PackageManager::Apt => parse_policy(&out).0,
+            _ if rc != 0 => None,
+            PackageManager::Dnf | PackageManager::Yum => parse_rpm_query(&out, self.version.as_deref()),
+            // the versioned formula being there is the version asked for, python@3.11 installs 3.11.6
+            PackageManager::Brew => parse_brew_versions(&out).map(|x| self.version.clone().unwrap_or(x))
+        };
+        Ok(version.map(|version| PackageDetails { name: self.package.clone(), version }))
+    }
+
+    fn get_remote_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
+        let manager = self.get_package_manager();
+        let cmd = handle.remote.unwrap_string_result(request, &get_package_latest_command(manager, &self.package))?;
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (_rc,out) = cmd_info(&result);
+        let version = match manager {
+            PackageManager::Apt => parse_policy(&out).1,
+            // repoquery has no useful return codes, see yum_dnf.rs
+            PackageManager::Dnf | PackageManager::Yum => out.lines().map(|x| x.trim()).find(|x| ! x.is_empty() && ! x.contains("metadata expiration")).map(|x| x.to_string()),
+            PackageManager::Brew => parse_brew_info(&out)
+        };
+        Ok(version.map(|version| PackageDetails { name: self.package.clone(), version }))
+    }
+
+    fn install_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let cmd = handle.remote.unwrap_string_result(request, &get_package_install_command(self.get_package_manager(), &self.package, self.version.as_deref()))?;
+        handle.remote.run(request, &cmd, CheckRc::Checked)
+    }
+
+    fn update_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let cmd = handle.remote.unwrap_string_result(request, &get_package_update_command(self.get_package_manager(), &self.package, self.version.as_deref()))?;
+        handle.remote.run(request, &cmd, CheckRc::Checked)
+    }
+
+    fn remove_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let cmd = handle.remote.unwrap_string_result(request, &get_package_remove_command(self.get_package_manager(), &self.package))?;
+        handle.remote.run(request, &cmd, CheckRc::Checked)
+    }
+
+}
+
+impl PackageAction {
+
+    // jet_package_manager wins, then the jet_os_flavor fact if the facts module has run, and otherwise
+    // whichever package manager is found first
+
+    fn choose_package_manager(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<PackageManager,Arc<TaskResponse>> {
+        if handle.remote.get_os_type() == HostOSType::MacOS {
+            return Ok(PackageManager::Brew);
+        }
+        match handle.host.read().unwrap().package_preference {
+            Some(PackagePreference::Dnf) => { return Ok(PackageManager::Dnf); },
+            Some(PackagePreference::Yum) => { return Ok(PackageManager::Yum); },
+            None => {}
+        }
+        let flavor = handle.host.read().unwrap().get_blended_variables(handle.run_state.context.read().unwrap().list_merge).get("jet_os_flavor").and_then(|x| x.as_str().map(|y| y.to_string()));
+        let candidates = match flavor.as_deref() {
+            Some("EL") => vec![PackageManager::Dnf, PackageManager::Yum],
+            Some("Debian") => vec![PackageManager::Apt],
+            _ => vec![PackageManager::Dnf, PackageManager::Yum, PackageManager::Apt]
+        };
+        for candidate in candidates {
+            let path = match candidate {
+                PackageManager::Dnf => "/usr/bin/dnf",
+                PackageManager::Yum => "/usr/bin/yum",
+                _ => "/usr/bin/apt-get"
+            };
+            if handle.remote.get_mode(request, &String::from(path))?.is_some() {
+                let preference = match candidate {
+                    PackageManager::Dnf => Some(PackagePreference::Dnf),
+                    PackageManager::Yum => Some(PackagePreference::Yum),
+                    _ => None
+                };
+                if preference.is_some() {
+                    handle.host.write().unwrap().package_preference = preference;
+                }
+                return Ok(candidate);
+            }
+        }
+        Err(handle.response.is_failed(request, &String::from("no supported package manager found (dnf, yum, or apt)")))
+    }
+
+    fn get_package_manager(&self) -> PackageManager {
+        match *self.manager.lock().unwrap() {
+            Some(x) => x,
+            None => { panic!("internal error, package manager not chosen before use"); }
+        }
+    }
+
+}
+
+// brew list --versions prints the name followed by every installed version, newest last
+
+fn parse_brew_versions(out: &str) -> Option<String> {
+    out.lines().next().and_then(|x| x.split_whitespace().skip(1).last()).map(|x| x.to_string())
+}
+
+fn parse_brew_info(out: &str) -> Option<String> {
+    let info : serde_json::Value = serde_json::from_str(out).ok()?;
+    let formula = info.get("formulae").and_then(|x| x.get(0)).and_then(|x| x.get("versions")).and_then(|x| x.get("stable"));
+    let cask = info.get("casks").and_then(|x| x.get(0)).and_then(|x| x.get("version"));
+    formula.or(cask).and_then(|x| x.as_str()).map(|x| x.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::fields::Field;
+    use crate::tasks::request::SudoDetails;
+    use crate::tasks::response::TaskStatus;
+
+    #[test]
+    fn test_version_change_uses_preferred_manager() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("rpm -q --whatprovides", 0, "1.0 1.el9"),
+        ]);
+        handle.host.write().unwrap().package_preference = Some(PackagePreference::Yum);
+        let action = PackageAction { package: String::from("nginx"), state: PackageState::Present, version: Some(String::from("2.0")), manager: Mutex::new(None) };
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Version]);
+
+        let request = TaskRequest::modify(&SudoDetails::none(), false, response.changes.clone());
+        assert!(action.dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.contains(&String::from("yum install 'nginx-2.0' -y")));
+    }
+
+    #[test]
+    fn test_brew_version_is_its_own_formula() {
+        let query = |rc: i32, out: &str| {
+            let (handle, connection) = TaskHandle::scripted(vec![
+                ("brew list --versions 'python@3.11'", rc, out),
+            ]);
+            handle.host.write().unwrap().os_type = Some(HostOSType::MacOS);
+            let action = PackageAction { package: String::from("python"), state: PackageState::Present, version: Some(String::from("3.11")), manager: Mutex::new(None) };
+            let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+            if response.status == TaskStatus::NeedsCreation {
+                assert!(action.dispatch(&handle, &TaskRequest::create(&SudoDetails::none(), false)).is_ok());
+            }
+            let commands = connection.lock().unwrap().get_commands();
+            (response, commands)
+        };
+
+        let (response, _) = query(0, "python@3.11 3.11.6\n");
+        assert_eq!(response.status, TaskStatus::IsMatched);
+        let (response, commands) = query(1, "Error: No such keg");
+        assert_eq!(response.status, TaskStatus::NeedsCreation);
+        assert_eq!(commands, vec![String::from("brew list --versions 'python@3.11'"), String::from("brew install 'python@3.11'")]);
+    }
+
+    #[test]
+    fn test_brew_output_and_package_screening() {
+        assert_eq!(parse_brew_versions("wget 1.21.3 1.21.4\n"), Some(String::from("1.21.4")));
+        assert_eq!(parse_brew_versions(""), None);
+        assert_eq!(parse_brew_info("{\"formulae\":[{\"versions\":{\"stable\":\"1.21.4\"}}],\"casks\":[]}"), Some(String::from("1.21.4")));
+        assert!(get_package_install_command(PackageManager::Apt, "nginx", Some("1.2'; rm")).is_err());
+        assert!(get_package_remove_command(PackageManager::Dnf, "-y").is_err());
+    }
+}
@@ -34,6 +34,7 @@ pub struct PacmanTask {
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct PacmanAction {
     pub package: String,
     pub version: Option<String>,
@@ -31,15 +31,20 @@ pub struct YumDnfTask {
     pub version: Option<String>,
     pub update: Option<String>,
     pub remove: Option<String>,
+    pub enablerepo: Option<String>,
+    pub disablerepo: Option<String>,
     pub with: Option<PreLogicInput>,
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct YumDnfAction {
     pub package: String,
     pub version: Option<String>,
     pub update: bool,
     pub remove: bool,
+    pub enablerepo: Vec<String>,
+    pub disablerepo: Vec<String>,
 }
 
 impl IsTask for YumDnfTask {
@@ -49,13 +54,36 @@ impl IsTask for YumDnfTask {
     fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
 
     fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let package = handle.template.string_no_spaces(request, tm, &String::from("package"), &self.package)?;
+        let version = handle.template.string_option_no_spaces(request, tm, &String::from("version"), &self.version)?;
+        let enablerepo = handle.template.string_option(request, tm, &String::from("enablerepo"), &self.enablerepo)?;
+        let disablerepo = handle.template.string_option(request, tm, &String::from("disablerepo"), &self.disablerepo)?;
+        if tm != TemplateMode::Off {
+            if package.starts_with('@') && version.is_some() {
+                return Err(handle.response.is_failed(request, &String::from("a version cannot be given for a package group")));
+            }
+            // rpm queries use run_unsafe, so quotes must not get through
+            for (field, value) in [("package", Some(&package)), ("version", version.as_ref())] {
+                if value.is_some_and(|x| ! x.chars().all(|c| c.is_ascii_alphanumeric() || "@_.+-:~^".contains(c))) {
+                    return Err(handle.response.is_failed(request, &format!("invalid {}: {}", field, value.unwrap())));
+                }
+            }
+        }
+        let split_repos = |repos: &Option<String>| -> Result<Vec<String>, Arc<TaskResponse>> {
+            match repos {
+                Some(x) if tm != TemplateMode::Off => split_repo_ids(x).map_err(|msg| handle.response.is_failed(request, &msg)),
+                _ => Ok(Vec::new())
+            }
+        };
         Ok(
             EvaluatedTask {
                 action: Arc::new(YumDnfAction {
-                    package:    handle.template.string_no_spaces(request, tm, &String::from("package"), &self.package)?,
-                    version:    handle.template.string_option_no_spaces(request, tm, &String::from("version"), &self.version)?,
-                    update:     handle.template.boolean_option_default_false(request, tm, &String::from("update"), &self.update)?,
-                    remove:     handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?
+                    package,
+                    version,
+                    update:      handle.template.boolean_option_default_false(request, tm, &String::from("update"), &self.update)?,
+                    remove:      handle.template.boolean_option_default_false(request, tm, &String::from("remove"), &self.remove)?,
+                    enablerepo:  split_repos(&enablerepo)?,
+                    disablerepo: split_repos(&disablerepo)?,
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
                 and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
@@ -92,20 +120,45 @@ impl PackageManagementModule for YumDnfAction {
         Ok(())
     }
 
+    // rpm -q answers for anything the package provides (so 'vim' finds vim-enhanced) and is much faster than
+    // asking dnf or yum. groups are only known to the package manager.
+
     fn get_local_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
-        let which = self.get_package_manager(handle);
-        let cmd = match self.version.is_none() {
-            true => format!("{} info {}", which, self.package),
-            false => format!("{} info {}-{}", which, self.package, self.version.as_ref().unwrap())
-        };
-        let result = handle.remote.run(request, &cmd, CheckRc::Unchecked)?;
-        let (_rc,out) = cmd_info(&result);
-        let details = self.parse_local_package_details(&out.clone())?;
-        Ok(details)
+        if let Some(group) = self.get_group() {
+            let which = self.get_package_manager(handle);
+            // dnf 4 shows ids only with --ids, old yum wants the ids and installed words instead, and dnf 5 has no
+            // such option because its table starts with the id anyway
+            let fallback = match which.as_str() {
+                "yum" => "ids installed",
+                _ => "--installed"
+            };
+            let cmd = format!("LANG=C {} group list --installed --ids || LANG=C {} group list {}", which, which, fallback);
+            let result = handle.remote.run(request, &cmd, CheckRc::Checked)?;
+            let (_rc,out) = cmd_info(&result);
+            return match is_group_listed(&out, group) {
+                true => Ok(Some(PackageDetails { name: self.package.clone(), version: String::from("group") })),
+                false => Ok(None)
+            };
+        }
+        // the query format needs braces, which the usual screening refuses, but the package name has already been screened
+        let cmd = format!("rpm -q --whatprovides --queryformat '%{{VERSION}} %{{RELEASE}}\\n' '{}'", self.package);
+        let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
+        let (rc,out) = cmd_info(&result);
+        if rc != 0 {
+            return Ok(None);
+        }
+        Ok(parse_rpm_query(&out, self.version.as_deref()).map(|version| PackageDetails { name: self.package.clone(), version }))
     }
 
     fn get_remote_version(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
-        let cmd = format!("repoquery {} --queryformat {}", self.package, "'%{version}'");
+        if self.get_group().is_some() {
+            // groups have no version, 'update' on a group always runs group update
+            return Ok(None);
+        }
+        let cmd = match self.get_package_preference(handle) {
+            Some(PackagePreference::Dnf) => format!("dnf -q repoquery --latest-limit 1 --queryformat '%{{version}}'{} '{}'", self.get_repo_options(), self.package),
+            _ => format!("repoquery{} '{}' --queryformat '%{{version}}'", self.get_repo_options(), self.package)
+        };
         let result = handle.remote.run_unsafe(request, &cmd, CheckRc::Unchecked)?;
         let (_rc,out) = cmd_info(&result);
         let details = self.parse_remote_package_details(&out.clone())?;
@@ -114,22 +167,31 @@ impl PackageManagementModule for YumDnfAction {
     
     fn install_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>>{
         let which = self.get_package_manager(handle);
-        let cmd = match self.version.is_none() {
-            true => format!("{} install '{}' -y", which, self.package),
-            false => format!("{} install '{}-{}' -y", which, self.package, self.version.as_ref().unwrap())
+        let cmd = match (self.get_group(), &self.version) {
+            (Some(group), _) => format!("{} group install '{}' -y{}", which, group, self.get_repo_options()),
+            (None, None) => format!("{} install '{}' -y{}", which, self.package, self.get_repo_options()),
+            (None, Some(version)) => format!("{} install '{}-{}' -y{}", which, self.package, version, self.get_repo_options())
         };
         handle.remote.run(request, &cmd, CheckRc::Checked)
     }
 
     fn update_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>>{
         let which = self.get_package_manager(handle);
-        let cmd = format!("{} update '{}' -y", which, self.package);
+        let cmd = match (self.get_group(), &self.version) {
+            (Some(group), _) => format!("{} group update '{}' -y{}", which, group, self.get_repo_options()),
+            (None, None) => format!("{} update '{}' -y{}", which, self.package, self.get_repo_options()),
+            // update would go to the newest version rather than the pinned one
+            (None, Some(version)) => format!("{} install '{}-{}' -y{}", which, self.package, version, self.get_repo_options())
+        };
         handle.remote.run(request, &cmd, CheckRc::Checked)
     }
 
     fn remove_package(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>>{
         let which = self.get_package_manager(handle);
-        let cmd = format!("{} remove '{}' -y", which, self.package);
+        let cmd = match self.get_group() {
+            Some(group) => format!("{} group remove '{}' -y{}", which, group, self.get_repo_options()),
+            None => format!("{} remove '{}' -y{}", which, self.package, self.get_repo_options())
+        };
         handle.remote.run(request, &cmd, CheckRc::Checked)
     }
 
@@ -167,29 +229,21 @@ impl YumDnfAction {
         }
     }
 
-    fn parse_local_package_details(&self, out: &str) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
-        let mut name: Option<String> = None;
-        let mut version: Option<String> = None;
-        for line in out.lines() {
-            if line.starts_with("Available") {
-                return Ok(None);
-            }
-            let mut tokens = line.split(":");
-            let key = tokens.nth(0);
-            let value = tokens.nth(0);
-            if key.is_some() && value.is_some() {
-                let key2 = key.unwrap().trim();
-                let value2 = value.unwrap().trim();
-                if key2.eq("Name")    { name = Some(value2.to_string());           }
-                if key2.eq("Version") { version = Some(value2.to_string()); break; }
-            }
+    fn get_group(&self) -> Option<&str> {
+        self.package.strip_prefix('@')
+    }
+
+    fn get_repo_options(&self) -> String {
+        let mut options = String::new();
+        if ! self.enablerepo.is_empty() {
+            options.push_str(&format!(" --enablerepo='{}'", self.enablerepo.join(",")));
         }
-        match name.is_some() && version.is_some() {
-            true => Ok(Some(PackageDetails { name: name.unwrap().clone(), version: version.unwrap().clone() })),
-            false => Ok(None),
+        if ! self.disablerepo.is_empty() {
+            options.push_str(&format!(" --disablerepo='{}'", self.disablerepo.join(",")));
         }
+        options
     }
-    
+
     fn parse_remote_package_details(&self, out: &str) -> Result<Option<PackageDetails>,Arc<TaskResponse>> {
         // FYI: this command doesn't have useful return codes
         for line in out.lines() {
@@ -204,3 +258,68 @@ impl YumDnfAction {
     }
 
 }
+
+// enablerepo and disablerepo take a comma separated list of repository ids
+
+pub fn split_repo_ids(repos: &str) -> Result<Vec<String>, String> {
+    let ids : Vec<String> = repos.split(',').map(|x| x.trim().to_string()).filter(|x| ! x.is_empty()).collect();
+    for id in ids.iter() {
+        if ! id.chars().all(|c| c.is_ascii_alphanumeric() || "_.:-".contains(c)) {
+            return Err(format!("invalid repository id: {}", id));
+        }
+    }
+    Ok(ids)
+}
+
+// rpm prints one 'version release' line per matching package. the release is only compared when the
+// requested version includes one, as in 1.2.3-4.el9
+
+pub fn parse_rpm_query(out: &str, wanted_version: Option<&str>) -> Option<String> {
+    let with_release = wanted_version.is_some_and(|x| x.contains('-'));
+    let line = out.lines().map(|x| x.trim()).find(|x| ! x.is_empty())?;
+    let (version, release) = line.split_once(' ').unwrap_or((line, ""));
+    match with_release && ! release.is_empty() {
+        true => Some(format!("{}-{}", version, release)),
+        false => Some(version.to_string())
+    }
+}
+
+// dnf 4 and yum list installed groups under a heading as 'Name (id)', dnf 5 prints a table starting with the id
+
+pub fn is_group_listed(out: &str, group: &str) -> bool {
+    out.lines().any(|line| {
+        let line = line.trim();
+        let id = match line.strip_suffix(')').and_then(|x| x.rsplit_once(" (")) {
+            Some((_name, id)) => Some(id),
+            None => line.split_whitespace().next()
+        };
+        id.is_some_and(|x| x.eq_ignore_ascii_case(group))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpm_query_groups_and_repo_ids() {
+        assert_eq!(parse_rpm_query("2.0.1 3.el9\n", None), Some(String::from("2.0.1")));
+        assert_eq!(parse_rpm_query("2.0.1 3.el9\n", Some("2.0.1-3.el9")), Some(String::from("2.0.1-3.el9")));
+        assert_eq!(parse_rpm_query("", None), None);
+        // dnf 4 with --ids
+        let dnf4 = "Last metadata expiration check: 0:10:41 ago on Mon 01 Jan 2024 10:00:00 AM UTC.\nInstalled Environment Groups:\n   \
+            Server (server-product-environment)\nInstalled Groups:\n   Container Management (container-management)\n   Development Tools (development)\n";
+        assert!(is_group_listed(dnf4, "development"));
+        assert!(is_group_listed(dnf4, "server-product-environment"));
+        assert!(! is_group_listed(dnf4, "Container"));
+        assert!(! is_group_listed(dnf4, "container"));
+        // yum on EL7 with ids installed
+        let yum = "Loaded plugins: fastestmirror\nInstalled Groups:\n   Development Tools (development)\n   Security Tools (security-tools)\nDone\n";
+        assert!(is_group_listed(yum, "security-tools"));
+        assert!(! is_group_listed(yum, "development-extra"));
+        // dnf 5
+        assert!(is_group_listed("ID                   Name               Installed\ndevelopment          Development Tools        yes\n", "development"));
+        assert_eq!(split_repo_ids("epel, crb").unwrap(), vec![String::from("epel"), String::from("crb")]);
+        assert!(split_repo_ids("epel;reboot").is_err());
+    }
+}
@@ -34,6 +34,7 @@ pub struct ZypperTask {
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct ZypperAction {
     pub package: String,
     pub version: Option<String>,
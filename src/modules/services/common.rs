@@ -0,0 +1,111 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::{TaskHandle,CheckRc};
+use crate::tasks::fields::Field;
+use crate::tasks::response::ValueDiff;
+use crate::tasks::cmd_library::{ServiceCommand,get_service_command,get_service_is_active_command,get_service_is_enabled_command};
+use crate::inventory::hosts::HostOSType;
+use std::sync::Arc;
+use std::vec::Vec;
+
+// shared by sd_service, which always talks to systemd, and service, which picks systemd or launchd from the
+// host's OS type
+
+pub fn is_service_running(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, os_type: HostOSType, service: &str) -> Result<bool, Arc<TaskResponse>> {
+    let cmd = handle.remote.unwrap_string_result(request, &get_service_is_active_command(os_type, service))?;
+    let result = handle.remote.run(request, &cmd, CheckRc::Unchecked)?;
+    let (rc, out) = cmd_info(&result);
+    match os_type {
+        HostOSType::Linux => match out.lines().next().unwrap_or("").trim() {
+            "active" | "activating" | "reloading" => Ok(true),
+            "inactive" | "failed" | "deactivating" => Ok(false),
+            x => Err(handle.response.is_failed(request, &format!("systemctl activity status unexpected for service({}): {}", service, x)))
+        },
+        HostOSType::MacOS => match rc {
+            0 => Ok(out.lines().any(|x| x.trim().eq("state = running"))),
+            _ => Err(handle.response.is_failed(request, &format!("launchd job {} is not loaded in the system domain", service)))
+        }
+    }
+}
+
+// None is a unit that systemd starts on behalf of others (static, indirect, generated...), which has no
+// enablement of its own to change
+
+pub fn get_service_enabled(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, os_type: HostOSType, service: &str) -> Result<Option<bool>, Arc<TaskResponse>> {
+    let cmd = handle.remote.unwrap_string_result(request, &get_service_is_enabled_command(os_type, service))?;
+    let result = handle.remote.run(request, &cmd, CheckRc::Unchecked)?;
+    let (_rc, out) = cmd_info(&result);
+    match os_type {
+        HostOSType::Linux => match out.lines().next().unwrap_or("").trim() {
+            "enabled" | "enabled-runtime" | "alias" => Ok(Some(true)),
+            "disabled" | "masked" | "masked-runtime" => Ok(Some(false)),
+            "static" | "indirect" | "generated" | "transient" => Ok(None),
+            x => Err(handle.response.is_failed(request, &format!("systemctl enablement status unexpected for service({}): ({})", service, x)))
+        },
+        HostOSType::MacOS => Ok(Some(is_launchd_job_enabled(&out, service)))
+    }
+}
+
+pub fn run_service_command(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, os_type: HostOSType, service: &str, command: ServiceCommand) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+    let cmd = handle.remote.unwrap_string_result(request, &get_service_command(os_type, service, command))?;
+    handle.remote.run(request, &cmd, CheckRc::Checked)
+}
+
+// print-disabled lists lines like "com.example.job" => disabled, older releases say true for disabled and false
+// for enabled
+
+fn is_launchd_job_enabled(out: &str, service: &str) -> bool {
+    let label = format!("\"{}\"", service);
+    for line in out.lines() {
+        if let Some((name, value)) = line.trim().split_once("=>") {
+            if name.trim().eq(&label) {
+                return ! matches!(value.trim(), "disabled" | "true");
+            }
+        }
+    }
+    true
+}
+
+// the query only asks for a change when the current value is the opposite one, so before and after
+// can be worked out from the changes alone.  a restart leaves the service started and isn't shown.
+
+pub fn get_service_diff(changes: &[Field]) -> Vec<ValueDiff> {
+    let mut diff : Vec<ValueDiff> = Vec::new();
+    let mut add = |name: &str, before: bool, after: bool| {
+        diff.push(ValueDiff { name: name.to_owned(), before: before.to_string(), after: after.to_string() });
+    };
+    if changes.contains(&Field::Enable)       { add("enabled", false, true); }
+    else if changes.contains(&Field::Disable) { add("enabled", true, false); }
+    if changes.contains(&Field::Start)        { add("started", false, true); }
+    else if changes.contains(&Field::Stop)    { add("started", true, false); }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launchd_disabled_listing() {
+        let out = "disabled services = {\n\t\"com.example.a\" => disabled\n\t\"com.example.b\" => enabled\n\t\"com.example.c\" => true\n}";
+        assert!(!is_launchd_job_enabled(out, "com.example.a"));
+        assert!(is_launchd_job_enabled(out, "com.example.b"));
+        assert!(!is_launchd_job_enabled(out, "com.example.c"));
+        assert!(is_launchd_job_enabled(out, "com.example.d"));
+    }
+}
@@ -17,4 +17,6 @@
 #[allow(clippy::empty_line_after_doc_comments)]
 /** ADD MODULES HERE, KEEP ALPHABETIZED **/
 
+pub mod common;
 pub mod sd_service;
+pub mod service;
@@ -15,8 +15,11 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::tasks::*;
-use crate::handle::handle::{TaskHandle,CheckRc};
+use crate::handle::handle::TaskHandle;
 use crate::tasks::fields::Field;
+use crate::tasks::cmd_library::ServiceCommand;
+use crate::modules::services::common::{is_service_running,get_service_enabled,run_service_command,get_service_diff};
+use crate::inventory::hosts::HostOSType;
 use serde::Deserialize;
 use std::sync::Arc;
 use std::vec::Vec;
@@ -35,6 +38,7 @@ pub struct SystemdServiceTask {
     pub and: Option<PostLogicInput>
 }
 
+#[derive(Debug)]
 struct SystemdServiceAction {
     pub service: String,
     pub enabled: Option<bool>,
@@ -44,7 +48,7 @@ struct SystemdServiceAction {
 
 #[derive(Clone,PartialEq,Debug)]
 struct ServiceDetails {
-    enabled: bool,
+    enabled: Option<bool>,
     started: bool,
 }
 
@@ -84,8 +88,11 @@ impl IsAction for SystemdServiceAction {
                 let actual = self.get_service_details(handle, request)?; 
 
                 match (actual.enabled, self.enabled) {
-                    (true, Some(false)) => { changes.push(Field::Disable); },
-                    (false, Some(true)) => { changes.push(Field::Enable);  },
+                    (Some(true), Some(false)) => { changes.push(Field::Disable); },
+                    (Some(false), Some(true)) => { changes.push(Field::Enable);  },
+                    (None, Some(_)) => {
+                        return Err(handle.response.is_failed(request, &format!("{} cannot be enabled or disabled, it is started by other units", self.service)));
+                    },
                     _  => {}
                 };
 
@@ -110,14 +117,14 @@ impl IsAction for SystemdServiceAction {
 
             TaskRequestType::Modify => {
 
-                if request.changes.contains(&Field::Start)        { self.do_start(handle, request)?;   }
-                else if request.changes.contains(&Field::Stop)    { self.do_stop(handle, request)?;    }
-                else if request.changes.contains(&Field::Restart) { self.do_restart(handle, request)?; }
+                if request.changes.contains(&Field::Start)        { self.run(handle, request, ServiceCommand::Start)?;   }
+                else if request.changes.contains(&Field::Stop)    { self.run(handle, request, ServiceCommand::Stop)?;    }
+                else if request.changes.contains(&Field::Restart) { self.run(handle, request, ServiceCommand::Restart)?; }
 
-                if request.changes.contains(&Field::Enable)       { self.do_enable(handle, request)?;  }
-                else if request.changes.contains(&Field::Disable) { self.do_disable(handle, request)?; }
+                if request.changes.contains(&Field::Enable)       { self.run(handle, request, ServiceCommand::Enable)?;  }
+                else if request.changes.contains(&Field::Disable) { self.run(handle, request, ServiceCommand::Disable)?; }
 
-                Ok(handle.response.is_modified(request, request.changes.clone()))
+                Ok(handle.response.is_modified_with_diff(request, request.changes.clone(), get_service_diff(&request.changes)))
             }
     
             _ => { Err(handle.response.not_supported(request))}
@@ -129,59 +136,22 @@ impl IsAction for SystemdServiceAction {
 
 impl SystemdServiceAction {
 
-    pub fn get_service_details(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<ServiceDetails,Arc<TaskResponse>> {
-        
-        let is_enabled : bool;
-        let is_active  : bool;
-        let is_enabled_cmd = format!("systemctl is-enabled '{}'", self.service);
-        let is_active_cmd = format!("systemctl is-active '{}'", self.service);
-        
-        let result = handle.remote.run(request, &is_enabled_cmd, CheckRc::Unchecked)?;
-        let (_rc,out) = cmd_info(&result);
-        if out.contains("disabled") || out.contains("deactivating") { is_enabled = false; }
-        else if out.contains("enabled") || out.contains("alias") { is_enabled = true; } 
-        else {
-            return Err(handle.response.is_failed(request, &format!("systemctl enablement status unexpected for service({}): ({})", self.service, out))); 
-        }
-
-        let result2 = handle.remote.run(request, &is_active_cmd, CheckRc::Unchecked)?;
-        let (_rc2,out2) = cmd_info(&result2);
-        let inactive = "inactive";
-        if out2.contains(inactive) || out2.contains("failed") { is_active = false; }
-        else if out2.contains("active") { is_active = true; }
-        else { 
-            return Err(handle.response.is_failed(request, &format!("systemctl activity status unexpected for service({}): {}", self.service, out2))); 
-        }
+    // enablement is only looked up when the task sets it, so units that systemd starts on behalf of others
+    // can still be started and stopped
 
+    pub fn get_service_details(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<ServiceDetails,Arc<TaskResponse>> {
+        let enabled = match self.enabled {
+            Some(_) => get_service_enabled(handle, request, HostOSType::Linux, &self.service)?,
+            None => None
+        };
         Ok(ServiceDetails {
-            enabled: is_enabled,
-            started: is_active,
+            enabled,
+            started: is_service_running(handle, request, HostOSType::Linux, &self.service)?,
         })
     }
 
-    pub fn do_start(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        let cmd = format!("systemctl start '{}'", self.service);
-        handle.remote.run(request, &cmd, CheckRc::Checked)
-    }
-    
-    pub fn do_stop(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        let cmd = format!("systemctl stop '{}'", self.service);
-        handle.remote.run(request, &cmd, CheckRc::Checked)
-    }
-    
-    pub fn do_enable(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        let cmd = format!("systemctl enable '{}'", self.service);
-        handle.remote.run(request, &cmd, CheckRc::Checked)
-    }
-    
-    pub fn do_disable(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        let cmd = format!("systemctl disable '{}'", self.service);
-        handle.remote.run(request, &cmd, CheckRc::Checked)
-    }
-
-    pub fn do_restart(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        let cmd = format!("systemctl restart '{}'", self.service);
-        handle.remote.run(request, &cmd, CheckRc::Checked)
+    fn run(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, command: ServiceCommand) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        run_service_command(handle, request, HostOSType::Linux, &self.service, command)
     }
 
 }
@@ -0,0 +1,179 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use crate::tasks::cmd_library::ServiceCommand;
+use crate::modules::services::common::{is_service_running,get_service_enabled,run_service_command,get_service_diff};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::vec::Vec;
+
+const MODULE: &str = "service";
+
+// like sd_service, but also manages launchd jobs when the host is a Mac.  on MacOS the service is the label of
+// a job in the system domain, which has to be loaded already.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceTask {
+    pub name: Option<String>,
+    pub service: String,
+    pub state: Option<String>,
+    pub enabled: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+enum ServiceState {
+    Started,
+    Stopped,
+    Restarted
+}
+
+#[derive(Debug)]
+struct ServiceAction {
+    pub service: String,
+    pub state: Option<ServiceState>,
+    pub enabled: Option<bool>,
+}
+
+impl IsTask for ServiceTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let state = match handle.template.string_option_no_spaces(request, tm, &String::from("state"), &self.state)?.as_deref() {
+            None => None,
+            Some("started") => Some(ServiceState::Started),
+            Some("stopped") => Some(ServiceState::Stopped),
+            Some("restarted") => Some(ServiceState::Restarted),
+            Some(_) if tm == TemplateMode::Off => None,
+            Some(x) => { return Err(handle.response.is_failed(request, &format!("field state: must be one of started, stopped, or restarted, got: {}", x))); }
+        };
+        Ok(
+            EvaluatedTask {
+                action: Arc::new(ServiceAction {
+                    service:    handle.template.string_no_spaces(request, tm, &String::from("service"), &self.service)?,
+                    state,
+                    enabled:    handle.template.boolean_option_default_none(request, tm, &String::from("enabled"), &self.enabled)?,
+                }),
+                with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?)
+            }
+        )
+    }
+
+}
+
+impl IsAction for ServiceAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+
+                let mut changes : Vec<Field> = Vec::new();
+
+                if let Some(enabled) = self.enabled {
+                    match (self.get_enabled(handle, request)?, enabled) {
+                        (Some(true), false) => { changes.push(Field::Disable); },
+                        (Some(false), true) => { changes.push(Field::Enable); },
+                        (None, _) => {
+                            return Err(handle.response.is_failed(request, &format!("{} cannot be enabled or disabled, it is started by other units", self.service)));
+                        },
+                        _ => {}
+                    }
+                }
+
+                if let Some(state) = self.state {
+                    match (self.is_running(handle, request)?, state) {
+                        (false, ServiceState::Started) | (false, ServiceState::Restarted) => { changes.push(Field::Start); },
+                        (true, ServiceState::Stopped) => { changes.push(Field::Stop); },
+                        (true, ServiceState::Restarted) => { changes.push(Field::Restart); },
+                        _ => {}
+                    }
+                }
+
+                if ! changes.is_empty() {
+                    Ok(handle.response.needs_modification(request, &changes))
+                } else {
+                    Ok(handle.response.is_matched(request))
+                }
+            },
+
+            TaskRequestType::Modify => {
+
+                if request.changes.contains(&Field::Start)        { self.run(handle, request, ServiceCommand::Start)?; }
+                else if request.changes.contains(&Field::Stop)    { self.run(handle, request, ServiceCommand::Stop)?; }
+                else if request.changes.contains(&Field::Restart) { self.run(handle, request, ServiceCommand::Restart)?; }
+
+                if request.changes.contains(&Field::Enable)       { self.run(handle, request, ServiceCommand::Enable)?; }
+                else if request.changes.contains(&Field::Disable) { self.run(handle, request, ServiceCommand::Disable)?; }
+
+                Ok(handle.response.is_modified_with_diff(request, request.changes.clone(), get_service_diff(&request.changes)))
+            },
+
+            _ => { Err(handle.response.not_supported(request))}
+
+        }
+    }
+
+}
+
+impl ServiceAction {
+
+    fn is_running(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<bool, Arc<TaskResponse>> {
+        is_service_running(handle, request, handle.remote.get_os_type(), &self.service)
+    }
+
+    fn get_enabled(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Option<bool>, Arc<TaskResponse>> {
+        get_service_enabled(handle, request, handle.remote.get_os_type(), &self.service)
+    }
+
+    fn run(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, command: ServiceCommand) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        run_service_command(handle, request, handle.remote.get_os_type(), &self.service, command)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_only_differing_fields_are_applied() {
+        let (handle, connection) = TaskHandle::scripted(vec![
+            ("systemctl is-active 'nginx'", 3, "inactive"),
+            ("systemctl is-enabled 'nginx'", 0, "enabled"),
+        ]);
+        let action = ServiceAction { service: String::from("nginx"), state: Some(ServiceState::Restarted), enabled: Some(true) };
+        let response = action.dispatch(&handle, &TaskRequest::query(&SudoDetails::none(), false)).expect("query");
+        assert_eq!(response.changes, vec![Field::Start]);
+
+        let request = TaskRequest::modify(&SudoDetails::none(), false, response.changes.clone());
+        assert!(action.dispatch(&handle, &request).is_ok());
+        let commands = connection.lock().unwrap().get_commands();
+        assert!(commands.contains(&String::from("systemctl start 'nginx'")));
+        assert!(!commands.iter().any(|x| x.contains("systemctl enable")));
+    }
+}
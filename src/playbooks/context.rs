@@ -16,18 +16,20 @@
 
 use crate::util::io::{path_as_string,directory_as_string};
 use crate::playbooks::language::{Play,Role,RoleInvocation};
-use std::path::Path;
+use std::path::{Path,PathBuf};
 use std::collections::HashMap;
 use crate::inventory::hosts::Host;
 use std::sync::{Arc,RwLock};
 use crate::connection::cache::ConnectionCache;
+use crate::connection::ssh::SshOptions;
 use crate::registry::list::Task;
-use crate::util::yaml::blend_variables;
+use crate::util::yaml::{blend_variables,ListMerge};
 use crate::playbooks::templar::{Templar,TemplateMode};
 use crate::cli::parser::CliParser;
 use crate::handle::template::BlendTarget;
 use std::ops::Deref;
 use std::env;
+use std::time::Duration;
 use guid_create::GUID;
 use expanduser::expanduser;
 
@@ -38,6 +40,21 @@ use expanduser::expanduser;
 pub struct PlaybookContext {
 
     pub verbosity: u32,
+    pub explain: bool,
+    pub profile: bool,
+    pub debug_params: bool,
+    pub diff: bool,
+    pub flush_cache: bool,
+    pub force_handlers: bool,
+    pub remote_tmp: Option<String>,
+    // from --timeout, the longest any command may run unless its task sets its own timeout
+    pub timeout: Option<u64>,
+    // the modes copy, template and directory use when attributes do not give one, set with
+    // --default-file-mode and --default-dir-mode and replaced by the same settings on a play
+    pub default_file_mode: Option<String>,
+    pub default_dir_mode: Option<String>,
+    cli_default_file_mode: Option<String>,
+    cli_default_dir_mode: Option<String>,
 
     pub playbook_path: Option<String>,
     pub playbook_directory: Option<String>,
@@ -47,6 +64,7 @@ pub struct PlaybookContext {
     pub role_path: Option<String>,
     pub play_count: usize,
     pub role_count: usize,
+    pub search_paths: Vec<PathBuf>,
 
     pub task_count: usize,
     pub task: Option<String>,
@@ -54,6 +72,14 @@ pub struct PlaybookContext {
     seen_hosts:               HashMap<String, Arc<RwLock<Host>>>,
     targetted_hosts:          HashMap<String, Arc<RwLock<Host>>>,
     failed_hosts:             HashMap<String, Arc<RwLock<Host>>>,
+    unreachable_hosts:        HashMap<String, Arc<RwLock<Host>>>,
+
+    // consecutive connection failures, a host is given up on as unreachable once this reaches unreachable_after
+    connection_failures_for_host: HashMap<String, usize>,
+    // every host that skipped a task for a failed connection, even if it connected again later
+    connection_failed_hosts:  HashMap<String, Arc<RwLock<Host>>>,
+    connection_failed_tasks:  usize,
+    unreachable_after:        usize,
 
     attempted_count_for_host: HashMap<String, usize>,
     adjusted_count_for_host:  HashMap<String, usize>,
@@ -65,6 +91,9 @@ pub struct PlaybookContext {
     matched_count_for_host:   HashMap<String, usize>,
     skipped_count_for_host:   HashMap<String, usize>,
     failed_count_for_host:    HashMap<String, usize>,
+
+    // only filled in with --profile, task name -> host name -> time spent
+    task_timings:             HashMap<String, HashMap<String, Duration>>,
     
     // TODO: some of these don't need to be pub.
     pub failed_tasks:           usize,
@@ -72,15 +101,24 @@ pub struct PlaybookContext {
     pub vars_storage:           RwLock<serde_yaml::Mapping>,
     pub role_defaults_storage:  RwLock<serde_yaml::Mapping>,
     pub role_vars_storage:      RwLock<serde_yaml::Mapping>,
+    pub include_vars_storage:   RwLock<serde_yaml::Mapping>,
+    pub task_vars_storage:      RwLock<serde_yaml::Mapping>,
     pub env_storage:            RwLock<serde_yaml::Mapping>,
+    // the play's 'environment' block, exported to every remote command run by the play's tasks
+    pub environment_storage:    RwLock<Vec<(String,String)>>,
     
     pub connection_cache:     RwLock<ConnectionCache>,
     pub templar:              RwLock<Templar>,
 
     pub ssh_user:             String,
     pub ssh_port:             i64,
+    pub ssh_options:          serde_yaml::Mapping,
     pub sudo:                 Option<String>,
+    // from --ask-sudo-password, never templated or logged
+    pub sudo_password:        Option<String>,
     extra_vars:               serde_yaml::Value,
+    // from --list-merge or $JET_LIST_MERGE, how lists combine when variable layers are blended
+    pub list_merge:           ListMerge,
 
 }
 
@@ -89,6 +127,19 @@ impl PlaybookContext {
     pub fn new(parser: &CliParser) -> Self {
         let mut s = Self {
             verbosity: parser.verbosity,
+            explain: parser.explain,
+            profile: parser.profile,
+            force_handlers: parser.force_handlers,
+            debug_params: parser.debug_params,
+            diff: parser.diff,
+            flush_cache: parser.flush_cache,
+            list_merge: parser.list_merge,
+            remote_tmp: parser.remote_tmp.clone(),
+            timeout: parser.timeout,
+            default_file_mode: parser.default_file_mode.clone(),
+            default_dir_mode: parser.default_dir_mode.clone(),
+            cli_default_file_mode: parser.default_file_mode.clone(),
+            cli_default_dir_mode: parser.default_dir_mode.clone(),
             playbook_path: None,
             playbook_directory: None,
             failed_tasks: 0,
@@ -97,10 +148,16 @@ impl PlaybookContext {
             task: None,
             play_count : 0,
             role_count : 0,
+            search_paths: Vec::new(),
             task_count : 0,
             seen_hosts: HashMap::new(),
             targetted_hosts: HashMap::new(),
             failed_hosts: HashMap::new(),
+            unreachable_hosts: HashMap::new(),
+            connection_failures_for_host: HashMap::new(),
+            connection_failed_hosts: HashMap::new(),
+            connection_failed_tasks: 0,
+            unreachable_after: parser.unreachable_after,
             role_path: None,
             adjusted_count_for_host:  HashMap::new(),
             attempted_count_for_host: HashMap::new(),
@@ -112,16 +169,22 @@ impl PlaybookContext {
             matched_count_for_host:   HashMap::new(),
             failed_count_for_host:    HashMap::new(),
             skipped_count_for_host:   HashMap::new(),
+            task_timings:             HashMap::new(),
             connection_cache:         RwLock::new(ConnectionCache::new()),
-            templar:                  RwLock::new(Templar::new()),
+            templar:                  RwLock::new(Templar::with_lookup_programs(&parser.allow_lookup_cmd)),
             defaults_storage:         RwLock::new(serde_yaml::Mapping::new()),
             vars_storage:             RwLock::new(serde_yaml::Mapping::new()),
             role_vars_storage:        RwLock::new(serde_yaml::Mapping::new()),
             role_defaults_storage:    RwLock::new(serde_yaml::Mapping::new()),
+            include_vars_storage:     RwLock::new(serde_yaml::Mapping::new()),
+            task_vars_storage:        RwLock::new(serde_yaml::Mapping::new()),
             env_storage:              RwLock::new(serde_yaml::Mapping::new()),
+            environment_storage:      RwLock::new(Vec::new()),
             ssh_user:                 parser.default_user.clone(),
             ssh_port:                 parser.default_port,
+            ssh_options:              serde_yaml::Mapping::new(),
             sudo:                     parser.sudo.clone(),
+            sudo_password:            parser.sudo_password.clone(),
             extra_vars:               parser.extra_vars.clone(),
         };
         s.load_environment();
@@ -182,6 +245,60 @@ impl PlaybookContext {
         self.failed_hosts.insert(hostname.clone(), Arc::clone(host));
     }
 
+    // with force_handlers, hosts of the batch that failed during this play are brought back for the handlers
+    // they were notified about. they stay on the failed list, so the play still fails and later plays leave them out
+
+    pub fn retarget_notified_hosts(&mut self, hosts: &[Arc<RwLock<Host>>]) {
+        for host in hosts.iter() {
+            let hostname = host.read().unwrap().name.clone();
+            if self.failed_hosts.contains_key(&hostname) && ! self.unreachable_hosts.contains_key(&hostname)
+                && host.read().unwrap().has_notifications(self.play_count) {
+                self.targetted_hosts.insert(hostname, Arc::clone(host));
+            }
+        }
+    }
+
+    // a host that can't be connected to skips the task, and once that happens unreachable_after times
+    // in a row it is failed and dropped from the rest of the run.  returns true when that happens.
+
+    pub fn record_connection_failure(&mut self, host: &Arc<RwLock<Host>>) -> bool {
+        let hostname = host.read().unwrap().name.clone();
+        self.connection_failed_tasks += 1;
+        self.connection_failed_hosts.insert(hostname.clone(), Arc::clone(host));
+        let failures = self.connection_failures_for_host.entry(hostname.clone()).or_insert(0);
+        *failures += 1;
+        if *failures < self.unreachable_after {
+            return false;
+        }
+        self.unreachable_hosts.insert(hostname, Arc::clone(host));
+        self.fail_host(host);
+        true
+    }
+
+    pub fn record_connection_ok(&mut self, host: &Arc<RwLock<Host>>) {
+        self.connection_failures_for_host.remove(&host.read().unwrap().name);
+    }
+
+    pub fn get_connection_failures(&self, host: &Arc<RwLock<Host>>) -> usize {
+        *self.connection_failures_for_host.get(&host.read().unwrap().name).unwrap_or(&0)
+    }
+
+    pub fn get_unreachable_after(&self) -> usize {
+        self.unreachable_after
+    }
+
+    pub fn get_total_connection_failed_count(&self) -> usize {
+        self.connection_failed_tasks
+    }
+
+    pub fn get_hosts_unreachable_count(&self) -> usize {
+        self.unreachable_hosts.len()
+    }
+
+    pub fn get_hosts_connection_failed_count(&self) -> usize {
+        self.connection_failed_hosts.len()
+    }
+
     pub fn set_playbook_path(&mut self, path: &Path) {
         self.playbook_path = Some(path_as_string(path));
         self.playbook_directory = Some(directory_as_string(path));
@@ -191,9 +308,48 @@ impl PlaybookContext {
         self.task = Some(task.get_display_name());
     }
 
+    pub fn has_extra_var(&self, name: &str) -> bool {
+        match self.extra_vars.as_mapping() {
+            Some(x) => x.contains_key(name),
+            None => false
+        }
+    }
+
     pub fn set_play(&mut self, play: &Play) {
         self.play = Some(play.name.clone());
         self.play_count += 1;
+        self.default_file_mode = play.default_file_mode.clone().or(self.cli_default_file_mode.clone());
+        self.default_dir_mode = play.default_dir_mode.clone().or(self.cli_default_dir_mode.clone());
+        self.ssh_options = play.ssh_options.clone().unwrap_or_default();
+        // relative search paths are relative to the playbook
+        self.search_paths.clear();
+        if let Some(search_paths) = &play.search_paths {
+            for search_path in search_paths.iter() {
+                let mut pb = PathBuf::new();
+                if let Some(pbdir) = &self.playbook_directory {
+                    pb.push(pbdir);
+                }
+                pb.push(search_path);
+                self.search_paths.push(pb);
+            }
+        }
+    }
+
+    // when looking for files/ or templates/ (the prefix) the active role is checked first, then
+    // any search_paths on the play, then the directory of the playbook, in that order
+
+    pub fn get_search_paths(&self, prefix: &str) -> Vec<PathBuf> {
+        let mut results : Vec<PathBuf> = Vec::new();
+        if let Some(role_path) = &self.role_path {
+            results.push(Path::new(role_path).join(prefix));
+        }
+        for search_path in self.search_paths.iter() {
+            results.push(search_path.join(prefix));
+        }
+        if let Some(pbdir) = &self.playbook_directory {
+            results.push(Path::new(pbdir).join(prefix));
+        }
+        results
     }
 
     pub fn get_play_name(&self) -> String {
@@ -209,11 +365,53 @@ impl PlaybookContext {
         if role.defaults.is_some() { 
              *self.role_defaults_storage.write().unwrap() = role.defaults.as_ref().unwrap().clone();
         }
-        if invocation.vars.is_some() { 
-            *self.role_vars_storage.write().unwrap() = invocation.vars.as_ref().unwrap().clone();
+        // vars from the role itself are overridden by any vars passed in the role invocation
+        let mut role_vars = serde_yaml::Value::from(serde_yaml::Mapping::new());
+        if let Some(vars) = &role.vars {
+            blend_variables(&mut role_vars, serde_yaml::Value::Mapping(vars.clone()), self.list_merge);
+        }
+        if let Some(vars) = &invocation.vars {
+            blend_variables(&mut role_vars, serde_yaml::Value::Mapping(vars.clone()), self.list_merge);
+        }
+        match role_vars {
+            serde_yaml::Value::Mapping(x) => { *self.role_vars_storage.write().unwrap() = x },
+            _ => panic!("unexpected, blend_variables produced a non-mapping (role)")
         }
     }
 
+    // vars passed to an !include apply only while the included tasks are running, nested
+    // includes layer their vars on top and the previous set is returned so it can be restored
+
+    pub fn push_include_vars(&mut self, vars: &Option<serde_yaml::Mapping>) -> serde_yaml::Mapping {
+        let previous = self.include_vars_storage.read().unwrap().clone();
+        if let Some(include_vars) = vars {
+            let mut blended = serde_yaml::Value::Mapping(previous.clone());
+            blend_variables(&mut blended, serde_yaml::Value::Mapping(include_vars.clone()), self.list_merge);
+            match blended {
+                serde_yaml::Value::Mapping(x) => { *self.include_vars_storage.write().unwrap() = x },
+                _ => panic!("unexpected, blend_variables produced a non-mapping (include)")
+            }
+        }
+        previous
+    }
+
+    pub fn restore_include_vars(&mut self, previous: serde_yaml::Mapping) {
+        *self.include_vars_storage.write().unwrap() = previous;
+    }
+
+    // vars from a task's with/vars only exist while that one task runs, they are cleared again before the next
+
+    pub fn set_task_vars(&mut self, vars: &Option<serde_yaml::Mapping>) {
+        *self.task_vars_storage.write().unwrap() = match vars {
+            Some(x) => x.clone(),
+            None => serde_yaml::Mapping::new()
+        };
+    }
+
+    pub fn clear_task_vars(&mut self) {
+        self.task_vars_storage.write().unwrap().clear();
+    }
+
     pub fn unset_role(&mut self) {
         self.role = None;
         self.role_path = None;
@@ -232,29 +430,41 @@ impl PlaybookContext {
         }
     }
 
+    // precedence, lowest to highest: play defaults, role defaults, host and group vars (inventory),
+    // play vars and vars_files, role vars, include vars, task vars, and finally --extra-vars.
+    // the play 'environment' block is not a variable layer, see environment_storage.
+
     pub fn get_complete_blended_variables_as_value(&self, host: &Arc<RwLock<Host>>, blend_target: BlendTarget) -> serde_yaml::Value  {
         
         let mut blended = serde_yaml::Value::from(serde_yaml::Mapping::new());
         let src1 = self.defaults_storage.read().unwrap();
         let src1a = src1.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src1a.clone()));
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src1a.clone()), self.list_merge);
         
         let src1r = self.role_defaults_storage.read().unwrap();
         let src1ar = src1r.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src1ar.clone()));
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src1ar.clone()), self.list_merge);
 
-        let src2 = host.read().unwrap().get_blended_variables();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src2));
+        let src2 = host.read().unwrap().get_blended_variables(self.list_merge);
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src2), self.list_merge);
 
         let src3 = self.vars_storage.read().unwrap();
         let src3a = src3.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3a.clone()));
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3a.clone()), self.list_merge);
 
         let src3r = self.role_vars_storage.read().unwrap();
         let src3ar = src3r.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3ar.clone()));
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3ar.clone()), self.list_merge);
 
-        blend_variables(&mut blended, self.extra_vars.clone());
+        let src3i = self.include_vars_storage.read().unwrap();
+        let src3ai = src3i.deref();
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3ai.clone()), self.list_merge);
+
+        let src3t = self.task_vars_storage.read().unwrap();
+        let src3at = src3t.deref();
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3at.clone()), self.list_merge);
+
+        blend_variables(&mut blended, self.extra_vars.clone(), self.list_merge);
 
         match blend_target {
             BlendTarget::NotTemplateModule => { },
@@ -263,7 +473,7 @@ impl PlaybookContext {
                 // to prevent accidental leakage into logs and history
                 let src4 = self.env_storage.read().unwrap();
                 let src4a = src4.deref();
-                blend_variables(&mut blended, serde_yaml::Value::Mapping(src4a.clone()));
+                blend_variables(&mut blended, serde_yaml::Value::Mapping(src4a.clone()), self.list_merge);
             }
         };
         blended
@@ -278,6 +488,11 @@ impl PlaybookContext {
         return self.templar.read().unwrap().render(template, vars, template_mode);
     }
 
+    pub fn render_template_lenient(&self, template: &str, host: &Arc<RwLock<Host>>, blend_target: BlendTarget) -> Result<String,String> {
+        let vars = self.get_complete_blended_variables(host, blend_target);
+        return self.templar.read().unwrap().render_lenient(template, vars);
+    }
+
     // testing conditions for truthiness works much like templating strings
 
     pub fn test_condition(&self, expr: &String, host: &Arc<RwLock<Host>>, tm: TemplateMode) -> Result<bool,String> {
@@ -289,7 +504,7 @@ impl PlaybookContext {
 
     pub fn test_condition_with_extra_data(&self, expr: &String, host: &Arc<RwLock<Host>>, vars_input: serde_yaml::Mapping, tm: TemplateMode) -> Result<bool,String> {
         let mut vars = self.get_complete_blended_variables_as_value(host, BlendTarget::NotTemplateModule);
-        blend_variables(&mut vars, serde_yaml::Value::Mapping(vars_input));
+        blend_variables(&mut vars, serde_yaml::Value::Mapping(vars_input), self.list_merge);
         match vars {
             serde_yaml::Value::Mapping(x) => self.templar.read().unwrap().test_condition(expr, x, tm),
             _ => { panic!("impossible input to test_condition"); }
@@ -301,6 +516,17 @@ impl PlaybookContext {
 
     // FIXME: this should return a struct
 
+    // the scratch directory for file transfers on a host, jet_remote_tmp wins over --remote-tmp / $JET_REMOTE_TMP,
+    // None means the default under the login user's home directory
+
+    pub fn get_remote_tmp(&self, host: &Arc<RwLock<Host>>) -> Option<String> {
+        let vars = self.get_complete_blended_variables(host, BlendTarget::NotTemplateModule);
+        match vars.get("jet_remote_tmp").and_then(|x| x.as_str()) {
+            Some(x) => Some(x.to_owned()),
+            None => self.remote_tmp.clone()
+        }
+    }
+
     pub fn get_ssh_connection_details(&self, host: &Arc<RwLock<Host>>) -> (String,String,i64,Option<String>,Option<String>,Option<String>) {
 
         let vars = self.get_complete_blended_variables(host,BlendTarget::NotTemplateModule);
@@ -362,6 +588,24 @@ impl PlaybookContext {
         (remote_hostname, remote_user, remote_port, keyfile, passphrase, key_comment)
     } 
 
+    // ssh_options on the play apply to every host, a jet_ssh_options mapping in inventory replaces them option by
+    // option, with the usual host over group precedence among the inventory values
+
+    pub fn get_ssh_options(&self, host: &Arc<RwLock<Host>>) -> Result<SshOptions, String> {
+        let vars = self.get_complete_blended_variables(host,BlendTarget::NotTemplateModule);
+        let mut options = self.ssh_options.clone();
+        match vars.get(String::from("jet_ssh_options")) {
+            Some(serde_yaml::Value::Mapping(x)) => {
+                for (k,v) in x.iter() {
+                    options.insert(k.clone(), v.clone());
+                }
+            },
+            Some(_) => { return Err(String::from("jet_ssh_options must be a mapping")); },
+            None => {}
+        }
+        SshOptions::from_mapping(&options)
+    }
+
     // loads environment variables into the context, adding an "ENV_foo" prefix
     // to each environment variable "foo". These variables will only be made available
     // to the template module since we use them for secret management features.
@@ -531,6 +775,27 @@ impl PlaybookContext {
     pub fn get_hosts_seen_count(&self) -> usize {
         self.seen_hosts.keys().len()
     }
+
+    // PROFILING
+
+    pub fn record_task_time(&mut self, task: &str, host: &str, elapsed: Duration) {
+        // tasks with the same name (or run more than once) accumulate
+        *self.task_timings.entry(task.to_owned()).or_default().entry(host.to_owned()).or_insert(Duration::ZERO) += elapsed;
+    }
+
+    // returns (task, total time across hosts, slowest host, time on slowest host) for the slowest tasks first
+
+    pub fn get_slowest_tasks(&self, limit: usize) -> Vec<(String, Duration, String, Duration)> {
+        let mut results : Vec<(String, Duration, String, Duration)> = Vec::new();
+        for (task, hosts) in self.task_timings.iter() {
+            let total : Duration = hosts.values().sum();
+            let (worst_host, worst_time) = hosts.iter().max_by_key(|(_,v)| **v).map(|(k,v)| (k.clone(), *v)).unwrap();
+            results.push((task.clone(), total, worst_host, worst_time));
+        }
+        results.sort_by_key(|x| std::cmp::Reverse(x.1));
+        results.truncate(limit);
+        results
+    }
     
 
 
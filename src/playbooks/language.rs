@@ -28,13 +28,30 @@ pub struct Play {
     pub defaults: Option<serde_yaml::Mapping>,
     pub vars : Option<serde_yaml::Mapping>,
     pub vars_files: Option<Vec<String>>,
+    pub vars_prompt: Option<Vec<VarsPrompt>>,
+    pub environment: Option<serde_yaml::Mapping>,
     pub sudo: Option<String>,
     pub sudo_template: Option<String>,
     pub ssh_user : Option<String>,
     pub ssh_port : Option<i64>,
+    pub ssh_options : Option<serde_yaml::Mapping>,
     pub tasks : Option<Vec<Task>>,
     pub handlers : Option<Vec<Task>>,
     pub batch_size : Option<usize>,
+    pub any_errors_fatal : Option<bool>,
+    pub force_handlers : Option<bool>,
+    pub search_paths : Option<Vec<String>>,
+    pub default_file_mode : Option<String>,
+    pub default_dir_mode : Option<String>,
+}
+
+#[derive(Debug,Deserialize,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VarsPrompt {
+    pub name: String,
+    pub prompt: Option<String>,
+    pub private: Option<bool>,
+    pub default: Option<String>
 }
 
 #[derive(Debug,Deserialize,Clone)]
@@ -42,6 +59,7 @@ pub struct Play {
 pub struct Role {
     pub name: String,
     pub defaults: Option<serde_yaml::Mapping>,
+    pub vars: Option<serde_yaml::Mapping>,
     pub tasks: Option<Vec<String>>,
     pub handlers: Option<Vec<String>>
 }
@@ -0,0 +1,145 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::language::Play;
+use crate::playbooks::traversal::{RunState,HandlerMode,expand_imports,validate_groups,validate_limit_groups,
+    validate_limit_hosts,get_play_hosts};
+use crate::playbooks::walker::{TaskWalker,WalkedTask,walk_role,walk_tasks};
+use crate::registry::list::Task;
+use crate::util::io::{jet_file_open,directory_as_string};
+use crate::util::yaml::show_yaml_error_in_context;
+use std::sync::Arc;
+use std::path::Path;
+use std::env;
+
+// --list-hosts and --list-tasks print what a playbook would act on, without connecting to anything.
+// tasks are listed in the order they would run. Conditions are not evaluated and tags are only shown,
+// not used for filtering (tags from roles and includes are shown on each task they apply to), so the
+// output describes the playbook rather than a particular run of it.
+
+pub fn playbook_listing(run_state: &Arc<RunState>, list_hosts: bool, list_tasks: bool) -> Result<(), String> {
+
+    for playbook_path in run_state.playbook_paths.read().unwrap().iter() {
+
+        let playbook_file = jet_file_open(playbook_path)?;
+        let parsed: Result<Vec<Play>, serde_yaml::Error> = serde_yaml::from_reader(playbook_file);
+        if let Err(e) = parsed {
+            show_yaml_error_in_context(&e, playbook_path);
+            return Err("edit the file and try again?".to_string());
+        }
+        println!("playbook: {}", playbook_path.display());
+
+        let p1 = env::current_dir().expect("could not get current directory");
+        let previous = p1.as_path();
+        let pbdirname = directory_as_string(playbook_path);
+        if ! pbdirname.is_empty() {
+            env::set_current_dir(Path::new(&pbdirname)).expect("could not chdir into playbook directory");
+        }
+
+        let mut plays : Vec<Play> = parsed.unwrap();
+        for play in plays.iter_mut() {
+            if let Some(tasks) = play.tasks.take() {
+                play.tasks = Some(expand_imports(run_state, tasks, HandlerMode::NormalTasks, 0)?);
+            }
+            if let Some(handlers) = play.handlers.take() {
+                play.handlers = Some(expand_imports(run_state, handlers, HandlerMode::Handlers, 0)?);
+            }
+        }
+
+        for play in plays.iter() {
+            println!("  play: {}", play.name);
+            if list_hosts {
+                show_play_hosts(run_state, play)?;
+            }
+            if list_tasks {
+                show_play_tasks(run_state, play)?;
+            }
+        }
+
+        env::set_current_dir(previous).expect("could not restore previous directory");
+    }
+    Ok(())
+}
+
+fn show_play_hosts(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+    validate_limit_groups(run_state, play)?;
+    validate_limit_hosts(run_state, play)?;
+    validate_groups(run_state, play)?;
+    let mut names : Vec<String> = get_play_hosts(run_state, play).iter().map(|h| h.read().unwrap().name.clone()).collect();
+    names.sort();
+    println!("    hosts ({}):", names.len());
+    for name in names.iter() {
+        println!("      {}", name);
+    }
+    Ok(())
+}
+
+fn show_play_tasks(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+
+    // same order as handle_batch: role tasks, play tasks, role handlers, play handlers
+
+    let mut lister = TaskLister {};
+    let sections = [("tasks", HandlerMode::NormalTasks, &play.tasks), ("handlers", HandlerMode::Handlers, &play.handlers)];
+    for (title, are_handlers, tasks) in sections {
+        println!("    {}:", title);
+        if let Some(roles) = &play.roles {
+            for invocation in roles.iter() {
+                walk_role(run_state, play, invocation, &[are_handlers], &play.name, &mut lister)?;
+            }
+        }
+        run_state.context.write().unwrap().unset_role();
+        if let Some(tasks) = tasks {
+            walk_tasks(run_state, tasks, are_handlers, None, &[], &play.name, 0, &mut lister)?;
+        }
+    }
+    Ok(())
+}
+
+// prints each task indented beneath whatever included it, and stops at the first file that can't be loaded
+
+struct TaskLister {}
+
+impl TaskWalker for TaskLister {
+
+    fn visit(&mut self, walked: &WalkedTask) -> bool {
+        let indent = 6 + 2 * walked.depth;
+        let name = match (walked.task.get_name(), walked.task) {
+            (Some(x), _) => x,
+            (None, Task::Include(include)) => format!("!include {}", include.file),
+            (None, _) => format!("!{}", walked.task.get_module())
+        };
+        let prefix = match walked.invocation {
+            Some(invoke) => format!("{}: ", invoke.role),
+            None => String::new()
+        };
+        match walked.tags.is_empty() {
+            true  => println!("{:indent$}{}{}", "", prefix, name, indent = indent),
+            false => println!("{:indent$}{}{} [{}]", "", prefix, name, walked.tags.join(", "), indent = indent)
+        }
+        // included files are shown beneath the include when we can tell which file it is
+        if let Task::Include(include) = walked.task {
+            if include.file.contains("{{") {
+                println!("{:indent$}(included file is templated: {})", "", include.file, indent = indent + 2);
+            }
+        }
+        true
+    }
+
+    fn problem(&mut self, _location: &str, error: String) -> Result<(), String> {
+        Err(error)
+    }
+
+}
@@ -0,0 +1,202 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::language::Play;
+use crate::playbooks::traversal::{RunState,HandlerMode,expand_imports,validate_groups,load_vars_into_context};
+use crate::playbooks::walker::{TaskWalker,WalkedTask,walk_role,walk_tasks};
+use crate::playbooks::templar::TemplateMode;
+use crate::registry::list::Task;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::request::TaskRequest;
+use crate::tasks::files::SrcInput;
+use crate::connection::connection::Connection;
+use crate::connection::no::NoConnection;
+use crate::inventory::hosts::Host;
+use crate::util::io::{jet_file_open,directory_as_string};
+use crate::util::yaml::show_yaml_error_in_context;
+use std::sync::{Arc,Mutex,RwLock};
+use std::path::Path;
+use std::env;
+
+// --syntax-check walks the same playbooks, roles and task files a real run would, but never builds a connection
+// or looks at a host. Problems are collected rather than stopping at the first one, so that a single CI run
+// can report everything that is wrong.
+
+pub fn playbook_syntax_check(run_state: &Arc<RunState>) -> Result<(), String> {
+
+    let mut problems : Vec<String> = Vec::new();
+
+    for playbook_path in run_state.playbook_paths.read().unwrap().iter() {
+
+        run_state.context.write().unwrap().set_playbook_path(playbook_path);
+        let pb_name = playbook_path.display().to_string();
+
+        let playbook_file = match jet_file_open(playbook_path) {
+            Ok(x) => x,
+            Err(e) => { problems.push(e); continue; }
+        };
+        let parsed: Result<Vec<Play>, serde_yaml::Error> = serde_yaml::from_reader(playbook_file);
+        let mut plays = match parsed {
+            Ok(x) => x,
+            Err(e) => {
+                show_yaml_error_in_context(&e, playbook_path);
+                problems.push(format!("{}: invalid YAML", pb_name));
+                continue;
+            }
+        };
+
+        // paths are relative to the playbook the same way they are during a real run
+        let p1 = env::current_dir().expect("could not get current directory");
+        let previous = p1.as_path();
+        let pbdirname = directory_as_string(playbook_path);
+        if ! pbdirname.is_empty() {
+            env::set_current_dir(Path::new(&pbdirname)).expect("could not chdir into playbook directory");
+        }
+
+        for play in plays.iter_mut() {
+            if let Some(tasks) = play.tasks.take() {
+                match expand_imports(run_state, tasks, HandlerMode::NormalTasks, 0) {
+                    Ok(x) => { play.tasks = Some(x); },
+                    Err(e) => { problems.push(format!("{}: play ({}): {}", pb_name, play.name, e)); }
+                }
+            }
+            if let Some(handlers) = play.handlers.take() {
+                match expand_imports(run_state, handlers, HandlerMode::Handlers, 0) {
+                    Ok(x) => { play.handlers = Some(x); },
+                    Err(e) => { problems.push(format!("{}: play ({}): {}", pb_name, play.name, e)); }
+                }
+            }
+            check_play(run_state, play, &format!("{}: play ({})", pb_name, play.name), &mut problems);
+        }
+
+        env::set_current_dir(previous).expect("could not restore previous directory");
+    }
+
+    if problems.is_empty() {
+        println!("syntax check passed");
+        return Ok(());
+    }
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    Err(format!("syntax check found {} problem(s)", problems.len()))
+}
+
+fn check_play(run_state: &Arc<RunState>, play: &Play, location: &String, problems: &mut Vec<String>) {
+
+    {
+        let mut ctx = run_state.context.write().unwrap();
+        ctx.set_play(play);
+        ctx.unset_role();
+    }
+    if let Err(e) = validate_groups(run_state, play) {
+        problems.push(format!("{}: {}", location, e));
+    }
+    if let Err(e) = load_vars_into_context(run_state, play) {
+        problems.push(format!("{}: {}", location, e));
+    }
+
+    // a placeholder host and a connection that can't do anything, evaluation needs both to exist
+    let host = Arc::new(RwLock::new(Host::new(&String::from("localhost"))));
+    let connection : Arc<Mutex<dyn Connection>> = Arc::new(Mutex::new(NoConnection::new()));
+    let handle = Arc::new(TaskHandle::new(Arc::clone(run_state), connection, host));
+
+    // the checker notes problems and carries on, so the walks themselves never fail
+    let mut checker = SyntaxChecker { handle, problems };
+    if let Some(roles) = &play.roles {
+        for invocation in roles.iter() {
+            let modes = [HandlerMode::NormalTasks, HandlerMode::Handlers];
+            let _ = walk_role(run_state, play, invocation, &modes, location, &mut checker);
+        }
+    }
+    run_state.context.write().unwrap().unset_role();
+
+    if let Some(tasks) = &play.tasks {
+        let _ = walk_tasks(run_state, tasks, HandlerMode::NormalTasks, None, &[], location, 0, &mut checker);
+    }
+    if let Some(handlers) = &play.handlers {
+        let _ = walk_tasks(run_state, handlers, HandlerMode::Handlers, None, &[], location, 0, &mut checker);
+    }
+}
+
+// evaluates each task with templating off and looks for its source file, noting every problem and carrying on
+
+struct SyntaxChecker<'a> {
+    handle: Arc<TaskHandle>,
+    problems: &'a mut Vec<String>
+}
+
+impl TaskWalker for SyntaxChecker<'_> {
+
+    fn visit(&mut self, walked: &WalkedTask) -> bool {
+
+        let task = walked.task;
+        let task_location = &walked.location;
+        let handle = &self.handle;
+        let validate = TaskRequest::validate();
+
+        // includes are normally loaded when reached, but if the file name is not templated we can look at it now
+        if let Task::Include(include) = task {
+            if let Err(e) = include.check_with() {
+                self.problems.push(format!("{}: {}", task_location, e));
+                return false;
+            }
+            return true;
+        }
+
+        handle.template.set_trusted_fields(&task.get_with().and_then(|w| w.trusted));
+        if let Err(e) = task.evaluate(handle, &validate, TemplateMode::Off) {
+            self.problems.push(format!("{}: {}", task_location, e.msg.as_ref().unwrap_or(&String::from("invalid task"))));
+            return false;
+        }
+
+        // source files can only be checked when no variables are involved in finding them
+        let src = match task {
+            Task::Copy(x)     => Some((&x.src, "files")),
+            Task::Template(x) => Some((&x.src, "templates")),
+            _ => None
+        };
+        if let Some((input, prefix)) = src {
+            let candidates = match input {
+                SrcInput::SrcString(x) => vec![x.clone()],
+                SrcInput::SrcList(x) => x.clone()
+            };
+            if candidates.iter().any(|x| is_templated(x)) {
+                return false;
+            }
+            let field = String::from("src");
+            let result = match prefix {
+                "templates" => handle.template.find_template_path(&validate, TemplateMode::Strict, &field, input),
+                _           => handle.template.find_file_path(&validate, TemplateMode::Strict, &field, input)
+            };
+            if let Err(e) = result {
+                let msg = e.msg.clone().unwrap_or(String::from("src not found"));
+                self.problems.push(format!("{}: {}", task_location, msg));
+            }
+        }
+        false
+    }
+
+    fn problem(&mut self, location: &str, error: String) -> Result<(), String> {
+        self.problems.push(format!("{}: {}", location, error));
+        Ok(())
+    }
+
+}
+
+fn is_templated(input: &str) -> bool {
+    input.contains("{{")
+}
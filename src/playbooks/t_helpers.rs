@@ -14,6 +14,9 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use handlebars::{Handlebars, RenderError, HelperDef, RenderContext, ScopedJson, JsonValue, Helper, Context, handlebars_helper};
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Arc,Mutex};
 
 //#[allow(non_camel_case_types)]
 pub struct IsDefined;
@@ -42,7 +45,260 @@ impl HelperDef for IsDefined {
     }
 }
 
-pub fn register_helpers(handlebars: &mut Handlebars) {
+// strict mode only applies to variables that are rendered or used as block parameters.  a variable passed to a
+// helper arrives as a PathAndJson with no value, is_value_missing is true, and the helper decides what that means,
+// just like isdefined above.  default and mandatory are called as {{default x "y"}} and {{mandatory x}},
+// handlebars has no filter pipes.
+
+pub struct DefaultValue;
+
+impl HelperDef for DefaultValue {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        if h.params().len() != 2 {
+            return Err(RenderError::new(
+                "default: requires a variable and a fallback value",
+            ));
+        }
+        let value = h.param(0).ok_or_else(|| RenderError::new("default: Couldn't read parameter"))?;
+        let fallback = h.param(1).ok_or_else(|| RenderError::new("default: Couldn't read fallback"))?;
+        // an empty string or null counts as missing too, which is what an unset inventory variable usually looks like
+        let use_fallback = value.is_value_missing() || match value.value() {
+            JsonValue::Null => true,
+            JsonValue::String(x) => x.is_empty(),
+            _ => false
+        };
+        match use_fallback {
+            true => Ok(ScopedJson::Derived(fallback.value().clone())),
+            false => Ok(ScopedJson::Derived(value.value().clone()))
+        }
+    }
+}
+
+pub struct Mandatory;
+
+impl HelperDef for Mandatory {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        if h.params().len() != 1 {
+            return Err(RenderError::new(
+                "mandatory: requires one parameter",
+            ));
+        }
+        let value = h.param(0).ok_or_else(|| RenderError::new("mandatory: Couldn't read parameter"))?;
+        if value.is_value_missing() {
+            let name = value.relative_path().cloned().unwrap_or_default();
+            return Err(RenderError::new(format!("mandatory: variable {} is not defined", name)));
+        }
+        Ok(ScopedJson::Derived(value.value().clone()))
+    }
+}
+
+// to_json and to_yaml write out a whole variable, say a mapping from inventory, for config files that take
+// structured data.  the output is not escaped, so quotes inside strings come through as the serializer wrote them.
+// from_json goes the other way, for use in subexpressions like {{#each (from_json x)}}
+
+fn get_resolved_param<'a>(h: &'a Helper, name: &str) -> Result<&'a JsonValue, RenderError> {
+    if h.params().len() != 1 {
+        return Err(RenderError::new(format!("{}: requires one parameter", name)));
+    }
+    let param = h.param(0).ok_or_else(|| RenderError::new(format!("{}: Couldn't read parameter", name)))?;
+    if param.is_value_missing() {
+        let variable = param.relative_path().cloned().unwrap_or_default();
+        return Err(RenderError::new(format!("{}: variable {} is not defined", name, variable)));
+    }
+    Ok(param.value())
+}
+
+pub struct ToJson;
+
+impl HelperDef for ToJson {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = get_resolved_param(h, "to_json")?;
+        let json = serde_json::to_string(value).map_err(|e| RenderError::new(format!("to_json: unable to serialize: {}", e)))?;
+        Ok(ScopedJson::Derived(JsonValue::from(json)))
+    }
+}
+
+pub struct ToYaml;
+
+impl HelperDef for ToYaml {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = get_resolved_param(h, "to_yaml")?;
+        let yaml = serde_yaml::to_string(value).map_err(|e| RenderError::new(format!("to_yaml: unable to serialize: {}", e)))?;
+        // the final newline is left to the template
+        Ok(ScopedJson::Derived(JsonValue::from(yaml.trim_end_matches('\n'))))
+    }
+}
+
+pub struct FromJson;
+
+impl HelperDef for FromJson {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let text = get_resolved_param(h, "from_json")?
+            .as_str()
+            .ok_or_else(|| RenderError::new("from_json: the parameter must be a string"))?;
+        let value : JsonValue = serde_json::from_str(text).map_err(|e| RenderError::new(format!("from_json: invalid JSON: {}", e)))?;
+        Ok(ScopedJson::Derived(value))
+    }
+}
+
+// fact_ip and default_ipv4 read the jet_network mapping made by the facts module
+
+fn get_network_fact<'a>(ctx: &'a Context, path: &[&str]) -> Option<&'a JsonValue> {
+    let mut value = ctx.data().get("jet_network")?;
+    for key in path.iter() {
+        value = value.get(key)?;
+    }
+    Some(value)
+}
+
+pub struct FactIp;
+
+impl HelperDef for FactIp {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let params = h.params();
+        if params.is_empty() || params.len() > 2 {
+            return Err(RenderError::new(
+                "fact_ip: requires an interface name and optionally \"ipv4\" or \"ipv6\"",
+            ));
+        }
+        let interface = h.param(0)
+            .and_then(|x| x.value().as_str())
+            .ok_or_else(|| RenderError::new("fact_ip: interface name must be a string"))?;
+        let family = match h.param(1) {
+            None => "ipv4",
+            Some(x) => match x.value().as_str() {
+                Some("ipv4") => "ipv4",
+                Some("ipv6") => "ipv6",
+                _ => { return Err(RenderError::new("fact_ip: address family must be \"ipv4\" or \"ipv6\"")); }
+            }
+        };
+        let address = get_network_fact(ctx, &["interfaces", interface, family])
+            .and_then(|x| x.as_array())
+            .and_then(|x| x.first())
+            .ok_or_else(|| RenderError::new(format!("fact_ip: no {} address for interface {} in gathered facts", family, interface)))?;
+        Ok(ScopedJson::Derived(address.clone()))
+    }
+}
+
+pub struct DefaultIpv4;
+
+impl HelperDef for DefaultIpv4 {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        if ! h.params().is_empty() {
+            return Err(RenderError::new(
+                "default_ipv4: takes no parameters",
+            ));
+        }
+        let address = get_network_fact(ctx, &["default_ipv4"])
+            .ok_or_else(|| RenderError::new("default_ipv4: no default route address in gathered facts"))?;
+        Ok(ScopedJson::Derived(address.clone()))
+    }
+}
+
+// lookup_cmd runs a program on the control machine and renders its output, say to read from a secrets
+// manager.  it is off unless --allow-lookup-cmd names the programs it may run, which the templar passes in.
+// there is no shell, the command is split on whitespace, and output is never shown in errors.  results are
+// kept for the run so a lookup is not repeated for every host
+
+#[derive(Clone,Default)]
+pub struct LookupCmd {
+    programs: Vec<String>,
+    results: Arc<Mutex<HashMap<String, String>>>
+}
+
+impl LookupCmd {
+
+    pub fn new(programs: &[String]) -> Self {
+        Self { programs: programs.to_vec(), results: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    fn run(&self, cmd: &str) -> Result<String, RenderError> {
+        if let Some(x) = self.results.lock().unwrap().get(cmd) {
+            return Ok(x.clone());
+        }
+        let tokens : Vec<&str> = cmd.split_whitespace().collect();
+        let program = tokens.first().ok_or_else(|| RenderError::new("lookup_cmd: the command is empty"))?;
+        if self.programs.is_empty() {
+            return Err(RenderError::new("lookup_cmd: disabled, pass --allow-lookup-cmd with the programs it may run"));
+        }
+        if ! self.programs.iter().any(|x| x.eq(program)) {
+            return Err(RenderError::new(format!("lookup_cmd: {} is not one of the programs allowed by --allow-lookup-cmd", program)));
+        }
+        let output = Command::new(program).args(&tokens[1..]).output()
+            .map_err(|e| RenderError::new(format!("lookup_cmd: unable to run {}: {}", program, e)))?;
+        if ! output.status.success() {
+            return Err(RenderError::new(format!("lookup_cmd: {} failed ({})", program, output.status)));
+        }
+        let out = String::from_utf8_lossy(&output.stdout).trim_end_matches(['\r', '\n']).to_string();
+        self.results.lock().unwrap().insert(cmd.to_owned(), out.clone());
+        Ok(out)
+    }
+
+}
+
+impl HelperDef for LookupCmd {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        if h.params().len() != 1 {
+            return Err(RenderError::new(
+                "lookup_cmd: requires one parameter",
+            ));
+        }
+        let cmd = h.param(0)
+            .and_then(|x| x.value().as_str())
+            .ok_or_else(|| RenderError::new("lookup_cmd: the command must be a string"))?;
+        Ok(ScopedJson::Derived(JsonValue::from(self.run(cmd)?)))
+    }
+}
+
+pub fn register_helpers(handlebars: &mut Handlebars, lookup_cmd: &LookupCmd) {
     {
         handlebars_helper!(to_lower_case: |v: str| v.to_lowercase());
         handlebars.register_helper("to_lower_case", Box::new(to_lower_case))
@@ -78,6 +334,22 @@ pub fn register_helpers(handlebars: &mut Handlebars) {
     {
         handlebars.register_helper("isdefined", Box::new(IsDefined));
     }
+    {
+        handlebars.register_helper("default", Box::new(DefaultValue));
+        handlebars.register_helper("mandatory", Box::new(Mandatory));
+    }
+    {
+        handlebars.register_helper("to_json", Box::new(ToJson));
+        handlebars.register_helper("to_yaml", Box::new(ToYaml));
+        handlebars.register_helper("from_json", Box::new(FromJson));
+    }
+    {
+        handlebars.register_helper("fact_ip", Box::new(FactIp));
+        handlebars.register_helper("default_ipv4", Box::new(DefaultIpv4));
+    }
+    {
+        handlebars.register_helper("lookup_cmd", Box::new(lookup_cmd.clone()));
+    }
 }
 
 #[cfg(test)]
@@ -89,10 +361,14 @@ mod tests {
     use handlebars::{no_escape, Handlebars};
 
     pub fn new_handlebars<'reg>() -> Handlebars<'reg> {
+        new_handlebars_with_lookups(&[])
+    }
+
+    fn new_handlebars_with_lookups<'reg>(programs: &[String]) -> Handlebars<'reg> {
         let mut handlebars = Handlebars::new();
         handlebars.set_strict_mode(true);
         handlebars.register_escape_fn(no_escape); //html escaping is the default and cause issue
-        register_helpers(&mut handlebars);
+        register_helpers(&mut handlebars, &LookupCmd::new(programs));
         handlebars
     }
 
@@ -216,4 +492,44 @@ mod tests {
         assert_eq!(result.unwrap(), "true false a ");
         Ok(())
     }
+
+    #[test]
+    fn test_default_and_mandatory() -> Result<(), Box<dyn Error>> {
+        let handlebars = new_handlebars();
+        assert_eq!(handlebars.render_template(r#"{{default x "y"}}"#, &json!({}))?, "y");
+        assert_eq!(handlebars.render_template(r#"{{default x "y"}}"#, &json!({"x": "z"}))?, "z");
+        assert_eq!(handlebars.render_template(r#"{{default x "y"}}"#, &json!({"x": ""}))?, "y");
+        assert_eq!(handlebars.render_template(r#"{{default a.b 8080}}"#, &json!({"a": {}}))?, "8080");
+        assert_eq!(handlebars.render_template(r#"{{mandatory x}}"#, &json!({"x": 1}))?, "1");
+        let err = handlebars.render_template(r#"{{mandatory db.host}}"#, &json!({})).unwrap_err();
+        assert!(err.to_string().contains("mandatory: variable db.host is not defined"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_helpers() -> Result<(), Box<dyn Error>> {
+        let handlebars = new_handlebars();
+        let data = json!({"jet_network": {
+            "interfaces": { "eth0": { "ipv4": ["192.0.2.2", "192.0.2.3"], "ipv6": ["fe80::1"] }, "lo": { "ipv4": ["127.0.0.1"], "ipv6": [] } },
+            "default_interface": "eth0",
+            "default_ipv4": "192.0.2.2"
+        }});
+        let result = handlebars.render_template(r#"{{fact_ip "eth0"}} {{fact_ip "eth0" "ipv6"}} {{fact_ip "lo"}} {{default_ipv4}}"#, &data);
+        assert_eq!(result.unwrap(), "192.0.2.2 fe80::1 127.0.0.1 192.0.2.2");
+        assert!(handlebars.render_template(r#"{{fact_ip "lo" "ipv6"}}"#, &data).is_err());
+        assert!(handlebars.render_template(r#"{{fact_ip "eth1"}}"#, &data).is_err());
+        assert!(handlebars.render_template(r#"{{default_ipv4}}"#, &json!({})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_cmd() -> Result<(), Box<dyn Error>> {
+        assert!(new_handlebars().render_template(r#"{{lookup_cmd "echo hi"}}"#, &json!({})).is_err());
+        let handlebars = new_handlebars_with_lookups(&[String::from("echo"), String::from("ls")]);
+        assert_eq!(handlebars.render_template(r#"{{lookup_cmd "echo s3cret  value"}}"#, &json!({})).unwrap(), "s3cret value");
+        let err = handlebars.render_template(r#"{{lookup_cmd "ls /nonexistent-s3cret"}}"#, &json!({})).unwrap_err();
+        assert!(! err.to_string().contains("s3cret"));
+        assert!(handlebars.render_template(r#"{{lookup_cmd "printf x"}}"#, &json!({})).is_err());
+        Ok(())
+    }
 }
@@ -18,7 +18,7 @@ use crate::registry::list::Task;
 use crate::connection::connection::Connection;
 use crate::handle::handle::TaskHandle;
 use crate::playbooks::traversal::RunState;
-use crate::inventory::hosts::Host;
+use crate::inventory::hosts::{Host,HostOSType};
 use crate::playbooks::traversal::HandlerMode;
 use crate::playbooks::language::Play;
 use crate::tasks::request::SudoDetails;
@@ -30,6 +30,8 @@ use std::sync::{Arc,RwLock,Mutex};
 use std::collections::HashMap;
 use rayon::prelude::*;
 use std::{thread, time};
+use std::time::Instant;
+use crate::modules::control::wait_for_connection::WaitForConnectionTask;
 
 // this module contains the guts of running tasks inside per-host threads
 // while the actual core finite state machine is not terribly complicated
@@ -41,6 +43,7 @@ pub fn fsm_run_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_han
 
     // if running in check mode various functions will short circuit early
     let check =  run_state.visitor.read().unwrap().is_check_mode();
+    let profile = run_state.context.read().unwrap().profile;
 
     // the hosts to configure are not those specified in the batch but the subset of those that have not yet failed
     let hosts : HashMap<String, Arc<RwLock<Host>>> = run_state.context.read().unwrap().get_remaining_hosts();
@@ -51,13 +54,23 @@ pub fn fsm_run_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_han
     let _total : i64 = host_objects.par_iter().map(|host| {
 
         // get the connection to each host, which should be left open until the play ends
-        let connection_result = run_state.connection_factory.read().unwrap().get_connection(&run_state.context, host);
+        let connection_result = match task {
+            Task::Wait_For_Connection(wait) => wait_for_connection(run_state, host, wait),
+            _ => run_state.connection_factory.read().unwrap().get_connection(&run_state.context, host)
+        };
         match connection_result {
             Ok(_)  => {
                 let connection = connection_result.unwrap();
+                run_state.context.write().unwrap().record_connection_ok(host);
                 run_state.visitor.read().unwrap().on_host_task_start(&run_state.context, host);
                 // the actual task is invoked here
+                let started = match profile { true => Some(Instant::now()), false => None };
                 let task_response = run_task_on_host(run_state,connection,host,play,task,are_handlers);
+                if let Some(started) = started {
+                    let mut ctx = run_state.context.write().unwrap();
+                    let task_name = ctx.task.clone().unwrap_or_else(|| task.get_display_name());
+                    ctx.record_task_time(&task_name, &host.read().unwrap().name, started.elapsed());
+                }
 
                 match task_response {
                     Ok(x) => {
@@ -75,10 +88,11 @@ pub fn fsm_run_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_han
                 }
             },
             Err(x) => {
-                // hosts with connection failures are removed from the pool
+                // hosts that keep failing to connect are removed from the pool as unreachable,
+                // until then only this task is skipped for them
                 run_state.visitor.read().unwrap().debug_host(host, &x);
-                run_state.context.write().unwrap().fail_host(host);
-                run_state.visitor.read().unwrap().on_host_connect_failed(&run_state.context, host);
+                let unreachable = run_state.context.write().unwrap().record_connection_failure(host);
+                run_state.visitor.read().unwrap().on_host_connect_failed(&run_state.context, host, unreachable);
             }
         }
         // rayon needs some math to add up, hence the 1. It seems to short-circuit without some work to do.
@@ -88,6 +102,31 @@ pub fn fsm_run_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_han
     Ok(())
 }
 
+fn wait_for_connection(run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, wait: &WaitForConnectionTask) -> Result<Arc<Mutex<dyn Connection>>, String> {
+
+    // a cached connection may be from before a reboot, so always start with a fresh one.
+    // failed attempts here do not count towards the host being unreachable, only the final one does
+
+    let (timeout, delay) = wait.get_timing(run_state, host)?;
+    run_state.context.read().unwrap().connection_cache.write().unwrap().remove_connection(host);
+    let deadline = Instant::now() + time::Duration::from_secs(timeout);
+    loop {
+        let error = match run_state.connection_factory.read().unwrap().get_connection(&run_state.context, host) {
+            Ok(x) => { return Ok(x); },
+            Err(x) => x
+        };
+        // the last wait is cut short so that there is always one more try right at the deadline
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(format!("could not connect within {} seconds: {}", timeout, error));
+        }
+        if run_state.context.read().unwrap().verbosity > 0 {
+            run_state.visitor.read().unwrap().debug_host(host, &format!("waiting for connection: {}", error));
+        }
+        thread::sleep(time::Duration::from_secs(delay).min(deadline - now));
+    }
+}
+
 fn get_actual_connection(run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, task: &Task, input_connection: Arc<Mutex<dyn Connection>>) -> Result<(Option<String>,Arc<Mutex<dyn Connection>>), String> {
     
     // usually the connection we already have is the one we will use, but this is not the case for using the delegate_to feature
@@ -95,8 +134,9 @@ fn get_actual_connection(run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, ta
 
     match task.get_with() {
         
-        // if the task has a with section then the task might be delegated
-        Some(task_with) => match task_with.delegate_to {
+        // if the task has a with section then the task might be delegated, local_action is shorthand
+        // for delegating to the control machine
+        Some(task_with) => match local_action_delegate(run_state, host, &task_with)? {
 
             // we have found the delegate_to keyword
             Some(pre_delegate) => {
@@ -141,6 +181,36 @@ fn get_actual_connection(run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, ta
     }
 }
 
+fn local_action_delegate(run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, task_with: &PreLogicInput) -> Result<Option<String>, String> {
+    let local = match &task_with.local_action {
+        None => false,
+        Some(x) => match run_state.context.read().unwrap().render_template(x, host, BlendTarget::NotTemplateModule, TemplateMode::Strict)?.trim() {
+            "true" => true,
+            "false" => false,
+            y => { return Err(format!("local_action: expected true or false, got: {}", y)); }
+        }
+    };
+    match (local, &task_with.delegate_to) {
+        (true, Some(_)) => Err(String::from("local_action and delegate_to cannot be used together")),
+        (true, None) => Ok(Some(String::from("localhost"))),
+        (false, x) => Ok(x.clone())
+    }
+}
+
+fn delegate_os_type(run_state: &Arc<RunState>, delegate: &String) -> Option<HostOSType> {
+    if delegate.eq("localhost") {
+        return match cfg!(target_os = "macos") {
+            true => Some(HostOSType::MacOS),
+            false => Some(HostOSType::Linux)
+        };
+    }
+    let inventory = run_state.inventory.read().unwrap();
+    match inventory.has_host(delegate) {
+        true => inventory.get_host(delegate).read().unwrap().os_type,
+        false => None
+    }
+}
+
 fn run_task_on_host(
     run_state: &Arc<RunState>,
     input_connection: Arc<Mutex<dyn Connection>>,
@@ -167,7 +237,7 @@ fn run_task_on_host(
         Ok((Some(delegate), ref conn)) => (
             Some(delegate.clone()), 
             conn, 
-            Arc::new(TaskHandle::new(Arc::clone(run_state), Arc::clone(conn), Arc::clone(host)))
+            Arc::new(TaskHandle::new_delegated(Arc::clone(run_state), Arc::clone(conn), Arc::clone(host), delegate_os_type(run_state, &delegate)))
         ),
         // something went wrong when processing delegates, create a throw-away handle just so we can use the response functions
         Err(msg) => {
@@ -181,20 +251,42 @@ fn run_task_on_host(
         run_state.visitor.read().unwrap().on_host_delegate(host, &delegated1);
     }
 
+    // parameters the task author has marked as trusted get lighter input screening
+    if let Some(with) = task.get_with() {
+        handle.template.set_trusted_fields(&with.trusted);
+    }
+
+    let result = run_task_items_on_host(run_state, connection, host, play, task, are_handlers, &handle, &validate);
+
+    // with 'rollback' set, files the task replaced are put back if it failed, even if the failure was ignored
+    let failed = match &result {
+        Ok(x) => x.status == TaskStatus::Failed,
+        Err(_) => true
+    };
+    let restored = handle.remote.finish_rollback(failed)?;
+    for path in restored.iter() {
+        run_state.visitor.read().unwrap().on_host_rollback(&run_state.context, host, path);
+    }
+    handle.remote.save_checksum_cache(&validate)?;
+    result
+}
+
+// evaluates the task and runs it once per item, with retries
+#[allow(clippy::too_many_arguments)]
+fn run_task_items_on_host(
+    run_state: &Arc<RunState>,
+    connection: &Arc<Mutex<dyn Connection>>,
+    host: &Arc<RwLock<Host>>,
+    play: &Play,
+    task: &Task,
+    are_handlers: HandlerMode,
+    handle: &Arc<TaskHandle>,
+    validate: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+
     // process the YAML inputs of the task and turn them into something we can  use
     // initially we run this in 'template off' mode which returns basically junk
     // but allows us to get the 'items' data off the collection. 
-    let evaluated = task.evaluate(&handle, &validate, TemplateMode::Off)?;
-
-    if evaluated.with.is_some() {
-        let condition = &evaluated.with.as_ref().as_ref().unwrap().condition; // lol rust
-        if condition.is_some() {
-            let cond = handle.template.test_condition(&validate, TemplateMode::Strict, condition.as_ref().unwrap())?;
-            if ! cond {
-                return Ok(handle.response.is_skipped(&Arc::clone(&validate)));
-            }
-        }
-    }
+    let evaluated = task.evaluate(handle, validate, TemplateMode::Off)?;
 
     // see if we are iterating over a list of items or not
     let items_input = match evaluated.with.is_some() {
@@ -202,15 +294,29 @@ fn run_task_on_host(
         false => &None
     };
 
+    // a condition that uses the 'item' variable in a loop is tested once per item and filters items instead of the
+    // whole task. otherwise it is tested up front, so it can still guard against items that are not defined.
+    let condition = match evaluated.with.is_some() {
+        true => evaluated.with.as_ref().as_ref().unwrap().condition.clone(), // lol rust
+        false => None
+    };
+    let per_item = items_input.is_some() && condition.as_ref().is_some_and(|x| references_item(x));
+    if let (Some(cond), false) = (&condition, per_item) {
+        if ! handle.template.test_condition(validate, TemplateMode::Strict, cond)? {
+            return Ok(handle.response.is_skipped(&Arc::clone(validate), &format!("condition was false: {}", cond.trim())));
+        }
+    }
+
     // mapping to store the 'item' variable when using 'with_items'
     let mut mapping = serde_yaml::Mapping::new();
 
     // storing the last result of the items loop so we always have something to return
     // if a failure occurs it will be returned immediately
     let mut last : Option<Result<Arc<TaskResponse>,Arc<TaskResponse>>> = None;
+    let mut skipped_items : usize = 0;
 
     // even if we are not iterating over a list of items, make a list of one item to simplify the logic
-    let evaluated_items = template_items(&handle, &validate, TemplateMode::Strict, items_input)?;
+    let evaluated_items = template_items(handle, validate, TemplateMode::Strict, items_input)?;
 
     // walking over each item or just the single task if 'with_items' was not used
     for item in evaluated_items.iter() {
@@ -219,8 +325,22 @@ fn run_task_on_host(
         mapping.insert(serde_yaml::Value::String(String::from("item")), item.clone());
         host.write().unwrap().update_facts2(mapping.clone());
 
+        if let (Some(cond), true) = (&condition, per_item) {
+            if ! handle.template.test_condition(validate, TemplateMode::Strict, cond)? {
+                run_state.visitor.read().unwrap().on_host_task_item_skipped(&run_state.context, host, item);
+                skipped_items += 1;
+                continue;
+            }
+        }
+
         // re-evaluate the task, allowing the 'items' to be plugged in.
-        let evaluated = task.evaluate(&handle, &validate, TemplateMode::Strict)?;
+        let evaluated = task.evaluate(handle, validate, TemplateMode::Strict)?;
+        run_state.visitor.read().unwrap().on_task_params(&run_state.context, host, &evaluated);
+        if evaluated.and.as_ref().as_ref().is_some_and(|x| x.rollback) {
+            handle.remote.enable_rollback();
+        }
+        handle.remote.set_timeout(evaluated.with.as_ref().as_ref().and_then(|x| x.timeout));
+        handle.remote.set_max_output(None);
 
         // see if there is any retry or delay logic in the task
         let mut retries = match evaluated.and.as_ref().is_some() {
@@ -235,7 +355,7 @@ fn run_task_on_host(
             
             // here we finally call the actual task, everything around this is just support
             // for delegation, loops, and retries!
-            match run_task_on_host_inner(run_state, connection, host, play, task, are_handlers, &handle, &validate, &evaluated) {
+            match run_task_on_host_inner(run_state, connection, host, play, task, are_handlers, handle, validate, &evaluated) {
                 Err(e) => match retries {
                     // retries are used up
                     0 => { return Err(e); },
@@ -263,12 +383,41 @@ fn run_task_on_host(
     }; if res {
         last.unwrap()
     }
+    else if skipped_items > 0 {
+        // every item was filtered out by the condition
+        Ok(handle.response.is_skipped(&Arc::clone(validate), &format!("condition was false for every item: {}", condition.as_ref().map(|x| x.trim()).unwrap_or_default())))
+    }
     else {
-        Err(handle.response.is_failed(&validate, &String::from("with/items contained no entries")))
+        Err(handle.response.is_failed(validate, &String::from("with/items contained no entries")))
     }
 
 }
 
+// whether a condition refers to the item variable itself, as in item, item.name or (eq item 3), rather than just
+// containing the word, as in items_left or "no such item" inside a string
+
+fn references_item(condition: &str) -> bool {
+    let is_name_char = |c: char| c.is_alphanumeric() || "_-.@".contains(c);
+    let mut quote : Option<char> = None;
+    let mut previous = ' ';
+    for (index, c) in condition.char_indices() {
+        match quote {
+            Some(q) if c == q => { quote = None; },
+            Some(_) => {},
+            None if c == '"' || c == '\'' => { quote = Some(c); },
+            None if condition[index..].starts_with("item") && ! is_name_char(previous) => {
+                match condition[index+4..].chars().next() {
+                    Some(next) if next.is_alphanumeric() || next == '_' || next == '-' => {},
+                    _ => { return true; }
+                }
+            },
+            None => {}
+        }
+        previous = c;
+    }
+    false
+}
+
 // the "on this host" method body from _task
 #[allow(clippy::too_many_arguments)] // FIXME: too many args
 fn run_task_on_host_inner(
@@ -297,9 +446,13 @@ fn run_task_on_host_inner(
         false => run_state.context.read().unwrap().sudo.clone() 
     };
     // see if the sudo template is configured, if not use the most basic default
-    let sudo_template = match &play.sudo_template {
-        None => String::from("/usr/bin/sudo -u '{{jet_sudo_user}}' {{jet_command}}"),
-        Some(x) => x.clone()
+    // with a sudo password, sudo reads it from standard input. -k makes sure sudo always asks for it, rather than
+    // leaving it unread for the command itself when the credentials are cached.
+    let has_sudo_password = run_state.context.read().unwrap().sudo_password.is_some();
+    let sudo_template = match (&play.sudo_template, has_sudo_password) {
+        (None, false) => String::from("/usr/bin/sudo -u '{{jet_sudo_user}}' {{jet_command}}"),
+        (None, true) => String::from("/usr/bin/sudo -k -S -p '' -u '{{jet_sudo_user}}' {{jet_command}}"),
+        (Some(x), _) => x.clone()
     };
     
     // is 'with' provided?
@@ -307,9 +460,14 @@ fn run_task_on_host_inner(
         let logic = pre_logic.as_ref().as_ref().unwrap();
         let my_host = host.read().unwrap();
         if are_handlers == HandlerMode::Handlers  {
-            // if we are running handlers at the moment, skip any un-notified handlers
-            if ! my_host.is_notified(play_count, &logic.subscribe.as_ref().unwrap().clone()) {
-                return Ok(handle.response.is_skipped(&Arc::clone(validate))); 
+            // if we are running handlers at the moment, skip any un-notified handlers. a handler
+            // answers to its subscribe name and to any listen topics, whichever was notified
+            let mut signals = logic.listen.clone();
+            if let Some(subscribe) = &logic.subscribe {
+                signals.push(subscribe.clone());
+            }
+            if ! signals.iter().any(|signal| my_host.is_notified(play_count, signal)) {
+                return Ok(handle.response.is_skipped(&Arc::clone(validate), "handler was not notified")); 
             }
         }
         
@@ -319,6 +477,12 @@ fn run_task_on_host_inner(
         }
     }
 
+    // no_log hides commands and their output from --explain and verbose output
+    let no_log = match pre_logic.as_ref() {
+        Some(logic) => logic.no_log,
+        None => false
+    };
+
     let sudo_details = SudoDetails {
         user     : sudo.clone(),
         template : sudo_template.clone()
@@ -329,7 +493,7 @@ fn run_task_on_host_inner(
     // don't return the wrong states, even when returning an error, to prevent
     // unpredictability in the program
 
-    let query = TaskRequest::query(&sudo_details);
+    let query = TaskRequest::query(&sudo_details, no_log);
 
     // invoke the resource and see what actions it thinks need to be performed
 
@@ -357,7 +521,7 @@ fn run_task_on_host_inner(
 
             TaskStatus::NeedsCreation => match modify_mode {
                 true => {
-                    let req = TaskRequest::create(&sudo_details);
+                    let req = TaskRequest::create(&sudo_details, no_log);
                     let crc = action.dispatch(handle, &req);
                     match crc {
                         Ok(ref crc_ok) => match crc_ok.status {
@@ -376,7 +540,7 @@ fn run_task_on_host_inner(
 
             TaskStatus::NeedsRemoval => match modify_mode {
                 true => {
-                    let req = TaskRequest::remove(&sudo_details);
+                    let req = TaskRequest::remove(&sudo_details, no_log);
                     let rrc = action.dispatch(handle, &req);
                     match rrc {
                         Ok(ref rrc_ok) => match rrc_ok.status {
@@ -394,7 +558,7 @@ fn run_task_on_host_inner(
 
             TaskStatus::NeedsModification => match modify_mode {
                 true => {
-                    let req = TaskRequest::modify(&sudo_details, qrc_ok.changes.clone());
+                    let req = TaskRequest::modify(&sudo_details, no_log, qrc_ok.changes.clone());
                     let mrc = action.dispatch(handle, &req);
                     match mrc {
                         Ok(ref mrc_ok) => match mrc_ok.status {
@@ -412,7 +576,7 @@ fn run_task_on_host_inner(
 
             TaskStatus::NeedsExecution => match modify_mode {
                 true => {
-                    let req = TaskRequest::execute(&sudo_details);
+                    let req = TaskRequest::execute(&sudo_details, no_log);
                     let erc = action.dispatch(handle, &req);
                     match erc {
                         Ok(ref erc_ok) => match erc_ok.status {
@@ -430,7 +594,7 @@ fn run_task_on_host_inner(
             },
 
             TaskStatus::NeedsPassive => {
-                let req = TaskRequest::passive(&sudo_details);
+                let req = TaskRequest::passive(&sudo_details, no_log);
                 let prc = action.dispatch(handle, &req);
                 match prc {
                     Ok(ref prc_ok) => match prc_ok.status {
@@ -481,13 +645,14 @@ fn run_task_on_host_inner(
 
     if result.is_ok() && post_logic.is_some() {
         let logic = post_logic.as_ref().as_ref().unwrap();
-        if are_handlers == HandlerMode::NormalTasks && result.is_ok() && logic.notify.is_some() {
-            let notify = logic.notify.as_ref().unwrap().clone();
+        if are_handlers == HandlerMode::NormalTasks && result.is_ok() && ! logic.notify.is_empty() {
             let status = &result.as_ref().unwrap().status;
             match status {
                 TaskStatus::IsCreated | TaskStatus::IsModified | TaskStatus::IsRemoved | TaskStatus::IsExecuted => {
-                    run_state.visitor.read().unwrap().on_notify_handler(host, &notify.clone());
-                    host.write().unwrap().notify(play_count, &notify.clone());
+                    for notify in logic.notify.iter() {
+                        run_state.visitor.read().unwrap().on_notify_handler(host, notify);
+                        host.write().unwrap().notify(play_count, notify);
+                    }
                 },
                 _ => { }
             }
@@ -498,3 +663,82 @@ fn run_task_on_host_inner(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::factory::ConnectionFactory;
+    use crate::connection::no::NoConnection;
+    use crate::playbooks::context::PlaybookContext;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+
+    // refuses the first few connections, like a host that is still booting
+    struct FlakyFactory {
+        failures: usize,
+        attempts: AtomicUsize
+    }
+
+    impl ConnectionFactory for FlakyFactory {
+        fn get_connection(&self, _context: &Arc<RwLock<PlaybookContext>>, _host: &Arc<RwLock<Host>>) -> Result<Arc<Mutex<dyn Connection>>, String> {
+            match self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                true => Err(String::from("connection refused")),
+                false => Ok(Arc::new(Mutex::new(NoConnection::new())))
+            }
+        }
+        fn get_local_connection(&self, _context: &Arc<RwLock<PlaybookContext>>) -> Result<Arc<Mutex<dyn Connection>>, String> {
+            Ok(Arc::new(Mutex::new(NoConnection::new())))
+        }
+    }
+
+    fn wait_with(failures: usize, task: &str) -> (Result<(), String>, usize) {
+        let (simulated, _) = TaskHandle::scripted(Vec::new());
+        let base = &simulated.run_state;
+        let factory = Arc::new(RwLock::new(FlakyFactory { failures, attempts: AtomicUsize::new(0) }));
+        let run_state = Arc::new(RunState {
+            inventory: Arc::clone(&base.inventory),
+            playbook_paths: Arc::clone(&base.playbook_paths),
+            role_paths: Arc::clone(&base.role_paths),
+            module_paths: Arc::clone(&base.module_paths),
+            limit_hosts: Vec::new(),
+            limit_groups: Vec::new(),
+            batch_size: None,
+            context: Arc::clone(&base.context),
+            visitor: Arc::clone(&base.visitor),
+            connection_factory: factory.clone(),
+            tags: None,
+            skip_tags: None,
+            allow_localhost_delegation: false
+        });
+        let wait : WaitForConnectionTask = serde_yaml::from_str(task).unwrap();
+        let result = wait_for_connection(&run_state, &simulated.host, &wait).map(|_| ());
+        let attempts = factory.read().unwrap().attempts.load(Ordering::SeqCst);
+        (result, attempts)
+    }
+
+    #[test]
+    fn test_wait_for_connection_tries_before_sleeping_and_again_at_the_deadline() {
+        let (result, attempts) = wait_with(0, "delay: 30");
+        assert!(result.is_ok());
+        assert_eq!(attempts, 1);
+
+        let (result, attempts) = wait_with(2, "delay: 0\ntimeout: 5");
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+
+        let started = Instant::now();
+        let (result, attempts) = wait_with(usize::MAX, "delay: 30\ntimeout: 1");
+        assert_eq!(result.unwrap_err(), "could not connect within 1 seconds: connection refused");
+        assert_eq!(attempts, 2);
+        assert!(started.elapsed() < time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_references_item_looks_for_the_variable_not_the_word() {
+        for condition in ["item", "item.enabled", "(eq item 3)", "(not item.[0])", "(and ready (eq item.name 'web'))"] {
+            assert!(references_item(condition), "{}", condition);
+        }
+        for condition in ["items_left", "(gt line_item 2)", "system.ready", "(contains out \"no such item\")", "(eq name 'item')"] {
+            assert!(! references_item(condition), "{}", condition);
+        }
+    }
+}
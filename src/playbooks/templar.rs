@@ -15,28 +15,18 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use serde_yaml;
-use once_cell::sync::Lazy;
 use handlebars::{Handlebars,RenderError};
 
-use crate::playbooks::t_helpers::register_helpers;
+use crate::playbooks::t_helpers::{register_helpers,LookupCmd};
 
 // templar contains low-level wrapping around handlebars.
 // this is not used directly when evaluating templates and template
 // expressions, for this, see handle/template.rs
 
-static HANDLEBARS: Lazy<Handlebars> = Lazy::new(|| {
-    let mut hb = Handlebars::new();
-    // very important: we are not plugging variables into HTML, turn escaping off
-    hb.register_escape_fn(handlebars::no_escape);
-    hb.set_strict_mode(true);
-    register_helpers(&mut hb);
-    hb
-});
-
 // 'off' mode is used in a bit of a weird traversal/engine
 // situation where we need to get access to some task parameters
-// before templates are evaluated. You will notice there is no way
-// to evaluate templates in unstrict mode. This is by design.
+// before templates are evaluated. Outside of render_lenient there is
+// no way to evaluate templates in unstrict mode. This is by design.
 
 #[derive(PartialEq,Copy,Clone,Debug)]
 pub enum TemplateMode {
@@ -45,19 +35,38 @@ pub enum TemplateMode {
 }
 
 pub struct Templar {
+    handlebars: Handlebars<'static>,
+    // the same but undefined variables render as nothing, only the template module can ask for this with
+    // strict_undefined: false
+    handlebars_lenient: Handlebars<'static>
 }
 
 impl Templar {
 
     pub fn new() -> Self {
-        Self {}
+        Self::with_lookup_programs(&[])
+    }
+
+    // the programs lookup_cmd may run, from --allow-lookup-cmd.  both registries share one lookup cache
+
+    pub fn with_lookup_programs(programs: &[String]) -> Self {
+        let lookup_cmd = LookupCmd::new(programs);
+        let mut handlebars = Handlebars::new();
+        // very important: we are not plugging variables into HTML, turn escaping off
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.set_strict_mode(true);
+        register_helpers(&mut handlebars, &lookup_cmd);
+        let mut handlebars_lenient = Handlebars::new();
+        handlebars_lenient.register_escape_fn(handlebars::no_escape);
+        register_helpers(&mut handlebars_lenient, &lookup_cmd);
+        Self { handlebars, handlebars_lenient }
     }
 
     // evaluate a string
 
     pub fn render(&self, template: &str, data: serde_yaml::Mapping, template_mode: TemplateMode) -> Result<String, String> {
         let result : Result<String, RenderError> = match template_mode {
-            TemplateMode::Strict => HANDLEBARS.render_template(template, &data),
+            TemplateMode::Strict => self.handlebars.render_template(template, &data),
             /* this is only used to get back the raw 'items' collection inside the task FSM */
             TemplateMode::Off => Ok(String::from("empty"))
         };
@@ -71,6 +80,13 @@ impl Templar {
         }
     }
     
+    pub fn render_lenient(&self, template: &str, data: serde_yaml::Mapping) -> Result<String, String> {
+        match self.handlebars_lenient.render_template(template, &data) {
+            Ok(x) => Ok(x),
+            Err(y) => Err(format!("Template error: {}", y.desc))
+        }
+    }
+
     // used for with/cond and also in the shell module
 
     pub fn test_condition(&self, expr: &String, data: serde_yaml::Mapping, template_mode: TemplateMode) -> Result<bool, String> {
@@ -101,3 +117,32 @@ impl Templar {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nested_mapping() -> serde_yaml::Mapping {
+        serde_yaml::from_str("app:\n  name: web \"blue\"\n  ports: [80, 443]\n  tls: { enabled: true, cert: null }\nraw: '{\"a\": [1, 2]}'\n").unwrap()
+    }
+
+    #[test]
+    fn test_to_json_and_to_yaml_round_trip() {
+        let templar = Templar::new();
+        let data = nested_mapping();
+        let app = data.get("app").unwrap().clone();
+
+        let json = templar.render("{{to_json app}}", data.clone(), TemplateMode::Strict).unwrap();
+        assert_eq!(json, r#"{"name":"web \"blue\"","ports":[80,443],"tls":{"enabled":true,"cert":null}}"#);
+        assert_eq!(serde_yaml::from_str::<serde_yaml::Value>(&json).unwrap(), app);
+
+        let yaml = templar.render("{{to_yaml app}}", data.clone(), TemplateMode::Strict).unwrap();
+        assert_eq!(serde_yaml::from_str::<serde_yaml::Value>(&yaml).unwrap(), app);
+
+        let parsed = templar.render("{{to_json (from_json raw)}} {{#with (from_json raw)}}{{#each a}}{{this}}{{/each}}{{/with}}", data.clone(), TemplateMode::Strict).unwrap();
+        assert_eq!(parsed, r#"{"a":[1,2]} 12"#);
+
+        let err = templar.render("{{to_json nope}}", data, TemplateMode::Strict).unwrap_err();
+        assert!(err.contains("to_json: variable nope is not defined"), "{}", err);
+    }
+}
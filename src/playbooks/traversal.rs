@@ -15,21 +15,26 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::playbooks::language::Play;
+use crate::util::terminal::prompt_line;
+use crate::tasks::cmd_library::is_environment_name;
 use crate::playbooks::visitor::PlaybookVisitor;
 use crate::playbooks::context::PlaybookContext;
 use crate::playbooks::language::{Role,RoleInvocation};
 use crate::connection::factory::ConnectionFactory;
 use crate::registry::list::Task;
 use crate::playbooks::task_fsm::fsm_run_task;
+use crate::playbooks::templar::TemplateMode;
+use crate::modules::control::include::IncludeTask;
 use crate::inventory::inventory::Inventory;
 use crate::inventory::hosts::Host;
-use crate::util::io::{jet_file_open,directory_as_string};
-use crate::util::yaml::{blend_variables,show_yaml_error_in_context};
+use crate::util::io::{jet_file_open,directory_as_string,path_as_string};
+use crate::util::yaml::{blend_variables,show_yaml_error_in_context,ListMerge};
 use std::path::PathBuf;
 use std::collections::HashMap;
 use std::sync::{Arc,RwLock};
 use std::path::Path;
 use std::env;
+use std::io::IsTerminal;
 
 // this module contains the start of everything related to playbook evaluation
 
@@ -40,6 +45,9 @@ pub enum HandlerMode {
     Handlers
 }
 
+// imports and includes may nest, but not forever
+pub const MAX_INCLUDE_DEPTH: usize = 32;
+
 // the run state is a quasi-global that can be used to access all
 // import 'objects' related to playbook evaluation
 
@@ -55,6 +63,7 @@ pub struct RunState {
     pub visitor: Arc<RwLock<PlaybookVisitor>>,
     pub connection_factory: Arc<RwLock<dyn ConnectionFactory>>,
     pub tags: Option<Vec<String>>,
+    pub skip_tags: Option<Vec<String>>,
     pub allow_localhost_delegation: bool
 }
 
@@ -93,8 +102,20 @@ pub fn playbook_traversal(run_state: &Arc<RunState>) -> Result<(), String> {
             env::set_current_dir(pbdir).expect("could not chdir into playbook directory");
         }
 
+        // splice in any imported task files, this happens before anything is run
+        // so a bad import fails the playbook early
+        let mut plays: Vec<Play> = parsed.unwrap();
+        for play in plays.iter_mut() {
+            if let Some(tasks) = play.tasks.take() {
+                play.tasks = Some(expand_imports(run_state, tasks, HandlerMode::NormalTasks, 0)?);
+            }
+            if let Some(handlers) = play.handlers.take() {
+                play.handlers = Some(expand_imports(run_state, handlers, HandlerMode::Handlers, 0)?);
+            }
+            validate_handlers(play.handlers.as_deref().unwrap_or_default())?;
+        }
+
         // walk each play in the playbook
-        let plays: Vec<Play> = parsed.unwrap();
         for play in plays.iter() {
             match handle_play(run_state, play) {
                 Ok(_) => {},
@@ -146,6 +167,7 @@ fn handle_play(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
     let hosts = get_play_hosts(run_state, play);
     validate_hosts(run_state, play, &hosts)?;
     load_vars_into_context(run_state, play)?;
+    prompt_for_vars(run_state, play)?;
 
     // support for serialization if using push configuration
     // means we may not configure hosts all at once but may take
@@ -190,6 +212,23 @@ fn handle_batch(run_state: &Arc<RunState>, play: &Play, hosts: &[Arc<RwLock<Host
     // assign the batch
     { let mut ctx = run_state.context.write().unwrap(); ctx.set_targetted_hosts(hosts); }
 
+    let result = process_batch_tasks(run_state, play);
+    let force_handlers = play.force_handlers.unwrap_or(run_state.context.read().unwrap().force_handlers);
+    if ! force_handlers {
+        result?;
+        return process_handlers(run_state, play);
+    }
+
+    // a failure does not stop handlers that were already notified, whether it failed a host or the whole play
+    { let mut ctx = run_state.context.write().unwrap(); ctx.unset_role(); ctx.retarget_notified_hosts(hosts); }
+    let handler_result = process_handlers(run_state, play);
+    result?;
+    handler_result
+
+}
+
+fn process_batch_tasks(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+
     // handle role tasks
     if play.roles.is_some() {
         let roles = play.roles.as_ref().unwrap();
@@ -200,8 +239,36 @@ fn handle_batch(run_state: &Arc<RunState>, play: &Play, hosts: &[Arc<RwLock<Host
     // handle loose play tasks
     if play.tasks.is_some() {
         let tasks = play.tasks.as_ref().unwrap();
-        for task in tasks.iter() { process_task(run_state, play, task, HandlerMode::NormalTasks, None)?; }
+        for task in tasks.iter() { process_task(run_state, play, task, HandlerMode::NormalTasks, None, &[])?; }
+    }
+    Ok(())
+
+}
+
+pub fn validate_handlers(handlers: &[Task]) -> Result<(), String> {
+
+    // every handler needs a name or topic to be notified by, otherwise it could never run
+
+    for handler in handlers.iter() {
+        if let Task::Include(_) = handler {
+            continue;
+        }
+        let signals = match handler.get_with() {
+            Some(with) => with.subscribe.is_some() || with.listen.is_some_and(|x| ! x.to_vec().is_empty()),
+            None => false
+        };
+        if ! signals {
+            return Err(format!("handler '{}' needs with/subscribe or with/listen so that tasks can notify it", handler.get_display_name()));
+        }
     }
+    Ok(())
+}
+
+fn process_handlers(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+
+    // handlers always run in the order they are defined, role handlers before play handlers, and
+    // at most once per batch. the notify order and the number of notifications don't matter
+    // because each handler definition is walked in turn and only checks whether it was notified.
 
     // handle role handlers
     if play.roles.is_some() {
@@ -213,49 +280,46 @@ fn handle_batch(run_state: &Arc<RunState>, play: &Play, hosts: &[Arc<RwLock<Host
     // handle loose play handlers
     if play.handlers.is_some() {
         let handlers = play.handlers.as_ref().unwrap();
-        for handler in handlers { process_task(run_state, play, handler, HandlerMode::Handlers, None)?;  }
+        for handler in handlers { process_task(run_state, play, handler, HandlerMode::Handlers, None, &[])?;  }
     }
     Ok(())
-
 }
 
-fn check_tags(run_state: &Arc<RunState>, task: &Task, role_invocation: Option<&RoleInvocation>) -> bool {
-
-    // a given task may have tags associated from either the current role or directly on the task
-    // if the CLI --tags argument was used, we will skip the task if those tags don't match or
-    // if the tags are ommitted
-
-    match &run_state.tags {
-        Some(cli_tags) => {
-            // CLI tags were specified
-            match task.get_with() {
-                // a with section was present
-                Some(task_with) => match task_with.tags {
-                    // tags are applied to the task
-                    Some(task_tags) => {
-                        for x in task_tags.iter() {  if cli_tags.contains(x) { return true; } }
-                    },
-                    // no tags
-                    None => {}
-                },
-                None => {}
-            };
-            match role_invocation {
-                // the role invocation has tags applied
-                Some(role_invoke) => if let Some(role_tags) = &role_invoke.tags {
-                    for x in role_tags.iter() { if cli_tags.contains(x) { return true; } }
-                },
-                None => {}
-            };
+// a task's tags are its own plus those of the role invocation and of every include it was reached through.
+// with --tags, a task runs only if one of those is listed.  --skip-tags wins over --tags, and an include
+// with a skipped tag is not entered at all.  other includes are always entered so tagged tasks inside
+// them can still be selected.
+
+pub fn get_effective_tags(task: &Task, role_invocation: Option<&RoleInvocation>, inherited_tags: &[String]) -> Vec<String> {
+    let mut tags : Vec<String> = inherited_tags.to_vec();
+    let role_tags = role_invocation.and_then(|x| x.tags.clone()).unwrap_or_default();
+    let task_tags = task.get_with().and_then(|x| x.tags).unwrap_or_default();
+    for tag in role_tags.into_iter().chain(task_tags) {
+        if ! tags.contains(&tag) {
+            tags.push(tag);
         }
-        // no CLI tags so run the task
-        None => { return true; }
     }
-    // we didn't match any tags, so don't run the task
-    false
+    tags
 }
 
-fn process_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>) -> Result<(), String> {
+fn is_skipped_by_tags(skip_tags: &Option<Vec<String>>, tags: &[String]) -> bool {
+    match skip_tags {
+        Some(skip) => tags.iter().any(|x| skip.contains(x)),
+        None => false
+    }
+}
+
+fn check_tags(cli_tags: &Option<Vec<String>>, skip_tags: &Option<Vec<String>>, tags: &[String]) -> bool {
+    if is_skipped_by_tags(skip_tags, tags) {
+        return false;
+    }
+    match cli_tags {
+        Some(selected) => tags.iter().any(|x| selected.contains(x)),
+        None => true
+    }
+}
+
+fn process_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>, inherited_tags: &[String]) -> Result<(), String> {
 
     // this function is the final wrapper before fsm_run_task, the low-level finite state machine around task execution that is wrapped
     // by rayon, for multi-threaded execution with our thread worker pool.
@@ -263,13 +327,34 @@ fn process_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_handler
     let hosts : HashMap<String, Arc<RwLock<Host>>> = run_state.context.read().unwrap().get_remaining_hosts();
     if hosts.is_empty() { return Err(String::from("no hosts remaining")) }
 
+    let tags = get_effective_tags(task, role_invocation, inherited_tags);
+
+    // includes are not run by the FSM, they load more tasks to run
+    if let Task::Include(include) = task {
+        return match is_skipped_by_tags(&run_state.skip_tags, &tags) {
+            true  => Ok(()),
+            false => process_include(run_state, play, task, include, are_handlers, role_invocation, &tags, 0)
+        };
+    }
+
     // we will run tasks with the FSM only if not skipped by tags
-    let should_run = check_tags(run_state, task, role_invocation);
+    let should_run = check_tags(&run_state.tags, &run_state.skip_tags, &tags);
     if should_run {
         run_state.context.write().unwrap().set_task(task);
         run_state.visitor.read().unwrap().on_task_start(&run_state.context, are_handlers);
         run_state.context.write().unwrap().increment_task_count();
-        fsm_run_task(run_state, play, task, are_handlers)?;
+        let failed_before = run_state.context.read().unwrap().failed_tasks;
+        // with/vars layer over everything but extra vars, for this task only
+        let task_vars = task.get_with().and_then(|x| x.vars);
+        run_state.context.write().unwrap().set_task_vars(&task_vars);
+        let result = fsm_run_task(run_state, play, task, are_handlers);
+        run_state.context.write().unwrap().clear_task_vars();
+        result?;
+        // with any_errors_fatal a failure on one host stops the play everywhere once this task is done,
+        // for clusters where a partial change is worse than none
+        if play.any_errors_fatal.unwrap_or(false) && run_state.context.read().unwrap().failed_tasks > failed_before {
+            return Err(String::from("a host failed and any_errors_fatal is set, stopping the play"));
+        }
     }
 
     Ok(())
@@ -288,7 +373,7 @@ fn process_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocat
     {
         // we're good.
         let mut ctx = run_state.context.write().unwrap();
-        let str_path = directory_as_string(&role_path);
+        let str_path = path_as_string(&role_path);
         ctx.set_role(&role, invocation, &str_path);
         if are_handlers == HandlerMode::NormalTasks {
             ctx.increment_role_count();
@@ -340,19 +425,13 @@ fn process_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocat
 
             // parse the YAML file
 
-            let task_fh = jet_file_open(task_buf.as_path())?;
-            let parsed: Result<Vec<Task>, serde_yaml::Error> = serde_yaml::from_reader(task_fh);
-            if let Err(e) = parsed {
-                show_yaml_error_in_context(&e, task_buf.as_path());
-                return Err("edit the file and try again?".to_string());
-            }   
-            let tasks = parsed.unwrap();
+            let tasks = load_task_file(run_state, task_buf.as_path(), are_handlers, 0)?;
             for task in tasks.iter() {
 
                 // process all tasks in the YAML file, this is the same function used
                 // for processing loose tasks outside of roles
 
-                process_task(run_state, play, task, are_handlers, Some(invocation))?;
+                process_task(run_state, play, task, are_handlers, Some(invocation), &[])?;
             }
         }
 
@@ -369,6 +448,139 @@ fn process_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocat
 
 }
 
+#[allow(clippy::too_many_arguments)]
+fn process_include(run_state: &Arc<RunState>, play: &Play, task: &Task, include: &IncludeTask, are_handlers: HandlerMode, 
+    role_invocation: Option<&RoleInvocation>, tags: &[String], depth: usize) -> Result<(), String> {
+
+    // an !include loads its task file when it is reached, so unlike !import it can be made conditional.
+    // the condition is checked per host and only the hosts that pass will run the included tasks.
+
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Err(format!("includes nested too deeply, at: {}", include.file));
+    }
+
+    include.check_with()?;
+    let condition = include.with.as_ref().and_then(|x| x.condition.clone());
+
+    run_state.context.write().unwrap().set_task(task);
+    run_state.visitor.read().unwrap().on_task_start(&run_state.context, are_handlers);
+
+    let hosts : HashMap<String, Arc<RwLock<Host>>> = run_state.context.read().unwrap().get_remaining_hosts();
+    let previous_hosts : Vec<Arc<RwLock<Host>>> = hosts.values().map(Arc::clone).collect();
+    let mut selected_hosts : Vec<Arc<RwLock<Host>>> = Vec::new();
+    for (host_name, host) in hosts.iter() {
+        match &condition {
+            None => selected_hosts.push(Arc::clone(host)),
+            Some(cond) => match run_state.context.read().unwrap().test_condition(cond, host, TemplateMode::Strict) {
+                Ok(true)  => selected_hosts.push(Arc::clone(host)),
+                Ok(false) => {},
+                Err(e)    => { return Err(format!("failed to evaluate condition for !include of {} on host {}: {}", include.file, host_name, e)); }
+            }
+        }
+    }
+
+    // if no hosts satisfy the condition, the file is not even loaded
+    if selected_hosts.is_empty() {
+        return Ok(());
+    }
+
+    let task_buf = find_task_file(run_state, &include.file, are_handlers);
+    let tasks = load_task_file(run_state, task_buf.as_path(), are_handlers, depth)?;
+
+    // narrow the hosts and layer on the include vars, both are put back when we are done
+    // hosts that fail inside the include stay failed since set_targetted_hosts skips them.
+    // vars given in 'with' work like they do on a task, the include's own vars win over them
+    let previous_vars = {
+        let mut ctx = run_state.context.write().unwrap();
+        ctx.set_targetted_hosts(&selected_hosts);
+        let previous = ctx.push_include_vars(&include.with.as_ref().and_then(|x| x.vars.clone()));
+        ctx.push_include_vars(&include.vars);
+        previous
+    };
+
+    let mut result : Result<(), String> = Ok(());
+    for included_task in tasks.iter() {
+        result = match included_task {
+            Task::Include(nested) => {
+                let nested_tags = get_effective_tags(included_task, role_invocation, tags);
+                match is_skipped_by_tags(&run_state.skip_tags, &nested_tags) {
+                    true  => Ok(()),
+                    false => process_include(run_state, play, included_task, nested, are_handlers, role_invocation, &nested_tags, depth + 1)
+                }
+            },
+            _ => process_task(run_state, play, included_task, are_handlers, role_invocation, tags)
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+
+    {
+        let mut ctx = run_state.context.write().unwrap();
+        ctx.restore_include_vars(previous_vars);
+        ctx.set_targetted_hosts(&previous_hosts);
+    }
+    result
+}
+
+pub fn find_task_file(run_state: &Arc<RunState>, task_file: &String, are_handlers: HandlerMode) -> PathBuf {
+
+    // relative paths to imported or included task files are resolved against the tasks/ or handlers/
+    // directory when inside a role (traversal has already changed into the role directory), and against 
+    // the playbook directory otherwise
+
+    let path = Path::new(task_file);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let mut pb = PathBuf::new();
+    if run_state.context.read().unwrap().role.is_some() {
+        match are_handlers {
+            HandlerMode::NormalTasks => { pb.push("tasks"); },
+            HandlerMode::Handlers    => { pb.push("handlers"); },
+        };
+    }
+    pb.push(task_file);
+    pb
+}
+
+pub fn load_task_file(run_state: &Arc<RunState>, path: &Path, are_handlers: HandlerMode, depth: usize) -> Result<Vec<Task>, String> {
+
+    // parses a file containing a list of tasks, any imports inside of it are expanded as well
+
+    let task_fh = jet_file_open(path)?;
+    let parsed: Result<Vec<Task>, serde_yaml::Error> = serde_yaml::from_reader(task_fh);
+    if let Err(e) = parsed {
+        show_yaml_error_in_context(&e, path);
+        return Err("edit the file and try again?".to_string());
+    }
+    let tasks = expand_imports(run_state, parsed.unwrap(), are_handlers, depth)?;
+    if are_handlers == HandlerMode::Handlers {
+        validate_handlers(&tasks)?;
+    }
+    Ok(tasks)
+}
+
+pub fn expand_imports(run_state: &Arc<RunState>, tasks: Vec<Task>, are_handlers: HandlerMode, depth: usize) -> Result<Vec<Task>, String> {
+
+    // replaces each !import in a list of tasks with the tasks from the imported file
+
+    let mut results : Vec<Task> = Vec::new();
+    for task in tasks.into_iter() {
+        match task {
+            Task::Import(import) => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(format!("imports nested too deeply, at: {}", import.file));
+                }
+                let task_buf = find_task_file(run_state, &import.file, are_handlers);
+                results.extend(load_task_file(run_state, task_buf.as_path(), are_handlers, depth + 1)?);
+            },
+            _ => { results.push(task); }
+        }
+    }
+    Ok(results)
+}
+
 fn get_host_batches(run_state: &Arc<RunState>, play: &Play, hosts: Vec<Arc<RwLock<Host>>>) 
     -> (usize, usize, HashMap<usize, Vec<Arc<RwLock<Host>>>>) {
 
@@ -422,7 +634,7 @@ fn get_host_batches(run_state: &Arc<RunState>, play: &Play, hosts: Vec<Arc<RwLoc
 
 }
 
-fn get_play_hosts(run_state: &Arc<RunState>,play: &Play) -> Vec<Arc<RwLock<Host>>> {
+pub fn get_play_hosts(run_state: &Arc<RunState>,play: &Play) -> Vec<Arc<RwLock<Host>>> {
 
     // the hosts we want to talk to are the ones specified in the play but may
     // be further constrained by the parameters --limit-hosts and limit--groups
@@ -472,7 +684,7 @@ fn get_play_hosts(run_state: &Arc<RunState>,play: &Play) -> Vec<Arc<RwLock<Host>
     results.values().map(Arc::clone).collect()
 }
 
-fn validate_limit_groups(run_state: &Arc<RunState>, _play: &Play) -> Result<(), String> {
+pub fn validate_limit_groups(run_state: &Arc<RunState>, _play: &Play) -> Result<(), String> {
 
     // limit groups on the command line can't mention any groups that aren't in inventory
 
@@ -486,7 +698,7 @@ fn validate_limit_groups(run_state: &Arc<RunState>, _play: &Play) -> Result<(),
     Ok(())
 }
 
-fn validate_limit_hosts(run_state: &Arc<RunState>, _play: &Play) -> Result<(), String> {
+pub fn validate_limit_hosts(run_state: &Arc<RunState>, _play: &Play) -> Result<(), String> {
 
     // limit hosts on the command line can't mention any hosts that aren't in inventory
 
@@ -500,7 +712,7 @@ fn validate_limit_hosts(run_state: &Arc<RunState>, _play: &Play) -> Result<(), S
     Ok(())
 }
 
-fn validate_groups(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+pub fn validate_groups(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
 
     // groups on the play can't mention any groups that aren't in inventory
 
@@ -525,7 +737,51 @@ fn validate_hosts(_run_state: &Arc<RunState>, _play: &Play, hosts: &[Arc<RwLock<
     Ok(())
 }
 
-fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+fn prompt_for_vars(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+
+    // vars_prompt asks for values that don't belong in inventory, like a release version, before any
+    // tasks run.  anything already given with --extra-vars is not asked for again, and when nobody
+    // is at a terminal the default is used, or the play fails if there isn't one.
+
+    if play.vars_prompt.is_none() {
+        return Ok(());
+    }
+    let interactive = std::io::stdin().is_terminal();
+    let ctx = run_state.context.read().unwrap();
+    for item in play.vars_prompt.as_ref().unwrap().iter() {
+        if ctx.has_extra_var(&item.name) {
+            continue;
+        }
+        let private = item.private.unwrap_or(false);
+        let value = match (interactive, &item.default) {
+            (false, Some(default)) => default.clone(),
+            (false, None) => {
+                return Err(format!("vars_prompt: {} has no default and input is not a terminal, supply it with --extra-vars", item.name));
+            },
+            (true, default) => {
+                let question = item.prompt.clone().unwrap_or(item.name.clone());
+                let text = match (default, private) {
+                    (Some(d), false) => format!("{} [{}]: ", question, d),
+                    _ => format!("{}: ", question)
+                };
+                loop {
+                    let answer = prompt_line(&text, private)?;
+                    if !answer.is_empty() {
+                        break answer;
+                    }
+                    if let Some(d) = default {
+                        break d.clone();
+                    }
+                    println!("a value is required for {}", item.name);
+                }
+            }
+        };
+        ctx.vars_storage.write().unwrap().insert(serde_yaml::Value::String(item.name.clone()), serde_yaml::Value::String(value));
+    }
+    Ok(())
+}
+
+pub fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
 
     // the context object is fairly pervasive throughout the running of the program
     // and is (eventually) the gateway that template requests pass through, since
@@ -539,7 +795,7 @@ fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(),
     if play.vars.is_some() {
         // vars are inline variables that are loaded at maximum precedence
         let vars = play.vars.as_ref().unwrap();
-        blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(vars.clone()));
+        blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(vars.clone()), ctx.list_merge);
     }
 
     if play.vars_files.is_some() {
@@ -553,16 +809,38 @@ fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(),
                 show_yaml_error_in_context(&e, path);
                 return Err("edit the file and try again?".to_string());
             }
-            blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(parsed.unwrap()));
+            blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(parsed.unwrap()), ctx.list_merge);
         }
     }
 
     if play.defaults.is_some() {
         // defaults works like 'vars' but has the lowest precedence
         let defaults = play.defaults.as_ref().unwrap();
-        blend_variables(&mut ctx_defaults_storage, serde_yaml::Value::Mapping(defaults.clone()));
+        blend_variables(&mut ctx_defaults_storage, serde_yaml::Value::Mapping(defaults.clone()), ctx.list_merge);
     }
 
+    // the environment block is exported to remote commands, values are still templated per host when each command runs
+    let mut environment : Vec<(String,String)> = Vec::new();
+    if let Some(play_environment) = &play.environment {
+        for (k, v) in play_environment.iter() {
+            let key = match k {
+                serde_yaml::Value::String(x) => x.clone(),
+                _ => { return Err(format!("environment keys must be strings, found: {:?}", k)); }
+            };
+            if ! is_environment_name(&key) {
+                return Err(format!("invalid environment variable name: {}", key));
+            }
+            let value = match v {
+                serde_yaml::Value::String(x) => x.clone(),
+                serde_yaml::Value::Number(x) => x.to_string(),
+                serde_yaml::Value::Bool(x) => x.to_string(),
+                _ => { return Err(format!("environment variable {} must be a string, number, or boolean", key)); }
+            };
+            environment.push((key, value));
+        }
+    }
+    *ctx.environment_storage.write().unwrap() = environment;
+
     // these match expressions are just used to 'de-enum' the serde values so we can write to them
     match ctx_vars_storage {
         serde_yaml::Value::Mapping(x) => { *ctx.vars_storage.write().unwrap() = x },
@@ -576,9 +854,11 @@ fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(),
     Ok(())
 }
 
-fn find_role(run_state: &Arc<RunState>, _play: &Play, role_name: String) -> Result<(Role,PathBuf), String> {
+pub fn find_role(run_state: &Arc<RunState>, _play: &Play, role_name: String) -> Result<(Role,PathBuf), String> {
 
-    // when we need to find a role we look for it in the configured role paths
+    // when we need to find a role we look for it in the configured role paths.
+    // a role is either described by a role.yml file, or follows the directory convention
+    // of tasks/main.yml, handlers/main.yml, defaults/main.yml and vars/main.yml
 
     for path_buf in run_state.role_paths.read().unwrap().iter() {
 
@@ -587,10 +867,7 @@ fn find_role(run_state: &Arc<RunState>, _play: &Play, role_name: String) -> Resu
         let mut pb2 = pb.clone();
         pb2.push("role.yml");
 
-        // a role.yml file must exist in a directory once we find a directory with a matching
-        // name
-
-        if pb2.exists() {
+        let mut role = if pb2.exists() {
             let path = pb2.as_path();
             let role_file = jet_file_open(path)?;
 
@@ -601,12 +878,227 @@ fn find_role(run_state: &Arc<RunState>, _play: &Play, role_name: String) -> Resu
                 show_yaml_error_in_context(&e, path);
                 return Err("edit the file and try again?".to_string());
             }   
-            let role = parsed.unwrap();
-            
-            return Ok((role,pb));
+            parsed.unwrap()
         }
+        else if pb.join("tasks").join("main.yml").is_file() || pb.join("handlers").join("main.yml").is_file() {
+            // no role.yml, so the main.yml files are the entry points
+            Role {
+                name: role_name.clone(),
+                defaults: None,
+                vars: None,
+                tasks: match pb.join("tasks").join("main.yml").is_file() {
+                    true => Some(vec![String::from("main.yml")]), false => None
+                },
+                handlers: match pb.join("handlers").join("main.yml").is_file() {
+                    true => Some(vec![String::from("main.yml")]), false => None
+                }
+            }
+        }
+        else {
+            continue;
+        };
+
+        // variables in defaults/main.yml and vars/main.yml are loaded underneath anything 
+        // given inline in role.yml
+
+        let list_merge = run_state.context.read().unwrap().list_merge;
+        role.defaults = load_role_vars_dir(&pb, "defaults", &role.defaults, list_merge)?;
+        role.vars = load_role_vars_dir(&pb, "vars", &role.vars, list_merge)?;
+        return Ok((role,pb));
     }
     Err(format!("role not found: {}", role_name))
-}  
+}
 
+fn load_role_vars_dir(role_path: &Path, subdir: &str, inline: &Option<serde_yaml::Mapping>, list_merge: ListMerge) -> Result<Option<serde_yaml::Mapping>, String> {
 
+    let path = role_path.join(subdir).join("main.yml");
+    if ! path.is_file() {
+        return Ok(inline.clone());
+    }
+    let vars_file = jet_file_open(path.as_path())?;
+    let parsed: Result<serde_yaml::Mapping, serde_yaml::Error> = serde_yaml::from_reader(vars_file);
+    if let Err(e) = parsed {
+        show_yaml_error_in_context(&e, path.as_path());
+        return Err("edit the file and try again?".to_string());
+    }
+    let mut blended = serde_yaml::Value::Mapping(parsed.unwrap());
+    if let Some(inline_vars) = inline {
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(inline_vars.clone()), list_merge);
+    }
+    match blended {
+        serde_yaml::Value::Mapping(x) => Ok(Some(x)),
+        _ => panic!("unexpected, blend_variables produced a non-mapping (role vars)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::handle::handle::TaskHandle;
+
+    const PLAY: &str = "
+name: handlers
+groups: [ all ]
+handlers:
+  - !set
+    vars: { trail: '{{ trail }}-a' }
+    with: { subscribe: a }
+  - !set
+    vars: { trail: '{{ trail }}-b' }
+    with: { subscribe: b }
+  - !set
+    vars: { trail: '{{ trail }}-c' }
+    with: { subscribe: c }
+  - !set
+    vars: { trail: '{{ trail }}-d' }
+    with: { subscribe: d }
+  - !set
+    vars: { trail: '{{ trail }}-e' }
+    with: { listen: [ other, b ] }
+";
+
+    #[test]
+    fn test_handlers_run_once_in_definition_order_by_name_or_topic() {
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let run_state = Arc::clone(&handle.run_state);
+        let play: Play = serde_yaml::from_str(PLAY).unwrap();
+        let play_count = {
+            let mut ctx = run_state.context.write().unwrap();
+            ctx.set_play(&play);
+            ctx.set_targetted_hosts(&[Arc::clone(&handle.host)]);
+            ctx.play_count
+        };
+        let mut vars = serde_yaml::Mapping::new();
+        vars.insert(serde_yaml::Value::from("trail"), serde_yaml::Value::from(""));
+        handle.host.write().unwrap().update_variables(vars);
+        for signal in ["c", "a", "c", "b"] {
+            handle.host.write().unwrap().notify(play_count, signal);
+        }
+        process_handlers(&run_state, &play).unwrap();
+        let trail = handle.host.read().unwrap().get_blended_variables(ListMerge::Replace).get("trail").cloned();
+        assert_eq!(trail, Some(serde_yaml::Value::from("-a-b-c-e")));
+    }
+
+    #[test]
+    fn test_force_handlers_runs_notified_handlers_after_a_later_failure() {
+        let run = |force_handlers: bool| {
+            let (handle, _) = TaskHandle::scripted(Vec::new());
+            let run_state = Arc::clone(&handle.run_state);
+            let play: Play = serde_yaml::from_str(&format!("
+name: forced
+groups: [ all ]
+force_handlers: {}
+tasks:
+  - !shell
+    cmd: deploy
+    and: {{ notify: restart }}
+  - !fail
+    msg: broken
+handlers:
+  - !set
+    vars: {{ restarted: 'yes' }}
+    with: {{ subscribe: restart }}
+", force_handlers)).unwrap();
+            run_state.context.write().unwrap().set_play(&play);
+            let _ = handle_batch(&run_state, &play, &[Arc::clone(&handle.host)]);
+            let failed = run_state.context.read().unwrap().get_hosts_failed_count() == 1;
+            let restarted = handle.host.read().unwrap().get_blended_variables(ListMerge::Replace).get("restarted").cloned();
+            (failed, restarted)
+        };
+
+        // the host failed either way, the handler it was notified about only runs when forced
+        assert_eq!(run(true), (true, Some(serde_yaml::Value::from("yes"))));
+        assert_eq!(run(false), (true, None));
+    }
+
+    #[test]
+    fn test_imports_are_spliced_in_and_includes_run_when_reached() {
+        let directory = std::env::temp_dir().join(format!("jetp-import-include-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("imported.yml"), "- !set\n  vars: { trail: imported }\n").unwrap();
+        std::fs::write(directory.join("included.yml"), "- !set\n  vars: { trail: '{{ trail }}-included' }\n").unwrap();
+        std::fs::write(directory.join("loop.yml"), format!("- !import\n  file: {}\n", directory.join("loop.yml").display())).unwrap();
+
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let run_state = Arc::clone(&handle.run_state);
+        let mut play: Play = serde_yaml::from_str(&format!("
+name: imports
+groups: [ all ]
+tasks:
+  - !import
+    file: {dir}/imported.yml
+  - !include
+    file: {dir}/included.yml
+  - !include
+    file: {dir}/missing.yml
+    with: {{ condition: 'false' }}
+", dir = directory.display())).unwrap();
+        let tasks = play.tasks.take().unwrap();
+        play.tasks = Some(expand_imports(&run_state, tasks, HandlerMode::NormalTasks, 0).unwrap());
+        assert!(matches!(play.tasks.as_ref().unwrap()[0], Task::Set(_)));
+
+        // the skipped include never loads its file, which does not exist
+        run_state.context.write().unwrap().set_play(&play);
+        let result = handle_batch(&run_state, &play, &[Arc::clone(&handle.host)]);
+        assert!(result.is_ok(), "{:?}", result);
+        let trail = handle.host.read().unwrap().get_blended_variables(ListMerge::Replace).get("trail").cloned();
+        assert_eq!(trail, Some(serde_yaml::Value::from("imported-included")));
+
+        let looping: Vec<Task> = serde_yaml::from_str(&format!("- !import\n  file: {}/loop.yml\n", directory.display())).unwrap();
+        let result = expand_imports(&run_state, looping, HandlerMode::NormalTasks, 0);
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(result.unwrap_err().starts_with("imports nested too deeply"));
+    }
+
+    #[test]
+    fn test_include_applies_with_vars_and_refuses_other_with_keys() {
+        let directory = std::env::temp_dir().join(format!("jetp-include-with-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("inner.yml");
+        std::fs::write(&path, "- !set\n  vars: { seen: '{{ who }}-{{ what }}' }\n").unwrap();
+        let run = |with: &str| {
+            let (handle, _) = TaskHandle::scripted(Vec::new());
+            let run_state = Arc::clone(&handle.run_state);
+            let play: Play = serde_yaml::from_str(&format!("
+name: include
+groups: [ all ]
+tasks:
+  - !include
+    file: {}
+    vars: {{ what: file }}
+    with: {}
+", path.display(), with)).unwrap();
+            run_state.context.write().unwrap().set_play(&play);
+            let result = handle_batch(&run_state, &play, &[Arc::clone(&handle.host)]);
+            let seen = handle.host.read().unwrap().get_blended_variables(ListMerge::Replace).get("seen").cloned();
+            (result, seen)
+        };
+
+        let (result, seen) = run("{ vars: { who: with, what: with } }");
+        assert!(result.is_ok());
+        assert_eq!(seen, Some(serde_yaml::Value::from("with-file")));
+
+        let (result, seen) = run("{ sudo: root, no_log: 'true' }");
+        assert_eq!(result.unwrap_err(), format!("sudo, no_log not supported in 'with' on !include, only condition, tags, and vars are: {}", path.display()));
+        assert_eq!(seen, None);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_tags_inherit_and_skip_tags_win() {
+        let invocation: RoleInvocation = serde_yaml::from_str("{ role: web, tags: [ web ] }").unwrap();
+        let task: Task = serde_yaml::from_str("!echo { msg: hi, with: { tags: [ config, web ] } }").unwrap();
+        let tags = get_effective_tags(&task, Some(&invocation), &[String::from("setup")]);
+        assert_eq!(tags, vec![String::from("setup"), String::from("web"), String::from("config")]);
+
+        let list = |x: &[&str]| Some(x.iter().map(|y| y.to_string()).collect::<Vec<String>>());
+        assert!(check_tags(&None, &None, &tags));
+        assert!(check_tags(&list(&["setup"]), &None, &tags));
+        assert!(! check_tags(&list(&["db"]), &None, &tags));
+        assert!(! check_tags(&list(&["config"]), &list(&["setup"]), &tags));
+        assert!(check_tags(&None, &list(&["db"]), &tags));
+        assert!(! check_tags(&list(&["db"]), &None, &[]));
+    }
+
+}
@@ -20,7 +20,7 @@ use std::sync::Arc;
 use crate::tasks::*;
 use std::sync::RwLock;
 use crate::inventory::hosts::Host;
-use inline_colorization::{color_red,color_blue,color_green,color_cyan,color_reset,color_yellow};
+use crate::util::terminal::{color_red,color_blue,color_green,color_cyan,color_reset,color_yellow};
 use crate::connection::command::CommandResult;
 use crate::playbooks::traversal::HandlerMode;
 use std::fs::OpenOptions;
@@ -34,6 +34,8 @@ use std::env;
 // visitor contains various functions that are called from all over the program
 // to send feedback to the user and logs
 
+const TRACE_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
 #[derive(PartialEq)]
 pub enum CheckMode {
     Yes,
@@ -43,6 +45,9 @@ pub enum CheckMode {
 pub struct PlaybookVisitor {
     pub check_mode: CheckMode,
     pub logfile: Option<Arc<RwLock<File>>>,
+    pub tracefile: Option<Arc<RwLock<File>>>,
+    trace_path: Option<String>,
+    trace_max_bytes: u64,
     pub run_id: String,
     pub utc_start: DateTime<Utc>
 }
@@ -58,6 +63,8 @@ pub struct LogData {
     pub cmd_rc: Option<i32>,
     pub cmd_out: Option<String>,
     pub task_status: Option<String>,
+    pub changes: Option<Vec<String>>,
+    pub msg: Option<String>,
     pub host: Option<String>,
     pub summary: Option<serde_json::map::Map<String,serde_json::Value>>
 }
@@ -83,6 +90,9 @@ impl PlaybookVisitor {
         Self {
             check_mode,
             logfile,
+            tracefile: None,
+            trace_path: None,
+            trace_max_bytes: TRACE_LOG_MAX_BYTES,
             utc_start: Utc::now(),
             run_id: GUID::rand().to_string()
         }
@@ -101,13 +111,30 @@ impl PlaybookVisitor {
             cmd_rc: None,
             cmd_out: None,
             task_status: None,
+            changes: None,
+            msg: None,
             host: None,
             summary: None
         }
     }
 
+    // --trace-log, unlike JET_LOG, is asked for explicitly, so failing to open it is an error
+
+    pub fn set_trace_log(&mut self, path: &str) -> Result<(), String> {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(x) => {
+                self.tracefile = Some(Arc::new(RwLock::new(x)));
+                self.trace_path = Some(path.to_owned());
+                Ok(())
+            },
+            Err(y) => Err(format!("cannot open trace log {}: {}", path, y))
+        }
+    }
+
     pub fn log(&self, log: &LogData) {
 
+        self.trace(log);
+
         if self.logfile.is_none() {
             return;
         }
@@ -131,6 +158,8 @@ impl PlaybookVisitor {
         if log.cmd_rc.is_some()      { obj.insert(String::from("cmd_rc"),      json!(log.cmd_rc.unwrap()));        }
         if log.cmd_out.is_some()     { obj.insert(String::from("cmd_out"),     json!(log.cmd_out.clone().unwrap()));       }
         if log.task_status.is_some() { obj.insert(String::from("task_status"), json!(log.task_status.clone().unwrap()));   }
+        if log.changes.is_some()     { obj.insert(String::from("changes"),     json!(log.changes.clone().unwrap()));       }
+        if log.msg.is_some()         { obj.insert(String::from("msg"),         json!(log.msg.clone().unwrap()));           }
         if log.host.is_some()        { obj.insert(String::from("host"),        json!(log.host.clone().unwrap()));          }
         
         if log.summary.is_some()     { obj.insert(String::from("summary"),     json!(log.summary.clone().unwrap()));       }
@@ -150,6 +179,49 @@ impl PlaybookVisitor {
 
     }
 
+    // the trace gets everything log() does plus every command and its result, one event per line
+    // (with command output indented below it) so a run can be read back afterwards
+
+    pub fn trace(&self, log: &LogData) {
+
+        if self.tracefile.is_none() {
+            return;
+        }
+
+        let mut line = format!("{} {} {}", Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true), &self.run_id[..8], log.event);
+        if let Some(host) = &log.host       { line.push_str(&format!(" host={}", host)); }
+        if let Some(play) = &log.play       { line.push_str(&format!(" play={:?}", play)); }
+        if let Some(role) = &log.role       { line.push_str(&format!(" role={:?}", role)); }
+        if let Some(task) = &log.task       { line.push_str(&format!(" task={:?}", task)); }
+        if let Some(status) = &log.task_status { line.push_str(&format!(" status={}", status)); }
+        if let Some(changes) = &log.changes { line.push_str(&format!(" changes={}", changes.join(","))); }
+        if let Some(rc) = log.cmd_rc        { line.push_str(&format!(" rc={}", rc)); }
+        if let Some(msg) = &log.msg         { line.push_str(&format!(" msg={:?}", msg)); }
+        if let Some(cmd) = &log.cmd         { line.push_str(&format!("\n    cmd: {}", cmd)); }
+        if let Some(out) = &log.cmd_out {
+            for out_line in out.lines() {
+                line.push_str(&format!("\n    | {}", out_line));
+            }
+        }
+        if let Some(summary) = &log.summary {
+            line.push_str(&format!(" {}", serde_json::to_string(summary).unwrap_or_default()));
+        }
+
+        let mut f = self.tracefile.as_ref().unwrap().write().unwrap();
+        let _ = writeln!(f, "{}", line);
+
+        // once the trace is big enough it is moved to <path>.1, replacing the one before, and a new file is started
+        if f.metadata().is_ok_and(|x| x.len() >= self.trace_max_bytes) {
+            let path = self.trace_path.as_ref().unwrap();
+            let rotated = format!("{}.1", path);
+            if std::fs::rename(path, &rotated).is_ok() {
+                if let Ok(x) = OpenOptions::new().create(true).append(true).open(path) {
+                    *f = x;
+                }
+            }
+        }
+    }
+
     pub fn is_check_mode(&self) -> bool { 
         self.check_mode == CheckMode::Yes
     }
@@ -163,6 +235,11 @@ impl PlaybookVisitor {
         println!("{color_cyan}  ..... {} : {}{color_reset}", host.read().unwrap().name, message);
     }
 
+    // advisory messages from modules, these never change the outcome of a task
+    pub fn warning_host(&self, host: &Arc<RwLock<Host>>, message: &String) {
+        println!("{color_yellow}  ..... {} : warning: {}{color_reset}", host.read().unwrap().name, message);
+    }
+
     pub fn on_playbook_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
         let ctx = context.read().unwrap();
         let path = ctx.playbook_path.as_ref().unwrap();
@@ -209,6 +286,9 @@ impl PlaybookVisitor {
         println!("----------------------------------------------------------");
         println!();
         self.show_playbook_summary(context);
+        if context.read().unwrap().profile {
+            self.show_profile_report(context);
+        }
     }
 
     pub fn on_task_start(&self, context: &Arc<RwLock<PlaybookContext>>, is_handler: HandlerMode) {
@@ -271,6 +351,12 @@ impl PlaybookVisitor {
                     let changes2 : Vec<String> = task_response.changes.iter().map(|x| { format!("{:?}", x) }).collect();
                     let change_str = changes2.join(",");
                     println!("{color_blue}✓ {} => modified ({}){color_reset}", &host2.name, change_str);
+                    if context2.diff {
+                        // the values of no_log tasks were already redacted by is_modified_with_diff
+                        for d in task_response.diff.iter() {
+                            println!("{color_blue}  ..... {} | {}: {} → {}{color_reset}", &host2.name, d.name, d.before, d.after);
+                        }
+                    }
                     context2.increment_modified_for_host(&host2.name);
                 },
                 TaskStatus::IsExecuted =>  {
@@ -286,7 +372,7 @@ impl PlaybookVisitor {
                     context2.increment_matched_for_host(&host2.name);
                 }
                 TaskStatus::IsSkipped  =>  {
-                    println!("{color_yellow}✓ {} => skipped {color_reset}", &host2.name);
+                    println!("{}", get_skipped_line(&host2.name, &task_response.msg));
                     context2.increment_skipped_for_host(&host2.name);
                 }
                 TaskStatus::Failed => {
@@ -301,6 +387,12 @@ impl PlaybookVisitor {
         let mut log_entry = self.log_entry(&String::from("TASK_STATUS"), Arc::clone(context));
         log_entry.host = Some(host2.name.clone());
         log_entry.task_status = Some(format!("{:?}", &task_response.status));
+        if matches!(task_response.status, TaskStatus::IsSkipped) {
+            log_entry.msg = task_response.msg.clone();
+        }
+        if ! task_response.changes.is_empty() {
+            log_entry.changes = Some(task_response.changes.iter().map(|x| format!("{:?}", x)).collect());
+        }
         self.log(&log_entry);
 
     }
@@ -339,7 +431,7 @@ impl PlaybookVisitor {
                     context2.increment_matched_for_host(&host2.name);
                 }
                 TaskStatus::IsSkipped  =>  {
-                    println!("{color_yellow}✓ {} => skipped {color_reset}", &host2.name);
+                    println!("{}", get_skipped_line(&host2.name, &task_response.msg));
                     context2.increment_skipped_for_host(&host2.name);
                 }
                 TaskStatus::Failed => {
@@ -354,6 +446,9 @@ impl PlaybookVisitor {
         let mut log_entry = self.log_entry(&String::from("TASK_CHECK_STATUS"), Arc::clone(context));
         log_entry.host = Some(host2.name.clone());
         log_entry.task_status = Some(format!("{:?}", &task_response.status));
+        if matches!(task_response.status, TaskStatus::IsSkipped) {
+            log_entry.msg = task_response.msg.clone();
+        }
         self.log(&log_entry);
     }
 
@@ -362,10 +457,31 @@ impl PlaybookVisitor {
         println!("{color_blue}! {} => retrying ({} retries left) in {} seconds{color_reset}",host2.name,retries,delay);
     }
 
+    pub fn on_host_task_item_skipped(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, item: &serde_yaml::Value) {
+        let host2 = host.read().unwrap();
+        let label = match item {
+            serde_yaml::Value::String(x) => x.clone(),
+            _ => serde_json::to_string(item).unwrap_or_default()
+        };
+        println!("{color_blue}! {} => skipped item: {}{color_reset}", host2.name, label);
+    }
+
+    pub fn on_host_rollback(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, path: &str) {
+        let host2 = host.read().unwrap();
+        println!("{color_red}! {} => rolled back: {}{color_reset}", host2.name, path);
+        let mut log_entry = self.log_entry(&String::from("ROLLBACK"), Arc::clone(context));
+        log_entry.host = Some(host2.name.clone());
+        log_entry.msg = Some(path.to_owned());
+        self.log(&log_entry);
+    }
+
     pub fn on_host_task_failed(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
         let mut log_entry = self.log_entry(&String::from("TASK_FAILED"), Arc::clone(context));
         let host2 = host.read().unwrap();
-        if task_response.msg.is_some() {
+        if task_response.no_log {
+            // the message of a no_log task may carry the values it was hiding, and so may the command and its output
+            println!("{color_red}! {} => failed (no_log){color_reset}", host2.name);
+        } else if task_response.msg.is_some() {
             let msg = &task_response.msg;
             if task_response.command_result.is_some() {
                 {
@@ -387,22 +503,41 @@ impl PlaybookVisitor {
         }
 
         context.write().unwrap().increment_failed_for_host(&host2.name);
+        log_entry.msg = match task_response.no_log {
+            true => Some(String::from("(no_log)")),
+            false => task_response.msg.clone()
+        };
         log_entry.host = Some(host2.name.clone());
         log_entry.task_status = Some(format!("{:?}", &task_response.status));
         self.log(&log_entry);
     }
 
-    pub fn on_host_connect_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>) {
+    pub fn on_host_connect_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, unreachable: bool) {
         let host2 = host.read().unwrap();
-        context.write().unwrap().increment_failed_for_host(&host2.name);
-        println!("{color_red}! connection failed to host: {}{color_reset}", host2.name);
-        let mut log_entry = self.log_entry(&String::from("HOST_CONNECT_FAILED"), Arc::clone(context));
+        let (failures, limit) = {
+            let ctx = context.read().unwrap();
+            (ctx.get_connection_failures(host), ctx.get_unreachable_after())
+        };
+        let event = match unreachable {
+            true => {
+                println!("{color_red}! connection failed to host: {}, unreachable{color_reset}", host2.name);
+                "HOST_UNREACHABLE"
+            },
+            false => {
+                println!("{color_red}! connection failed to host: {} ({} of {}), skipping task{color_reset}", host2.name, failures, limit);
+                "HOST_CONNECT_FAILED"
+            }
+        };
+        let mut log_entry = self.log_entry(&String::from(event), Arc::clone(context));
         log_entry.host = Some(host2.name.clone());
         self.log(&log_entry);
     }
 
     pub fn get_exit_status(&self, context: &Arc<RwLock<PlaybookContext>>) -> i32 {
-        let failed_hosts = context.read().unwrap().get_hosts_failed_count();
+        let ctx = context.read().unwrap();
+        // a host that skipped tasks because it could not be connected to did not get them applied,
+        // even if it came back later, so that is a failed run as well
+        let failed_hosts = ctx.get_hosts_failed_count() + ctx.get_hosts_connection_failed_count();
         match failed_hosts {
             0 => 0,
             _ => 1
@@ -416,17 +551,65 @@ impl PlaybookVisitor {
         }
     }
 
-    pub fn on_command_run(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, cmd: &str) {
+    pub fn on_command_run(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, cmd: &str, cmd_out: &str) {
         let host2 = host.read().unwrap();
-        if context.read().unwrap().verbosity > 0 {
+        let (verbosity, explain) = {
+            let ctx = context.read().unwrap();
+            (ctx.verbosity, ctx.explain)
+        };
+        // with --explain the command is logged as COMMAND_EXPLAIN, which log() also traces
+        if explain && ! request.no_log {
+            // cmd_out is the command after screening and sudo wrapping, which is exactly what is sent to the host
+            println!("{color_blue}! {} => explain: {}{color_reset}", host2.name, &cmd_out);
+            let mut log_entry = self.log_entry(&String::from("COMMAND_EXPLAIN"), context.clone());
+            log_entry.host = Some(host2.name.clone());
+            log_entry.cmd = Some(cmd_out.to_owned());
+            self.log(&log_entry);
+            return;
+        }
+        let mut trace_entry = self.log_entry(&String::from("COMMAND"), context.clone());
+        trace_entry.host = Some(host2.name.clone());
+        trace_entry.cmd = Some(match request.no_log { true => String::from("(no_log)"), false => cmd_out.to_owned() });
+        self.trace(&trace_entry);
+        if request.no_log {
+            if verbosity > 0 || explain {
+                println!("{color_blue}! {} => exec: (no_log){color_reset}", host2.name);
+            }
+        } else if verbosity > 0 {
             println!("{color_blue}! {} => exec: {}", host2.name, &cmd);
         }
     }
 
-    pub fn on_command_ok(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>,) {
+    pub fn on_task_params(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, evaluated: &EvaluatedTask) {
+        if ! context.read().unwrap().debug_params {
+            return;
+        }
+        let host2 = host.read().unwrap();
+        let no_log = evaluated.with.as_ref().as_ref().is_some_and(|x| x.no_log);
+        if no_log {
+            println!("{color_blue}! {} => params: (no_log){color_reset}", host2.name);
+            return;
+        }
+        let _ctx2 = context.write().unwrap(); // lock for multi-line output
+        println!("{color_blue}! {} => params: {:#?}{color_reset}", host2.name, evaluated.action);
+    }
+
+    pub fn on_command_output_line(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, line: &str) {
+        if request.no_log {
+            return;
+        }
+        println!("{color_blue}  ..... {} | {}{color_reset}", host.read().unwrap().name, line);
+        let mut log_entry = self.log_entry(&String::from("COMMAND_OUTPUT"), context.clone());
+        log_entry.host = Some(host.read().unwrap().name.clone());
+        log_entry.cmd_out = Some(line.to_owned());
+        self.log(&log_entry);
+    }
+
+    pub fn on_command_ok(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>,) {
         let host2 = host.read().unwrap();
         let cmd_result = result.as_ref().as_ref().expect("missing command result");
-        if context.read().unwrap().verbosity > 2 {
+        self.trace_command_result(context, &host2.name, request, cmd_result);
+        if context.read().unwrap().verbosity > 2 && ! request.no_log {
             let _ctx2 = context.write().unwrap(); // lock for multi-line output
             println!("{color_blue}! {} ... command ok", host2.name);
             println!("    cmd: {}", cmd_result.cmd);           
@@ -435,10 +618,11 @@ impl PlaybookVisitor {
         }
     }
 
-    pub fn on_command_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>,) {
+    pub fn on_command_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, request: &Arc<TaskRequest>, result: &Arc<Option<CommandResult>>,) {
         let host2 = host.read().expect("context read");
         let cmd_result = result.as_ref().as_ref().expect("missing command result");
-        if context.read().unwrap().verbosity > 2 {
+        self.trace_command_result(context, &host2.name, request, cmd_result);
+        if context.read().unwrap().verbosity > 2 && ! request.no_log {
             let _ctx2 = context.write().unwrap(); // lock for multi-line output
             println!("{color_red}! {} ... command failed", host2.name);
             println!("    cmd: {}", cmd_result.cmd);
@@ -447,6 +631,19 @@ impl PlaybookVisitor {
         }
     }
 
+    fn trace_command_result(&self, context: &Arc<RwLock<PlaybookContext>>, host: &str, request: &Arc<TaskRequest>, cmd_result: &CommandResult) {
+        if self.tracefile.is_none() {
+            return;
+        }
+        let mut trace_entry = self.log_entry(&String::from("COMMAND_RESULT"), context.clone());
+        trace_entry.host = Some(host.to_owned());
+        trace_entry.cmd_rc = Some(cmd_result.rc);
+        if ! request.no_log {
+            trace_entry.cmd_out = Some(cmd_result.out.clone());
+        }
+        self.trace(&trace_entry);
+    }
+
     pub fn show_playbook_summary(&self, context: &Arc<RwLock<PlaybookContext>>) {
 
         let ctx = context.read().unwrap();
@@ -475,8 +672,10 @@ impl PlaybookVisitor {
         let unchanged_ct = action_ct - adjusted_ct;
         let failed_ct    = ctx.get_total_failed_count();
         let failed_hosts = ctx.get_hosts_failed_count();
+        let unreachable_ct = ctx.get_total_connection_failed_count();
+        let unreachable_hosts = ctx.get_hosts_unreachable_count();
 
-        let summary = match failed_hosts {
+        let summary = match failed_hosts + ctx.get_hosts_connection_failed_count() {
             0 => match adjusted_hosts {
                 0 => format!("{color_green}(✓) Perfect. All hosts matched policy.{color_reset}"),
                 _ => format!("{color_blue}(✓) Actions were applied.{color_reset}"),
@@ -501,6 +700,7 @@ impl PlaybookVisitor {
                           | Unchanged | {unchanged_ct} | {unchanged_hosts}\n\
                           | Changed | {adjusted_ct} | {adjusted_hosts}\n\
                           | Failed | {failed_ct} | {failed_hosts}\n\
+                          | Unreachable | {unreachable_ct} | {unreachable_hosts}\n\
                           |-|-|-");
 
         crate::util::terminal::markdown_print(&mode_table);
@@ -527,9 +727,119 @@ impl PlaybookVisitor {
         map.insert(String::from("adjusted_hosts"),  json!(adjusted_hosts));
         map.insert(String::from("failed_ct"),       json!(failed_ct));
         map.insert(String::from("failed_hosts"),    json!(failed_hosts));
+        map.insert(String::from("unreachable_ct"),  json!(unreachable_ct));
+        map.insert(String::from("unreachable_hosts"), json!(unreachable_hosts));
         log_entry.summary = Some(map.clone());
         self.log(&log_entry);
 
     }
 
+    // with --profile, show where the time went
+
+    pub fn show_profile_report(&self, context: &Arc<RwLock<PlaybookContext>>) {
+
+        let slowest = context.read().unwrap().get_slowest_tasks(20);
+        if slowest.is_empty() {
+            return;
+        }
+
+        let mut table = String::from("|:-|:-|:-|:-|\n\
+                                      | Slowest Tasks | Total | Slowest Host | Host Time \n\
+                                      | --- | --- | --- | --- |\n");
+        for (task, total, worst_host, worst_time) in slowest.iter() {
+            table.push_str(&format!("| {} | {:.2}s | {} | {:.2}s\n", task, total.as_secs_f64(), worst_host, worst_time.as_secs_f64()));
+        }
+        table.push_str("|-|-|-|-");
+
+        crate::util::terminal::markdown_print(&table);
+        println!();
+    }
+
+}
+
+// the same in and out of check mode, the reason is whatever the FSM put in msg, usually the condition
+
+fn get_skipped_line(host_name: &str, reason: &Option<String>) -> String {
+    match reason {
+        Some(reason) => format!("{color_yellow}✓ {} => skipped ({}){color_reset}", host_name, reason),
+        None => format!("{color_yellow}✓ {} => skipped {color_reset}", host_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle::handle::TaskHandle;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_trace_log_redacts_no_log_failures_and_rotates() {
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let context = &handle.run_state.context;
+        let path = env::temp_dir().join(format!("jet_trace_test_{}.log", std::process::id())).display().to_string();
+        let mut visitor = PlaybookVisitor::new(CheckMode::No);
+        visitor.set_trace_log(&path).unwrap();
+        let sudo = SudoDetails::none();
+
+        let failed = handle.response.is_failed(&TaskRequest::execute(&sudo, true), "bad password: s3cret");
+        visitor.on_host_task_failed(context, &failed, &handle.host);
+        let failed = handle.response.is_failed(&TaskRequest::execute(&sudo, false), "no such file: /etc/x");
+        visitor.on_host_task_failed(context, &failed, &handle.host);
+        let trace = std::fs::read_to_string(&path).unwrap();
+        assert!(! trace.contains("s3cret"));
+        assert!(trace.contains("msg=\"(no_log)\""));
+        assert!(trace.contains("no such file: /etc/x"));
+
+        visitor.trace_max_bytes = 1;
+        visitor.on_host_task_failed(context, &failed, &handle.host);
+        assert!(std::fs::read_to_string(format!("{}.1", path)).unwrap().contains("TASK_FAILED"));
+        visitor.on_host_task_failed(context, &failed, &handle.host);
+        assert!(std::fs::read_to_string(&path).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(format!("{}.1", path)).unwrap();
+    }
+
+    #[test]
+    fn test_explained_commands_are_traced_once() {
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let context = &handle.run_state.context;
+        let path = env::temp_dir().join(format!("jet_trace_explain_test_{}.log", std::process::id())).display().to_string();
+        let mut visitor = PlaybookVisitor::new(CheckMode::No);
+        visitor.set_trace_log(&path).unwrap();
+        let request = TaskRequest::execute(&SudoDetails::none(), false);
+
+        for explain in [false, true] {
+            context.write().unwrap().explain = explain;
+            visitor.on_command_run(context, &handle.host, &request, "uptime", "uptime");
+        }
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let events : Vec<&str> = trace.lines().filter_map(|x| x.split_whitespace().nth(2)).filter(|x| x.starts_with("COMMAND")).collect();
+        assert_eq!(events, vec!["COMMAND", "COMMAND_EXPLAIN"], "{}", trace);
+    }
+
+    #[test]
+    fn test_tasks_skipped_for_a_failed_connection_fail_the_run() {
+        let mut parser = crate::cli::parser::CliParser::new();
+        parser.unreachable_after = 3;
+        let context = Arc::new(RwLock::new(PlaybookContext::new(&parser)));
+        let host = Arc::new(RwLock::new(Host::new(&String::from("web1"))));
+        let visitor = PlaybookVisitor::new(CheckMode::No);
+        assert_eq!(visitor.get_exit_status(&context), 0);
+
+        // the host skips one task and then connects again, it is never unreachable
+        assert!(! context.write().unwrap().record_connection_failure(&host));
+        context.write().unwrap().record_connection_ok(&host);
+        assert_eq!(context.read().unwrap().get_hosts_unreachable_count(), 0);
+        assert_eq!(visitor.get_exit_status(&context), 1);
+    }
+
+    #[test]
+    fn test_skipped_line_shows_the_reason_when_there_is_one() {
+        let line = get_skipped_line("web1", &Some(String::from("condition was false: (eq role 'db')")));
+        assert!(line.contains("✓ web1 => skipped (condition was false: (eq role 'db'))"));
+        let line = get_skipped_line("web1", &None);
+        assert!(line.contains("✓ web1 => skipped "));
+        assert!(! line.contains('('));
+    }
 }
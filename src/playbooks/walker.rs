@@ -0,0 +1,164 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+use crate::playbooks::language::{Play,RoleInvocation};
+use crate::playbooks::traversal::{RunState,HandlerMode,MAX_INCLUDE_DEPTH,find_role,find_task_file,load_task_file,
+    get_effective_tags};
+use crate::registry::list::Task;
+use crate::util::io::path_as_string;
+use std::sync::Arc;
+use std::env;
+
+// --list-tasks and --syntax-check both go through the tasks of a play without running them, following roles
+// and untemplated includes the way a real run would.  the walk is here, what is done with each task is up to
+// the TaskWalker.
+
+pub struct WalkedTask<'a> {
+    pub task: &'a Task,
+    pub invocation: Option<&'a RoleInvocation>,
+    // tags from the role and any includes above the task, plus its own
+    pub tags: Vec<String>,
+    pub location: String,
+    pub depth: usize
+}
+
+pub trait TaskWalker {
+
+    // every task as it is reached, includes included.  returning false does not descend into an include.
+    fn visit(&mut self, walked: &WalkedTask) -> bool;
+
+    // a role or task file that could not be loaded, or includes nested too deeply.  an error stops the walk.
+    fn problem(&mut self, location: &str, error: String) -> Result<(), String>;
+
+}
+
+pub fn walk_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocation, modes: &[HandlerMode],
+    location: &str, walker: &mut dyn TaskWalker) -> Result<(), String> {
+
+    let (role, role_path) = match find_role(run_state, play, invocation.role.clone()) {
+        Ok(x) => x,
+        Err(e) => { return walker.problem(location, e); }
+    };
+
+    // find_task_file looks under tasks/ and handlers/ only while a role is set
+    run_state.context.write().unwrap().set_role(&role, invocation, &path_as_string(&role_path));
+    let role_location = format!("{}: role ({})", location, invocation.role);
+
+    let p1 = env::current_dir().expect("could not get current directory");
+    let previous = p1.as_path();
+    if let Err(e) = env::set_current_dir(&role_path) {
+        return walker.problem(&role_location, format!("could not chdir into role directory {:?}, {}", role_path, e));
+    }
+
+    let mut result = Ok(());
+    'modes: for are_handlers in modes.iter() {
+        let files = match are_handlers {
+            HandlerMode::NormalTasks => &role.tasks,
+            HandlerMode::Handlers    => &role.handlers
+        };
+        for task_file in files.iter().flatten() {
+            let file_location = format!("{}: {}", role_location, task_file);
+            let task_buf = find_task_file(run_state, task_file, *are_handlers);
+            result = match load_task_file(run_state, task_buf.as_path(), *are_handlers, 0) {
+                Ok(tasks) => walk_tasks(run_state, &tasks, *are_handlers, Some(invocation), &[], &file_location, 0, walker),
+                Err(e) => walker.problem(&file_location, e)
+            };
+            if result.is_err() {
+                break 'modes;
+            }
+        }
+    }
+    env::set_current_dir(previous).expect("could not restore previous directory");
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn walk_tasks(run_state: &Arc<RunState>, tasks: &[Task], are_handlers: HandlerMode, invocation: Option<&RoleInvocation>,
+    inherited_tags: &[String], location: &str, depth: usize, walker: &mut dyn TaskWalker) -> Result<(), String> {
+
+    for task in tasks.iter() {
+
+        let walked = WalkedTask {
+            task,
+            invocation,
+            tags: get_effective_tags(task, invocation, inherited_tags),
+            location: match task.get_name() {
+                Some(name) => format!("{}: task ({})", location, name),
+                None => format!("{}: task (!{})", location, task.get_module())
+            },
+            depth
+        };
+        let descend = walker.visit(&walked);
+
+        if let Task::Include(include) = task {
+            if ! descend || include.file.contains("{{") {
+                continue;
+            }
+            if depth >= MAX_INCLUDE_DEPTH {
+                walker.problem(&walked.location, format!("includes nested too deeply, at: {}", include.file))?;
+                continue;
+            }
+            let task_buf = find_task_file(run_state, &include.file, are_handlers);
+            match load_task_file(run_state, task_buf.as_path(), are_handlers, depth + 1) {
+                Ok(included) => {
+                    let include_location = format!("{}: {}", walked.location, include.file);
+                    walk_tasks(run_state, &included, are_handlers, invocation, &walked.tags, &include_location,
+                        depth + 1, walker)?;
+                },
+                Err(e) => { walker.problem(&walked.location, e)?; }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle::handle::TaskHandle;
+
+    struct Recorder {
+        depths: Vec<usize>,
+        problems: Vec<String>
+    }
+
+    impl TaskWalker for Recorder {
+        fn visit(&mut self, walked: &WalkedTask) -> bool {
+            self.depths.push(walked.depth);
+            true
+        }
+        fn problem(&mut self, location: &str, error: String) -> Result<(), String> {
+            self.problems.push(format!("{}: {}", location, error));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_include_of_itself_stops_at_the_depth_limit() {
+        let directory = std::env::temp_dir().join(format!("jetp-walker-include-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("loop.yml");
+        std::fs::write(&path, format!("- !include\n  file: {}\n", path.display())).unwrap();
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        let tasks = load_task_file(&handle.run_state, &path, HandlerMode::NormalTasks, 0).unwrap();
+        let mut recorder = Recorder { depths: Vec::new(), problems: Vec::new() };
+        let result = walk_tasks(&handle.run_state, &tasks, HandlerMode::NormalTasks, None, &[], "loop.yml", 0, &mut recorder);
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(recorder.depths, (0..=MAX_INCLUDE_DEPTH).collect::<Vec<usize>>());
+        assert_eq!(recorder.problems.len(), 1);
+        assert!(recorder.problems[0].contains("includes nested too deeply"), "{}", recorder.problems[0]);
+    }
+}
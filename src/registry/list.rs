@@ -31,42 +31,57 @@ use crate::modules::access::group::GroupTask;
 use crate::modules::access::user::UserTask;
 
 // commands
+use crate::modules::commands::async_status::AsyncStatusTask;
 use crate::modules::commands::external::ExternalTask;
+use crate::modules::commands::reboot::RebootTask;
 use crate::modules::commands::shell::ShellTask;
 
 // control
+use crate::modules::control::add_host::AddHostTask;
 use crate::modules::control::assert::AssertTask;
 use crate::modules::control::debug::DebugTask;
 use crate::modules::control::echo::EchoTask;
 use crate::modules::control::fail::FailTask;
 use crate::modules::control::facts::FactsTask;
+use crate::modules::control::import::ImportTask;
+use crate::modules::control::include::IncludeTask;
+use crate::modules::control::meta::MetaTask;
 use crate::modules::control::set::SetTask;
+use crate::modules::control::wait_for_connection::WaitForConnectionTask;
 
 // files
 use crate::modules::files::copy::CopyTask;
 use crate::modules::files::directory::DirectoryTask;
 use crate::modules::files::file::FileTask;
 use crate::modules::files::git::GitTask;
+use crate::modules::files::ini_file::IniFileTask;
 use crate::modules::files::stat::StatTask;
 use crate::modules::files::template::TemplateTask;
+use crate::modules::files::yaml_edit::YamlEditTask;
 
 // packages
 use crate::modules::packages::apt::AptTask;
+use crate::modules::packages::apt_repository::AptRepositoryTask;
 use crate::modules::packages::homebrew::HomebrewTask;
+use crate::modules::packages::package::PackageTask;
 use crate::modules::packages::pacman::PacmanTask;
 use crate::modules::packages::yum_dnf::YumDnfTask;
 use crate::modules::packages::zypper::ZypperTask;
 
 // services
 use crate::modules::services::sd_service::SystemdServiceTask;
+use crate::modules::services::service::ServiceTask;
 
 #[allow(non_camel_case_types)]
 #[derive(Deserialize,Debug)]
 #[serde(rename_all="lowercase")]
 pub enum Task {
     // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
+    Add_Host(AddHostTask),
     Apt(AptTask),
+    Apt_Repository(AptRepositoryTask),
     Assert(AssertTask),
+    Async_Status(AsyncStatusTask),
     Copy(CopyTask),
     Debug(DebugTask),
     Directory(DirectoryTask),
@@ -79,13 +94,22 @@ pub enum Task {
     Git(GitTask),
     Group(GroupTask),
     Homebrew(HomebrewTask),
+    Import(ImportTask),
+    Include(IncludeTask),
+    Ini_File(IniFileTask),
+    Meta(MetaTask),
+    Package(PackageTask),
     Pacman(PacmanTask),
+    Reboot(RebootTask),
     Sd_Service(SystemdServiceTask),
+    Service(ServiceTask),
     Set(SetTask),
     Shell(ShellTask),
     Stat(StatTask),
     Template(TemplateTask),
     User(UserTask),
+    Wait_For_Connection(WaitForConnectionTask),
+    Yaml_Edit(YamlEditTask),
     Yum(YumDnfTask),
     Zypper(ZypperTask),
 }
@@ -95,8 +119,11 @@ impl Task {
     pub fn get_module(&self) -> String {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         match self {
+            Task::Add_Host(x)   => x.get_module(),
             Task::Apt(x)        => x.get_module(),
+            Task::Apt_Repository(x) => x.get_module(),
             Task::Assert(x)     => x.get_module(),
+            Task::Async_Status(x) => x.get_module(),
             Task::Copy(x)       => x.get_module(),
             Task::Debug(x)      => x.get_module(),
             Task::Directory(x)  => x.get_module(),
@@ -109,13 +136,22 @@ impl Task {
             Task::Git(x)        => x.get_module(), 
             Task::Group(x)      => x.get_module(),
             Task::Homebrew(x)   => x.get_module(),
+            Task::Import(x)     => x.get_module(),
+            Task::Include(x)    => x.get_module(),
+            Task::Ini_File(x)   => x.get_module(),
+            Task::Meta(x)       => x.get_module(),
+            Task::Package(x)    => x.get_module(),
             Task::Pacman(x)     => x.get_module(),
+            Task::Reboot(x)     => x.get_module(),
             Task::Sd_Service(x) => x.get_module(),
+            Task::Service(x)    => x.get_module(),
             Task::Set(x)        => x.get_module(), 
             Task::Shell(x)      => x.get_module(), 
             Task::Stat(x)       => x.get_module(), 
             Task::Template(x)   => x.get_module(), 
             Task::User(x)       => x.get_module(),
+            Task::Wait_For_Connection(x) => x.get_module(),
+            Task::Yaml_Edit(x)  => x.get_module(),
             Task::Yum(x)        => x.get_module(),
             Task::Zypper(x)     => x.get_module(),
         }
@@ -124,8 +160,11 @@ impl Task {
     pub fn get_name(&self) -> Option<String> {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         match self {
+            Task::Add_Host(x)   => x.get_name(),
             Task::Apt(x)        => x.get_name(),
+            Task::Apt_Repository(x) => x.get_name(),
             Task::Assert(x)     => x.get_name(),
+            Task::Async_Status(x) => x.get_name(),
             Task::Copy(x)       => x.get_name(),
             Task::Debug(x)      => x.get_name(), 
             Task::Directory(x)  => x.get_name(),
@@ -138,13 +177,22 @@ impl Task {
             Task::Git(x)        => x.get_name(),
             Task::Group(x)      => x.get_name(),
             Task::Homebrew(x)   => x.get_name(),
+            Task::Import(x)     => x.get_name(),
+            Task::Include(x)    => x.get_name(),
+            Task::Ini_File(x)   => x.get_name(),
+            Task::Meta(x)       => x.get_name(),
+            Task::Package(x)    => x.get_name(),
             Task::Pacman(x)     => x.get_name(),
+            Task::Reboot(x)     => x.get_name(),
             Task::Sd_Service(x) => x.get_name(),
+            Task::Service(x)    => x.get_name(),
             Task::Set(x)        => x.get_name(),
             Task::Shell(x)      => x.get_name(), 
             Task::Stat(x)       => x.get_name(),
             Task::Template(x)   => x.get_name(), 
             Task::User(x)       => x.get_name(),
+            Task::Wait_For_Connection(x) => x.get_name(),
+            Task::Yaml_Edit(x)  => x.get_name(),
             Task::Yum(x)        => x.get_name(),
             Task::Zypper(x)     => x.get_name(),
         }
@@ -153,8 +201,11 @@ impl Task {
     pub fn get_with(&self) -> Option<PreLogicInput> {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         match self {
+            Task::Add_Host(x)   => x.get_with(),
             Task::Apt(x)        => x.get_with(),
+            Task::Apt_Repository(x) => x.get_with(),
             Task::Assert(x)     => x.get_with(),
+            Task::Async_Status(x) => x.get_with(),
             Task::Copy(x)       => x.get_with(),
             Task::Debug(x)      => x.get_with(), 
             Task::Directory(x)  => x.get_with(),
@@ -167,13 +218,22 @@ impl Task {
             Task::Git(x)        => x.get_with(), 
             Task::Group(x)      => x.get_with(),
             Task::Homebrew(x)   => x.get_with(),
+            Task::Import(x)     => x.get_with(),
+            Task::Include(x)    => x.get_with(),
+            Task::Ini_File(x)   => x.get_with(),
+            Task::Meta(x)       => x.get_with(),
+            Task::Package(x)    => x.get_with(),
             Task::Pacman(x)     => x.get_with(),
+            Task::Reboot(x)     => x.get_with(),
             Task::Sd_Service(x) => x.get_with(),
+            Task::Service(x)    => x.get_with(),
             Task::Set(x)        => x.get_with(),
             Task::Shell(x)      => x.get_with(), 
             Task::Stat(x)       => x.get_with(), 
             Task::Template(x)   => x.get_with(),
             Task::User(x)       => x.get_with(),
+            Task::Wait_For_Connection(x) => x.get_with(),
+            Task::Yaml_Edit(x)  => x.get_with(),
             Task::Yum(x)        => x.get_with(), 
             Task::Zypper(x)     => x.get_with(),
         }
@@ -182,8 +242,11 @@ impl Task {
     pub fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         match self {
+            Task::Add_Host(x)   => x.evaluate(handle, request, tm),
             Task::Apt(x)        => x.evaluate(handle, request, tm),
+            Task::Apt_Repository(x) => x.evaluate(handle, request, tm),
             Task::Assert(x)     => x.evaluate(handle, request, tm),
+            Task::Async_Status(x) => x.evaluate(handle, request, tm),
             Task::Copy(x)       => x.evaluate(handle, request, tm),
             Task::Debug(x)      => x.evaluate(handle, request, tm), 
             Task::Directory(x)  => x.evaluate(handle, request, tm), 
@@ -196,13 +259,22 @@ impl Task {
             Task::Git(x)        => x.evaluate(handle, request, tm),
             Task::Group(x)      => x.evaluate(handle, request, tm),
             Task::Homebrew(x)   => x.evaluate(handle, request, tm),
+            Task::Import(x)     => x.evaluate(handle, request, tm),
+            Task::Include(x)    => x.evaluate(handle, request, tm),
+            Task::Ini_File(x)   => x.evaluate(handle, request, tm),
+            Task::Meta(x)       => x.evaluate(handle, request, tm),
+            Task::Package(x)    => x.evaluate(handle, request, tm),
             Task::Pacman(x)     => x.evaluate(handle, request, tm),
+            Task::Reboot(x)     => x.evaluate(handle, request, tm),
             Task::Sd_Service(x) => x.evaluate(handle, request, tm),
+            Task::Service(x)    => x.evaluate(handle, request, tm),
             Task::Set(x)        => x.evaluate(handle, request, tm),
             Task::Shell(x)      => x.evaluate(handle, request, tm), 
             Task::Stat(x)       => x.evaluate(handle, request, tm),
             Task::Template(x)   => x.evaluate(handle, request, tm), 
             Task::User(x)       => x.evaluate(handle, request, tm),
+            Task::Wait_For_Connection(x) => x.evaluate(handle, request, tm),
+            Task::Yaml_Edit(x)  => x.evaluate(handle, request, tm),
             Task::Yum(x)        => x.evaluate(handle, request, tm), 
             Task::Zypper(x)     => x.evaluate(handle, request, tm), 
         }
@@ -14,11 +14,79 @@
 // You should have received a copy of the GNU General Public License
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use sha2::{Sha512, Digest};
+use sha2::{Sha256, Sha512, Digest};
+use std::io::Read;
+use std::path::Path;
+
+// copy and template always compare files with sha512, other algorithms are for checking against
+// checksums published somewhere else, which are often md5 or sha256
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ChecksumAlgo {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgo {
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "md5"    => Ok(ChecksumAlgo::Md5),
+            "sha1"   => Ok(ChecksumAlgo::Sha1),
+            "sha256" => Ok(ChecksumAlgo::Sha256),
+            "sha512" => Ok(ChecksumAlgo::Sha512),
+            _ => Err(format!("unknown checksum algorithm: {}, expected md5, sha1, sha256 or sha512", name))
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ChecksumAlgo::Md5    => "md5",
+            ChecksumAlgo::Sha1   => "sha1",
+            ChecksumAlgo::Sha256 => "sha256",
+            ChecksumAlgo::Sha512 => "sha512",
+        }
+    }
+}
 
 pub fn sha512(data: &String) -> String {
+    checksum(ChecksumAlgo::Sha512, data).expect("sha512 is always available")
+}
+
+// md5 and sha1 are only computed by the checksum commands in cmd_library, which work on files
+
+pub fn checksum(algo: ChecksumAlgo, data: &String) -> Result<String, String> {
+    match algo {
+        ChecksumAlgo::Sha256 => {
+            let mut hasher = Sha256::new();
+            hasher.update(data.as_bytes());
+            let result = hasher.finalize();
+            Ok(format!("{result:x}"))
+        },
+        ChecksumAlgo::Sha512 => {
+            let mut hasher = Sha512::new();
+            hasher.update(data.as_bytes());
+            let result = hasher.finalize();
+            Ok(format!("{result:x}"))
+        },
+        _ => Err(format!("{} checksums are only supported for files", algo.get_name()))
+    }
+}
+
+// for connections that can read the managed filesystem directly, reads in chunks so large files are fine
+
+pub fn sha512_file(path: &Path) -> Result<String, std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
     let mut hasher = Sha512::new();
-    hasher.update(data.as_bytes());
+    let mut chunk = vec![0u8; 1000000];
+    loop {
+        match file.read(&mut chunk)? {
+            0 => break,
+            n => hasher.update(&chunk[..n])
+        }
+    }
     let result = hasher.finalize();
-    format!("{result:x}")
+    Ok(format!("{result:x}"))
 }
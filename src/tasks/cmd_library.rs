@@ -20,6 +20,7 @@
 use crate::inventory::hosts::HostOSType;
 use crate::tasks::FileAttributesInput;
 use crate::tasks::files::Recurse;
+use crate::tasks::checksum::ChecksumAlgo;
 
 // **IMPORTANT**
 //
@@ -56,6 +57,23 @@ pub fn screen_general_input_strict(input: &str) -> Result<String,String> {
     Ok(input2.to_string())
 }
 
+// used instead of the strict check for parameters a task lists under 'trusted' in its 'with' block.
+// the value may contain characters that the strict check rejects (for passwords, JSON, etc) but
+// must still not be able to close the single quotes the command library wraps arguments in.
+// modules that build commands themselves and run them with run_unsafe get no further screening,
+// so they have to quote the value with shell_quote.
+
+pub fn screen_trusted_input(input: &str) -> Result<String,String> {
+    let input2 = input.trim();
+    let bad = ["'", "`", "\n"];
+    for invalid in bad.iter() {
+        if input2.contains(invalid) {
+            return Err(format!("illegal characters found in trusted value ('{}')", invalid));
+        }
+    }
+    Ok(input2.to_string())
+}
+
 // a slightly lighter version of checking, that allows = signs and such
 // this is applied across all commands executed by the system, not just per-parameter checks
 // unless run_unsafe is used internally. It is assumed that all inputs going into this command
@@ -91,22 +109,103 @@ pub fn get_mode_command(os_type: HostOSType, untrusted_path: &str) -> Result<Str
     }
 }
 
-pub fn get_sha512_command(os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+// all of these print the checksum first, followed by whitespace and the path
+
+fn get_checksum_tool(os_type: HostOSType, algo: ChecksumAlgo) -> &'static str {
+    match (os_type, algo) {
+        (HostOSType::Linux, ChecksumAlgo::Md5)    => "md5sum",
+        (HostOSType::Linux, ChecksumAlgo::Sha1)   => "sha1sum",
+        (HostOSType::Linux, ChecksumAlgo::Sha256) => "sha256sum",
+        (HostOSType::Linux, ChecksumAlgo::Sha512) => "sha512sum",
+        (HostOSType::MacOS, ChecksumAlgo::Md5)    => "md5 -r",
+        (HostOSType::MacOS, ChecksumAlgo::Sha1)   => "shasum -b -a 1",
+        (HostOSType::MacOS, ChecksumAlgo::Sha256) => "shasum -b -a 256",
+        (HostOSType::MacOS, ChecksumAlgo::Sha512) => "shasum -b -a 512",
+    }
+}
+
+pub fn get_checksum_command(os_type: HostOSType, algo: ChecksumAlgo, untrusted_path: &str) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    Ok(format!("{} '{}'", get_checksum_tool(os_type, algo), path))
+}
+
+// the same as above for several files in one command. Missing files produce an error line
+// instead of a checksum, so callers must not treat a non-zero return code as failure
+
+pub fn get_checksum_many_command(os_type: HostOSType, algo: ChecksumAlgo, untrusted_paths: &[String]) -> Result<String,String>  {
+    let mut paths : Vec<String> = Vec::new();
+    for untrusted_path in untrusted_paths.iter() {
+        paths.push(format!("'{}'", screen_path(untrusted_path)?));
+    }
+    Ok(format!("{} {}", get_checksum_tool(os_type, algo), paths.join(" ")))
+}
+
+// size in bytes and modification time, to nanoseconds where the filesystem keeps them
+
+pub fn get_size_mtime_command(os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    match os_type {
+        HostOSType::Linux => Ok(format!("stat --format '%s %.9Y' '{}'", path)),
+        HostOSType::MacOS => Ok(format!("stat -f '%z %Fm' '{}'", path)),
+    }
+}
+
+pub fn get_ownership_command(os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    match os_type {
+        HostOSType::Linux => Ok(format!("stat --format '%U %G' '{}'", path)),
+        HostOSType::MacOS => Ok(format!("stat -f '%Su %Sg' '{}'", path)),
+    }
+}
+
+pub fn get_numeric_ownership_command(os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    match os_type {
+        HostOSType::Linux => Ok(format!("stat --format '%u %g' '{}'", path)),
+        HostOSType::MacOS => Ok(format!("stat -f '%u %g' '{}'", path)),
+    }
+}
+
+// these print something and return 0 only if the user or group exists, names and numeric ids both work
+
+pub fn get_user_exists_command(_os_type: HostOSType, untrusted_user: &str) -> Result<String,String>  {
+    let user = screen_general_input_strict(untrusted_user)?;
+    Ok(format!("id -u '{}'", user))
+}
+
+pub fn get_group_exists_command(os_type: HostOSType, untrusted_group: &str) -> Result<String,String>  {
+    let group = screen_general_input_strict(untrusted_group)?;
+    match (os_type, is_numeric_id(&group)) {
+        (HostOSType::Linux, _)     => Ok(format!("getent group '{}'", group)),
+        (HostOSType::MacOS, false) => Ok(format!("dscl . -read '/Groups/{}' PrimaryGroupID", group)),
+        (HostOSType::MacOS, true)  => Ok(format!("dscacheutil -q group -a gid '{}'", group)),
+    }
+}
+
+pub fn is_numeric_id(input: &str) -> bool {
+    !input.is_empty() && input.chars().all(|c| c.is_ascii_digit())
+}
+
+// the file type is printed as "directory" on Linux and "Directory" on MacOS, compare case insensitively
+
+pub fn get_is_directory_command(os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
     let path = screen_path(untrusted_path)?;
     match os_type {
-        HostOSType::Linux => Ok(format!("sha512sum '{}'", path)),
-        HostOSType::MacOS => Ok(format!("shasum -b -a 512 '{}'", path)),
+        HostOSType::Linux => Ok(format!("stat --format '%F' '{}'", path)),
+        HostOSType::MacOS => Ok(format!("stat -f '%HT' '{}'", path)),
     }
 }
 
-pub fn get_ownership_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+// readlink fails on anything that is not a symlink, readlink -f then gives the final target of a chain of links
+
+pub fn get_read_link_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
     let path = screen_path(untrusted_path)?;
-    Ok(format!("ls -ld '{}'", path))
+    Ok(format!("readlink '{}'", path))
 }
 
-pub fn get_is_directory_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+pub fn get_resolve_link_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
     let path = screen_path(untrusted_path)?;
-    Ok(format!("ls -ld '{}'", path))
+    Ok(format!("readlink -f '{}'", path))
 }
 
 pub fn get_touch_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
@@ -119,6 +218,116 @@ pub fn get_create_directory_command(_os_type: HostOSType, untrusted_path: &str)
     Ok(format!("mkdir -p '{}'", path))
 }
 
+pub fn get_copy_file_command(_os_type: HostOSType, untrusted_src: &str, untrusted_dest: &str) -> Result<String,String>  {
+    let src = screen_path(untrusted_src)?;
+    let dest = screen_path(untrusted_dest)?;
+    Ok(format!("cp '{}' '{}'", src, dest))
+}
+
+// -p so that a restored file gets back its original mode and ownership along with its contents
+
+pub fn get_backup_file_command(_os_type: HostOSType, untrusted_src: &str, untrusted_dest: &str) -> Result<String,String>  {
+    let src = screen_path(untrusted_src)?;
+    let dest = screen_path(untrusted_dest)?;
+    Ok(format!("cp -p '{}' '{}'", src, dest))
+}
+
+// names are used unquoted in the env command, so only the usual shell variable names are allowed
+
+pub fn is_environment_name(name: &str) -> bool {
+    ! name.is_empty() && ! name.starts_with(|c: char| c.is_ascii_digit()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn get_environment_wrapped_command(_os_type: HostOSType, environment: &[(String,String)], cmd: &str) -> Result<String,String>  {
+    // the command runs in a child shell so the variables also reach every part of a compound command, and sudo
+    // wraps the whole thing rather than just the first word
+    let mut assignments : Vec<String> = Vec::new();
+    for (k, v) in environment.iter() {
+        if v.contains('\'') {
+            return Err(format!("value of environment variable {} may not contain single quotes", k));
+        }
+        assignments.push(format!("{}='{}'", k, v));
+    }
+    Ok(format!("env {} sh -c '{}'", assignments.join(" "), cmd.replace('\'', "'\\''")))
+}
+
+// the command is only screened by the shell module before this, the directory is quoted here and so may not
+// contain quotes itself
+
+pub fn get_chdir_wrapped_command(_os_type: HostOSType, untrusted_dir: &str, cmd: &str) -> Result<String,String>  {
+    let dir = screen_path(untrusted_dir)?;
+    if dir.contains('\'') {
+        return Err(format!("chdir may not contain single quotes: {}", dir));
+    }
+    Ok(wrap_script(&format!("cd '{}' && ( {}\n)", dir, cmd)))
+}
+
+// coreutils timeout stops the command with TERM, then KILL if it is still there a few seconds later, and exits
+// 124 either way.  MacOS does not have it.
+
+pub fn get_timeout_wrapped_command(os_type: HostOSType, seconds: u64, cmd: &str) -> Result<String,String>  {
+    match os_type {
+        HostOSType::Linux => Ok(format!("timeout -k 5 {} {}", seconds, wrap_script(cmd))),
+        HostOSType::MacOS => Err(String::from("timeout is not available on MacOS, use a timeout in the task's 'with' block instead"))
+    }
+}
+
+// the output is copied to a file on the host as it goes, and the command's own rc is passed out of the pipeline
+// on fd 3 since sh has no pipefail
+
+pub fn get_output_file_wrapped_command(_os_type: HostOSType, untrusted_path: &str, cmd: &str) -> Result<String,String>  {
+    let path = shell_quote(&screen_path(untrusted_path)?);
+    Ok(wrap_script(&format!(": > {} || exit 1; exec 4>&1; rc=$( {{ {{ ( {}\n) 2>&1; echo $? >&3; }} | tee {} >&4; }} 3>&1 ); exit $rc", path, cmd, path)))
+}
+
+// a file written through a temp file is a new inode, and whatever SELinux label it picked up under ~/.jet/tmp goes
+// with it through the rename.  before the rename the label and any extended ACL entries of the file being replaced
+// are copied onto the new one, the base entries are left out so the mode that was just set is kept.  each tool is
+// only used if it is installed, and macOS has neither.
+
+pub fn get_keep_security_attributes_command(os_type: HostOSType, untrusted_dest: &str, untrusted_staged: &str) -> Result<Option<String>,String> {
+    let dest = screen_path(untrusted_dest)?;
+    let staged = screen_path(untrusted_staged)?;
+    match os_type {
+        HostOSType::MacOS => Ok(None),
+        HostOSType::Linux => {
+            let script = format!("if command -v selinuxenabled >/dev/null 2>&1 && selinuxenabled; then chcon --reference='{dest}' '{staged}'; fi && \
+                if command -v getfacl >/dev/null 2>&1 && command -v setfacl >/dev/null 2>&1; then \
+                acl=$(getfacl --absolute-names --omit-header --skip-base '{dest}' | grep -v -e '^user::' -e '^group::' -e '^other::' -e '^mask::'); \
+                if [ -n \"$acl\" ]; then printf '%s\\n' \"$acl\" | setfacl -M - '{staged}'; fi; fi",
+                dest = dest, staged = staged);
+            Ok(Some(wrap_script(&script)))
+        }
+    }
+}
+
+// a file that did not exist before has nothing to copy from, so it gets the label the policy gives its path
+
+pub fn get_restore_security_context_command(os_type: HostOSType, untrusted_path: &str) -> Result<Option<String>,String> {
+    let path = screen_path(untrusted_path)?;
+    match os_type {
+        HostOSType::MacOS => Ok(None),
+        HostOSType::Linux => Ok(Some(wrap_script(&format!("if command -v selinuxenabled >/dev/null 2>&1 && selinuxenabled; then restorecon '{}'; fi", path))))
+    }
+}
+
+// LANG=C and sudo are put in front of every command, which only works if it starts with a simple command
+
+fn wrap_script(script: &str) -> String {
+    format!("sh -c {}", shell_quote(script))
+}
+
+// single quotes one argument for the shell, a quote inside it is closed, escaped and reopened
+
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+pub fn get_read_file_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    Ok(format!("cat '{}'", path))
+}
+
 pub fn get_delete_file_command(_os_type: HostOSType, untrusted_path: &str) -> Result<String,String>  {
     let path = screen_path(untrusted_path)?;
     Ok(format!("rm -f '{}'", path))
@@ -161,13 +370,274 @@ pub fn set_mode_command(_os_type: HostOSType, untrusted_path: &str, untrusted_mo
     }
 }
 
+// recursive versions of the three above that leave out anything matching the exclude patterns.  the patterns mean
+// the same as in is_path_excluded: with a / they match the path under the root, without one they match names.
+
+pub fn set_owner_excluding_command(_os_type: HostOSType, untrusted_path: &str, untrusted_owner: &str, exclude: &[String]) -> Result<String,String> {
+    let owner = screen_general_input_strict(untrusted_owner)?;
+    find_excluding(untrusted_path, exclude, &format!("-exec chown '{}' {{}} +", owner))
+}
+
+pub fn set_group_excluding_command(_os_type: HostOSType, untrusted_path: &str, untrusted_group: &str, exclude: &[String]) -> Result<String,String> {
+    let group = screen_general_input_strict(untrusted_group)?;
+    find_excluding(untrusted_path, exclude, &format!("-exec chgrp '{}' {{}} +", group))
+}
+
+pub fn set_mode_excluding_command(_os_type: HostOSType, untrusted_path: &str, untrusted_mode: &str, exclude: &[String]) -> Result<String,String> {
+    let mode = screen_mode(untrusted_mode)?;
+    find_excluding(untrusted_path, exclude, &format!("-exec chmod '{}' {{}} +", mode))
+}
+
+// the query side of the recursive commands, each prints the first path under the root that the matching set command
+// would change, or nothing.  links are passed over, chmod -R does not touch them and their own mode is always 777.
+// find's complaints about unreadable directories are dropped so they are not mistaken for a path.
+
+pub fn get_owner_drift_command(_os_type: HostOSType, untrusted_path: &str, untrusted_owner: &str, exclude: &[String]) -> Result<String,String> {
+    let owner = screen_general_input_strict(untrusted_owner)?;
+    let cmd = find_excluding(untrusted_path, exclude, &format!("! -type l ! -user '{}' -print -quit", owner))?;
+    Ok(format!("{} 2>/dev/null | head -n 1", cmd))
+}
+
+pub fn get_group_drift_command(_os_type: HostOSType, untrusted_path: &str, untrusted_group: &str, exclude: &[String]) -> Result<String,String> {
+    let group = screen_general_input_strict(untrusted_group)?;
+    let cmd = find_excluding(untrusted_path, exclude, &format!("! -type l ! -group '{}' -print -quit", group))?;
+    Ok(format!("{} 2>/dev/null | head -n 1", cmd))
+}
+
+pub fn get_mode_drift_command(_os_type: HostOSType, untrusted_path: &str, untrusted_mode: &str, exclude: &[String]) -> Result<String,String> {
+    let mode = screen_mode(untrusted_mode)?;
+    let cmd = find_excluding(untrusted_path, exclude, &format!("! -type l ! -perm '{}' -print -quit", mode))?;
+    Ok(format!("{} 2>/dev/null | head -n 1", cmd))
+}
+
+fn find_excluding(untrusted_path: &str, exclude: &[String], expression: &str) -> Result<String,String> {
+    let path = screen_path(untrusted_path)?;
+    let root = match path.trim_end_matches('/') {
+        "" => "/",
+        x => x
+    };
+    let mut tests : Vec<String> = Vec::new();
+    for untrusted_pattern in exclude.iter() {
+        // wildcards are passed through to find, everything else has to look like a path
+        screen_path(&untrusted_pattern.replace(['*', '?'], ""))?;
+        let pattern = untrusted_pattern.trim_end_matches('/');
+        match pattern.contains('/') {
+            true  => tests.push(format!("-path '{}/{}'", root, pattern.trim_start_matches('/'))),
+            false => tests.push(format!("-name '{}'", pattern))
+        }
+    }
+    match tests.is_empty() {
+        true  => Ok(format!("find '{}' {}", path, expression)),
+        // the root itself is never excluded, even if its own name matches
+        false => Ok(format!("find '{}' ! -path '{}' \\( {} \\) -prune -o {}", root, root, tests.join(" -o "), expression))
+    }
+}
+
 pub fn get_arch_command(os_type: HostOSType) -> Result<String, String> {
-    #[allow(clippy::match_single_binding)] // TODO: what was the intention of passing in os_type?
+    // both return names like x86_64, but Apple Silicon reports arm64 where Linux says aarch64
+    match os_type {
+        HostOSType::Linux => Ok(String::from("uname -m")),
+        HostOSType::MacOS => Ok(String::from("uname -m")),
+    }
+}
+
+pub fn get_boot_id_command(os_type: HostOSType) -> Result<String, String> {
+    // something that changes on every boot, so a reboot can be told apart from a host that never went down
+    match os_type {
+        HostOSType::Linux => Ok(String::from("cat /proc/sys/kernel/random/boot_id")),
+        HostOSType::MacOS => Ok(String::from("sysctl -n kern.boottime")),
+    }
+}
+
+pub fn get_reboot_command(_os_type: HostOSType) -> Result<String, String> {
+    // detached and slightly delayed so the command can return before sshd goes away
+    Ok(String::from("nohup sh -c 'sleep 2 && shutdown -r now' > /dev/null 2>&1 &"))
+}
+
+// async shell jobs keep their files in ~/.jet/async as <job>.pid, <job>.out and, once the command
+// is done, <job>.rc.  the rc file is renamed into place so it is never seen half written.
+// each command is a single sh -c so that, under sudo, all of it runs as the sudo user and ~ is that user's home
+
+fn screen_job_id(untrusted_job: &str) -> Result<String,String> {
+    match ! untrusted_job.is_empty() && untrusted_job.chars().all(|x| x.is_ascii_alphanumeric() || x == '-') {
+        true => Ok(untrusted_job.to_owned()),
+        false => Err(format!("invalid async job id: {}", untrusted_job))
+    }
+}
+
+pub fn get_async_start_command(_os_type: HostOSType, untrusted_job: &str, cmd: &str) -> Result<String, String> {
+    let job = screen_job_id(untrusted_job)?;
+    let script = format!("( {}\n) > \"$HOME/.jet/async/{job}.out\" 2>&1; echo $? > \"$HOME/.jet/async/{job}.rc.tmp\"; mv \"$HOME/.jet/async/{job}.rc.tmp\" \"$HOME/.jet/async/{job}.rc\"", cmd, job = job);
+    Ok(wrap_script(&format!("mkdir -p ~/.jet/async && chmod 0700 ~/.jet/async && {{ nohup {} < /dev/null > /dev/null 2>&1 & echo $! > ~/.jet/async/{}.pid; }}",
+        wrap_script(&script), job)))
+}
+
+pub fn get_async_status_command(_os_type: HostOSType, untrusted_job: &str) -> Result<String, String> {
+    // exits 2 for a job that was never started on this host, 3 while it is still running and 4 for one that
+    // is gone without leaving an rc, because it was killed or the host rebooted.  the rc is looked for again
+    // after kill -0 in case the job finished in between
+    let job = screen_job_id(untrusted_job)?;
+    Ok(wrap_script(&format!("test -f ~/.jet/async/{job}.pid || exit 2; \
+        if ! test -f ~/.jet/async/{job}.rc; then kill -0 \"$(cat ~/.jet/async/{job}.pid)\" 2>/dev/null && exit 3; test -f ~/.jet/async/{job}.rc || exit 4; fi; \
+        cat ~/.jet/async/{job}.rc", job = job)))
+}
+
+pub fn get_async_output_command(_os_type: HostOSType, untrusted_job: &str) -> Result<String, String> {
+    let job = screen_job_id(untrusted_job)?;
+    Ok(wrap_script(&format!("cat ~/.jet/async/{}.out", job)))
+}
+
+pub fn get_async_cleanup_command(_os_type: HostOSType, untrusted_job: &str) -> Result<String, String> {
+    let job = screen_job_id(untrusted_job)?;
+    Ok(wrap_script(&format!("rm -f ~/.jet/async/{job}.pid ~/.jet/async/{job}.out ~/.jet/async/{job}.rc", job = job)))
+}
+
+// services are systemd units on Linux and launchd jobs in the system domain on MacOS, see modules/services/common.rs
+// for how the output of the two query commands is read
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum ServiceCommand {
+    Start,
+    Stop,
+    Restart,
+    Enable,
+    Disable
+}
+
+fn screen_service(untrusted_service: &str) -> Result<String,String> {
+    let service = screen_general_input_strict(untrusted_service)?;
+    match service.is_empty() || service.contains(['\'', '"', ' ', '\t', '\n']) {
+        true => Err(format!("invalid service name: {}", untrusted_service)),
+        false => Ok(service)
+    }
+}
+
+pub fn get_service_is_active_command(os_type: HostOSType, untrusted_service: &str) -> Result<String,String> {
+    let service = screen_service(untrusted_service)?;
     match os_type {
-        _ => { Ok(String::from("uname -m")) },
+        HostOSType::Linux => Ok(format!("systemctl is-active '{}'", service)),
+        HostOSType::MacOS => Ok(format!("launchctl print 'system/{}'", service)),
+    }
+}
+
+pub fn get_service_is_enabled_command(os_type: HostOSType, untrusted_service: &str) -> Result<String,String> {
+    let service = screen_service(untrusted_service)?;
+    match os_type {
+        HostOSType::Linux => Ok(format!("systemctl is-enabled '{}'", service)),
+        // launchd only lists the jobs whose enablement was ever changed, anything else is enabled
+        HostOSType::MacOS => Ok(String::from("launchctl print-disabled system")),
+    }
+}
+
+pub fn get_service_command(os_type: HostOSType, untrusted_service: &str, command: ServiceCommand) -> Result<String,String> {
+    let service = screen_service(untrusted_service)?;
+    let cmd = match (os_type, command) {
+        (HostOSType::Linux, ServiceCommand::Start)   => format!("systemctl start '{}'", service),
+        (HostOSType::Linux, ServiceCommand::Stop)    => format!("systemctl stop '{}'", service),
+        (HostOSType::Linux, ServiceCommand::Restart) => format!("systemctl restart '{}'", service),
+        (HostOSType::Linux, ServiceCommand::Enable)  => format!("systemctl enable '{}'", service),
+        (HostOSType::Linux, ServiceCommand::Disable) => format!("systemctl disable '{}'", service),
+        (HostOSType::MacOS, ServiceCommand::Start)   => format!("launchctl kickstart 'system/{}'", service),
+        (HostOSType::MacOS, ServiceCommand::Stop)    => format!("launchctl kill SIGTERM 'system/{}'", service),
+        (HostOSType::MacOS, ServiceCommand::Restart) => format!("launchctl kickstart -k 'system/{}'", service),
+        (HostOSType::MacOS, ServiceCommand::Enable)  => format!("launchctl enable 'system/{}'", service),
+        (HostOSType::MacOS, ServiceCommand::Disable) => format!("launchctl disable 'system/{}'", service),
+    };
+    Ok(cmd)
+}
+
+// used by the generic package module, see modules/packages/package.rs for how the manager is chosen.
+// the rpm and dnf query formats need braces that the loose check refuses, so the query commands are
+// run with run_unsafe and depend on the screening here.
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Yum,
+    Brew
+}
+
+fn screen_package(untrusted_package: &str) -> Result<String,String> {
+    let package = screen_general_input_strict(untrusted_package)?;
+    match package.is_empty() || package.starts_with('-') || ! package.chars().all(|c| c.is_ascii_alphanumeric() || "@_.+-:~/".contains(c)) {
+        true => Err(format!("invalid package name or version: {}", untrusted_package)),
+        false => Ok(package)
+    }
+}
+
+// the version, if given, is part of the package argument in the way each manager expects it
+
+fn get_package_spec(manager: PackageManager, untrusted_package: &str, untrusted_version: Option<&str>) -> Result<String,String> {
+    let package = screen_package(untrusted_package)?;
+    match untrusted_version {
+        None => Ok(package),
+        Some(v) => {
+            let version = screen_package(v)?;
+            match manager {
+                PackageManager::Apt => Ok(format!("{}={}", package, version)),
+                PackageManager::Dnf | PackageManager::Yum => Ok(format!("{}-{}", package, version)),
+                PackageManager::Brew => Ok(format!("{}@{}", package, version)),
+            }
+        }
+    }
+}
+
+// a brew version is a separate formula (python@3.11), so that is what gets looked for.  rpm is asked about
+// the package and the versions are sorted out from its output.
+
+pub fn get_package_query_command(manager: PackageManager, untrusted_package: &str, untrusted_version: Option<&str>) -> Result<String,String> {
+    let package = screen_package(untrusted_package)?;
+    match manager {
+        PackageManager::Apt => Ok(format!("apt-cache policy '{}'", package)),
+        PackageManager::Dnf | PackageManager::Yum => Ok(format!("rpm -q --whatprovides --queryformat '%{{VERSION}} %{{RELEASE}}\\n' '{}'", package)),
+        PackageManager::Brew => Ok(format!("brew list --versions '{}'", get_package_spec(manager, untrusted_package, untrusted_version)?)),
     }
 }
 
+pub fn get_package_latest_command(manager: PackageManager, untrusted_package: &str) -> Result<String,String> {
+    let package = screen_package(untrusted_package)?;
+    match manager {
+        PackageManager::Apt => Ok(format!("apt-cache policy '{}'", package)),
+        PackageManager::Dnf => Ok(format!("dnf -q repoquery --latest-limit 1 --queryformat '%{{version}}' '{}'", package)),
+        PackageManager::Yum => Ok(format!("repoquery '{}' --queryformat '%{{version}}'", package)),
+        PackageManager::Brew => Ok(format!("brew info --json=v2 '{}'", package)),
+    }
+}
 
+pub fn get_package_install_command(manager: PackageManager, untrusted_package: &str, untrusted_version: Option<&str>) -> Result<String,String> {
+    let spec = get_package_spec(manager, untrusted_package, untrusted_version)?;
+    match manager {
+        PackageManager::Apt => Ok(format!("DEBIAN_FRONTEND=noninteractive apt-get install '{}' -qq", spec)),
+        PackageManager::Dnf => Ok(format!("dnf install '{}' -y", spec)),
+        PackageManager::Yum => Ok(format!("yum install '{}' -y", spec)),
+        PackageManager::Brew => Ok(format!("brew install '{}'", spec)),
+    }
+}
 
+// without a version this goes to the newest version, with one it moves to exactly that version, which
+// may be older than the installed one
 
+pub fn get_package_update_command(manager: PackageManager, untrusted_package: &str, untrusted_version: Option<&str>) -> Result<String,String> {
+    let spec = get_package_spec(manager, untrusted_package, untrusted_version)?;
+    match (manager, untrusted_version.is_some()) {
+        (PackageManager::Apt, false) => Ok(format!("DEBIAN_FRONTEND=noninteractive apt-get install '{}' --only-upgrade -qq", spec)),
+        (PackageManager::Apt, true)  => Ok(format!("DEBIAN_FRONTEND=noninteractive apt-get install '{}' --allow-downgrades -qq", spec)),
+        (PackageManager::Dnf, false) => Ok(format!("dnf update '{}' -y", spec)),
+        (PackageManager::Dnf, true)  => Ok(format!("dnf install '{}' -y", spec)),
+        (PackageManager::Yum, false) => Ok(format!("yum update '{}' -y", spec)),
+        (PackageManager::Yum, true)  => Ok(format!("yum install '{}' -y", spec)),
+        (PackageManager::Brew, false) => Ok(format!("brew upgrade '{}'", spec)),
+        (PackageManager::Brew, true)  => Ok(format!("brew install '{}'", spec)),
+    }
+}
+
+pub fn get_package_remove_command(manager: PackageManager, untrusted_package: &str) -> Result<String,String> {
+    let package = screen_package(untrusted_package)?;
+    match manager {
+        PackageManager::Apt => Ok(format!("DEBIAN_FRONTEND=noninteractive apt-get remove '{}' -qq", package)),
+        PackageManager::Dnf => Ok(format!("dnf remove '{}' -y", package)),
+        PackageManager::Yum => Ok(format!("yum remove '{}' -y", package)),
+        PackageManager::Brew => Ok(format!("brew uninstall '{}'", package)),
+    }
+}
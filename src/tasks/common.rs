@@ -45,7 +45,7 @@ pub trait IsTask : Send + Sync {
 
 }
 
-pub trait IsAction : Send + Sync {
+pub trait IsAction : Send + Sync + std::fmt::Debug {
 
     fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>>;
 }
@@ -24,6 +24,7 @@ use std::vec::Vec;
 #[derive(Eq,Hash,PartialEq,Clone,Copy,Debug)]
 pub enum Field {
     Branch,
+    Config,
     Content,
     Disable,
     Enable,
@@ -31,13 +32,18 @@ pub enum Field {
     Gid,
     Group,
     Groups,
+    Key,
     Mode,
     Owner,
     Restart,
     Shell,
+    Signature,
+    SparsePaths,
     Start,
     Stop,
+    Submodules,
     Uid,
+    Url,
     Users,
     Version,
 }
@@ -16,9 +16,13 @@
 
 use crate::handle::handle::TaskHandle;
 use crate::tasks::request::TaskRequest;
-use crate::tasks::response::TaskResponse;
+use crate::tasks::response::{TaskResponse,TaskStatus};
+use crate::tasks::fields::Field;
+use crate::util::diff::line_diff;
 use crate::tasks::TemplateMode;
 use std::sync::Arc;
+use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
 use serde::Deserialize;
 
 // this is storage behind all 'and' and 'with' statements in the program, which
@@ -29,7 +33,8 @@ use serde::Deserialize;
 pub struct FileAttributesInput {
     pub owner: Option<String>,
     pub group: Option<String>,
-    pub mode: Option<String>
+    pub mode: Option<String>,
+    pub allow_unknown_ids: Option<String>
 }
 
 #[derive(Deserialize,Debug)]
@@ -37,7 +42,21 @@ pub struct FileAttributesInput {
 pub struct FileAttributesEvaluated {
     pub owner: Option<String>,
     pub group: Option<String>,
-    pub mode: Option<String>
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub preserve_mode: bool,
+    #[serde(default)]
+    pub allow_unknown_ids: bool
+}
+
+// copy and template take either one src or a list of candidates where the first
+// one found is used, for instance to pick OS specific files by templating in facts
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(untagged)]
+pub enum SrcInput {
+    SrcString(String),
+    SrcList(Vec<String>),
 }
 
 #[derive(Deserialize,Debug,Copy,Clone,PartialEq)]
@@ -58,6 +77,19 @@ impl FileAttributesInput {
         }
     }
 
+    // turns 0o755, 0755 or 755 into "755", or returns None if it isn't a valid octal mode
+    pub fn normalize_mode(mode: &str) -> Option<String> {
+        let mode = mode.trim();
+        let digits = mode.strip_prefix("0o").unwrap_or(mode);
+        if digits.is_empty() || ! digits.chars().all(|c| c.is_digit(8)) {
+            return None;
+        }
+        match u32::from_str_radix(digits, 8) {
+            Ok(x) if x <= 0o7777 => Some(format!("{:o}", x)),
+            _ => None
+        }
+    }
+
     // given an octal string, like 0o755 or 755, return the numeric value
     /*
     fn octal_string_to_number(response: &Arc<Response>, request: &Arc<TaskRequest>, mode: &String) -> Result<i32,Arc<TaskResponse>> {
@@ -72,6 +104,15 @@ impl FileAttributesInput {
 
     // template **all** the fields in FileAttributesInput fields, checking values and returning errors as needed
     pub fn template(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<Self>) -> Result<Option<FileAttributesEvaluated>,Arc<TaskResponse>> {
+        Self::template_internal(handle, request, tm, input, false)
+    }
+
+    // the same, for copy and template, which also accept a mode of preserve
+    pub fn template_preserving(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<Self>) -> Result<Option<FileAttributesEvaluated>,Arc<TaskResponse>> {
+        Self::template_internal(handle, request, tm, input, true)
+    }
+
+    fn template_internal(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<Self>, allow_preserve: bool) -> Result<Option<FileAttributesEvaluated>,Arc<TaskResponse>> {
 
         if tm == TemplateMode::Off {
             return Ok(None);
@@ -82,43 +123,43 @@ impl FileAttributesInput {
         }
         
         let input2 = input.as_ref().unwrap();
-        let final_mode_value : Option<String>;
+        let mut final_mode_value : Option<String> = None;
+        let mut preserve_mode = false;
 
-        // owner & group is easy but mode is complex
-        // makes sure mode is octal and not accidentally enter decimal or hex or leave off the octal prefix
-        // as the input field is a YAML string unwanted conversion shouldn't happen but we want to be strict with other tools
-        // that might read the file and encourage users to use YAML-spec required input here even though YAML isn't doing
-        // the evaluation.
+        // owner & group is easy but mode is complex.  YAML reads an unquoted 0644 as the text "0644" here,
+        // so all of 0o644, 0644 and 644 are taken as octal and stored as "644", which is also what stat reports
+        // when checking for changes.  a variable is different: an unquoted 0o644 in vars is the integer 420 by
+        // the time it is rendered, and 644 is just as much a number, so a templated mode has to come out with
+        // its leading 0 or 0o.
+        // copy and template accept "preserve" to take the mode of the source file.
 
         if input2.mode.is_some()  { 
             let mode_input = input2.mode.as_ref().unwrap();
             let templated_mode_string = handle.template.string(request, tm, &String::from("mode"), mode_input)?;
-            if ! templated_mode_string.starts_with("0o") {
-                return Err(handle.response.is_failed(request, &format!("(a) field (mode) must have an octal-prefixed value of form 0o755, was {}", templated_mode_string)));
-            }
-
-            let octal_no_prefix = str::replace(&templated_mode_string, "0o", "");
-
-            // we may have gotten an 0oExampleJunkString which is still not neccessarily valid - so check if it's a number
-            // and return the value with the 0o stripped off, for easier use elsewhere
-            let decimal_mode = i32::from_str_radix(&octal_no_prefix, 8);
-            match decimal_mode {
-                Ok(_x) => { 
-                    final_mode_value = Some(octal_no_prefix);
-                },
-                Err(_y) => { 
-                    return Err(handle.response.is_failed(request, &format!("(b) field (mode) must have an octal-prefixed value of form 0o755, was {}", templated_mode_string)));
+            if templated_mode_string.trim() == "preserve" {
+                if ! allow_preserve {
+                    return Err(handle.response.is_failed(request, &String::from("field (mode): preserve is only supported by copy and template")));
                 }
-            };
-        } else {
-            // mode was left off in the automation content
-            final_mode_value = None;
+                preserve_mode = true;
+            } else if mode_input.contains("{{") && ! templated_mode_string.trim().starts_with('0') {
+                return Err(handle.response.is_failed(request, &format!("field (mode): a templated mode must render with a leading 0 or 0o, such as 0644, was {}; quote the variable so YAML does not turn it into a number", templated_mode_string)));
+            } else {
+                match Self::normalize_mode(&templated_mode_string) {
+                    Some(x) => { final_mode_value = Some(x); },
+                    None => { 
+                        return Err(handle.response.is_failed(request, &format!("field (mode) must be an octal value such as 0o755, 0755 or 755, or preserve, was {}", templated_mode_string)));
+                    }
+                }
+            }
         }
 
         Ok(Some(FileAttributesEvaluated {
             owner:         handle.template.string_option_no_spaces(request, tm, &String::from("owner"), &input2.owner)?,
             group:         handle.template.string_option_no_spaces(request, tm, &String::from("group"), &input2.group)?,
             mode:          final_mode_value,
+            preserve_mode,
+            // numeric ids are normally checked like names, this allows ones no user or group has yet
+            allow_unknown_ids: handle.template.boolean_option_default_false(request, tm, &String::from("allow_unknown_ids"), &input2.allow_unknown_ids)?
         }))
     }
 }
@@ -126,6 +167,46 @@ impl FileAttributesInput {
 
 impl FileAttributesEvaluated {
 
+    // fills in the mode from a local source file, like rsync -p, used by copy and template when asked to
+    // preserve the mode.  an explicit mode in attributes still wins.
+    pub fn with_source_mode(handle: &TaskHandle, request: &Arc<TaskRequest>, src: &Path, attributes: Option<Self>) -> Result<Option<Self>, Arc<TaskResponse>> {
+        if attributes.as_ref().is_some_and(|x| x.mode.is_some()) {
+            return Ok(attributes);
+        }
+        let mode = match std::fs::metadata(src) {
+            Ok(x) => format!("{:o}", x.permissions().mode() & 0o7777),
+            Err(e) => { return Err(handle.response.is_failed(request, &format!("unable to read mode of {}: {}", src.display(), e))); }
+        };
+        Ok(Some(match attributes {
+            Some(x) => Self { owner: x.owner, group: x.group, mode: Some(mode), preserve_mode: false, allow_unknown_ids: x.allow_unknown_ids },
+            None => Self { owner: None, group: None, mode: Some(mode), preserve_mode: false, allow_unknown_ids: false }
+        }))
+    }
+
+    // fills in the default mode from --default-file-mode, --default-dir-mode or the play when the task does not
+    // choose one itself, whether as a mode or as preserve
+    pub fn with_default_mode(handle: &TaskHandle, request: &Arc<TaskRequest>, default: &Option<String>, attributes: Option<Self>) -> Result<Option<Self>, Arc<TaskResponse>> {
+        let default = match default {
+            Some(x) => x,
+            None => { return Ok(attributes); }
+        };
+        if attributes.as_ref().is_some_and(|x| x.mode.is_some() || x.preserve_mode) {
+            return Ok(attributes);
+        }
+        let mode = match FileAttributesInput::normalize_mode(default) {
+            Some(x) => x,
+            None => { return Err(handle.response.is_failed(request, &format!("the default mode must be an octal value such as 0644, was {}", default))); }
+        };
+        Ok(Some(match attributes {
+            Some(x) => Self { owner: x.owner, group: x.group, mode: Some(mode), preserve_mode: false, allow_unknown_ids: x.allow_unknown_ids },
+            None => Self { owner: None, group: None, mode: Some(mode), preserve_mode: false, allow_unknown_ids: false }
+        }))
+    }
+
+    pub fn wants_source_mode(this: &Option<Self>) -> bool {
+        this.as_ref().is_some_and(|x| x.preserve_mode)
+    }
+
     // if the action has an evaluated Attributes section, the mode will be stored as an octal string like "777", but we need
     // an integer for some internal APIs like the SSH connection put requests.
 
@@ -149,3 +230,160 @@ impl FileAttributesEvaluated {
     */
 
 }
+
+// line_endings and trailing_newline on copy and template rewrite the content before it is checksummed, so a
+// file that only differs from src in how its lines end is fixed once and then left alone
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum LineEndings {
+    Unix,
+    Dos,
+    Keep
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct TextNormalization {
+    pub line_endings: LineEndings,
+    // true ends the content with exactly one line ending, false with none
+    pub trailing_newline: Option<bool>
+}
+
+impl TextNormalization {
+
+    pub fn template(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, line_endings: &Option<String>, trailing_newline: &Option<String>) -> Result<Self, Arc<TaskResponse>> {
+        let field = String::from("line_endings");
+        let value = handle.template.string_option_default(request, tm, &field, line_endings, "keep")?;
+        let line_endings = match value.as_str() {
+            "unix" => LineEndings::Unix,
+            "dos" => LineEndings::Dos,
+            "keep" => LineEndings::Keep,
+            _ if tm == TemplateMode::Off => LineEndings::Keep,
+            _ => { return Err(handle.response.is_failed(request, &format!("field {}: must be one of unix, dos, or keep, got: {}", field, value))); }
+        };
+        Ok(Self {
+            line_endings,
+            trailing_newline: handle.template.boolean_option_default_none(request, tm, &String::from("trailing_newline"), trailing_newline)?
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.line_endings != LineEndings::Keep || self.trailing_newline.is_some()
+    }
+
+    pub fn apply(&self, data: &str) -> String {
+        let mut result = match self.line_endings {
+            LineEndings::Unix => data.replace("\r\n", "\n"),
+            LineEndings::Dos => data.replace("\r\n", "\n").replace('\n', "\r\n"),
+            LineEndings::Keep => data.to_owned()
+        };
+        if let Some(trailing_newline) = self.trailing_newline {
+            let eol = match self.line_endings == LineEndings::Dos || (self.line_endings == LineEndings::Keep && result.contains("\r\n")) {
+                true => "\r\n",
+                false => "\n"
+            };
+            result.truncate(result.trim_end_matches(['\r','\n']).len());
+            if trailing_newline && ! result.is_empty() {
+                result.push_str(eol);
+            }
+        }
+        result
+    }
+}
+
+// with preview: true, copy and template report what their query found and stop there.  after is the content
+// the file would get, or None when that is not text.
+
+pub fn preview_file(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, dest: &str, result: &Arc<TaskResponse>, after: Option<String>) -> Result<(), Arc<TaskResponse>> {
+    let exists = match result.status {
+        TaskStatus::NeedsCreation => {
+            handle.debug(request, &format!("preview: {} would be created", dest));
+            false
+        },
+        TaskStatus::NeedsModification => {
+            let changes : Vec<String> = result.changes.iter().map(|x| format!("{:?}", x)).collect();
+            handle.debug(request, &format!("preview: {} would be modified ({})", dest, changes.join(",")));
+            if ! result.changes.contains(&Field::Content) {
+                return Ok(());
+            }
+            true
+        },
+        _ => {
+            handle.debug(request, &format!("preview: {} would not change", dest));
+            return Ok(());
+        }
+    };
+    if request.no_log {
+        handle.debug(request, &String::from("preview: content not shown, the task sets no_log"));
+        return Ok(());
+    }
+    let after = match after {
+        Some(x) => x,
+        None => {
+            handle.debug(request, &String::from("preview: content not shown, it is not text"));
+            return Ok(());
+        }
+    };
+    let before = match exists {
+        true => handle.remote.read_file(request, dest)?,
+        false => String::new()
+    };
+    for line in line_diff(&before, &after).iter() {
+        handle.debug(request, line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::request::SudoDetails;
+
+    #[test]
+    fn test_normalize_mode() {
+        for (input, expected) in [("0o755", "755"), ("0755", "755"), ("755", "755"), ("0o0644", "644"), ("04755", "4755"), ("0", "0")] {
+            assert_eq!(FileAttributesInput::normalize_mode(input), Some(String::from(expected)));
+        }
+        for input in ["", "0o", "789", "0x1ed", "rwxr-xr-x", "17777", "-644"] {
+            assert_eq!(FileAttributesInput::normalize_mode(input), None);
+        }
+    }
+
+    #[test]
+    fn test_templated_mode_needs_its_leading_zero() {
+        let (handle, _) = TaskHandle::scripted(Vec::new());
+        // YAML turns 0o644 into 420 and keeps 0644 as text
+        let vars : serde_yaml::Mapping = serde_yaml::from_str("int_mode: 0o644\ndecimal_mode: 644\nzero_mode: 0644\nquoted_mode: \"0o644\"").unwrap();
+        handle.host.write().unwrap().set_variables(vars);
+        let request = TaskRequest::query(&SudoDetails::none(), false);
+        let attributes = |mode: &str| Some(FileAttributesInput { owner: None, group: None, mode: Some(mode.to_owned()), allow_unknown_ids: None });
+        let mode = |mode: &str| FileAttributesInput::template(&handle, &request, TemplateMode::Strict, &attributes(mode)).map(|x| x.unwrap().mode.unwrap());
+
+        assert_eq!(mode("{{ quoted_mode }}").unwrap(), "644");
+        assert_eq!(mode("{{ zero_mode }}").unwrap(), "644");
+        assert_eq!(mode("644").unwrap(), "644");
+        assert!(mode("{{ int_mode }}").is_err());
+        assert!(mode("{{ decimal_mode }}").is_err());
+
+        // preserve only means something to copy and template
+        assert!(mode("preserve").is_err());
+        let preserved = FileAttributesInput::template_preserving(&handle, &request, TemplateMode::Strict, &attributes("preserve")).unwrap().unwrap();
+        assert!(preserved.preserve_mode);
+    }
+
+    #[test]
+    fn test_text_normalization() {
+        let normalize = |line_endings: LineEndings, trailing_newline: Option<bool>, data: &str| {
+            TextNormalization { line_endings, trailing_newline }.apply(data)
+        };
+        assert_eq!(normalize(LineEndings::Dos, None, "a\nb\r\nc"), "a\r\nb\r\nc");
+        assert_eq!(normalize(LineEndings::Unix, None, "a\r\nb\n"), "a\nb\n");
+        assert_eq!(normalize(LineEndings::Unix, Some(true), "a\r\nb\n\n\n"), "a\nb\n");
+        assert_eq!(normalize(LineEndings::Dos, Some(true), "a\nb"), "a\r\nb\r\n");
+        assert_eq!(normalize(LineEndings::Keep, Some(true), "a\r\nb"), "a\r\nb\r\n");
+        assert_eq!(normalize(LineEndings::Keep, Some(false), "a\nb\r\n\n"), "a\nb");
+        assert_eq!(normalize(LineEndings::Keep, Some(true), ""), "");
+        // normalizing twice changes nothing, which is what keeps the task from reporting changes forever
+        let once = normalize(LineEndings::Dos, Some(true), "a\nb\n\n");
+        assert_eq!(normalize(LineEndings::Dos, Some(true), &once), once);
+    }
+}
@@ -30,10 +30,16 @@ use crate::playbooks::templar::TemplateMode;
 pub struct PreLogicInput {
     pub condition: Option<String>,
     pub subscribe: Option<String>,
+    pub listen: Option<SignalInput>,
     pub sudo: Option<String>,
     pub items: Option<ItemsInput>,
     pub tags: Option<Vec<String>>,
-    pub delegate_to: Option<String>
+    pub delegate_to: Option<String>,
+    pub local_action: Option<String>,
+    pub no_log: Option<String>,
+    pub timeout: Option<String>,
+    pub trusted: Option<Vec<String>>,
+    pub vars: Option<serde_yaml::Mapping>
 }
 
 #[derive(Deserialize,Debug,Clone)]
@@ -47,27 +53,51 @@ pub enum ItemsInput {
 pub struct PreLogicEvaluated {
     pub condition: Option<String>, // this is not evaluated here
     pub subscribe: Option<String>,
+    pub listen: Vec<String>,
     pub sudo: Option<String>,
     pub items: Option<ItemsInput>,
     #[allow(dead_code)] // FIXME: remove if not needed
-    pub tags: Option<Vec<String>>
+    pub tags: Option<Vec<String>>,
+    pub no_log: bool,
+    // seconds any one command of the task may take, overriding --timeout
+    pub timeout: Option<u64>
 }
 
 #[derive(Deserialize,Debug)]
 #[serde(deny_unknown_fields)]
 pub struct PostLogicInput {
-    pub notify: Option<String>,
+    pub notify: Option<SignalInput>,
     pub ignore_errors: Option<String>,
     pub retry: Option<String>,
-    pub delay: Option<String>
+    pub delay: Option<String>,
+    pub rollback: Option<String>
+}
+
+// notify and listen take one handler name or topic, or a list of them
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(untagged)]
+pub enum SignalInput {
+    SignalString(String),
+    SignalList(Vec<String>),
+}
+
+impl SignalInput {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            SignalInput::SignalString(x) => vec![x.trim().to_string()],
+            SignalInput::SignalList(x) => x.iter().map(|y| y.trim().to_string()).collect()
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct PostLogicEvaluated {
-    pub notify: Option<String>,
+    pub notify: Vec<String>,
     pub ignore_errors: bool,
     pub retry: u64,
     pub delay: u64,
+    pub rollback: bool,
 }
 
 
@@ -82,8 +112,11 @@ impl PreLogicInput {
             condition: input2.condition.clone(),
             sudo: handle.template.string_option_no_spaces(request, tm, &String::from("sudo"), &input2.sudo)?,
             subscribe: handle.template.no_template_string_option_trim(&input2.subscribe),
+            listen: match &input2.listen { Some(x) => x.to_vec(), None => Vec::new() },
             items: input2.items.clone(),
-            tags: input2.tags.clone()
+            tags: input2.tags.clone(),
+            no_log: handle.template.boolean_option_default_false(request, tm, &String::from("no_log"), &input2.no_log)?,
+            timeout: handle.template.integer_option(request, tm, &String::from("timeout"), &input2.timeout, None)?
         }))
     }
 
@@ -97,15 +130,30 @@ impl PostLogicInput {
         }
         let input2 = input.as_ref().unwrap();
         Ok(Some(PostLogicEvaluated {
-            notify: handle.template.string_option_trim(request, tm, &String::from("notify"), &input2.notify)?,
+            notify: template_notify(handle, request, tm, &input2.notify)?,
             // unsafe here means the options cannot be sent to the shell, which they are not.
             delay:         handle.template.integer_option_to_integer(request, tm, &String::from("delay"), &input2.delay, 1)?,
             ignore_errors: handle.template.boolean_option_default_false(request, tm, &String::from("ignore_errors"), &input2.ignore_errors)?,
             retry:         handle.template.integer_option_to_integer(request, tm, &String::from("retry"), &input2.retry, 0)?,
+            rollback:      handle.template.boolean_option_default_false(request, tm, &String::from("rollback"), &input2.rollback)?,
         }))
     }
 }
 
+fn template_notify(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<SignalInput>) -> Result<Vec<String>,Arc<TaskResponse>> {
+    let names = match input {
+        None => return Ok(Vec::new()),
+        Some(x) => x.to_vec()
+    };
+    let mut results : Vec<String> = Vec::new();
+    for name in names.into_iter() {
+        if let Some(x) = handle.template.string_option_trim(request, tm, &String::from("notify"), &Some(name))? {
+            results.push(x);
+        }
+    }
+    Ok(results)
+}
+
 /* this is called from the task_fsm, not above */
 pub fn template_items(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, items_input: &Option<ItemsInput>) 
     -> Result<Vec<serde_yaml::Value>, Arc<TaskResponse>> {
@@ -29,5 +29,5 @@ pub use crate::tasks::logic::{PreLogicInput,PostLogicInput};
 pub use crate::handle::handle::{TaskHandle,CheckRc};
 pub use crate::tasks::response::{TaskResponse,TaskStatus};
 pub use crate::tasks::request::{TaskRequestType,TaskRequest};
-pub use crate::tasks::files::{FileAttributesInput,FileAttributesEvaluated};
+pub use crate::tasks::files::{FileAttributesInput,FileAttributesEvaluated,SrcInput};
 pub use crate::playbooks::templar::TemplateMode;
@@ -39,7 +39,8 @@ pub enum TaskRequestType {
 pub struct TaskRequest {
     pub request_type: TaskRequestType,
     pub changes: Vec<Field>,
-    pub sudo_details: Option<SudoDetails>
+    pub sudo_details: Option<SudoDetails>,
+    pub no_log: bool
 }
 
 #[derive(Debug,PartialEq,Clone)]
@@ -48,6 +49,16 @@ pub struct SudoDetails {
     pub template: String
 }
 
+impl SudoDetails {
+
+    // for tests, requests that run as the connecting user
+
+    #[cfg(test)]
+    pub fn none() -> Self {
+        Self { user: None, template: String::from("") }
+    }
+}
+
 // most of the various methods in task requests are constructors for different TaskRequest type variants
 // as used by task_fsm.rs. 
 
@@ -58,67 +69,74 @@ impl TaskRequest {
             Self { 
                 request_type: TaskRequestType::Validate, 
                 changes: Vec::new(),
-                sudo_details: None
+                sudo_details: None,
+                no_log: false
             }
         )
     }
 
-    pub fn query(sudo_details: &SudoDetails) -> Arc<Self> {
+    pub fn query(sudo_details: &SudoDetails, no_log: bool) -> Arc<Self> {
         Arc::new(
             Self { 
                 request_type: TaskRequestType::Query, 
                 changes: Vec::new(),
-                sudo_details: Some(sudo_details.clone())
+                sudo_details: Some(sudo_details.clone()),
+                no_log
             }
         )
     }
 
-    pub fn create(sudo_details: &SudoDetails) -> Arc<Self> {
+    pub fn create(sudo_details: &SudoDetails, no_log: bool) -> Arc<Self> {
         Arc::new(
             Self { 
                 request_type: TaskRequestType::Create, 
                 changes: Vec::new(),
-                sudo_details: Some(sudo_details.clone())
+                sudo_details: Some(sudo_details.clone()),
+                no_log
             }
         )
     }
 
-    pub fn remove(sudo_details: &SudoDetails) -> Arc<Self> {
+    pub fn remove(sudo_details: &SudoDetails, no_log: bool) -> Arc<Self> {
         Arc::new(
             Self { 
                 request_type: TaskRequestType::Remove, 
                 changes: Vec::new(),
-                sudo_details: Some(sudo_details.clone())
+                sudo_details: Some(sudo_details.clone()),
+                no_log
             }
         )
     }
 
-    pub fn modify(sudo_details: &SudoDetails, changes: Vec<Field>) -> Arc<Self> {
+    pub fn modify(sudo_details: &SudoDetails, no_log: bool, changes: Vec<Field>) -> Arc<Self> {
         Arc::new(
             Self { 
                 request_type: TaskRequestType::Modify, 
                 changes,
-                sudo_details: Some(sudo_details.clone())
+                sudo_details: Some(sudo_details.clone()),
+                no_log
             }
         )
     }
 
-    pub fn execute(sudo_details: &SudoDetails) -> Arc<Self> {
+    pub fn execute(sudo_details: &SudoDetails, no_log: bool) -> Arc<Self> {
         Arc::new(
             Self { 
                 request_type: TaskRequestType::Execute, 
                 changes: Vec::new(),
-                sudo_details: Some(sudo_details.clone())
+                sudo_details: Some(sudo_details.clone()),
+                no_log
             }
         )
     }
 
-    pub fn passive(sudo_details: &SudoDetails) -> Arc<Self> {
+    pub fn passive(sudo_details: &SudoDetails, no_log: bool) -> Arc<Self> {
         Arc::new(
             Self { 
                 request_type: TaskRequestType::Passive, 
                 changes: Vec::new(),
-                sudo_details: Some(sudo_details.clone())
+                sudo_details: Some(sudo_details.clone()),
+                no_log
             }
         )
     }
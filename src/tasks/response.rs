@@ -42,6 +42,16 @@ pub enum TaskStatus {
     Failed
 }
 
+// the before and after value of something a module changed that is not a file, for instance a service
+// going from disabled to enabled.  modules fill these in from their Modify leg if they want --diff to show them.
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct ValueDiff {
+    pub name: String,
+    pub before: String,
+    pub after: String
+}
+
 #[derive(Debug)]
 pub struct TaskResponse {
     pub status: TaskStatus,
@@ -51,7 +61,10 @@ pub struct TaskResponse {
     #[allow(dead_code)] // FIXME: remove if truly not needed
     pub with: Arc<Option<PreLogicEvaluated>>,
     #[allow(dead_code)] // FIXME: remove if truly not needed
-    pub and: Arc<Option<PostLogicEvaluated>>
+    pub and: Arc<Option<PostLogicEvaluated>>,
+    pub diff: Vec<ValueDiff>,
+    // copied from the request, so that the visitor can keep msg and command output of no_log tasks out of the logs
+    pub no_log: bool
 }
 
 //impl TaskResponse {
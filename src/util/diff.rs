@@ -0,0 +1,126 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// a small line diff for showing how a file would change, such as for 'preview' on copy and template.
+// lines are prefixed with "- " (removed), "+ " (added) or "  " (unchanged, shown only around changes) and
+// unchanged stretches in between are collapsed to "...".
+
+const CONTEXT_LINES: usize = 2;
+
+// past this many lines (times lines) in the part that differs, the lines are not matched up any further
+// and the whole part is shown as removed and then added
+const MAX_COMPARE: usize = 4_000_000;
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+enum Edit {
+    Same,
+    Removed,
+    Added
+}
+
+pub fn line_diff(before: &str, after: &str) -> Vec<String> {
+    let old : Vec<&str> = before.lines().collect();
+    let new : Vec<&str> = after.lines().collect();
+
+    // the common beginning and end are matched up front, which is most of a file in the usual case
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let old_middle = &old[prefix..old.len()-suffix];
+    let new_middle = &new[prefix..new.len()-suffix];
+
+    let mut edits : Vec<(Edit, &str)> = old[..prefix].iter().map(|x| (Edit::Same, *x)).collect();
+    edits.extend(diff_middle(old_middle, new_middle));
+    edits.extend(old[old.len()-suffix..].iter().map(|x| (Edit::Same, *x)));
+    render(&edits)
+}
+
+fn diff_middle<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Edit, &'a str)> {
+    let mut edits : Vec<(Edit, &str)> = Vec::new();
+    if old.len() * new.len() > MAX_COMPARE {
+        edits.extend(old.iter().map(|x| (Edit::Removed, *x)));
+        edits.extend(new.iter().map(|x| (Edit::Added, *x)));
+        return edits;
+    }
+    // longest common subsequence lengths of every pair of suffixes
+    let width = new.len() + 1;
+    let mut lengths = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = match old[i] == new[j] {
+                true => lengths[(i + 1) * width + j + 1] + 1,
+                false => lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            edits.push((Edit::Same, old[i]));
+            i += 1; j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            edits.push((Edit::Removed, old[i]));
+            i += 1;
+        } else {
+            edits.push((Edit::Added, new[j]));
+            j += 1;
+        }
+    }
+    edits.extend(old[i..].iter().map(|x| (Edit::Removed, *x)));
+    edits.extend(new[j..].iter().map(|x| (Edit::Added, *x)));
+    edits
+}
+
+fn render(edits: &[(Edit, &str)]) -> Vec<String> {
+    let changed : Vec<usize> = edits.iter().enumerate().filter(|(_, (e, _))| *e != Edit::Same).map(|(i, _)| i).collect();
+    let near_change = |i: usize| changed.iter().any(|c| c.abs_diff(i) <= CONTEXT_LINES);
+    let mut lines : Vec<String> = Vec::new();
+    let mut skipped = false;
+    for (i, (edit, text)) in edits.iter().enumerate() {
+        match edit {
+            Edit::Removed => lines.push(format!("- {}", text)),
+            Edit::Added => lines.push(format!("+ {}", text)),
+            Edit::Same if near_change(i) => lines.push(format!("  {}", text)),
+            Edit::Same => {
+                if ! skipped && ! lines.is_empty() {
+                    lines.push(String::from("..."));
+                }
+                skipped = true;
+                continue;
+            }
+        }
+        skipped = false;
+    }
+    if lines.last().is_some_and(|x| x == "...") {
+        lines.pop();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff_shows_changes_with_context() {
+        let before = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        let after = "a\nb\nc\nD\ne\nf\ng\nh\ni\nj\n";
+        assert_eq!(line_diff(before, after), vec![
+            "  b", "  c", "- d", "+ D", "  e", "  f", "...", "  h", "  i", "+ j"
+        ]);
+        assert!(line_diff(before, before).is_empty());
+        assert_eq!(line_diff("", "new\n"), vec!["+ new"]);
+    }
+}
@@ -93,3 +93,120 @@ pub fn is_executable(path: &Path) -> bool {
     }
     true
 }
+
+// does a source path use wildcards, as in files/*.conf
+pub fn has_wildcards(path: &str) -> bool {
+    path.contains('*') || path.contains('?')
+}
+
+// matches a single file name against a pattern where * is any run of characters and ? is any one character
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p : Vec<char> = pattern.chars().collect();
+    let n : Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // where the last * was seen, and how far into the name it had matched
+    let mut star : Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((star_pi, star_ni)) = star {
+            pi = star_pi + 1;
+            ni = star_ni + 1;
+            star = Some((star_pi, star_ni + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+// exclude patterns for recursive walks, in the spirit of .gitignore lines.  a pattern containing a / is matched
+// against the whole path relative to the root of the walk, one without is matched against each name at any depth.
+pub fn is_path_excluded(relative: &Path, patterns: &[String]) -> bool {
+    let relative_str = relative.to_string_lossy().to_string();
+    let name = match relative.file_name() {
+        Some(x) => x.to_string_lossy().to_string(),
+        None => relative_str.clone()
+    };
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_end_matches('/');
+        match pattern.contains('/') {
+            true => wildcard_match(pattern.trim_start_matches('/'), &relative_str),
+            false => wildcard_match(pattern, &name)
+        }
+    })
+}
+
+// like path_walk but descends into subdirectories, giving with_each_path each path along with the same path
+// relative to the root.  anything matching an exclude pattern is skipped along with everything under it, and
+// symlinks to directories are not followed.
+pub fn path_walk_recursive<F>(root: &Path, exclude: &[String], mut with_each_path: F) -> Result<(), String>
+   where F: FnMut(&Path, &Path) -> Result<(), String> {
+    path_walk_recursive_inner(root, Path::new(""), exclude, &mut with_each_path)
+}
+
+fn path_walk_recursive_inner<F>(root: &Path, relative: &Path, exclude: &[String], with_each_path: &mut F) -> Result<(), String>
+   where F: FnMut(&Path, &Path) -> Result<(), String> {
+    let directory = root.join(relative);
+    let mut entries : Vec<fs::DirEntry> = Vec::new();
+    for entry in jet_read_dir(&directory)? {
+        entries.push(entry.map_err(|_x| format!("failed to read directory: {}", directory.display()))?);
+    }
+    entries.sort_by_key(|x| x.file_name());
+    for entry in entries.iter() {
+        let entry_relative = relative.join(entry.file_name());
+        if is_path_excluded(&entry_relative, exclude) {
+            continue;
+        }
+        with_each_path(&entry.path(), &entry_relative)?;
+        if entry.file_type().is_ok_and(|x| x.is_dir()) {
+            path_walk_recursive_inner(root, &entry_relative, exclude, with_each_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.conf", "a.conf"));
+        assert!(wildcard_match("*.conf", ".conf"));
+        assert!(!wildcard_match("*.conf", "a.conf.bak"));
+        assert!(wildcard_match("a?c*", "abcdef"));
+        assert!(!wildcard_match("a?c", "ac"));
+        assert!(wildcard_match("*a*b", "xxaxxb"));
+        assert!(!wildcard_match("*a*b", "xxaxxbc"));
+        assert!(wildcard_match("*", "anything"));
+    }
+
+    #[test]
+    fn test_path_walk_recursive_with_exclusions() {
+        let root = std::env::temp_dir().join(format!("jet-walk-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in [".git/objects", "src/cache", "cache", "node_modules/x"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [".git/config", "src/main.rs", "src/cache/a", "cache/b", "node_modules/x/y", "top.pyc"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let exclude = vec![String::from(".git"), String::from("node_modules/"), String::from("/cache"), String::from("*.pyc")];
+        let mut found : Vec<String> = Vec::new();
+        path_walk_recursive(&root, &exclude, |_path, relative| {
+            found.push(relative.display().to_string());
+            Ok(())
+        }).unwrap();
+        let _ = fs::remove_dir_all(&root);
+        // /cache only matches at the root, so src/cache is still walked
+        assert_eq!(found, vec!["src", "src/cache", "src/cache/a", "src/main.rs"]);
+    }
+}
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod diff;
 pub mod io;
 pub mod yaml;
 pub mod terminal;
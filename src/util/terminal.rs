@@ -14,8 +14,99 @@
 // You should have received a copy of the GNU General Public License
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::io::{self,Write,IsTerminal};
+use std::process::{Command,Stdio};
+use std::sync::OnceLock;
+use std::fmt;
+use std::env;
+use std::ffi::OsString;
+
+// output is colored only on a terminal, and not even then if $NO_COLOR is set, unless --no-color or
+// --force-color decide it either way.  the answer is worked out once, by main after the CLI is parsed,
+// and anything printed before that falls back to the automatic choice
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never
+}
+
+static COLORS: OnceLock<bool> = OnceLock::new();
+
+pub fn init_colors(mode: ColorMode) {
+    let _ = COLORS.set(should_color(mode, env::var_os("NO_COLOR"), io::stdout().is_terminal()));
+}
+
+pub fn colors_enabled() -> bool {
+    *COLORS.get_or_init(|| should_color(ColorMode::Auto, env::var_os("NO_COLOR"), io::stdout().is_terminal()))
+}
+
+fn should_color(mode: ColorMode, no_color: Option<OsString>, is_terminal: bool) -> bool {
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => no_color.is_none_or(|x| x.is_empty()) && is_terminal
+    }
+}
+
+// stand-ins for the inline_colorization constants, used the same way ("{color_red}...{color_reset}"),
+// that print nothing while colors are disabled
+
+pub struct Color(&'static str);
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match colors_enabled() {
+            true => f.write_str(self.0),
+            false => Ok(())
+        }
+    }
+}
+
+#[allow(non_upper_case_globals)]
+pub static color_red: Color = Color(inline_colorization::color_red);
+#[allow(non_upper_case_globals)]
+pub static color_green: Color = Color(inline_colorization::color_green);
+#[allow(non_upper_case_globals)]
+pub static color_yellow: Color = Color(inline_colorization::color_yellow);
+#[allow(non_upper_case_globals)]
+pub static color_blue: Color = Color(inline_colorization::color_blue);
+#[allow(non_upper_case_globals)]
+pub static color_cyan: Color = Color(inline_colorization::color_cyan);
+#[allow(non_upper_case_globals)]
+pub static color_reset: Color = Color(inline_colorization::color_reset);
+
+// reads one line from the user, with echo turned off for private values such as passwords
+
+pub fn prompt_line(prompt: &String, private: bool) -> Result<String, String> {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+    if private {
+        set_echo(false);
+    }
+    let mut value = String::new();
+    let result = io::stdin().read_line(&mut value);
+    if private {
+        set_echo(true);
+        println!();
+    }
+    match result {
+        Ok(_) => Ok(String::from(value.trim_end_matches(['\r', '\n']))),
+        Err(e) => Err(format!("failure reading input: {}", e))
+    }
+}
+
+fn set_echo(on: bool) {
+    let flag = match on { true => "echo", false => "-echo" };
+    let _ = Command::new("stty").arg(flag).stdin(Stdio::inherit()).status();
+}
+
 pub fn markdown_print(markdown: &str) {
-    termimad::print_text(markdown);
+    match colors_enabled() {
+        true => termimad::print_text(markdown),
+        false => termimad::MadSkin::no_style().print_text(markdown)
+    }
 }
 
 pub fn banner(msg: &String) {
@@ -48,3 +139,24 @@ pub fn captioned_display(caption: &String, body: &str) {
     }
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_flags_override_no_color_and_the_terminal_check() {
+        let set = || Some(OsString::from("1"));
+        let empty = || Some(OsString::from(""));
+
+        // --force-color and --no-color win regardless of the environment
+        assert!(should_color(ColorMode::Always, set(), false));
+        assert!(!should_color(ColorMode::Never, None, true));
+
+        // otherwise only an interactive terminal without a non-empty $NO_COLOR gets colors
+        assert!(should_color(ColorMode::Auto, None, true));
+        assert!(should_color(ColorMode::Auto, empty(), true));
+        assert!(!should_color(ColorMode::Auto, set(), true));
+        assert!(!should_color(ColorMode::Auto, None, false));
+    }
+}
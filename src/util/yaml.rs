@@ -88,7 +88,48 @@ pub fn show_yaml_error_in_context(yaml_error: &serde_yaml::Error, path: &Path) {
 
 }
 
-pub fn blend_variables(a: &mut serde_yaml::Value, b: serde_yaml::Value) {
+// blend_variables layers b over a: nested mappings are merged key by key and anything else in b
+// overwrites a.  lists follow the list merge strategy, which is "replace" unless changed with
+// --list-merge or $JET_LIST_MERGE, so a host setting "packages: [c]" discards a group's "packages: [a, b]".
+// a single key can pick its own behavior with a tag:
+//
+//   packages: !append [ c ]     => [ a, b, c ]
+//   packages: !prepend [ c ]    => [ c, a, b ]
+//   app: !replace { port: 80 }  => mappings are taken wholesale instead of merged
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ListMerge {
+    Replace,
+    Append,
+    Prepend
+}
+
+impl ListMerge {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "replace" => Ok(ListMerge::Replace),
+            "append"  => Ok(ListMerge::Append),
+            "prepend" => Ok(ListMerge::Prepend),
+            _ => Err(format!("invalid list merge strategy: {}, expecting replace, append, or prepend", name))
+        }
+    }
+
+    fn from_tag(tag: &serde_yaml::value::Tag) -> Option<Self> {
+        if tag == REPLACE_TAG      { Some(ListMerge::Replace) }
+        else if tag == APPEND_TAG  { Some(ListMerge::Append) }
+        else if tag == PREPEND_TAG { Some(ListMerge::Prepend) }
+        else { None }
+    }
+}
+
+const REPLACE_TAG: &str = "replace";
+const APPEND_TAG: &str = "append";
+const PREPEND_TAG: &str = "prepend";
+
+// the strategy for variables comes from the context, see PlaybookContext::list_merge.  facts and other values
+// that are not layered by the user are blended with ListMerge::Replace
+
+pub fn blend_variables(a: &mut serde_yaml::Value, b: serde_yaml::Value, strategy: ListMerge) {
 
     match (a, b) {
 
@@ -98,23 +139,105 @@ pub fn blend_variables(a: &mut serde_yaml::Value, b: serde_yaml::Value) {
         (a @ &mut serde_yaml::Value::Mapping(_), serde_yaml::Value::Mapping(b)) => {
             let a = a.as_mapping_mut().unwrap();
             for (k, v) in b {
+                let (v, key_strategy) = match v {
+                    serde_yaml::Value::Tagged(tagged) => match ListMerge::from_tag(&tagged.tag) {
+                        Some(ListMerge::Replace) => {
+                            a.insert(k, strip_merge_tags(tagged.value));
+                            continue;
+                        },
+                        Some(x) => (tagged.value, x),
+                        None => (serde_yaml::Value::Tagged(tagged), strategy)
+                    },
+                    v => (v, strategy)
+                };
                 if v.is_sequence() && a.contains_key(&k) && a[&k].is_sequence() {
-                    let mut _b = a.get(&k).unwrap().as_sequence().unwrap().to_owned();
-                    _b.append(&mut v.as_sequence().unwrap().to_owned());
-                    a[&k] = serde_yaml::Value::from(_b);
+                    let mut mine = strip_merge_tags(v).as_sequence().unwrap().to_owned();
+                    let theirs = a.get_mut(&k).unwrap().as_sequence_mut().unwrap();
+                    match key_strategy {
+                        ListMerge::Replace => { *theirs = mine; },
+                        ListMerge::Append  => { theirs.append(&mut mine); },
+                        ListMerge::Prepend => { mine.append(theirs); *theirs = mine; }
+                    }
                     continue;
                 }
                 if !a.contains_key(&k) {
-                    a.insert(k.to_owned(), v.to_owned());
+                    a.insert(k, strip_merge_tags(v));
                 }
                 else {
-                    blend_variables(&mut a[&k], v);
+                    blend_variables(&mut a[&k], v, strategy);
                 }
 
             }
         }
         (a, b) => {
-            *a = b
+            *a = strip_merge_tags(b)
         },
     }
 }
+
+// merge tags only mean something while blending, so they must not leak into templates
+
+fn strip_merge_tags(v: serde_yaml::Value) -> serde_yaml::Value {
+    match v {
+        serde_yaml::Value::Tagged(tagged) if ListMerge::from_tag(&tagged.tag).is_some() => strip_merge_tags(tagged.value),
+        serde_yaml::Value::Mapping(m) => serde_yaml::Value::Mapping(m.into_iter().map(|(k,v)| (k, strip_merge_tags(v))).collect()),
+        serde_yaml::Value::Sequence(s) => serde_yaml::Value::Sequence(s.into_iter().map(strip_merge_tags).collect()),
+        x => x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn blend(a: &str, b: &str) -> serde_yaml::Value {
+        blend_with(a, b, ListMerge::Replace)
+    }
+
+    fn blend_with(a: &str, b: &str, strategy: ListMerge) -> serde_yaml::Value {
+        let mut a: serde_yaml::Value = serde_yaml::from_str(a).unwrap();
+        blend_variables(&mut a, serde_yaml::from_str(b).unwrap(), strategy);
+        a
+    }
+
+    fn yaml(s: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_nested_mappings_deep_merge() {
+        let blended = blend("app: { port: 80, name: web }", "app: { debug: true, name: api }");
+        assert_eq!(blended, yaml("app: { port: 80, name: api, debug: true }"));
+    }
+
+    #[test]
+    fn test_lists_replace_by_default() {
+        let blended = blend("pkgs: [ a, b ]\nother: 1", "pkgs: [ c ]");
+        assert_eq!(blended, yaml("pkgs: [ c ]\nother: 1"));
+    }
+
+    #[test]
+    fn test_list_merge_strategies() {
+        assert_eq!(blend_with("pkgs: [ a, b ]", "pkgs: [ c ]", ListMerge::Append), yaml("pkgs: [ a, b, c ]"));
+        assert_eq!(blend_with("pkgs: [ a, b ]", "pkgs: [ c ]", ListMerge::Prepend), yaml("pkgs: [ c, a, b ]"));
+        assert_eq!(blend_with("app: { pkgs: [ a ] }", "app: { pkgs: [ b ] }", ListMerge::Append), yaml("app: { pkgs: [ a, b ] }"));
+    }
+
+    #[test]
+    fn test_list_merge_tags_override_strategy() {
+        assert_eq!(blend("pkgs: [ a, b ]", "pkgs: !append [ c ]"), yaml("pkgs: [ a, b, c ]"));
+        assert_eq!(blend("pkgs: [ a, b ]", "pkgs: !prepend [ c ]"), yaml("pkgs: [ c, a, b ]"));
+        assert_eq!(blend_with("pkgs: [ a, b ]", "pkgs: !replace [ c ]", ListMerge::Append), yaml("pkgs: [ c ]"));
+        assert_eq!(blend("{}", "pkgs: !append [ c ]"), yaml("pkgs: [ c ]"));
+    }
+
+    #[test]
+    fn test_replace_tag_takes_value_wholesale() {
+        let blended = blend("app: { port: 80 }\npkgs: [ a ]", "app: !replace { debug: true }\npkgs: !replace [ b ]");
+        assert_eq!(blended, yaml("app: { debug: true }\npkgs: [ b ]"));
+        let blended = blend("{}", "app: !replace { nested: !replace { x: 1 } }");
+        assert_eq!(blended, yaml("app: { nested: { x: 1 } }"));
+    }
+
+}